@@ -21,10 +21,29 @@
 // DO NOT USE THIS IN PRODUCTION - IT'S A SKELETON ONLY!
 // =============================================================================
 
-use candid::{CandidType, Principal};
+use candid::{CandidType, Decode, Encode, Nat, Principal};
 use ic_cdk::api;
+use ic_cdk::api::management_canister::bitcoin::{
+    bitcoin_get_current_fee_percentiles, bitcoin_get_utxos, bitcoin_send_transaction,
+    BitcoinNetwork, GetCurrentFeePercentilesRequest, GetUtxosRequest, SendTransactionRequest,
+    UtxoFilter,
+};
+use ic_cdk::api::management_canister::ecdsa::{
+    ecdsa_public_key, sign_with_ecdsa, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument,
+    SignWithEcdsaArgument,
+};
 use ic_cdk_macros::*;
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, StableCell, Storable};
+use ripemd::Ripemd160;
 use serde::{Deserialize, Serialize};
+use shared::check_rate_limit;
+use shared::interface::{CandidVersion, InterfaceVersion};
+use shared::memory::{self, MemoryStats};
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::time::Duration;
 
 // =============================================================================
 // TYPES (Based on official ckBTC minter interface)
@@ -48,36 +67,296 @@ pub enum MinterError {
     TemporarilyUnavailable,
 }
 
-#[derive(CandidType, Serialize, Deserialize, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct MintRequest {
     pub user: Principal,
     pub amount: Amount,
     pub bitcoin_txid: Option<String>, // Bitcoin transaction ID for verification
 }
 
-#[derive(CandidType, Serialize, Deserialize, Debug)]
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum BurnStatus {
+    /// Queued for the next aggregated withdrawal transaction
+    Pending,
+    Signing,
+    /// Broadcast, waiting for confirmations
+    Submitted,
+    Confirmed,
+    /// Dropped because the amount couldn't cover its network fee share; the
+    /// burned ckBTC is minted back to the user
+    Rejected,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct BurnRequest {
+    pub id: TxId,
     pub user: Principal,
     pub amount: Amount,
     pub destination: BitcoinAddress,
+    pub status: BurnStatus,
+    pub bitcoin_txid: Option<String>,
+    pub created_at: u64,
+}
+
+/// UTXO controlled by the minter; `owner` is None for the main address
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MinterUtxo {
+    pub txid: Vec<u8>,
+    pub vout: u32,
+    pub value: u64,
+    pub owner: Option<Principal>,
+}
+
+/// Aggregated withdrawal transaction waiting for confirmations
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct SubmittedTransaction {
+    pub txid: String,
+    pub burn_ids: Vec<TxId>,
+    pub inputs: Vec<MinterUtxo>,
+    /// Destination address and amount after the fee share, in output order
+    pub outputs: Vec<(BitcoinAddress, u64)>,
+    pub change: u64,
+    pub fee_rate: u64,
+    pub submitted_at: u64,
+    /// Earlier versions of this transaction replaced by fee bumps, oldest first
+    pub replaced_txids: Vec<String>,
+}
+
+/// Fee bump history of a stuck withdrawal transaction
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct RbfRecord {
+    pub original_txid: String,
+    pub replacement_txid: Option<String>,
+    pub original_fee_rate: u64,
+    pub replacement_fee_rate: u64,
+    pub attempts: u8,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MinterConfig {
+    pub network: BitcoinNetwork,
+    pub ecdsa_key_name: String,
+    /// Main address receiving all deposits, derived after init
+    pub main_address: Option<BitcoinAddress>,
+    /// ckBTC ICRC-1 ledger; the minter is its minting account
+    pub ledger_id: Option<Principal>,
+    /// Deducted from every mint and accumulated for the fee recipient
+    pub minter_fee_satoshis: u64,
+    pub minter_fee_recipient: Principal,
+    pub min_mint_amount: u64,
+    pub min_burn_amount: u64,
+    /// A withdrawal waits at most this long before a partial batch is sent
+    pub max_time_in_queue_seconds: u64,
+}
+
+// ICRC-1 ledger interface types
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct Account {
+    pub owner: Principal,
+    pub subaccount: Option<Vec<u8>>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct TransferArg {
+    pub from_subaccount: Option<Vec<u8>>,
+    pub to: Account,
+    pub fee: Option<Nat>,
+    pub created_at_time: Option<u64>,
+    pub memo: Option<Vec<u8>>,
+    pub amount: Nat,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub struct TransferFromArgs {
+    pub spender_subaccount: Option<Vec<u8>>,
+    pub from: Account,
+    pub to: Account,
+    pub amount: Nat,
+    pub fee: Option<Nat>,
+    pub memo: Option<Vec<u8>>,
+    pub created_at_time: Option<u64>,
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub enum TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone)]
+pub enum TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+impl MinterConfig {
+    fn for_network(network: BitcoinNetwork) -> Self {
+        let ecdsa_key_name = match network {
+            BitcoinNetwork::Mainnet => "key_1",
+            BitcoinNetwork::Testnet => "test_key_1",
+            BitcoinNetwork::Regtest => "dfx_test_key",
+        };
+        MinterConfig {
+            network,
+            ecdsa_key_name: ecdsa_key_name.to_string(),
+            main_address: None,
+            ledger_id: None,
+            minter_fee_satoshis: 1_000,
+            minter_fee_recipient: Principal::anonymous(),
+            min_mint_amount: 10_000,
+            min_burn_amount: 10_000,
+            max_time_in_queue_seconds: 600,
+        }
+    }
 }
 
 // =============================================================================
 // STATE MANAGEMENT
 // =============================================================================
 
-// TODO: Implement proper state management using ic-stable-structures
 // Reference: https://github.com/dfinity/ic/tree/master/rs/bitcoin/ckbtc/minter/src/state.rs
 
+/// Deposits are minted once they reach this many confirmations
+const MIN_DEPOSIT_CONFIRMATIONS: u32 = 3;
+
+const DEPOSIT_CHECK_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Every registered deposit address is polled on each deposit check, so the
+/// number of them is bounded
+const MAX_DEPOSIT_ADDRESSES: usize = 10_000;
+
+/// Pending withdrawals are checked for batching at this interval
+const WITHDRAWAL_BATCH_INTERVAL: Duration = Duration::from_secs(600);
+
+const MAX_WITHDRAWALS_PER_BATCH: usize = 100;
+
+/// Withdrawals are considered final after this many confirmations
+const WITHDRAWAL_CONFIRMATIONS: u32 = 6;
+
+/// Used when the fee percentiles are unavailable (e.g. on regtest)
+const DEFAULT_FEE_RATE: u64 = 2;
+
+const DUST_THRESHOLD: u64 = 546;
+
+/// Signals replaceability (BIP-125) on every input
+const RBF_SEQUENCE: u32 = 0xffff_fffd;
+
+/// Unconfirmed withdrawals are fee-bumped after this long
+const RBF_TIMEOUT_NANOS: u64 = 30 * 60 * 1_000_000_000;
+
+const MAX_RBF_ATTEMPTS: u8 = 3;
+
+/// Bump when the layout of `MinterState` changes and add a migration in `post_upgrade`
+const MINTER_STATE_SCHEMA_VERSION: u32 = 1;
+
+/// Snapshot of the minter state written to stable memory across upgrades
+#[derive(CandidType, Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MinterState {
+    pub schema_version: u32,
+    pub pending_mints: BTreeMap<TxId, MintRequest>,
+    pub pending_burns: BTreeMap<TxId, BurnRequest>,
+    pub next_tx_id: TxId,
+    pub config: MinterConfig,
+    pub minter_admin: Option<Principal>,
+    pub accumulated_fees: u64,
+    pub user_deposit_addresses: BTreeMap<Principal, BitcoinAddress>,
+    pub seen_utxos: BTreeSet<(String, u32)>,
+    pub pending_withdrawals: Vec<BurnRequest>,
+    pub available_utxos: BTreeMap<(String, u32), MinterUtxo>,
+    pub submitted_transactions: BTreeMap<String, SubmittedTransaction>,
+    pub pending_rbf: BTreeMap<String, RbfRecord>,
+}
+
+impl Default for MinterState {
+    fn default() -> Self {
+        MinterState {
+            schema_version: MINTER_STATE_SCHEMA_VERSION,
+            pending_mints: BTreeMap::new(),
+            pending_burns: BTreeMap::new(),
+            next_tx_id: 1,
+            config: MinterConfig::for_network(BitcoinNetwork::Mainnet),
+            minter_admin: None,
+            accumulated_fees: 0,
+            user_deposit_addresses: BTreeMap::new(),
+            seen_utxos: BTreeSet::new(),
+            pending_withdrawals: Vec::new(),
+            available_utxos: BTreeMap::new(),
+            submitted_transactions: BTreeMap::new(),
+            pending_rbf: BTreeMap::new(),
+        }
+    }
+}
+
+impl Storable for MinterState {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode minter state"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode minter state")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
 thread_local! {
-    // Placeholder state - replace with proper stable storage
-    static PENDING_MINTS: std::cell::RefCell<std::collections::HashMap<TxId, MintRequest>> 
-        = std::cell::RefCell::new(std::collections::HashMap::new());
+    // Heap state, persisted through STABLE_STATE across upgrades
+    // BTreeMap keeps iteration order deterministic across replicas
+    static PENDING_MINTS: std::cell::RefCell<BTreeMap<TxId, MintRequest>> 
+        = const { std::cell::RefCell::new(BTreeMap::new()) };
     
-    static PENDING_BURNS: std::cell::RefCell<std::collections::HashMap<TxId, BurnRequest>> 
-        = std::cell::RefCell::new(std::collections::HashMap::new());
+    static PENDING_BURNS: std::cell::RefCell<BTreeMap<TxId, BurnRequest>> 
+        = const { std::cell::RefCell::new(BTreeMap::new()) };
     
-    static NEXT_TX_ID: std::cell::RefCell<TxId> = std::cell::RefCell::new(1);
+    static NEXT_TX_ID: std::cell::RefCell<TxId> = const { std::cell::RefCell::new(1) };
+    
+    static MINTER_CONFIG: std::cell::RefCell<MinterConfig>
+        = std::cell::RefCell::new(MinterConfig::for_network(BitcoinNetwork::Mainnet));
+    
+    static MINTER_ADMIN: std::cell::RefCell<Principal> = const { std::cell::RefCell::new(Principal::anonymous()) };
+    
+    // Minter fees collected since the last claim
+    static ACCUMULATED_FEES: std::cell::RefCell<u64> = const { std::cell::RefCell::new(0) };
+    
+    static USER_DEPOSIT_ADDRESSES: std::cell::RefCell<BTreeMap<Principal, BitcoinAddress>> 
+        = const { std::cell::RefCell::new(BTreeMap::new()) };
+    
+    // Deposit outpoints (txid, vout) already minted or being minted
+    static SEEN_UTXOS: std::cell::RefCell<BTreeSet<(String, u32)>> 
+        = const { std::cell::RefCell::new(BTreeSet::new()) };
+    
+    static PENDING_WITHDRAWALS: std::cell::RefCell<VecDeque<BurnRequest>> 
+        = const { std::cell::RefCell::new(VecDeque::new()) };
+    
+    // Spendable outputs keyed by (txid hex, vout)
+    static AVAILABLE_UTXOS: std::cell::RefCell<BTreeMap<(String, u32), MinterUtxo>> 
+        = const { std::cell::RefCell::new(BTreeMap::new()) };
+    
+    static SUBMITTED_TRANSACTIONS: std::cell::RefCell<BTreeMap<String, SubmittedTransaction>> 
+        = const { std::cell::RefCell::new(BTreeMap::new()) };
+    
+    // Keyed by the txid of the first version of the transaction
+    static PENDING_RBF: std::cell::RefCell<BTreeMap<String, RbfRecord>> 
+        = const { std::cell::RefCell::new(BTreeMap::new()) };
+    
+    // Only written in pre_upgrade and read in post_upgrade
+    static STABLE_STATE: std::cell::RefCell<StableCell<MinterState, DefaultMemoryImpl>> = std::cell::RefCell::new(
+        StableCell::init(DefaultMemoryImpl::default(), MinterState::default())
+            .expect("Failed to initialize stable minter state")
+    );
 }
 
 fn get_next_tx_id() -> TxId {
@@ -105,12 +384,19 @@ fn get_next_tx_id() -> TxId {
 /// https://github.com/dfinity/ic/blob/master/rs/bitcoin/ckbtc/minter/src/updates/update_balance.rs
 #[update]
 pub async fn request_mint(user: Principal, amount: Amount) -> Result<TxId, MinterError> {
+    check_rate_limit(api::caller(), "request_mint").map_err(|_| MinterError::TemporarilyUnavailable)?;
+    
+    // Requests grow the minter's state, so they wait until memory is back in hand
+    if memory::is_memory_critical() {
+        return Err(MinterError::TemporarilyUnavailable);
+    }
+    
     // TODO: Replace with actual implementation
     
-    // Placeholder validation
-    if amount == 0 {
+    let config = MINTER_CONFIG.with(|c| c.borrow().clone());
+    if amount < config.min_mint_amount || amount <= config.minter_fee_satoshis {
         return Err(MinterError::InvalidDestination {
-            message: "Amount must be greater than 0".to_string(),
+            message: format!("Amount must be at least {} satoshis", config.min_mint_amount),
         });
     }
     
@@ -118,10 +404,13 @@ pub async fn request_mint(user: Principal, amount: Amount) -> Result<TxId, Minte
     
     let mint_request = MintRequest {
         user,
-        amount,
+        amount: amount - config.minter_fee_satoshis,
         bitcoin_txid: None, // TODO: Get from Bitcoin network
     };
     
+    // Nothing is minted here, so no fee is accrued. Only deposits minted by
+    // `check_new_deposits` add to ACCUMULATED_FEES.
+    
     // Store mint request (placeholder)
     PENDING_MINTS.with(|mints| {
         mints.borrow_mut().insert(tx_id, mint_request);
@@ -144,55 +433,64 @@ pub async fn request_mint(user: Principal, amount: Amount) -> Result<TxId, Minte
 
 /// Request burning of ckBTC tokens (withdrawal to Bitcoin)
 /// 
-/// PRODUCTION TODO: Implement actual Bitcoin transaction creation
-/// - Use threshold ECDSA to sign Bitcoin transaction
-/// - Calculate proper Bitcoin network fees
-/// - Implement UTXO management
-/// - Add proper error handling and retries
+/// The user must first approve the minter for `amount` on the ledger
+/// (ICRC-2). The tokens are burned before the withdrawal is queued and paid
+/// out by the next aggregated transaction, see `process_pending_withdrawals`.
 /// 
 /// Reference implementation:
 /// https://github.com/dfinity/ic/blob/master/rs/bitcoin/ckbtc/minter/src/updates/retrieve_btc.rs
 #[update]
 pub async fn request_burn(user: Principal, amount: Amount, destination: BitcoinAddress) -> Result<TxId, MinterError> {
-    // TODO: Replace with actual implementation
+    check_rate_limit(api::caller(), "request_burn").map_err(|_| MinterError::TemporarilyUnavailable)?;
+    
+    // Requests grow the minter's state, so they wait until memory is back in hand
+    if memory::is_memory_critical() {
+        return Err(MinterError::TemporarilyUnavailable);
+    }
+    
+    if api::caller() != user {
+        return Err(MinterError::TransactionFailed {
+            reason: "Only the user can withdraw their own ckBTC".to_string(),
+        });
+    }
     
-    // Placeholder validation
     if amount == 0 {
         return Err(MinterError::InvalidDestination {
             message: "Amount must be greater than 0".to_string(),
         });
     }
     
-    // TODO: Validate Bitcoin address format
-    if destination.is_empty() {
+    let config = MINTER_CONFIG.with(|c| c.borrow().clone());
+    if amount < config.min_burn_amount {
         return Err(MinterError::InvalidDestination {
-            message: "Invalid Bitcoin address".to_string(),
+            message: format!("Amount must be at least {} satoshis", config.min_burn_amount),
         });
     }
     
+    address_to_script(&destination, config.network)?;
+    
     let tx_id = get_next_tx_id();
     
+    // Nothing is queued unless the ckBTC is gone from the user's account
+    burn_ckbtc(user, amount, tx_id).await?;
+    
     let burn_request = BurnRequest {
+        id: tx_id,
         user,
         amount,
         destination: destination.clone(),
+        status: BurnStatus::Pending,
+        bitcoin_txid: None,
+        created_at: api::time(),
     };
     
-    // Store burn request (placeholder)
     PENDING_BURNS.with(|burns| {
-        burns.borrow_mut().insert(tx_id, burn_request);
+        burns.borrow_mut().insert(tx_id, burn_request.clone());
     });
-    
-    // TODO: Implement actual burning logic:
-    // 1. Verify user has sufficient ckBTC balance
-    // 2. Burn tokens from ICRC-1 ledger
-    // 3. Create Bitcoin transaction using threshold ECDSA
-    // 4. Broadcast Bitcoin transaction
-    // 5. Monitor transaction confirmation
-    // 6. Update internal state
+    PENDING_WITHDRAWALS.with(|queue| queue.borrow_mut().push_back(burn_request));
     
     ic_cdk::println!(
-        "PLACEHOLDER: Would burn {} ckBTC from user {} to Bitcoin address {}. TX ID: {}",
+        "Queued withdrawal of {} ckBTC from user {} to Bitcoin address {}. TX ID: {}",
         amount, user, destination, tx_id
     );
     
@@ -221,20 +519,1135 @@ pub fn get_minter_info() -> String {
     )
 }
 
-/// Get Bitcoin address for deposits (placeholder)
+/// Get a withdrawal request and its Bitcoin transaction status
+#[query]
+pub fn get_burn_request(tx_id: TxId) -> Option<BurnRequest> {
+    PENDING_BURNS.with(|b| b.borrow().get(&tx_id).cloned())
+}
+
+/// Get the fee bump history of a withdrawal transaction by its original txid
+#[query]
+pub fn get_rbf_status(original_txid: String) -> Option<RbfRecord> {
+    PENDING_RBF.with(|r| r.borrow().get(&original_txid).cloned())
+}
+
+/// Get the current minter configuration
+#[query]
+pub fn get_minter_config() -> MinterConfig {
+    MINTER_CONFIG.with(|c| c.borrow().clone())
+}
+
+/// Minter fees collected and not yet claimed
+#[query]
+pub fn get_accumulated_fees() -> u64 {
+    ACCUMULATED_FEES.with(|f| *f.borrow())
+}
+
+/// Heap and stable memory usage of the minter
+#[query]
+pub fn get_memory_stats() -> MemoryStats {
+    memory::get_memory_stats()
+}
+
+const INTERFACE_VERSION: InterfaceVersion = (1, 0, 0);
+
+/// Major version of the minter's Candid interface
+#[query]
+pub fn get_candid_interface_version() -> u32 {
+    INTERFACE_VERSION.0
+}
+
+/// Version and hash of the minter's Candid interface
+#[query]
+pub fn get_interface_metadata() -> CandidVersion {
+    CandidVersion::new(INTERFACE_VERSION, &__export_service())
+}
+
+// =============================================================================
+// FEE MANAGEMENT
+// =============================================================================
+
+/// Mint the accumulated minter fees to the fee recipient
+/// 
+/// Fees only accrue from confirmed deposits, so every claimed satoshi is
+/// backed by BTC held at a minter address.
+#[update]
+pub async fn claim_minter_fees() -> Result<u64, String> {
+    let caller = api::caller();
+    check_rate_limit(caller, "claim_minter_fees").map_err(|e| e.to_string())?;
+    let recipient = MINTER_CONFIG.with(|c| c.borrow().minter_fee_recipient);
+    if caller != recipient {
+        return Err("Only the minter fee recipient can claim fees".to_string());
+    }
+    
+    // Reset before the ledger call so a concurrent claim cannot mint twice
+    let amount = ACCUMULATED_FEES.with(|f| std::mem::take(&mut *f.borrow_mut()));
+    if amount == 0 {
+        return Err("No fees to claim".to_string());
+    }
+    
+    match mint_ckbtc(recipient, amount, b"minter_fees".to_vec()).await {
+        Ok(_) => Ok(amount),
+        Err(e) => {
+            ACCUMULATED_FEES.with(|f| *f.borrow_mut() += amount);
+            Err(format!("Failed to transfer fees: {:?}", e))
+        }
+    }
+}
+
+/// Update fee and limit settings
+/// 
+/// The network, ECDSA key and main address are fixed at install time.
+#[update]
+pub fn update_minter_config(new_config: MinterConfig) -> Result<String, String> {
+    let caller = api::caller();
+    check_rate_limit(caller, "update_minter_config").map_err(|e| e.to_string())?;
+    if caller != MINTER_ADMIN.with(|a| *a.borrow()) {
+        return Err("Only the minter admin can update the configuration".to_string());
+    }
+    
+    MINTER_CONFIG.with(|c| {
+        let mut config = c.borrow_mut();
+        if new_config.network != config.network
+            || new_config.ecdsa_key_name != config.ecdsa_key_name
+            || new_config.main_address != config.main_address
+        {
+            return Err("Network, ECDSA key and main address cannot be changed".to_string());
+        }
+        
+        *config = new_config;
+        Ok("Minter configuration updated".to_string())
+    })
+}
+
+/// Get the minter's main deposit address, once it has been derived
 #[query]
-pub fn get_bitcoin_address(user: Principal) -> BitcoinAddress {
-    // TODO: Generate actual Bitcoin address using threshold ECDSA
-    // This should derive a unique address for each user to track deposits
+pub fn get_minter_address() -> Option<BitcoinAddress> {
+    MINTER_CONFIG.with(|c| c.borrow().main_address.clone())
+}
+
+// =============================================================================
+// ADDRESS DERIVATION
+// =============================================================================
+
+/// Get the user's Bitcoin deposit subaddress
+/// 
+/// Derived from the minter's threshold ECDSA key with the user's principal in
+/// the derivation path, then cached. Only the user may request it.
+#[update]
+pub async fn get_bitcoin_address(user: Principal) -> Result<BitcoinAddress, MinterError> {
+    check_rate_limit(api::caller(), "get_bitcoin_address").map_err(|_| MinterError::TemporarilyUnavailable)?;
+    
+    // Requests grow the minter's state, so they wait until memory is back in hand
+    if memory::is_memory_critical() {
+        return Err(MinterError::TemporarilyUnavailable);
+    }
+    
+    if api::caller() != user {
+        return Err(MinterError::TransactionFailed {
+            reason: "Only the user can request their own deposit address".to_string(),
+        });
+    }
     
-    // PLACEHOLDER - DO NOT USE IN PRODUCTION
-    format!("bc1q{:x}...(placeholder)", user.as_slice()[0])
+    if user == Principal::anonymous() {
+        return Err(MinterError::TransactionFailed {
+            reason: "Anonymous callers cannot register a deposit address".to_string(),
+        });
+    }
+    
+    if let Some(address) = USER_DEPOSIT_ADDRESSES.with(|a| a.borrow().get(&user).cloned()) {
+        return Ok(address);
+    }
+    
+    if USER_DEPOSIT_ADDRESSES.with(|a| a.borrow().len()) >= MAX_DEPOSIT_ADDRESSES {
+        return Err(deposit_address_limit_error());
+    }
+    
+    let address = derive_address(user_derivation_path(&user)).await?;
+    
+    // Re-checked since other registrations may have completed during the call
+    USER_DEPOSIT_ADDRESSES.with(|a| {
+        let mut addresses = a.borrow_mut();
+        if !addresses.contains_key(&user) && addresses.len() >= MAX_DEPOSIT_ADDRESSES {
+            return Err(deposit_address_limit_error());
+        }
+        addresses.insert(user, address.clone());
+        Ok(())
+    })?;
+    
+    Ok(address)
+}
+
+fn deposit_address_limit_error() -> MinterError {
+    MinterError::SystemError {
+        message: format!("Deposit address limit of {} reached", MAX_DEPOSIT_ADDRESSES),
+    }
+}
+
+fn main_derivation_path() -> Vec<Vec<u8>> {
+    vec![b"ckbtc_minter".to_vec()]
+}
+
+fn user_derivation_path(user: &Principal) -> Vec<Vec<u8>> {
+    vec![b"ckbtc_minter".to_vec(), user.as_slice().to_vec()]
+}
+
+async fn derive_address(derivation_path: Vec<Vec<u8>>) -> Result<BitcoinAddress, MinterError> {
+    let config = MINTER_CONFIG.with(|c| c.borrow().clone());
+    
+    let (response,) = ecdsa_public_key(EcdsaPublicKeyArgument {
+        canister_id: None,
+        derivation_path,
+        key_id: EcdsaKeyId {
+            curve: EcdsaCurve::Secp256k1,
+            name: config.ecdsa_key_name,
+        },
+    })
+    .await
+    .map_err(|(code, msg)| MinterError::SystemError {
+        message: format!("ecdsa_public_key failed: {:?} {}", code, msg),
+    })?;
+    
+    p2wpkh_address(&response.public_key, config.network)
+}
+
+/// P2WPKH address: bech32(RIPEMD-160(SHA-256(compressed public key)))
+fn p2wpkh_address(public_key: &[u8], network: BitcoinNetwork) -> Result<BitcoinAddress, MinterError> {
+    if public_key.len() != 33 {
+        return Err(MinterError::SystemError {
+            message: "Expected a 33-byte compressed public key".to_string(),
+        });
+    }
+    
+    let witness_program = Ripemd160::digest(Sha256::digest(public_key));
+    
+    let hrp = match network {
+        BitcoinNetwork::Mainnet => bech32::hrp::BC,
+        BitcoinNetwork::Testnet => bech32::hrp::TB,
+        BitcoinNetwork::Regtest => bech32::hrp::BCRT,
+    };
+    
+    bech32::segwit::encode_v0(hrp, &witness_program).map_err(|e| MinterError::SystemError {
+        message: format!("Failed to encode address: {}", e),
+    })
+}
+
+async fn initialize_main_address() {
+    match derive_address(main_derivation_path()).await {
+        Ok(address) => {
+            ic_cdk::println!("Minter main address: {}", address);
+            MINTER_CONFIG.with(|c| c.borrow_mut().main_address = Some(address));
+        }
+        Err(e) => ic_cdk::println!("Failed to derive minter main address: {:?}", e),
+    }
 }
 
 // =============================================================================
 // TIMER FUNCTIONS (Bitcoin Network Integration)
 // =============================================================================
 
+fn start_timers() {
+    ic_cdk_timers::set_timer_interval(DEPOSIT_CHECK_INTERVAL, || ic_cdk::spawn(check_new_deposits()));
+    ic_cdk_timers::set_timer_interval(WITHDRAWAL_BATCH_INTERVAL, || {
+        ic_cdk::spawn(process_pending_withdrawals());
+        ic_cdk::spawn(check_transaction_confirmations());
+    });
+    memory::start_memory_monitor(memory::log_memory_alert);
+}
+
+/// Mint ckBTC for confirmed deposits to user subaddresses
+/// 
+/// Outputs at the main address are the minter's own funds (e.g. change) and
+/// are not attributable to a user, so only user subaddresses are polled.
+async fn check_new_deposits() {
+    let addresses: Vec<(Principal, BitcoinAddress)> = USER_DEPOSIT_ADDRESSES.with(|a| {
+        a.borrow().iter().map(|(user, address)| (*user, address.clone())).collect()
+    });
+    
+    for (user, address) in addresses {
+        let utxos = match fetch_utxos(&address, MIN_DEPOSIT_CONFIRMATIONS).await {
+            Ok(utxos) => utxos,
+            Err(e) => {
+                ic_cdk::println!("Failed to fetch UTXOs for {}: {:?}", address, e);
+                continue;
+            }
+        };
+        
+        for (txid, vout, value) in utxos {
+            let outpoint = (txid_to_hex(&txid), vout);
+            
+            // Mark before minting so a concurrent check cannot mint it twice
+            let is_new = SEEN_UTXOS.with(|seen| seen.borrow_mut().insert(outpoint.clone()));
+            if !is_new {
+                continue;
+            }
+            
+            let (fee, min_mint_amount) = MINTER_CONFIG.with(|c| {
+                let c = c.borrow();
+                (c.minter_fee_satoshis, c.min_mint_amount)
+            });
+            if value < min_mint_amount || value <= fee {
+                ic_cdk::println!("Ignoring deposit {}:{} below the minimum mint amount", outpoint.0, outpoint.1);
+                continue;
+            }
+            
+            match mint_ckbtc(user, value - fee, txid.clone()).await {
+                Ok(block_index) => {
+                    ic_cdk::println!(
+                        "Minted {} ckBTC for {} from {}:{} at block {}",
+                        value - fee, user, outpoint.0, outpoint.1, block_index
+                    );
+                    ACCUMULATED_FEES.with(|f| *f.borrow_mut() += fee);
+                    AVAILABLE_UTXOS.with(|utxos| {
+                        utxos.borrow_mut().insert(outpoint, MinterUtxo {
+                            txid,
+                            vout,
+                            value,
+                            owner: Some(user),
+                        });
+                    });
+                }
+                Err(e) => {
+                    // Allow the next check to retry
+                    SEEN_UTXOS.with(|seen| seen.borrow_mut().remove(&outpoint));
+                    ic_cdk::println!("Failed to mint for {}:{}: {:?}", outpoint.0, outpoint.1, e);
+                }
+            }
+        }
+    }
+}
+
+/// Returns (txid, vout, value) for outputs with at least `min_confirmations`
+async fn fetch_utxos(address: &str, min_confirmations: u32) -> Result<Vec<(Vec<u8>, u32, u64)>, MinterError> {
+    let network = MINTER_CONFIG.with(|c| c.borrow().network);
+    
+    let mut utxos = Vec::new();
+    let mut filter = Some(UtxoFilter::MinConfirmations(min_confirmations));
+    
+    loop {
+        let (response,) = bitcoin_get_utxos(GetUtxosRequest {
+            address: address.to_string(),
+            network,
+            filter,
+        })
+        .await
+        .map_err(|(code, msg)| MinterError::SystemError {
+            message: format!("bitcoin_get_utxos failed: {:?} {}", code, msg),
+        })?;
+        
+        for utxo in response.utxos {
+            utxos.push((utxo.outpoint.txid, utxo.outpoint.vout, utxo.value));
+        }
+        
+        match response.next_page {
+            Some(page) => filter = Some(UtxoFilter::Page(page)),
+            None => break,
+        }
+    }
+    
+    Ok(utxos)
+}
+
+/// Mint ckBTC on the ledger; the deposit txid is used as the memo
+async fn mint_ckbtc(to: Principal, amount: Amount, memo: Vec<u8>) -> Result<u64, MinterError> {
+    let ledger_id = MINTER_CONFIG.with(|c| c.borrow().ledger_id).ok_or(MinterError::TemporarilyUnavailable)?;
+    
+    let arg = TransferArg {
+        from_subaccount: None,
+        to: Account { owner: to, subaccount: None },
+        fee: None,
+        created_at_time: None,
+        memo: Some(memo),
+        amount: Nat::from(amount),
+    };
+    
+    let result: Result<(Result<Nat, TransferError>,), _> =
+        ic_cdk::call(ledger_id, "icrc1_transfer", (arg,)).await;
+    
+    match result {
+        Ok((Ok(block_index),)) => Ok(u64::try_from(block_index.0).unwrap_or(u64::MAX)),
+        Ok((Err(e),)) => Err(MinterError::TransactionFailed {
+            reason: format!("icrc1_transfer rejected: {:?}", e),
+        }),
+        Err((code, msg)) => Err(MinterError::SystemError {
+            message: format!("icrc1_transfer failed: {:?} {}", code, msg),
+        }),
+    }
+}
+
+/// Burn ckBTC by moving it from the user to the minter, which is the ledger's
+/// minting account
+async fn burn_ckbtc(user: Principal, amount: Amount, tx_id: TxId) -> Result<u64, MinterError> {
+    let ledger_id = MINTER_CONFIG.with(|c| c.borrow().ledger_id).ok_or(MinterError::TemporarilyUnavailable)?;
+    
+    let arg = TransferFromArgs {
+        spender_subaccount: None,
+        from: Account { owner: user, subaccount: None },
+        to: Account { owner: api::id(), subaccount: None },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: Some(tx_id.to_be_bytes().to_vec()),
+        created_at_time: None,
+    };
+    
+    let result: Result<(Result<Nat, TransferFromError>,), _> =
+        ic_cdk::call(ledger_id, "icrc2_transfer_from", (arg,)).await;
+    
+    match result {
+        Ok((Ok(block_index),)) => Ok(u64::try_from(block_index.0).unwrap_or(u64::MAX)),
+        Ok((Err(TransferFromError::InsufficientFunds { balance }),)) => Err(MinterError::InsufficientFunds {
+            balance: u64::try_from(balance.0).unwrap_or(u64::MAX),
+        }),
+        Ok((Err(e),)) => Err(MinterError::TransactionFailed {
+            reason: format!("icrc2_transfer_from rejected: {:?}", e),
+        }),
+        Err((code, msg)) => Err(MinterError::SystemError {
+            message: format!("icrc2_transfer_from failed: {:?} {}", code, msg),
+        }),
+    }
+}
+
+/// The Bitcoin API returns txids in internal byte order; display order is reversed
+fn txid_to_hex(txid: &[u8]) -> String {
+    txid.iter().rev().map(|b| format!("{:02x}", b)).collect()
+}
+
+// =============================================================================
+// WITHDRAWAL AGGREGATION
+// =============================================================================
+
+// Reference: https://github.com/dfinity/ic/blob/master/rs/bitcoin/ckbtc/minter/src/lib.rs (submit_pending_requests)
+
+/// Pay out queued withdrawals with a single Bitcoin transaction
+/// 
+/// A batch is sent once it is full or its oldest request has waited
+/// `max_time_in_queue_seconds`. The network fee is computed from the size of the aggregated transaction
+/// and deducted from the withdrawals in proportion to their amounts. Change
+/// goes back to the main address.
+async fn process_pending_withdrawals() {
+    let (network, main_address, max_wait) = MINTER_CONFIG.with(|c| {
+        let c = c.borrow();
+        (c.network, c.main_address.clone(), c.max_time_in_queue_seconds)
+    });
+    let main_address = match main_address {
+        Some(address) => address,
+        None => return,
+    };
+    
+    let now = api::time();
+    let ready = PENDING_WITHDRAWALS.with(|q| {
+        let q = q.borrow();
+        match q.front() {
+            Some(oldest) => {
+                q.len() >= MAX_WITHDRAWALS_PER_BATCH
+                    || now.saturating_sub(oldest.created_at) >= max_wait * 1_000_000_000
+            }
+            None => false,
+        }
+    });
+    if !ready {
+        return;
+    }
+    
+    let fee_rate = current_fee_rate(network).await;
+    
+    // Take the batch and its inputs out of the shared state before signing so
+    // a concurrent run cannot spend them again
+    let (batch, inputs) = match take_withdrawal_batch() {
+        Some(taken) => taken,
+        None => return,
+    };
+    
+    // A withdrawal that can't cover its fee share would fail the whole batch
+    // every time it is retried, so it is dropped and refunded instead
+    let (batch, rejected) = reject_undersized_withdrawals(batch, inputs.len(), fee_rate, &main_address, network);
+    for request in rejected {
+        ic_cdk::spawn(refund_withdrawal(request));
+    }
+    if batch.is_empty() {
+        restore_withdrawal_batch(batch, inputs);
+        return;
+    }
+    
+    match submit_withdrawal_transaction(&batch, &inputs, fee_rate, &main_address, network).await {
+        Ok(submitted) => {
+            ic_cdk::println!(
+                "Submitted withdrawal transaction {} for {} requests",
+                submitted.txid, batch.len()
+            );
+            set_burn_status(&submitted.burn_ids, BurnStatus::Submitted, Some(&submitted.txid));
+            
+            SUBMITTED_TRANSACTIONS.with(|txs| {
+                txs.borrow_mut().insert(submitted.txid.clone(), submitted);
+            });
+        }
+        Err(e) => {
+            ic_cdk::println!("Failed to submit withdrawal transaction: {:?}", e);
+            restore_withdrawal_batch(batch, inputs);
+        }
+    }
+}
+
+/// Pops as many queued withdrawals as the available UTXOs can cover and
+/// selects inputs for them, largest first
+fn take_withdrawal_batch() -> Option<(Vec<BurnRequest>, Vec<MinterUtxo>)> {
+    let mut utxos: Vec<MinterUtxo> = AVAILABLE_UTXOS.with(|u| u.borrow().values().cloned().collect());
+    utxos.sort_by_key(|u| std::cmp::Reverse(u.value));
+    let available: u64 = utxos.iter().map(|u| u.value).sum();
+    
+    let mut batch = Vec::new();
+    let mut total: u64 = 0;
+    PENDING_WITHDRAWALS.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        while batch.len() < MAX_WITHDRAWALS_PER_BATCH {
+            match queue.front() {
+                Some(next) if total + next.amount <= available => {
+                    total += next.amount;
+                    batch.extend(queue.pop_front());
+                }
+                _ => break,
+            }
+        }
+    });
+    
+    if batch.is_empty() {
+        return None;
+    }
+    
+    let mut inputs = Vec::new();
+    let mut selected: u64 = 0;
+    for utxo in utxos {
+        if selected >= total {
+            break;
+        }
+        selected += utxo.value;
+        inputs.push(utxo);
+    }
+    
+    AVAILABLE_UTXOS.with(|u| {
+        let mut u = u.borrow_mut();
+        for input in &inputs {
+            u.remove(&(txid_to_hex(&input.txid), input.vout));
+        }
+    });
+    
+    let burn_ids: Vec<TxId> = batch.iter().map(|b| b.id).collect();
+    set_burn_status(&burn_ids, BurnStatus::Signing, None);
+    
+    Some((batch, inputs))
+}
+
+/// Splits off withdrawals whose amount would drop below dust after their
+/// share of the network fee. Removing one changes everyone's share, so this
+/// repeats until the remaining batch is stable.
+fn reject_undersized_withdrawals(
+    mut batch: Vec<BurnRequest>,
+    input_count: usize,
+    fee_rate: u64,
+    main_address: &str,
+    network: BitcoinNetwork,
+) -> (Vec<BurnRequest>, Vec<BurnRequest>) {
+    let mut rejected = Vec::new();
+    
+    loop {
+        let mut scripts: Vec<Vec<u8>> = batch
+            .iter()
+            .filter_map(|b| address_to_script(&b.destination, network).ok())
+            .collect();
+        scripts.extend(address_to_script(main_address, network).ok());
+        let fee = estimate_vsize(input_count, &scripts) * fee_rate;
+        
+        let amounts: Vec<u64> = batch.iter().map(|b| b.amount).collect();
+        let shares = split_fee(fee, &amounts);
+        
+        let (kept, dropped): (Vec<_>, Vec<_>) = batch
+            .into_iter()
+            .zip(shares)
+            .partition(|(request, share)| request.amount.saturating_sub(*share) >= DUST_THRESHOLD);
+        
+        batch = kept.into_iter().map(|(request, _)| request).collect();
+        if dropped.is_empty() {
+            return (batch, rejected);
+        }
+        rejected.extend(dropped.into_iter().map(|(request, _)| request));
+    }
+}
+
+async fn refund_withdrawal(request: BurnRequest) {
+    set_burn_status(&[request.id], BurnStatus::Rejected, None);
+    
+    match mint_ckbtc(request.user, request.amount, request.id.to_be_bytes().to_vec()).await {
+        Ok(block_index) => ic_cdk::println!(
+            "Refunded rejected withdrawal {} of {} to {} at block {}",
+            request.id, request.amount, request.user, block_index
+        ),
+        Err(e) => ic_cdk::println!("Failed to refund rejected withdrawal {}: {:?}", request.id, e),
+    }
+}
+
+fn restore_withdrawal_batch(batch: Vec<BurnRequest>, inputs: Vec<MinterUtxo>) {
+    let burn_ids: Vec<TxId> = batch.iter().map(|b| b.id).collect();
+    set_burn_status(&burn_ids, BurnStatus::Pending, None);
+    
+    // Back to the front of the queue in their original order
+    PENDING_WITHDRAWALS.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        for request in batch.into_iter().rev() {
+            queue.push_front(request);
+        }
+    });
+    
+    AVAILABLE_UTXOS.with(|u| {
+        let mut u = u.borrow_mut();
+        for input in inputs {
+            u.insert((txid_to_hex(&input.txid), input.vout), input);
+        }
+    });
+}
+
+fn set_burn_status(burn_ids: &[TxId], status: BurnStatus, bitcoin_txid: Option<&str>) {
+    PENDING_BURNS.with(|burns| {
+        let mut burns = burns.borrow_mut();
+        for id in burn_ids {
+            if let Some(burn) = burns.get_mut(id) {
+                burn.status = status.clone();
+                if let Some(txid) = bitcoin_txid {
+                    burn.bitcoin_txid = Some(txid.to_string());
+                }
+            }
+        }
+    });
+}
+
+async fn submit_withdrawal_transaction(
+    batch: &[BurnRequest],
+    inputs: &[MinterUtxo],
+    fee_rate: u64,
+    main_address: &str,
+    network: BitcoinNetwork,
+) -> Result<SubmittedTransaction, MinterError> {
+    let total_in: u64 = inputs.iter().map(|i| i.value).sum();
+    let total_out: u64 = batch.iter().map(|b| b.amount).sum();
+    let change = total_in - total_out;
+    
+    let mut scripts = Vec::new();
+    for request in batch {
+        scripts.push(address_to_script(&request.destination, network)?);
+    }
+    let change_script = address_to_script(main_address, network)?;
+    
+    // Size with a change output; dropping dust change only overpays slightly
+    let mut sized_scripts = scripts.clone();
+    sized_scripts.push(change_script.clone());
+    let fee = estimate_vsize(inputs.len(), &sized_scripts) * fee_rate;
+    
+    let amounts: Vec<u64> = batch.iter().map(|b| b.amount).collect();
+    let shares = split_fee(fee, &amounts);
+    
+    let mut outputs = Vec::new();
+    for ((amount, share), script) in amounts.iter().zip(&shares).zip(scripts) {
+        if amount.saturating_sub(*share) < DUST_THRESHOLD {
+            return Err(MinterError::TransactionFailed {
+                reason: format!("Withdrawal of {} does not cover its fee share of {}", amount, share),
+            });
+        }
+        outputs.push((amount - share, script));
+    }
+    let change = if change >= DUST_THRESHOLD { change } else { 0 };
+    if change > 0 {
+        outputs.push((change, change_script));
+    }
+    
+    let signed = sign_transaction(inputs, &outputs).await?;
+    let txid = txid_to_hex(&double_sha256(&serialize_transaction(inputs, &outputs, None)));
+    
+    bitcoin_send_transaction(SendTransactionRequest {
+        transaction: signed,
+        network,
+    })
+    .await
+    .map_err(|(code, msg)| MinterError::SystemError {
+        message: format!("bitcoin_send_transaction failed: {:?} {}", code, msg),
+    })?;
+    
+    Ok(SubmittedTransaction {
+        txid,
+        burn_ids: batch.iter().map(|b| b.id).collect(),
+        inputs: inputs.to_vec(),
+        outputs: batch
+            .iter()
+            .zip(&outputs)
+            .map(|(request, (value, _))| (request.destination.clone(), *value))
+            .collect(),
+        change,
+        fee_rate,
+        submitted_at: api::time(),
+        replaced_txids: Vec::new(),
+    })
+}
+
+/// Marks withdrawals confirmed once their transaction has enough
+/// confirmations, and fee-bumps transactions that are stuck unconfirmed
+async fn check_transaction_confirmations() {
+    let submitted: Vec<SubmittedTransaction> =
+        SUBMITTED_TRANSACTIONS.with(|txs| txs.borrow().values().cloned().collect());
+    
+    for tx in submitted {
+        let (confirmations, mined_txid) = match transaction_confirmations(&tx).await {
+            Ok(found) => found,
+            Err(e) => {
+                ic_cdk::println!("Failed to check confirmations of {}: {:?}", tx.txid, e);
+                continue;
+            }
+        };
+        
+        if confirmations >= WITHDRAWAL_CONFIRMATIONS {
+            finalize_transaction(&tx, &mined_txid.unwrap_or_else(|| tx.txid.clone()));
+        } else if confirmations == 0 && api::time().saturating_sub(tx.submitted_at) >= RBF_TIMEOUT_NANOS {
+            // Outputs disappear once recipients spend them, so only a
+            // transaction whose inputs are still unspent is really stuck
+            match inputs_unspent(&tx).await {
+                Ok(true) => replace_transaction(tx).await,
+                Ok(false) => finalize_transaction(&tx, &tx.txid),
+                Err(e) => ic_cdk::println!("Failed to check inputs of {}: {:?}", tx.txid, e),
+            }
+        }
+    }
+}
+
+/// Confirmations of whichever version of the transaction was mined
+/// 
+/// Change is only spent after confirmation, so the change output at the main
+/// address stays visible even if recipients spend theirs.
+async fn transaction_confirmations(tx: &SubmittedTransaction) -> Result<(u32, Option<String>), MinterError> {
+    let network = MINTER_CONFIG.with(|c| c.borrow().network);
+    
+    let mut addresses: BTreeSet<BitcoinAddress> = tx.outputs.iter().map(|(a, _)| a.clone()).collect();
+    if tx.change > 0 {
+        if let Some(main_address) = MINTER_CONFIG.with(|c| c.borrow().main_address.clone()) {
+            addresses.insert(main_address);
+        }
+    }
+    
+    for address in addresses {
+        let mut filter = None;
+        loop {
+            let (response,) = bitcoin_get_utxos(GetUtxosRequest {
+                address: address.clone(),
+                network,
+                filter,
+            })
+            .await
+            .map_err(|(code, msg)| MinterError::SystemError {
+                message: format!("bitcoin_get_utxos failed: {:?} {}", code, msg),
+            })?;
+            
+            for utxo in &response.utxos {
+                let txid = txid_to_hex(&utxo.outpoint.txid);
+                if txid == tx.txid || tx.replaced_txids.contains(&txid) {
+                    let confirmations = response.tip_height.saturating_sub(utxo.height) + 1;
+                    return Ok((confirmations, Some(txid)));
+                }
+            }
+            
+            match response.next_page {
+                Some(page) => filter = Some(UtxoFilter::Page(page)),
+                None => break,
+            }
+        }
+    }
+    
+    Ok((0, None))
+}
+
+/// Whether any input of the transaction is still an unspent output in the
+/// chain. Only the minter spends these outputs, so once all are gone some
+/// version of the transaction has been mined.
+async fn inputs_unspent(tx: &SubmittedTransaction) -> Result<bool, MinterError> {
+    let mut addresses = BTreeSet::new();
+    for input in &tx.inputs {
+        let address = match &input.owner {
+            Some(user) => USER_DEPOSIT_ADDRESSES.with(|a| a.borrow().get(user).cloned()),
+            None => MINTER_CONFIG.with(|c| c.borrow().main_address.clone()),
+        };
+        addresses.extend(address);
+    }
+    
+    for address in addresses {
+        let utxos = fetch_utxos(&address, 1).await?;
+        let unspent = utxos.iter().any(|(txid, vout, _)| {
+            tx.inputs.iter().any(|input| input.txid == *txid && input.vout == *vout)
+        });
+        if unspent {
+            return Ok(true);
+        }
+    }
+    
+    Ok(false)
+}
+
+fn finalize_transaction(tx: &SubmittedTransaction, mined_txid: &str) {
+    set_burn_status(&tx.burn_ids, BurnStatus::Confirmed, Some(mined_txid));
+    
+    // Change is the minter's own output and can fund the next batch
+    if tx.change > 0 {
+        let vout = tx.outputs.len() as u32;
+        AVAILABLE_UTXOS.with(|utxos| {
+            utxos.borrow_mut().insert((mined_txid.to_string(), vout), MinterUtxo {
+                txid: hex_to_txid(mined_txid),
+                vout,
+                value: tx.change,
+                owner: None,
+            });
+        });
+    }
+    
+    SUBMITTED_TRANSACTIONS.with(|txs| txs.borrow_mut().remove(&tx.txid));
+}
+
+/// Re-sign a stuck transaction over the same inputs with a 25% higher fee rate
+async fn replace_transaction(tx: SubmittedTransaction) {
+    let original_txid = tx.replaced_txids.first().cloned().unwrap_or_else(|| tx.txid.clone());
+    
+    let attempts = PENDING_RBF.with(|r| r.borrow().get(&original_txid).map(|record| record.attempts).unwrap_or(0));
+    if attempts >= MAX_RBF_ATTEMPTS {
+        return;
+    }
+    
+    let (network, main_address) = MINTER_CONFIG.with(|c| {
+        let c = c.borrow();
+        (c.network, c.main_address.clone())
+    });
+    let main_address = match main_address {
+        Some(address) => address,
+        None => return,
+    };
+    
+    let batch: Vec<BurnRequest> = PENDING_BURNS.with(|burns| {
+        let burns = burns.borrow();
+        tx.burn_ids.iter().filter_map(|id| burns.get(id).cloned()).collect()
+    });
+    
+    // BIP-125 also requires paying at least the incremental relay fee
+    let fee_rate = (tx.fee_rate * 5 / 4).max(tx.fee_rate + 1);
+    
+    match submit_withdrawal_transaction(&batch, &tx.inputs, fee_rate, &main_address, network).await {
+        Ok(mut replacement) => {
+            ic_cdk::println!(
+                "Replaced withdrawal transaction {} with {} at {} sat/vbyte",
+                tx.txid, replacement.txid, fee_rate
+            );
+            replacement.replaced_txids = tx.replaced_txids.clone();
+            replacement.replaced_txids.push(tx.txid.clone());
+            
+            set_burn_status(&replacement.burn_ids, BurnStatus::Submitted, Some(&replacement.txid));
+            
+            PENDING_RBF.with(|r| {
+                let mut r = r.borrow_mut();
+                let record = r.entry(original_txid.clone()).or_insert(RbfRecord {
+                    original_txid: original_txid.clone(),
+                    replacement_txid: None,
+                    original_fee_rate: tx.fee_rate,
+                    replacement_fee_rate: tx.fee_rate,
+                    attempts: 0,
+                });
+                record.replacement_txid = Some(replacement.txid.clone());
+                record.replacement_fee_rate = fee_rate;
+                record.attempts += 1;
+            });
+            
+            SUBMITTED_TRANSACTIONS.with(|txs| {
+                let mut txs = txs.borrow_mut();
+                txs.remove(&tx.txid);
+                txs.insert(replacement.txid.clone(), replacement);
+            });
+        }
+        Err(e) => ic_cdk::println!("Failed to replace withdrawal transaction {}: {:?}", tx.txid, e),
+    }
+}
+
+/// Median fee rate in satoshis per vbyte
+async fn current_fee_rate(network: BitcoinNetwork) -> u64 {
+    match bitcoin_get_current_fee_percentiles(GetCurrentFeePercentilesRequest { network }).await {
+        // Percentiles are in millisatoshi per vbyte
+        Ok((percentiles,)) if percentiles.len() > 50 => (percentiles[50] / 1000).max(1),
+        _ => DEFAULT_FEE_RATE,
+    }
+}
+
+/// Splits `fee` across `amounts` proportionally; rounding goes to the last one
+fn split_fee(fee: u64, amounts: &[u64]) -> Vec<u64> {
+    let total: u128 = amounts.iter().map(|a| *a as u128).sum();
+    if total == 0 {
+        return vec![0; amounts.len()];
+    }
+    
+    let mut shares: Vec<u64> = amounts
+        .iter()
+        .map(|a| (fee as u128 * *a as u128 / total) as u64)
+        .collect();
+    let assigned: u64 = shares.iter().sum();
+    if let Some(last) = shares.last_mut() {
+        *last += fee - assigned;
+    }
+    shares
+}
+
+// =============================================================================
+// TRANSACTION BUILDING
+// =============================================================================
+
+/// Virtual size of a transaction spending P2WPKH inputs
+fn estimate_vsize(input_count: usize, output_scripts: &[Vec<u8>]) -> u64 {
+    // Version, locktime, counts and segwit marker
+    let overhead = 11;
+    // Outpoint, empty script_sig, sequence, plus the discounted witness
+    let input_vsize = 68;
+    let outputs: u64 = output_scripts.iter().map(|s| 9 + s.len() as u64).sum();
+    overhead + input_vsize * input_count as u64 + outputs
+}
+
+/// scriptPubKey for a segwit address on the configured network
+fn address_to_script(address: &str, network: BitcoinNetwork) -> Result<Vec<u8>, MinterError> {
+    let invalid = |message: String| MinterError::InvalidDestination { message };
+    
+    let (hrp, version, program) = bech32::segwit::decode(address)
+        .map_err(|e| invalid(format!("Unsupported Bitcoin address {}: {}", address, e)))?;
+    
+    let expected_hrp = match network {
+        BitcoinNetwork::Mainnet => bech32::hrp::BC,
+        BitcoinNetwork::Testnet => bech32::hrp::TB,
+        BitcoinNetwork::Regtest => bech32::hrp::BCRT,
+    };
+    if hrp != expected_hrp {
+        return Err(invalid(format!("Address {} is for a different network", address)));
+    }
+    
+    // OP_0 or OP_1..OP_16 followed by a push of the witness program
+    let version = version.to_u8();
+    let mut script = vec![if version == 0 { 0x00 } else { 0x50 + version }];
+    script.push(program.len() as u8);
+    script.extend_from_slice(&program);
+    Ok(script)
+}
+
+async fn sign_transaction(inputs: &[MinterUtxo], outputs: &[(u64, Vec<u8>)]) -> Result<Vec<u8>, MinterError> {
+    let key_name = MINTER_CONFIG.with(|c| c.borrow().ecdsa_key_name.clone());
+    let key_id = EcdsaKeyId {
+        curve: EcdsaCurve::Secp256k1,
+        name: key_name,
+    };
+    
+    let mut public_keys: BTreeMap<Option<Principal>, Vec<u8>> = BTreeMap::new();
+    let mut witnesses = Vec::new();
+    
+    for (index, input) in inputs.iter().enumerate() {
+        let derivation_path = match &input.owner {
+            Some(user) => user_derivation_path(user),
+            None => main_derivation_path(),
+        };
+        
+        let public_key = match public_keys.get(&input.owner) {
+            Some(public_key) => public_key.clone(),
+            None => {
+                let (response,) = ecdsa_public_key(EcdsaPublicKeyArgument {
+                    canister_id: None,
+                    derivation_path: derivation_path.clone(),
+                    key_id: key_id.clone(),
+                })
+                .await
+                .map_err(|(code, msg)| MinterError::SystemError {
+                    message: format!("ecdsa_public_key failed: {:?} {}", code, msg),
+                })?;
+                public_keys.insert(input.owner, response.public_key.clone());
+                response.public_key
+            }
+        };
+        
+        let sighash = p2wpkh_sighash(inputs, outputs, index, &public_key);
+        let (response,) = sign_with_ecdsa(SignWithEcdsaArgument {
+            message_hash: sighash.to_vec(),
+            derivation_path,
+            key_id: key_id.clone(),
+        })
+        .await
+        .map_err(|(code, msg)| MinterError::SystemError {
+            message: format!("sign_with_ecdsa failed: {:?} {}", code, msg),
+        })?;
+        
+        let mut signature = signature_to_der(&response.signature);
+        signature.push(SIGHASH_ALL as u8);
+        witnesses.push(vec![signature, public_key]);
+    }
+    
+    Ok(serialize_transaction(inputs, outputs, Some(&witnesses)))
+}
+
+const SIGHASH_ALL: u32 = 1;
+
+/// BIP-143 signature hash for a P2WPKH input
+fn p2wpkh_sighash(inputs: &[MinterUtxo], outputs: &[(u64, Vec<u8>)], index: usize, public_key: &[u8]) -> [u8; 32] {
+    let mut prevouts = Vec::new();
+    let mut sequences = Vec::new();
+    for input in inputs {
+        prevouts.extend_from_slice(&input.txid);
+        prevouts.extend_from_slice(&input.vout.to_le_bytes());
+        sequences.extend_from_slice(&RBF_SEQUENCE.to_le_bytes());
+    }
+    
+    let mut serialized_outputs = Vec::new();
+    for (value, script) in outputs {
+        write_output(&mut serialized_outputs, *value, script);
+    }
+    
+    // P2PKH-style script code for the key hash
+    let mut script_code = vec![0x19, 0x76, 0xa9, 0x14];
+    script_code.extend_from_slice(&Ripemd160::digest(Sha256::digest(public_key)));
+    script_code.extend_from_slice(&[0x88, 0xac]);
+    
+    let input = &inputs[index];
+    let mut preimage = Vec::new();
+    preimage.extend_from_slice(&TX_VERSION.to_le_bytes());
+    preimage.extend_from_slice(&double_sha256(&prevouts));
+    preimage.extend_from_slice(&double_sha256(&sequences));
+    preimage.extend_from_slice(&input.txid);
+    preimage.extend_from_slice(&input.vout.to_le_bytes());
+    preimage.extend_from_slice(&script_code);
+    preimage.extend_from_slice(&input.value.to_le_bytes());
+    preimage.extend_from_slice(&RBF_SEQUENCE.to_le_bytes());
+    preimage.extend_from_slice(&double_sha256(&serialized_outputs));
+    preimage.extend_from_slice(&0u32.to_le_bytes());
+    preimage.extend_from_slice(&SIGHASH_ALL.to_le_bytes());
+    
+    double_sha256(&preimage)
+}
+
+const TX_VERSION: u32 = 2;
+
+/// Serializes the transaction; without witnesses this is the txid preimage
+fn serialize_transaction(
+    inputs: &[MinterUtxo],
+    outputs: &[(u64, Vec<u8>)],
+    witnesses: Option<&[Vec<Vec<u8>>]>,
+) -> Vec<u8> {
+    let mut buf = Vec::new();
+    buf.extend_from_slice(&TX_VERSION.to_le_bytes());
+    if witnesses.is_some() {
+        buf.extend_from_slice(&[0x00, 0x01]);
+    }
+    
+    write_varint(&mut buf, inputs.len() as u64);
+    for input in inputs {
+        buf.extend_from_slice(&input.txid);
+        buf.extend_from_slice(&input.vout.to_le_bytes());
+        buf.push(0x00);
+        buf.extend_from_slice(&RBF_SEQUENCE.to_le_bytes());
+    }
+    
+    write_varint(&mut buf, outputs.len() as u64);
+    for (value, script) in outputs {
+        write_output(&mut buf, *value, script);
+    }
+    
+    if let Some(witnesses) = witnesses {
+        for items in witnesses {
+            write_varint(&mut buf, items.len() as u64);
+            for item in items {
+                write_varint(&mut buf, item.len() as u64);
+                buf.extend_from_slice(item);
+            }
+        }
+    }
+    
+    buf.extend_from_slice(&0u32.to_le_bytes());
+    buf
+}
+
+fn write_output(buf: &mut Vec<u8>, value: u64, script: &[u8]) {
+    buf.extend_from_slice(&value.to_le_bytes());
+    write_varint(buf, script.len() as u64);
+    buf.extend_from_slice(script);
+}
+
+fn write_varint(buf: &mut Vec<u8>, n: u64) {
+    match n {
+        0..=0xfc => buf.push(n as u8),
+        0xfd..=0xffff => {
+            buf.push(0xfd);
+            buf.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        0x1_0000..=0xffff_ffff => {
+            buf.push(0xfe);
+            buf.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+        _ => {
+            buf.push(0xff);
+            buf.extend_from_slice(&n.to_le_bytes());
+        }
+    }
+}
+
+/// secp256k1 group order, big-endian
+const CURVE_ORDER: [u8; 32] = [
+    0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xfe,
+    0xba, 0xae, 0xdc, 0xe6, 0xaf, 0x48, 0xa0, 0x3b, 0xbf, 0xd2, 0x5e, 0x8c, 0xd0, 0x36, 0x41, 0x41,
+];
+
+const HALF_CURVE_ORDER: [u8; 32] = [
+    0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff,
+    0x5d, 0x57, 0x6e, 0x73, 0x57, 0xa4, 0x50, 0x1d, 0xdf, 0xe9, 0x2f, 0x46, 0x68, 0x1b, 0x20, 0xa0,
+];
+
+/// DER-encodes a 64-byte (r, s) signature, normalizing s to the low half of
+/// the curve order as required by Bitcoin's standardness rules
+fn signature_to_der(signature: &[u8]) -> Vec<u8> {
+    let r = &signature[..32];
+    let mut s = [0u8; 32];
+    s.copy_from_slice(&signature[32..64]);
+    
+    if s > HALF_CURVE_ORDER {
+        let mut borrow = 0i16;
+        for i in (0..32).rev() {
+            let diff = CURVE_ORDER[i] as i16 - s[i] as i16 - borrow;
+            borrow = if diff < 0 { 1 } else { 0 };
+            s[i] = (diff + (borrow << 8)) as u8;
+        }
+    }
+    
+    let encode_integer = |bytes: &[u8]| {
+        let start = bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len() - 1);
+        let mut out = vec![0x02];
+        if bytes[start] & 0x80 != 0 {
+            out.push((bytes.len() - start + 1) as u8);
+            out.push(0x00);
+        } else {
+            out.push((bytes.len() - start) as u8);
+        }
+        out.extend_from_slice(&bytes[start..]);
+        out
+    };
+    
+    let r = encode_integer(r);
+    let s = encode_integer(&s);
+    let mut der = vec![0x30, (r.len() + s.len()) as u8];
+    der.extend(r);
+    der.extend(s);
+    der
+}
+
+fn double_sha256(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+fn hex_to_txid(txid: &str) -> Vec<u8> {
+    let mut bytes: Vec<u8> = (0..txid.len())
+        .step_by(2)
+        .filter_map(|i| u8::from_str_radix(&txid[i..i + 2], 16).ok())
+        .collect();
+    bytes.reverse();
+    bytes
+}
+
 /// Initialize periodic tasks for Bitcoin network monitoring
 /// 
 /// PRODUCTION TODO: Implement Bitcoin network monitoring
@@ -246,7 +1659,23 @@ pub fn get_bitcoin_address(user: Principal) -> BitcoinAddress {
 /// Reference implementation:
 /// https://github.com/dfinity/ic/blob/master/rs/bitcoin/ckbtc/minter/src/lifecycle/init.rs
 #[init]
-fn init() {
+fn init(network: Option<BitcoinNetwork>, ledger_id: Option<Principal>) {
+    let caller = api::caller();
+    MINTER_CONFIG.with(|c| {
+        let mut config = MinterConfig::for_network(network.unwrap_or(BitcoinNetwork::Mainnet));
+        config.ledger_id = ledger_id;
+        config.minter_fee_recipient = caller;
+        *c.borrow_mut() = config;
+    });
+    MINTER_ADMIN.with(|a| *a.borrow_mut() = caller);
+    
+    start_timers();
+    
+    // Management canister calls are not allowed during init, so derive the
+    // main address from a timer right after installation
+    ic_cdk_timers::set_timer(Duration::ZERO, || ic_cdk::spawn(initialize_main_address()));
+    
+
     // TODO: Set up periodic timers for:
     // 1. Bitcoin network monitoring
     // 2. Transaction confirmation checking  
@@ -263,13 +1692,58 @@ fn init() {
 
 #[pre_upgrade]
 fn pre_upgrade() {
-    // TODO: Implement state serialization for upgrades
     ic_cdk::println!("Preparing minter for upgrade...");
+    
+    let state = MinterState {
+        schema_version: MINTER_STATE_SCHEMA_VERSION,
+        pending_mints: PENDING_MINTS.with(|m| m.borrow().clone()),
+        pending_burns: PENDING_BURNS.with(|b| b.borrow().clone()),
+        next_tx_id: NEXT_TX_ID.with(|id| *id.borrow()),
+        config: MINTER_CONFIG.with(|c| c.borrow().clone()),
+        minter_admin: Some(MINTER_ADMIN.with(|a| *a.borrow())),
+        accumulated_fees: ACCUMULATED_FEES.with(|f| *f.borrow()),
+        user_deposit_addresses: USER_DEPOSIT_ADDRESSES.with(|a| a.borrow().clone()),
+        seen_utxos: SEEN_UTXOS.with(|s| s.borrow().clone()),
+        pending_withdrawals: PENDING_WITHDRAWALS.with(|q| q.borrow().iter().cloned().collect()),
+        available_utxos: AVAILABLE_UTXOS.with(|u| u.borrow().clone()),
+        submitted_transactions: SUBMITTED_TRANSACTIONS.with(|t| t.borrow().clone()),
+        pending_rbf: PENDING_RBF.with(|r| r.borrow().clone()),
+    };
+    
+    STABLE_STATE.with(|cell| {
+        cell.borrow_mut().set(state).expect("Failed to save minter state");
+    });
 }
 
 #[post_upgrade]
 fn post_upgrade() {
-    // TODO: Implement state deserialization after upgrades
+    let state = STABLE_STATE.with(|cell| cell.borrow().get().clone());
+    
+    if state.schema_version != MINTER_STATE_SCHEMA_VERSION {
+        ic_cdk::trap(&format!(
+            "Unsupported minter state schema version {}",
+            state.schema_version
+        ));
+    }
+    
+    PENDING_MINTS.with(|m| *m.borrow_mut() = state.pending_mints);
+    PENDING_BURNS.with(|b| *b.borrow_mut() = state.pending_burns);
+    NEXT_TX_ID.with(|id| *id.borrow_mut() = state.next_tx_id);
+    MINTER_CONFIG.with(|c| *c.borrow_mut() = state.config);
+    if let Some(admin) = state.minter_admin {
+        MINTER_ADMIN.with(|a| *a.borrow_mut() = admin);
+    }
+    ACCUMULATED_FEES.with(|f| *f.borrow_mut() = state.accumulated_fees);
+    USER_DEPOSIT_ADDRESSES.with(|a| *a.borrow_mut() = state.user_deposit_addresses);
+    SEEN_UTXOS.with(|s| *s.borrow_mut() = state.seen_utxos);
+    PENDING_WITHDRAWALS.with(|q| *q.borrow_mut() = state.pending_withdrawals.into());
+    AVAILABLE_UTXOS.with(|u| *u.borrow_mut() = state.available_utxos);
+    SUBMITTED_TRANSACTIONS.with(|t| *t.borrow_mut() = state.submitted_transactions);
+    PENDING_RBF.with(|r| *r.borrow_mut() = state.pending_rbf);
+    
+    // Timers do not survive upgrades
+    start_timers();
+    
     ic_cdk::println!("Minter upgrade completed");
 }
 
@@ -277,12 +1751,7 @@ fn post_upgrade() {
 // EXPORT CANDID INTERFACE
 // =============================================================================
 
-candid::export_service!();
-
-#[query(name = "__get_candid_interface_tmp_hack")]
-fn export_candid() -> String {
-    __export_service()
-}
+ic_cdk::export_candid!();
 
 #[cfg(test)]
 mod tests {
@@ -294,4 +1763,94 @@ mod tests {
         // Reference: https://github.com/dfinity/ic/tree/master/rs/bitcoin/ckbtc/minter/src/tests
         assert_eq!(2 + 2, 4);
     }
+    
+    #[test]
+    fn test_minter_state_candid_round_trip() {
+        let user = Principal::from_slice(&[7; 29]);
+        let mut state = MinterState::default();
+        state.pending_mints.insert(1, MintRequest {
+            user,
+            amount: 50_000,
+            bitcoin_txid: Some("abcd".to_string()),
+        });
+        state.pending_burns.insert(2, BurnRequest {
+            id: 2,
+            user,
+            amount: 25_000,
+            destination: "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4".to_string(),
+            status: BurnStatus::Submitted,
+            bitcoin_txid: Some("ef01".to_string()),
+            created_at: 1_700_000_000_000_000_000,
+        });
+        state.next_tx_id = 3;
+        state.config.main_address = Some("bc1qminter".to_string());
+        state.accumulated_fees = 3_000;
+        
+        let bytes = state.to_bytes().into_owned();
+        let decoded = MinterState::from_bytes(Cow::Owned(bytes));
+        
+        assert_eq!(decoded, state);
+        assert_eq!(decoded.schema_version, MINTER_STATE_SCHEMA_VERSION);
+    }
+    
+    #[test]
+    fn test_split_fee_is_proportional_and_exact() {
+        let shares = split_fee(1_001, &[10_000, 30_000, 60_000]);
+        
+        assert_eq!(shares, vec![100, 300, 601]);
+        assert_eq!(shares.iter().sum::<u64>(), 1_001);
+    }
+    
+    #[test]
+    fn test_reject_undersized_withdrawals_drops_dust_outputs() {
+        let user = Principal::from_slice(&[7; 29]);
+        let request = |id: TxId, amount: Amount| BurnRequest {
+            id,
+            user,
+            amount,
+            destination: "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4".to_string(),
+            status: BurnStatus::Signing,
+            bitcoin_txid: None,
+            created_at: 0,
+        };
+        
+        let (kept, rejected) = reject_undersized_withdrawals(
+            vec![request(1, 100_000), request(2, 550)],
+            1,
+            10,
+            "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4",
+            BitcoinNetwork::Mainnet,
+        );
+        
+        assert_eq!(kept.iter().map(|r| r.id).collect::<Vec<_>>(), vec![1]);
+        assert_eq!(rejected.iter().map(|r| r.id).collect::<Vec<_>>(), vec![2]);
+    }
+    
+    #[test]
+    fn test_address_to_script_p2wpkh() {
+        let script = address_to_script("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", BitcoinNetwork::Mainnet).unwrap();
+        
+        let mut expected = vec![0x00, 0x14];
+        expected.extend_from_slice(&[
+            0x75, 0x1e, 0x76, 0xe8, 0x19, 0x91, 0x96, 0xd4, 0x54, 0x94,
+            0x1c, 0x45, 0xd1, 0xb3, 0xa3, 0x23, 0xf1, 0x43, 0x3b, 0xd6,
+        ]);
+        assert_eq!(script, expected);
+        
+        assert!(address_to_script("bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4", BitcoinNetwork::Testnet).is_err());
+    }
+    
+    #[test]
+    fn test_signature_to_der_normalizes_high_s() {
+        let mut signature = vec![0x01; 32];
+        signature.extend_from_slice(&CURVE_ORDER);
+        signature[63] -= 1; // s = n - 1, normalized to 1
+        
+        let der = signature_to_der(&signature);
+        
+        let mut expected = vec![0x30, 0x25, 0x02, 0x20];
+        expected.extend_from_slice(&[0x01; 32]);
+        expected.extend_from_slice(&[0x02, 0x01, 0x01]);
+        assert_eq!(der, expected);
+    }
 }
@@ -1,8 +1,20 @@
 use candid::{CandidType, Principal};
+use ic_cdk::api::management_canister::ecdsa::{
+    ecdsa_public_key, sign_with_ecdsa, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument,
+    SignWithEcdsaArgument,
+};
 use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeMap;
+use shared::cycles::{self, CycleStats};
+use shared::hex;
+use shared::auth::{has_cached_role, has_role, set_auth_canister};
+use shared::context::{self, verified_request_context};
+use shared::{check_rate_limit, CustodyError, RequestContext};
+use shared::interface::{CandidVersion, InterfaceVersion};
+use shared::memory::{self, MemoryStats};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::cell::RefCell;
+use std::time::Duration;
 use uuid::Uuid;
 use sha2::{Sha256, Digest};
 
@@ -21,9 +33,14 @@ pub struct AuditEntry {
     pub previous_hash: Option<String>,
     pub compliance_relevant: bool,
     pub retention_until: Option<u64>,
+    pub merkle_leaf_index: u64,
+    pub merkle_proof: Vec<String>,
+    pub digital_signature: Option<String>,
+    // Shared by every entry, from any canister, that belongs to one business transaction
+    pub correlation_id: Option<String>,
 }
 
-#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
 pub enum EventType {
     AccountCreation,
     AccountModification,
@@ -46,7 +63,7 @@ pub enum EventType {
     AuditAccess,
 }
 
-#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
 pub enum ResourceType {
     CustodyAccount,
     MultisigWallet,
@@ -60,7 +77,7 @@ pub enum ResourceType {
     AuditLog,
 }
 
-#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
 pub struct AuditMetadata {
     pub ip_address: Option<String>,
     pub user_agent: Option<String>,
@@ -74,6 +91,18 @@ pub struct AuditMetadata {
     pub additional_context: BTreeMap<String, String>,
 }
 
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BatchAuditEntry {
+    pub event_type: EventType,
+    pub resource_type: ResourceType,
+    pub resource_id: String,
+    pub action: String,
+    pub details: String,
+    pub metadata: Option<AuditMetadata>,
+    pub compliance_relevant: bool,
+    pub correlation_id: Option<String>,
+}
+
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct AuditQuery {
     pub event_types: Option<Vec<EventType>>,
@@ -87,6 +116,58 @@ pub struct AuditQuery {
     pub offset: Option<u32>,
 }
 
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AuditQueryV2 {
+    pub event_types: Option<Vec<EventType>>,
+    pub resource_types: Option<Vec<ResourceType>>,
+    pub actors: Option<Vec<Principal>>,
+    pub resource_ids: Option<Vec<String>>,
+    pub start_time: Option<u64>,
+    pub end_time: Option<u64>,
+    pub compliance_relevant_only: bool,
+    pub limit: Option<u32>,
+    pub after_id: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AuditQueryPage {
+    pub entries: Vec<AuditEntry>,
+    pub next_cursor: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub enum ProofDirection {
+    Left,
+    Right,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct EntryProof {
+    pub entry_id: String,
+    pub entry_hash: String,
+    pub merkle_root: String,
+    // Sibling hashes from the leaf up, with the side each sibling sits on
+    pub proof_path: Vec<(String, ProofDirection)>,
+    pub computed_at: u64,
+    // Threshold ECDSA signature over merkle_root, when signatures are enabled
+    pub root_signature: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AuditExport {
+    pub entries: Vec<AuditEntry>,
+    pub generated_at: u64,
+    pub generated_by: Principal,
+    pub entry_count: u64,
+    // SHA-256 over each entry's hash followed by the JSON encoding of its
+    // metadata and correlation_id, in timestamp order
+    pub root_hash: String,
+    pub export_signature: Option<String>,
+    // More entries matched than MAX_EXPORT_ENTRIES; export again with a
+    // start_time after the last exported entry to get the rest
+    pub has_more: bool,
+}
+
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct ComplianceReport {
     pub id: String,
@@ -101,6 +182,16 @@ pub struct ComplianceReport {
     pub digital_signature: Option<String>,
 }
 
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ScheduledReportConfig {
+    pub id: String,
+    pub report_type: ReportType,
+    pub frequency_seconds: u64,
+    // Canister exposing `receive_compliance_report(ComplianceReport)`
+    pub recipient: Principal,
+    pub last_generated_at: Option<u64>,
+}
+
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub enum ReportType {
     Daily,
@@ -113,6 +204,23 @@ pub enum ReportType {
     Internal,
 }
 
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord, CandidType, Serialize, Deserialize)]
+pub enum AlertSeverity {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AlertSubscription {
+    pub id: String,
+    pub subscriber_canister_id: Principal,
+    // limit and offset are ignored when matching single entries
+    pub event_filter: AuditQuery,
+    pub min_severity: AlertSeverity,
+}
+
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct AuditSettings {
     pub retention_days: u32,
@@ -124,11 +232,32 @@ pub struct AuditSettings {
     pub audit_access_logging: bool,
 }
 
+// Threshold ECDSA key used to sign audit entries ("dfx_test_key" on a local replica)
+const ECDSA_KEY_NAME: &str = "key_1";
+
+const MAX_AUDIT_BATCH_SIZE: usize = 100;
+const MAX_EXPORT_ENTRIES: usize = 10_000;
+
+// Scheduled reports are checked once a day, so that is the shortest frequency
+const SCHEDULED_REPORT_CHECK_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+
+// Entries are signed in batches off a timer rather than one call per entry
+const SIGNING_INTERVAL: Duration = Duration::from_secs(60);
+const MAX_SIGNATURES_PER_BATCH: usize = 20;
+
 thread_local! {
-    static AUDIT_ENTRIES: RefCell<BTreeMap<String, AuditEntry>> = RefCell::new(BTreeMap::new());
-    static COMPLIANCE_REPORTS: RefCell<BTreeMap<String, ComplianceReport>> = RefCell::new(BTreeMap::new());
-    static LAST_ENTRY_HASH: RefCell<Option<String>> = RefCell::new(None);
-    static AUDIT_SETTINGS: RefCell<AuditSettings> = RefCell::new(AuditSettings {
+    static AUDIT_ENTRIES: RefCell<BTreeMap<String, AuditEntry>> = const { RefCell::new(BTreeMap::new()) };
+    static ARCHIVED_ENTRIES: RefCell<BTreeMap<String, AuditEntry>> = const { RefCell::new(BTreeMap::new()) };
+    static COMPLIANCE_REPORTS: RefCell<BTreeMap<String, ComplianceReport>> = const { RefCell::new(BTreeMap::new()) };
+    static LAST_ENTRY_HASH: RefCell<Option<String>> = const { RefCell::new(None) };
+    // Merkle mountain range over entry hashes: the current peaks (left to
+    // right, tallest first) and every node by height, leaves at height 0
+    static MERKLE_PEAKS: RefCell<Vec<String>> = const { RefCell::new(Vec::new()) };
+    static MERKLE_NODES: RefCell<Vec<Vec<String>>> = const { RefCell::new(Vec::new()) };
+    // Secondary index in insertion order (Merkle leaf index -> entry ID). Leaf
+    // indices grow with time but, unlike timestamps, are unique per entry.
+    static SORTED_ENTRY_IDS: RefCell<BTreeMap<u64, String>> = const { RefCell::new(BTreeMap::new()) };
+    static AUDIT_SETTINGS: RefCell<AuditSettings> = const { RefCell::new(AuditSettings {
         retention_days: 2555, // 7 years
         auto_archive_enabled: true,
         compliance_reporting_enabled: true,
@@ -136,15 +265,29 @@ thread_local! {
         hash_verification_enabled: true,
         digital_signatures_enabled: false,
         audit_access_logging: true,
-    });
-    static AUDITORS: RefCell<BTreeMap<Principal, String>> = RefCell::new(BTreeMap::new());
-    static ENTRY_COUNTER: RefCell<u64> = RefCell::new(0);
+    }) };
+    static AUDITORS: RefCell<BTreeMap<Principal, String>> = const { RefCell::new(BTreeMap::new()) };
+    static ENTRY_COUNTER: RefCell<u64> = const { RefCell::new(0) };
+    static ALERT_SUBSCRIPTIONS: RefCell<BTreeMap<String, AlertSubscription>> = const { RefCell::new(BTreeMap::new()) };
+    // Secondary indexes over AUDIT_ENTRIES: actor / resource_id -> entry IDs
+    static ACTOR_INDEX: RefCell<BTreeMap<Principal, BTreeSet<String>>> = const { RefCell::new(BTreeMap::new()) };
+    static RESOURCE_INDEX: RefCell<BTreeMap<String, BTreeSet<String>>> = const { RefCell::new(BTreeMap::new()) };
+    static CORRELATION_INDEX: RefCell<BTreeMap<String, BTreeSet<String>>> = const { RefCell::new(BTreeMap::new()) };
+    static SCHEDULED_REPORTS: RefCell<BTreeMap<String, ScheduledReportConfig>> = const { RefCell::new(BTreeMap::new()) };
+    // Entries waiting for the signing timer, oldest first
+    static UNSIGNED_ENTRIES: RefCell<VecDeque<String>> = const { RefCell::new(VecDeque::new()) };
 }
 
 #[init]
-fn init() {
+fn init(auth_canister: Option<Principal>) {
     ic_cdk::println!("Audit Trail canister initialized");
     
+    set_auth_canister(auth_canister);
+    cycles::start_cycle_monitor(notify_low_cycles);
+    memory::start_memory_monitor(notify_high_memory);
+    start_report_scheduler();
+    start_signing_scheduler();
+    
     // Add deployer as initial auditor
     AUDITORS.with(|auditors| {
         auditors.borrow_mut().insert(ic_cdk::caller(), "System Administrator".to_string());
@@ -162,27 +305,9 @@ fn init() {
         true,
     );
     
-    AUDIT_ENTRIES.with(|entries| {
-        entries.borrow_mut().insert(init_entry.id.clone(), init_entry);
-    });
+    store_audit_entry(init_entry);
 }
 
-impl Default for AuditMetadata {
-    fn default() -> Self {
-        AuditMetadata {
-            ip_address: None,
-            user_agent: None,
-            session_id: None,
-            request_id: None,
-            canister_id: None,
-            method_name: None,
-            before_state: None,
-            after_state: None,
-            error_code: None,
-            additional_context: BTreeMap::new(),
-        }
-    }
-}
 
 #[pre_upgrade]
 fn pre_upgrade() {
@@ -198,13 +323,17 @@ fn pre_upgrade() {
         true,
     );
     
-    AUDIT_ENTRIES.with(|entries| {
-        entries.borrow_mut().insert(upgrade_entry.id.clone(), upgrade_entry);
-    });
+    store_audit_entry(upgrade_entry);
 }
 
 #[post_upgrade]
-fn post_upgrade() {
+fn post_upgrade(auth_canister: Option<Principal>) {
+    set_auth_canister(auth_canister);
+    cycles::start_cycle_monitor(notify_low_cycles);
+    memory::start_memory_monitor(notify_high_memory);
+    start_report_scheduler();
+    start_signing_scheduler();
+    
     // Log upgrade completion
     let upgrade_entry = create_audit_entry(
         EventType::SystemConfiguration,
@@ -217,14 +346,13 @@ fn post_upgrade() {
         true,
     );
     
-    AUDIT_ENTRIES.with(|entries| {
-        entries.borrow_mut().insert(upgrade_entry.id.clone(), upgrade_entry);
-    });
+    store_audit_entry(upgrade_entry);
 }
 
 // === Core Audit Functions ===
 
 #[update]
+#[allow(clippy::too_many_arguments)]
 pub fn log_audit_event(
     event_type: EventType,
     resource_type: ResourceType,
@@ -233,14 +361,17 @@ pub fn log_audit_event(
     details: String,
     metadata: Option<AuditMetadata>,
     compliance_relevant: bool,
-) -> Result<String, String> {
+    request_context: Option<RequestContext>,
+) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "log_audit_event")?;
     
-    let audit_metadata = metadata.unwrap_or_default();
+    let mut audit_metadata = metadata.unwrap_or_default();
+    let actor = apply_request_context(caller, &mut audit_metadata, request_context);
     
     let entry = create_audit_entry(
         event_type,
-        caller,
+        actor,
         resource_type,
         resource_id,
         action,
@@ -251,9 +382,7 @@ pub fn log_audit_event(
     
     let entry_id = entry.id.clone();
     
-    AUDIT_ENTRIES.with(|entries| {
-        entries.borrow_mut().insert(entry_id.clone(), entry);
-    });
+    store_audit_entry(entry);
     
     // Log the audit access if enabled
     let settings = AUDIT_SETTINGS.with(|s| s.borrow().clone());
@@ -264,6 +393,75 @@ pub fn log_audit_event(
     Ok(entry_id)
 }
 
+/// Logs several events in one call. Entries are chained in the order given and
+/// no other entry can interleave with the batch.
+#[update]
+fn batch_log_audit_events(
+    entries: Vec<BatchAuditEntry>,
+    request_context: Option<RequestContext>,
+) -> Result<Vec<String>, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "batch_log_audit_events")?;
+    
+    if entries.is_empty() || entries.len() > MAX_AUDIT_BATCH_SIZE {
+        return Err(CustodyError::invalid_input(
+            "entries",
+            format!("batch must contain 1-{} entries", MAX_AUDIT_BATCH_SIZE),
+        ));
+    }
+    
+    let entry_ids: Vec<String> = entries.into_iter().map(|batch_entry| {
+        let mut metadata = batch_entry.metadata.unwrap_or_default();
+        let actor = apply_request_context(caller, &mut metadata, request_context.clone());
+        let mut entry = create_audit_entry(
+            batch_entry.event_type,
+            actor,
+            batch_entry.resource_type,
+            batch_entry.resource_id,
+            batch_entry.action,
+            batch_entry.details,
+            metadata,
+            batch_entry.compliance_relevant,
+        );
+        if batch_entry.correlation_id.is_some() {
+            entry.correlation_id = batch_entry.correlation_id;
+        }
+        let entry_id = entry.id.clone();
+        store_audit_entry(entry);
+        entry_id
+    }).collect();
+    
+    // Log the audit access if enabled
+    let settings = AUDIT_SETTINGS.with(|s| s.borrow().clone());
+    if settings.audit_access_logging {
+        log_audit_access(caller, "batch_log_audit_events", format!("{} entries", entry_ids.len()));
+    }
+    
+    Ok(entry_ids)
+}
+
+// Entries relayed by a trusted canister are attributed to the user behind the
+// request; the relaying canister and the request's session and correlation IDs
+// are kept in the metadata. Contexts from any other caller are ignored.
+fn apply_request_context(
+    caller: Principal,
+    metadata: &mut AuditMetadata,
+    request_context: Option<RequestContext>,
+) -> Principal {
+    let Some(context) = verified_request_context(caller, request_context) else {
+        return caller;
+    };
+    
+    if context.original_caller != caller && metadata.canister_id.is_none() {
+        metadata.canister_id = Some(caller.to_text());
+    }
+    metadata.session_id.get_or_insert(context.session_id);
+    metadata.additional_context.entry("correlation_id".to_string()).or_insert(context.correlation_id);
+    
+    context.original_caller
+}
+
+#[allow(clippy::too_many_arguments)]
 fn create_audit_entry(
     event_type: EventType,
     actor: Principal,
@@ -298,19 +496,20 @@ fn create_audit_entry(
         *hash.borrow_mut() = Some(entry_hash.clone());
     });
     
-    // Calculate retention period
-    let retention_until = if compliance_relevant {
-        let settings = AUDIT_SETTINGS.with(|s| s.borrow().clone());
-        Some(current_time + (settings.retention_days as u64 * 24 * 60 * 60 * 1_000_000_000))
-    } else {
-        None
-    };
+    // Append to the Merkle mountain range
+    let (merkle_leaf_index, merkle_proof) = append_merkle_leaf(&entry_hash);
+    
+    // Calculate retention period (compliance relevant entries are never archived)
+    let settings = AUDIT_SETTINGS.with(|s| s.borrow().clone());
+    let retention_until = Some(current_time + (settings.retention_days as u64 * 24 * 60 * 60 * 1_000_000_000));
     
     // Increment counter
     ENTRY_COUNTER.with(|counter| {
         *counter.borrow_mut() += 1;
     });
     
+    let correlation_id = derive_correlation_id(&resource_type, &resource_id, &metadata);
+    
     AuditEntry {
         id: entry_id,
         timestamp: current_time,
@@ -325,9 +524,22 @@ fn create_audit_entry(
         previous_hash,
         compliance_relevant,
         retention_until,
+        merkle_leaf_index,
+        merkle_proof,
+        digital_signature: None,
+        correlation_id,
     }
 }
 
+// Callers link entries with a "correlation_id" context value or a request ID;
+// otherwise a transaction entry is correlated by the transaction's own ID
+fn derive_correlation_id(resource_type: &ResourceType, resource_id: &str, metadata: &AuditMetadata) -> Option<String> {
+    metadata.additional_context.get("correlation_id").cloned()
+        .or_else(|| metadata.request_id.clone())
+        .or_else(|| (*resource_type == ResourceType::Transaction).then(|| resource_id.to_string()))
+}
+
+#[allow(clippy::too_many_arguments)]
 fn calculate_entry_hash(
     entry_id: &str,
     timestamp: u64,
@@ -369,19 +581,48 @@ fn query_audit_entries(query: AuditQuery) -> Vec<AuditEntry> {
         return Vec::new();
     }
     
+    // Query calls cannot persist state, so access is not logged here.
+    // Use query_audit_entries_logged when the access must be recorded.
+    collect_audit_entries(&query)
+}
+
+#[update]
+async fn query_audit_entries_logged(query: AuditQuery) -> Vec<AuditEntry> {
+    let caller = ic_cdk::caller();
+    if let Err(e) = check_rate_limit(caller, "query_audit_entries_logged") {
+        ic_cdk::trap(&e.to_string());
+    }
+    
+    // Check if caller is authorized auditor
+    if !authorize_auditor(caller).await {
+        ic_cdk::println!("Unauthorized audit query attempt from: {}", caller);
+        return Vec::new();
+    }
+    
     // Log audit access
-    log_audit_access(caller, "query_audit_entries", "multiple".to_string());
+    log_audit_access(caller, "query_audit_entries_logged", "multiple".to_string());
     
+    collect_audit_entries(&query)
+}
+
+fn collect_audit_entries(query: &AuditQuery) -> Vec<AuditEntry> {
     AUDIT_ENTRIES.with(|entries| {
         let entries_map = entries.borrow();
-        let mut results: Vec<AuditEntry> = entries_map
-            .values()
-            .filter(|entry| matches_query(entry, &query))
-            .cloned()
-            .collect();
+        let mut results: Vec<AuditEntry> = match indexed_candidates(query) {
+            Some(candidate_ids) => candidate_ids.iter()
+                .filter_map(|id| entries_map.get(id))
+                .filter(|entry| matches_query(entry, query))
+                .cloned()
+                .collect(),
+            None => entries_map
+                .values()
+                .filter(|entry| matches_query(entry, query))
+                .cloned()
+                .collect(),
+        };
         
         // Sort by timestamp (newest first)
-        results.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
+        results.sort_by_key(|e| std::cmp::Reverse(e.timestamp));
         
         // Apply limit and offset
         if let Some(offset) = query.offset {
@@ -399,6 +640,78 @@ fn query_audit_entries(query: AuditQuery) -> Vec<AuditEntry> {
     })
 }
 
+#[query]
+fn query_audit_entries_v2(query: AuditQueryV2) -> AuditQueryPage {
+    let caller = ic_cdk::caller();
+    
+    // Check if caller is authorized auditor
+    if !is_authorized_auditor(&caller) {
+        ic_cdk::println!("Unauthorized audit query attempt from: {}", caller);
+        return AuditQueryPage { entries: Vec::new(), next_cursor: None };
+    }
+    
+    let limit = query.limit.unwrap_or(100).clamp(1, 1000) as usize;
+    let filter = AuditQuery::from(&query);
+    
+    AUDIT_ENTRIES.with(|entries| {
+        let entries_map = entries.borrow();
+        
+        // Resume strictly before the cursor entry (newest first)
+        let upper_bound = match query.after_id {
+            Some(ref after_id) => match entries_map.get(after_id) {
+                Some(entry) => entry.merkle_leaf_index,
+                None => return AuditQueryPage { entries: Vec::new(), next_cursor: None },
+            },
+            None => u64::MAX,
+        };
+        
+        SORTED_ENTRY_IDS.with(|sorted| {
+            let sorted = sorted.borrow();
+            let mut results = Vec::new();
+            let mut has_more = false;
+            
+            for entry_id in sorted.range(..upper_bound).rev().map(|(_, id)| id) {
+                let entry = match entries_map.get(entry_id) {
+                    Some(e) => e,
+                    None => continue,
+                };
+                if !matches_query(entry, &filter) {
+                    continue;
+                }
+                if results.len() == limit {
+                    has_more = true;
+                    break;
+                }
+                results.push(entry.clone());
+            }
+            
+            let next_cursor = if has_more {
+                results.last().map(|entry| entry.id.clone())
+            } else {
+                None
+            };
+            
+            AuditQueryPage { entries: results, next_cursor }
+        })
+    })
+}
+
+impl From<&AuditQueryV2> for AuditQuery {
+    fn from(query: &AuditQueryV2) -> Self {
+        AuditQuery {
+            event_types: query.event_types.clone(),
+            resource_types: query.resource_types.clone(),
+            actors: query.actors.clone(),
+            resource_ids: query.resource_ids.clone(),
+            start_time: query.start_time,
+            end_time: query.end_time,
+            compliance_relevant_only: query.compliance_relevant_only,
+            limit: None,
+            offset: None,
+        }
+    }
+}
+
 fn matches_query(entry: &AuditEntry, query: &AuditQuery) -> bool {
     // Filter by event types
     if let Some(ref event_types) = query.event_types {
@@ -468,23 +781,145 @@ fn get_audit_entry(entry_id: String) -> Option<AuditEntry> {
 }
 
 #[query]
-fn verify_audit_chain() -> Result<String, String> {
+fn get_audit_entries_by_actor(actor: Principal, limit: u32, after_id: Option<String>) -> Vec<AuditEntry> {
+    let caller = ic_cdk::caller();
+    
+    // Check if caller is authorized auditor
+    if !is_authorized_auditor(&caller) {
+        ic_cdk::println!("Unauthorized audit query attempt from: {}", caller);
+        return Vec::new();
+    }
+    
+    let entry_ids = ACTOR_INDEX.with(|index| {
+        index.borrow().get(&actor).cloned().unwrap_or_default()
+    });
+    
+    entries_from_index(&entry_ids, limit, after_id)
+}
+
+/// Every entry sharing a correlation ID, oldest first, giving a full trace of
+/// one business transaction across canisters
+#[query]
+fn get_correlated_events(correlation_id: String) -> Vec<AuditEntry> {
+    let caller = ic_cdk::caller();
+    
+    // Check if caller is authorized auditor
+    if !is_authorized_auditor(&caller) {
+        ic_cdk::println!("Unauthorized audit query attempt from: {}", caller);
+        return Vec::new();
+    }
+    
+    let entry_ids = CORRELATION_INDEX.with(|index| {
+        index.borrow().get(&correlation_id).cloned().unwrap_or_default()
+    });
+    
+    AUDIT_ENTRIES.with(|entries| {
+        let entries_map = entries.borrow();
+        let mut results: Vec<AuditEntry> = entry_ids.iter()
+            .filter_map(|id| entries_map.get(id).cloned())
+            .collect();
+        results.sort_by_key(|entry| entry.merkle_leaf_index);
+        results
+    })
+}
+
+#[query]
+fn get_audit_entries_by_resource(resource_id: String, limit: u32, after_id: Option<String>) -> Vec<AuditEntry> {
+    let caller = ic_cdk::caller();
+    
+    // Check if caller is authorized auditor
+    if !is_authorized_auditor(&caller) {
+        ic_cdk::println!("Unauthorized audit query attempt from: {}", caller);
+        return Vec::new();
+    }
+    
+    let entry_ids = RESOURCE_INDEX.with(|index| {
+        index.borrow().get(&resource_id).cloned().unwrap_or_default()
+    });
+    
+    entries_from_index(&entry_ids, limit, after_id)
+}
+
+/// Pages through indexed entries oldest first (by Merkle leaf index, which
+/// follows append order), resuming after the entry `after_id`.
+fn entries_from_index(entry_ids: &BTreeSet<String>, limit: u32, after_id: Option<String>) -> Vec<AuditEntry> {
+    let limit = limit.clamp(1, 1000) as usize;
+    
+    AUDIT_ENTRIES.with(|entries| {
+        let entries_map = entries.borrow();
+        
+        let after_leaf = match after_id {
+            Some(ref after_id) => match entries_map.get(after_id) {
+                Some(entry) => Some(entry.merkle_leaf_index),
+                None => return Vec::new(),
+            },
+            None => None,
+        };
+        
+        let mut results: Vec<&AuditEntry> = entry_ids.iter()
+            .filter_map(|id| entries_map.get(id))
+            .filter(|entry| after_leaf.is_none_or(|leaf| entry.merkle_leaf_index > leaf))
+            .collect();
+        results.sort_by_key(|entry| entry.merkle_leaf_index);
+        results.into_iter().take(limit).cloned().collect()
+    })
+}
+
+// Narrows a query to the entries its actor and resource filters can match,
+// or None when it has neither and needs a full scan
+fn indexed_candidates(query: &AuditQuery) -> Option<BTreeSet<String>> {
+    let by_actor = query.actors.as_ref().map(|actors| {
+        ACTOR_INDEX.with(|index| {
+            let index = index.borrow();
+            actors.iter()
+                .filter_map(|actor| index.get(actor))
+                .flatten()
+                .cloned()
+                .collect::<BTreeSet<String>>()
+        })
+    });
+    
+    let by_resource = query.resource_ids.as_ref().map(|resource_ids| {
+        RESOURCE_INDEX.with(|index| {
+            let index = index.borrow();
+            resource_ids.iter()
+                .filter_map(|resource_id| index.get(resource_id))
+                .flatten()
+                .cloned()
+                .collect::<BTreeSet<String>>()
+        })
+    });
+    
+    match (by_actor, by_resource) {
+        (Some(actors), Some(resources)) => Some(actors.intersection(&resources).cloned().collect()),
+        (Some(ids), None) | (None, Some(ids)) => Some(ids),
+        (None, None) => None,
+    }
+}
+
+#[query]
+fn verify_audit_chain() -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
     
     // Check if caller is authorized auditor
     if !is_authorized_auditor(&caller) {
-        return Err("Unauthorized access".to_string());
+        return Err(CustodyError::unauthorized("audit access"));
     }
     
     // Log audit access
     log_audit_access(caller, "verify_audit_chain", "chain_verification".to_string());
     
+    // Archived entries are still part of the hash chain
+    let archived: Vec<AuditEntry> = ARCHIVED_ENTRIES.with(|archived| {
+        archived.borrow().values().cloned().collect()
+    });
+    
     AUDIT_ENTRIES.with(|entries| {
         let entries_map = entries.borrow();
-        let mut entries_vec: Vec<&AuditEntry> = entries_map.values().collect();
+        let mut entries_vec: Vec<&AuditEntry> = entries_map.values().chain(archived.iter()).collect();
         
-        // Sort by timestamp
-        entries_vec.sort_by(|a, b| a.timestamp.cmp(&b.timestamp));
+        // Sort by insertion order; timestamps are not unique within a message
+        entries_vec.sort_by_key(|a| a.merkle_leaf_index);
         
         let mut previous_hash: Option<String> = None;
         
@@ -503,265 +938,1372 @@ fn verify_audit_chain() -> Result<String, String> {
             );
             
             if calculated_hash != entry.hash {
-                return Err(format!("Hash mismatch in entry: {}", entry.id));
+                return Err(CustodyError::InternalError(format!("Hash mismatch in entry: {}", entry.id)));
             }
             
             // Verify chain integrity
             if entry.previous_hash != previous_hash {
-                return Err(format!("Chain integrity broken at entry: {}", entry.id));
+                return Err(CustodyError::InternalError(format!("Chain integrity broken at entry: {}", entry.id)));
             }
             
             previous_hash = Some(entry.hash.clone());
         }
         
+        // Verify the Merkle peaks still match the stored leaves
+        let leaves = MERKLE_NODES.with(|nodes| {
+            nodes.borrow().first().cloned().unwrap_or_default()
+        });
+        let mut peaks = Vec::new();
+        let mut heights = Vec::new();
+        for leaf in &leaves {
+            push_merkle_leaf(&mut peaks, &mut heights, leaf.clone());
+        }
+        if bag_merkle_peaks(&peaks) != get_merkle_root() {
+            return Err(CustodyError::InternalError("Merkle root does not match stored entries".to_string()));
+        }
+        
         Ok("Audit chain verification successful".to_string())
     })
 }
 
-// === Compliance Reporting Functions ===
+#[query]
+fn get_merkle_root() -> String {
+    MERKLE_PEAKS.with(|peaks| bag_merkle_peaks(&peaks.borrow()))
+}
 
-#[update]
-fn generate_compliance_report(
-    report_type: ReportType,
-    period_start: u64,
-    period_end: u64,
-) -> Result<String, String> {
+#[query]
+fn verify_entry_inclusion(entry_id: String) -> Result<Vec<String>, CustodyError> {
     let caller = ic_cdk::caller();
     
     // Check if caller is authorized auditor
     if !is_authorized_auditor(&caller) {
-        return Err("Unauthorized access".to_string());
+        return Err(CustodyError::unauthorized("audit access"));
     }
     
-    let report_id = Uuid::new_v4().to_string();
-    let current_time = ic_cdk::api::time();
+    let entry = AUDIT_ENTRIES.with(|entries| {
+        entries.borrow().get(&entry_id).cloned()
+    });
     
-    // Query compliance-relevant entries in the period
-    let query = AuditQuery {
-        event_types: None,
-        resource_types: None,
-        actors: None,
-        resource_ids: None,
-        start_time: Some(period_start),
-        end_time: Some(period_end),
-        compliance_relevant_only: true,
-        limit: None,
-        offset: None,
+    let entry = match entry {
+        Some(e) => e,
+        None => return Err(CustodyError::not_found("Audit entry", entry_id)),
     };
     
-    let entries = query_audit_entries(query);
-    let entries_count = entries.len() as u32;
-    
-    // Generate summary
-    let summary = format!(
-        "Compliance report covering period {} to {} with {} relevant entries",
-        period_start, period_end, entries_count
+    let calculated_hash = calculate_entry_hash(
+        &entry.id,
+        entry.timestamp,
+        &entry.event_type,
+        &entry.actor,
+        &entry.resource_type,
+        &entry.resource_id,
+        &entry.action,
+        &entry.details,
+        &entry.previous_hash,
     );
     
-    // Calculate report hash
-    let mut hasher = Sha256::new();
-    hasher.update(report_id.as_bytes());
-    hasher.update(current_time.to_be_bytes());
-    hasher.update(summary.as_bytes());
-    hasher.update(entries_count.to_be_bytes());
-    let report_hash = format!("{:x}", hasher.finalize());
+    if calculated_hash != entry.hash {
+        return Err(CustodyError::InternalError(format!("Hash mismatch in entry: {}", entry.id)));
+    }
     
-    let report = ComplianceReport {
-        id: report_id.clone(),
-        report_type,
-        period_start,
-        period_end,
-        generated_at: current_time,
-        generated_by: caller,
-        entries_count,
-        summary,
-        hash: report_hash,
-        digital_signature: None, // Would implement digital signatures in production
-    };
+    let proof = build_merkle_proof(entry.merkle_leaf_index, &entry.hash).map_err(CustodyError::InternalError)?;
     
-    COMPLIANCE_REPORTS.with(|reports| {
-        reports.borrow_mut().insert(report_id.clone(), report);
-    });
+    if fold_merkle_proof(&entry.hash, &proof) != Some(get_merkle_root()) {
+        return Err(CustodyError::InternalError(format!(
+            "Entry {} is not included in the current Merkle root",
+            entry.id
+        )));
+    }
     
-    // Log report generation
-    let _audit_entry = log_audit_event(
-        EventType::ComplianceCheck,
-        ResourceType::ComplianceReport,
-        report_id.clone(),
-        "generate_report".to_string(),
-        format!("Generated {} compliance report for period {} to {}", 
-            format!("{:?}", report.report_type), period_start, period_end),
-        None,
-        true,
-    )?;
+    Ok(proof)
+}
+
+/// Produces a self-contained proof that an entry is included in the current
+/// Merkle root, so third parties can verify it without the rest of the chain
+#[update]
+async fn generate_entry_proof(entry_id: String) -> Result<EntryProof, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "generate_entry_proof")?;
     
-    Ok(report_id)
+    // Check if caller is authorized auditor
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
+    }
+    
+    let entry = AUDIT_ENTRIES.with(|entries| {
+        entries.borrow().get(&entry_id).cloned()
+    });
+    
+    let entry = match entry {
+        Some(e) => e,
+        None => return Err(CustodyError::not_found("Audit entry", entry_id)),
+    };
+    
+    let steps = build_merkle_proof(entry.merkle_leaf_index, &entry.hash).map_err(CustodyError::InternalError)?;
+    let proof_path = proof_path_from_steps(&steps).ok_or_else(|| {
+        CustodyError::InternalError(format!("Malformed Merkle proof for entry {}", entry.id))
+    })?;
+    let merkle_root = get_merkle_root();
+    
+    let signatures_enabled = AUDIT_SETTINGS.with(|s| s.borrow().digital_signatures_enabled);
+    let root_signature = if signatures_enabled {
+        Some(sign_hash(&merkle_root).await.map_err(CustodyError::InternalError)?)
+    } else {
+        None
+    };
+    
+    log_audit_access(caller, "generate_entry_proof", entry.id.clone());
+    
+    Ok(EntryProof {
+        entry_id: entry.id,
+        entry_hash: entry.hash,
+        merkle_root,
+        proof_path,
+        computed_at: ic_cdk::api::time(),
+        root_signature,
+    })
 }
 
+/// Checks a proof against the current root. Proofs go stale as new entries
+/// are appended; verifiers holding an older proof should check its
+/// root_signature instead.
 #[query]
-fn get_compliance_report(report_id: String) -> Option<ComplianceReport> {
+fn verify_entry_proof(proof: EntryProof) -> bool {
+    let computed_root = fold_proof_path(&proof.entry_hash, &proof.proof_path);
+    computed_root == proof.merkle_root && computed_root == get_merkle_root()
+}
+
+#[update]
+async fn verify_entry_signature(entry_id: String) -> Result<bool, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "verify_entry_signature")?;
     
     // Check if caller is authorized auditor
-    if !is_authorized_auditor(&caller) {
-        ic_cdk::println!("Unauthorized compliance report access attempt from: {}", caller);
-        return None;
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
     }
     
-    // Log audit access
-    log_audit_access(caller, "get_compliance_report", report_id.clone());
+    let entry = AUDIT_ENTRIES.with(|entries| {
+        entries.borrow().get(&entry_id).cloned()
+    });
     
-    COMPLIANCE_REPORTS.with(|reports| {
-        reports.borrow().get(&report_id).cloned()
+    let entry = match entry {
+        Some(e) => e,
+        None => return Err(CustodyError::not_found("Audit entry", entry_id)),
+    };
+    
+    let signature = match entry.digital_signature {
+        Some(ref s) => hex::decode(s).map_err(|e| CustodyError::invalid_input("digital_signature", e))?,
+        None => return Err(CustodyError::status_conflict("unsigned", "signed")),
+    };
+    
+    let (response,) = ecdsa_public_key(EcdsaPublicKeyArgument {
+        canister_id: None,
+        derivation_path: audit_signing_derivation_path(),
+        key_id: audit_signing_key(),
     })
+    .await
+    .map_err(|(code, msg)| CustodyError::InternalError(format!("ecdsa_public_key failed: {:?} {}", code, msg)))?;
+    
+    let public_key = secp256k1::PublicKey::from_slice(&response.public_key)
+        .map_err(|e| CustodyError::InternalError(format!("Invalid public key: {}", e)))?;
+    let entry_hash = hex::decode(&entry.hash).map_err(|e| CustodyError::invalid_input("hash", e))?;
+    let message = secp256k1::Message::from_digest_slice(&entry_hash)
+        .map_err(|e| CustodyError::invalid_input("hash", e.to_string()))?;
+    let mut signature = secp256k1::ecdsa::Signature::from_compact(&signature)
+        .map_err(|e| CustodyError::invalid_input("digital_signature", e.to_string()))?;
+    signature.normalize_s();
+    
+    Ok(secp256k1::SECP256K1.verify_ecdsa(&message, &signature, &public_key).is_ok())
+}
+
+// === Retention Functions ===
+
+#[update]
+async fn enforce_retention_policy() -> Result<u64, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "enforce_retention_policy")?;
+    
+    // Check if caller is authorized auditor
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
+    }
+    
+    let current_time = ic_cdk::api::time();
+    
+    let expired: Vec<AuditEntry> = AUDIT_ENTRIES.with(|entries| {
+        let mut entries_map = entries.borrow_mut();
+        let expired_ids: Vec<String> = entries_map.values()
+            .filter(|e| !e.compliance_relevant)
+            .filter(|e| e.retention_until.is_some_and(|until| until <= current_time))
+            .map(|e| e.id.clone())
+            .collect();
+        
+        expired_ids.iter()
+            .filter_map(|id| entries_map.remove(id))
+            .collect()
+    });
+    
+    let archived_count = expired.len() as u64;
+    
+    SORTED_ENTRY_IDS.with(|sorted| {
+        let mut sorted = sorted.borrow_mut();
+        for entry in &expired {
+            sorted.remove(&entry.merkle_leaf_index);
+        }
+    });
+    
+    for entry in &expired {
+        unindex_audit_entry(entry);
+    }
+    
+    ARCHIVED_ENTRIES.with(|archived| {
+        let mut archived = archived.borrow_mut();
+        for entry in expired {
+            archived.insert(entry.id.clone(), entry);
+        }
+    });
+    
+    // Record the archive operation itself
+    let archive_entry = create_audit_entry(
+        EventType::DataModification,
+        caller,
+        ResourceType::AuditLog,
+        "retention_policy".to_string(),
+        "enforce_retention_policy".to_string(),
+        format!("Archived {} audit entries past their retention period", archived_count),
+        AuditMetadata::default(),
+        true,
+    );
+    
+    store_audit_entry(archive_entry);
+    
+    Ok(archived_count)
 }
 
 #[query]
-fn list_compliance_reports() -> Vec<ComplianceReport> {
+fn get_archived_entry(entry_id: String) -> Option<AuditEntry> {
     let caller = ic_cdk::caller();
     
     // Check if caller is authorized auditor
     if !is_authorized_auditor(&caller) {
-        ic_cdk::println!("Unauthorized compliance reports list access attempt from: {}", caller);
-        return Vec::new();
+        ic_cdk::println!("Unauthorized archived entry access attempt from: {}", caller);
+        return None;
     }
     
-    // Log audit access
-    log_audit_access(caller, "list_compliance_reports", "all_reports".to_string());
-    
-    COMPLIANCE_REPORTS.with(|reports| {
-        reports.borrow().values().cloned().collect()
+    ARCHIVED_ENTRIES.with(|archived| {
+        archived.borrow().get(&entry_id).cloned()
     })
 }
 
-// === Administrative Functions ===
+// === Compliance Reporting Functions ===
 
 #[update]
-fn add_auditor(auditor: Principal, name: String) -> Result<String, String> {
+async fn generate_compliance_report(
+    report_type: ReportType,
+    period_start: u64,
+    period_end: u64,
+) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "generate_compliance_report")?;
     
     // Check if caller is authorized auditor
-    if !is_authorized_auditor(&caller) {
-        return Err("Unauthorized access".to_string());
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
     }
     
-    AUDITORS.with(|auditors| {
-        auditors.borrow_mut().insert(auditor, name.clone());
+    generate_compliance_report_internal(report_type, period_start, period_end, caller)
+}
+
+fn generate_compliance_report_internal(
+    report_type: ReportType,
+    period_start: u64,
+    period_end: u64,
+    generated_by: Principal,
+) -> Result<String, CustodyError> {
+    let report_id = Uuid::new_v4().to_string();
+    let current_time = ic_cdk::api::time();
+    
+    // Query compliance-relevant entries in the period
+    let query = AuditQuery {
+        event_types: None,
+        resource_types: None,
+        actors: None,
+        resource_ids: None,
+        start_time: Some(period_start),
+        end_time: Some(period_end),
+        compliance_relevant_only: true,
+        limit: None,
+        offset: None,
+    };
+    
+    log_audit_access(generated_by, "generate_compliance_report", report_id.clone());
+    
+    let entries = collect_audit_entries(&query);
+    let entries_count = entries.len() as u32;
+    
+    // Generate summary
+    let summary = format!(
+        "Compliance report covering period {} to {} with {} relevant entries",
+        period_start, period_end, entries_count
+    );
+    
+    // Calculate report hash
+    let mut hasher = Sha256::new();
+    hasher.update(report_id.as_bytes());
+    hasher.update(current_time.to_be_bytes());
+    hasher.update(summary.as_bytes());
+    hasher.update(entries_count.to_be_bytes());
+    let report_hash = format!("{:x}", hasher.finalize());
+    
+    let report = ComplianceReport {
+        id: report_id.clone(),
+        report_type,
+        period_start,
+        period_end,
+        generated_at: current_time,
+        generated_by,
+        entries_count,
+        summary,
+        hash: report_hash,
+        digital_signature: None, // Would implement digital signatures in production
+    };
+    
+    COMPLIANCE_REPORTS.with(|reports| {
+        reports.borrow_mut().insert(report_id.clone(), report.clone());
     });
     
-    // Log auditor addition
+    // Log report generation
     let _audit_entry = log_audit_event(
-        EventType::SystemConfiguration,
-        ResourceType::User,
-        auditor.to_string(),
-        "add_auditor".to_string(),
-        format!("Added auditor: {}", name),
+        EventType::ComplianceCheck,
+        ResourceType::ComplianceReport,
+        report_id.clone(),
+        "generate_report".to_string(),
+        format!("Generated {:?} compliance report for period {} to {}", 
+            report.report_type, period_start, period_end),
         None,
         true,
+        None,
     )?;
     
-    Ok("Auditor added successfully".to_string())
+    Ok(report_id)
 }
 
 #[update]
-fn update_audit_settings(new_settings: AuditSettings) -> Result<String, String> {
+async fn schedule_recurring_report(config: ScheduledReportConfig) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "schedule_recurring_report")?;
     
     // Check if caller is authorized auditor
-    if !is_authorized_auditor(&caller) {
-        return Err("Unauthorized access".to_string());
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
     }
     
-    AUDIT_SETTINGS.with(|settings| {
-        *settings.borrow_mut() = new_settings;
+    if config.frequency_seconds < SCHEDULED_REPORT_CHECK_INTERVAL.as_secs() {
+        return Err(CustodyError::invalid_input(
+            "frequency_seconds",
+            format!("must be at least {} seconds", SCHEDULED_REPORT_CHECK_INTERVAL.as_secs()),
+        ));
+    }
+    
+    let schedule_id = Uuid::new_v4().to_string();
+    let schedule = ScheduledReportConfig {
+        id: schedule_id.clone(),
+        last_generated_at: None,
+        ..config
+    };
+    
+    let details = format!("Scheduled {:?} report every {} seconds for {}",
+        schedule.report_type, schedule.frequency_seconds, schedule.recipient);
+    
+    SCHEDULED_REPORTS.with(|schedules| {
+        schedules.borrow_mut().insert(schedule_id.clone(), schedule);
     });
     
-    // Log settings update
     let _audit_entry = log_audit_event(
         EventType::SystemConfiguration,
-        ResourceType::System,
-        "audit_settings".to_string(),
-        "update_settings".to_string(),
-        "Updated audit trail settings".to_string(),
+        ResourceType::ComplianceReport,
+        schedule_id.clone(),
+        "schedule_recurring_report".to_string(),
+        details,
         None,
         true,
+        None,
     )?;
     
-    Ok("Audit settings updated successfully".to_string())
+    Ok(schedule_id)
 }
 
-#[query]
-fn get_audit_settings() -> AuditSettings {
+#[update]
+async fn cancel_scheduled_report(schedule_id: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "cancel_scheduled_report")?;
     
     // Check if caller is authorized auditor
-    if !is_authorized_auditor(&caller) {
-        ic_cdk::println!("Unauthorized audit settings access attempt from: {}", caller);
-        return AuditSettings {
-            retention_days: 0,
-            auto_archive_enabled: false,
-            compliance_reporting_enabled: false,
-            real_time_alerts_enabled: false,
-            hash_verification_enabled: false,
-            digital_signatures_enabled: false,
-            audit_access_logging: false,
-        };
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
     }
     
-    AUDIT_SETTINGS.with(|settings| {
-        settings.borrow().clone()
-    })
+    let removed = SCHEDULED_REPORTS.with(|schedules| {
+        schedules.borrow_mut().remove(&schedule_id)
+    });
+    
+    if removed.is_none() {
+        return Err(CustodyError::not_found("Scheduled report", schedule_id));
+    }
+    
+    let _audit_entry = log_audit_event(
+        EventType::SystemConfiguration,
+        ResourceType::ComplianceReport,
+        schedule_id,
+        "cancel_scheduled_report".to_string(),
+        "Cancelled scheduled compliance report".to_string(),
+        None,
+        true,
+        None,
+    )?;
+    
+    Ok("Scheduled report cancelled".to_string())
 }
 
 #[query]
-fn get_audit_statistics() -> BTreeMap<String, u64> {
+fn list_scheduled_reports() -> Vec<ScheduledReportConfig> {
     let caller = ic_cdk::caller();
     
     // Check if caller is authorized auditor
     if !is_authorized_auditor(&caller) {
-        ic_cdk::println!("Unauthorized audit statistics access attempt from: {}", caller);
-        return BTreeMap::new();
+        ic_cdk::println!("Unauthorized scheduled report access attempt from: {}", caller);
+        return Vec::new();
     }
     
-    // Log audit access
-    log_audit_access(caller, "get_audit_statistics", "statistics".to_string());
+    SCHEDULED_REPORTS.with(|schedules| {
+        schedules.borrow().values().cloned().collect()
+    })
+}
+
+/// Timers don't survive upgrades, so call this from both init and post_upgrade.
+fn start_report_scheduler() {
+    ic_cdk_timers::set_timer_interval(SCHEDULED_REPORT_CHECK_INTERVAL, process_scheduled_reports);
+}
+
+fn process_scheduled_reports() {
+    let current_time = ic_cdk::api::time();
     
-    let mut stats = BTreeMap::new();
+    let due: Vec<ScheduledReportConfig> = SCHEDULED_REPORTS.with(|schedules| {
+        schedules.borrow()
+            .values()
+            .filter(|schedule| is_report_due(schedule, current_time))
+            .cloned()
+            .collect()
+    });
     
-    AUDIT_ENTRIES.with(|entries| {
-        let entries_map = entries.borrow();
-        stats.insert("total_entries".to_string(), entries_map.len() as u64);
+    for schedule in due {
+        // Each report covers the time since the previous one
+        let frequency_nanos = schedule.frequency_seconds.saturating_mul(1_000_000_000);
+        let period_start = schedule.last_generated_at
+            .unwrap_or_else(|| current_time.saturating_sub(frequency_nanos));
         
-        let compliance_entries = entries_map.values()
-            .filter(|e| e.compliance_relevant)
-            .count();
-        stats.insert("compliance_entries".to_string(), compliance_entries as u64);
+        let report_id = match generate_compliance_report_internal(
+            schedule.report_type.clone(),
+            period_start,
+            current_time,
+            ic_cdk::id(),
+        ) {
+            Ok(id) => id,
+            Err(e) => {
+                ic_cdk::println!("Scheduled report {} failed: {}", schedule.id, e);
+                continue;
+            }
+        };
         
-        // Count by event type
-        let mut event_type_counts = BTreeMap::new();
-        for entry in entries_map.values() {
-            let event_type_str = format!("{:?}", entry.event_type);
-            *event_type_counts.entry(event_type_str).or_insert(0) += 1;
-        }
+        SCHEDULED_REPORTS.with(|schedules| {
+            if let Some(s) = schedules.borrow_mut().get_mut(&schedule.id) {
+                s.last_generated_at = Some(current_time);
+            }
+        });
         
-        for (event_type, count) in event_type_counts {
-            stats.insert(format!("event_type_{}", event_type), count);
+        let report = COMPLIANCE_REPORTS.with(|reports| reports.borrow().get(&report_id).cloned());
+        if let Some(report) = report {
+            ic_cdk::spawn(notify_report_recipient(schedule.recipient, report));
         }
-    });
-    
-    COMPLIANCE_REPORTS.with(|reports| {
-        stats.insert("compliance_reports".to_string(), reports.borrow().len() as u64);
-    });
-    
-    stats
+    }
 }
 
-// === Helper Functions ===
+fn is_report_due(schedule: &ScheduledReportConfig, current_time: u64) -> bool {
+    match schedule.last_generated_at {
+        Some(last) => current_time.saturating_sub(last) / 1_000_000_000 >= schedule.frequency_seconds,
+        None => true,
+    }
+}
 
-fn is_authorized_auditor(principal: &Principal) -> bool {
-    AUDITORS.with(|auditors| {
-        auditors.borrow().contains_key(principal)
-    })
+async fn notify_report_recipient(recipient: Principal, report: ComplianceReport) {
+    let report_id = report.id.clone();
+    let result: Result<(), _> = ic_cdk::call(recipient, "receive_compliance_report", (report,)).await;
+    
+    if let Err((code, msg)) = result {
+        ic_cdk::println!("Failed to deliver compliance report {} to {}: {:?} {}", report_id, recipient, code, msg);
+    }
+}
+
+#[query]
+fn get_compliance_report(report_id: String) -> Option<ComplianceReport> {
+    let caller = ic_cdk::caller();
+    
+    // Check if caller is authorized auditor
+    if !is_authorized_auditor(&caller) {
+        ic_cdk::println!("Unauthorized compliance report access attempt from: {}", caller);
+        return None;
+    }
+    
+    // Log audit access
+    log_audit_access(caller, "get_compliance_report", report_id.clone());
+    
+    COMPLIANCE_REPORTS.with(|reports| {
+        reports.borrow().get(&report_id).cloned()
+    })
+}
+
+#[query]
+fn list_compliance_reports() -> Vec<ComplianceReport> {
+    let caller = ic_cdk::caller();
+    
+    // Check if caller is authorized auditor
+    if !is_authorized_auditor(&caller) {
+        ic_cdk::println!("Unauthorized compliance reports list access attempt from: {}", caller);
+        return Vec::new();
+    }
+    
+    // Log audit access
+    log_audit_access(caller, "list_compliance_reports", "all_reports".to_string());
+    
+    COMPLIANCE_REPORTS.with(|reports| {
+        reports.borrow().values().cloned().collect()
+    })
+}
+
+/// Serializes a stored report and the compliance-relevant entries in its
+/// period as JSON, laid out to map onto FinCEN BSA filing fields
+#[update]
+async fn export_compliance_report_json(report_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "export_compliance_report_json")?;
+    
+    // Check if caller is authorized auditor
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
+    }
+    
+    let report = COMPLIANCE_REPORTS.with(|reports| reports.borrow().get(&report_id).cloned());
+    
+    let report = match report {
+        Some(report) => report,
+        None => return Err(CustodyError::not_found("Compliance report", report_id)),
+    };
+    
+    let query = AuditQuery {
+        event_types: None,
+        resource_types: None,
+        actors: None,
+        resource_ids: None,
+        start_time: Some(report.period_start),
+        end_time: Some(report.period_end),
+        compliance_relevant_only: true,
+        limit: None,
+        offset: None,
+    };
+    
+    let entries = collect_audit_entries(&query);
+    let export = build_report_export(&report, entries, ic_cdk::api::time());
+    
+    let json = serde_json::to_string(&export)
+        .map_err(|e| CustodyError::InternalError(format!("Failed to serialize report: {}", e)))?;
+    
+    log_audit_event(
+        EventType::DataExport,
+        ResourceType::ComplianceReport,
+        report_id,
+        "export_report_json".to_string(),
+        format!("Exported compliance report with {} entries", export.entries.len()),
+        None,
+        true,
+        None,
+    )?;
+    
+    Ok(json)
+}
+
+/// Exports the entries matching a query for regulatory submission. A third
+/// party can recompute root_hash from the entries and check export_signature
+/// against this canister's threshold ECDSA public key.
+#[update]
+async fn export_audit_data(query: AuditQuery) -> Result<AuditExport, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "export_audit_data")?;
+    
+    // Check if caller is authorized auditor
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
+    }
+    
+    let mut entries = collect_audit_entries(&query);
+    
+    // Large exports are capped at the oldest entries rather than refused
+    entries.sort_by_key(|entry| (entry.timestamp, entry.merkle_leaf_index));
+    let has_more = entries.len() > MAX_EXPORT_ENTRIES;
+    entries.truncate(MAX_EXPORT_ENTRIES);
+    
+    let root_hash = calculate_export_root_hash(&entries);
+    
+    let signatures_enabled = AUDIT_SETTINGS.with(|s| s.borrow().digital_signatures_enabled);
+    let export_signature = if signatures_enabled {
+        Some(sign_hash(&root_hash).await.map_err(CustodyError::InternalError)?)
+    } else {
+        None
+    };
+    
+    let entry_count = entries.len() as u64;
+    
+    log_audit_event(
+        EventType::DataExport,
+        ResourceType::AuditLog,
+        root_hash.clone(),
+        "export_audit_data".to_string(),
+        format!("Exported {} audit entries", entry_count),
+        None,
+        true,
+        None,
+    )?;
+    
+    Ok(AuditExport {
+        entries,
+        generated_at: ic_cdk::api::time(),
+        generated_by: caller,
+        entry_count,
+        root_hash,
+        export_signature,
+        has_more,
+    })
+}
+
+// Entry hashes only cover the core fields, so the metadata and correlation ID
+// are hashed alongside them to make the whole exported entry tamper-evident
+fn calculate_export_root_hash(entries: &[AuditEntry]) -> String {
+    let mut hasher = Sha256::new();
+    for entry in entries {
+        hasher.update(entry.hash.as_bytes());
+        hasher.update(serde_json::to_vec(&entry.metadata).unwrap_or_default());
+        hasher.update(serde_json::to_vec(&entry.correlation_id).unwrap_or_default());
+    }
+    format!("{:x}", hasher.finalize())
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct ComplianceReportExport {
+    report_metadata: ReportMetadata,
+    entries_by_event_type: BTreeMap<String, u64>,
+    high_risk_transactions: Vec<TransactionSummary>,
+    sar_count: u64,
+    kyc_approved_count: u64,
+    flagged_accounts: Vec<String>,
+    entries: Vec<AuditEntry>,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct ReportMetadata {
+    schema_version: &'static str,
+    report_id: String,
+    report_type: String,
+    period_start: u64,
+    period_end: u64,
+    generated_at: u64,
+    generated_by: String,
+    report_hash: String,
+    exported_at: u64,
+}
+
+#[derive(Clone, Debug, Serialize)]
+struct TransactionSummary {
+    entry_id: String,
+    transaction_id: String,
+    event_type: String,
+    actor: String,
+    timestamp: u64,
+    details: String,
+}
+
+const REPORT_EXPORT_SCHEMA_VERSION: &str = "1.0";
+
+fn build_report_export(report: &ComplianceReport, mut entries: Vec<AuditEntry>, exported_at: u64) -> ComplianceReportExport {
+    // Regulators read the export chronologically
+    entries.sort_by_key(|entry| entry.timestamp);
+    
+    let mut entries_by_event_type = BTreeMap::new();
+    let mut high_risk_transactions = Vec::new();
+    let mut flagged_accounts = std::collections::BTreeSet::new();
+    let mut sar_count = 0;
+    let mut kyc_approved_count = 0;
+    
+    for entry in &entries {
+        *entries_by_event_type.entry(format!("{:?}", entry.event_type)).or_insert(0u64) += 1;
+        
+        let is_transaction_event = matches!(
+            entry.event_type,
+            EventType::TransactionInitiated
+                | EventType::TransactionApproved
+                | EventType::TransactionExecuted
+                | EventType::TransactionRejected
+        );
+        
+        if is_transaction_event && is_high_risk_entry(entry) {
+            high_risk_transactions.push(TransactionSummary {
+                entry_id: entry.id.clone(),
+                transaction_id: entry.resource_id.clone(),
+                event_type: format!("{:?}", entry.event_type),
+                actor: entry.actor.to_string(),
+                timestamp: entry.timestamp,
+                details: entry.details.clone(),
+            });
+            flagged_accounts.extend(entry.metadata.additional_context.get("account_id").cloned());
+        }
+        
+        if entry.event_type == EventType::EmergencyAction
+            && matches!(entry.resource_type, ResourceType::CustodyAccount | ResourceType::MultisigWallet)
+        {
+            flagged_accounts.insert(entry.resource_id.clone());
+        }
+        
+        if entry.action == "file_sar_report" {
+            sar_count += 1;
+        }
+        
+        if entry.event_type == EventType::KycUpdate && entry.action == "approve_kyc_profile" {
+            kyc_approved_count += 1;
+        }
+    }
+    
+    ComplianceReportExport {
+        report_metadata: ReportMetadata {
+            schema_version: REPORT_EXPORT_SCHEMA_VERSION,
+            report_id: report.id.clone(),
+            report_type: format!("{:?}", report.report_type),
+            period_start: report.period_start,
+            period_end: report.period_end,
+            generated_at: report.generated_at,
+            generated_by: report.generated_by.to_string(),
+            report_hash: report.hash.clone(),
+            exported_at,
+        },
+        entries_by_event_type,
+        high_risk_transactions,
+        sar_count,
+        kyc_approved_count,
+        flagged_accounts: flagged_accounts.into_iter().collect(),
+        entries,
+    }
+}
+
+fn is_high_risk_entry(entry: &AuditEntry) -> bool {
+    entry_severity(entry) >= AlertSeverity::High
+}
+
+// Callers mark risky events with a "risk_level" or "severity" context value;
+// entries without one are treated as low severity
+fn entry_severity(entry: &AuditEntry) -> AlertSeverity {
+    let context = &entry.metadata.additional_context;
+    ["risk_level", "severity"].iter()
+        .filter_map(|key| context.get(*key))
+        .filter_map(|value| match value.to_lowercase().as_str() {
+            "low" => Some(AlertSeverity::Low),
+            "medium" => Some(AlertSeverity::Medium),
+            "high" => Some(AlertSeverity::High),
+            "critical" => Some(AlertSeverity::Critical),
+            _ => None,
+        })
+        .max()
+        .unwrap_or(AlertSeverity::Low)
+}
+
+// === Administrative Functions ===
+
+#[update]
+async fn add_auditor(auditor: Principal, name: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_auditor")?;
+    
+    // Check if caller is authorized auditor
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
+    }
+    
+    AUDITORS.with(|auditors| {
+        auditors.borrow_mut().insert(auditor, name.clone());
+    });
+    
+    // Log auditor addition
+    let _audit_entry = log_audit_event(
+        EventType::SystemConfiguration,
+        ResourceType::User,
+        auditor.to_string(),
+        "add_auditor".to_string(),
+        format!("Added auditor: {}", name),
+        None,
+        true,
+        None,
+    )?;
+    
+    Ok("Auditor added successfully".to_string())
+}
+
+/// Allows or stops a canister relaying entries on behalf of its users
+#[update]
+async fn set_trusted_canister(canister: Principal, trusted: bool) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_trusted_canister")?;
+    
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
+    }
+    
+    context::set_trusted_canister(canister, trusted);
+    
+    let _audit_entry = log_audit_event(
+        EventType::SystemConfiguration,
+        ResourceType::System,
+        canister.to_string(),
+        "set_trusted_canister".to_string(),
+        format!("Trusted canister {}: {}", canister, trusted),
+        None,
+        true,
+        None,
+    )?;
+    
+    Ok("Trusted canister updated successfully".to_string())
+}
+
+#[query]
+fn list_trusted_canisters() -> Vec<Principal> {
+    context::trusted_canisters()
+}
+
+#[update]
+async fn update_audit_settings(new_settings: AuditSettings) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "update_audit_settings")?;
+    
+    // Check if caller is authorized auditor
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
+    }
+    
+    AUDIT_SETTINGS.with(|settings| {
+        *settings.borrow_mut() = new_settings;
+    });
+    
+    // Log settings update
+    let _audit_entry = log_audit_event(
+        EventType::SystemConfiguration,
+        ResourceType::System,
+        "audit_settings".to_string(),
+        "update_settings".to_string(),
+        "Updated audit trail settings".to_string(),
+        None,
+        true,
+        None,
+    )?;
+    
+    Ok("Audit settings updated successfully".to_string())
+}
+
+#[query]
+fn get_audit_settings() -> AuditSettings {
+    let caller = ic_cdk::caller();
+    
+    // Check if caller is authorized auditor
+    if !is_authorized_auditor(&caller) {
+        ic_cdk::println!("Unauthorized audit settings access attempt from: {}", caller);
+        return AuditSettings {
+            retention_days: 0,
+            auto_archive_enabled: false,
+            compliance_reporting_enabled: false,
+            real_time_alerts_enabled: false,
+            hash_verification_enabled: false,
+            digital_signatures_enabled: false,
+            audit_access_logging: false,
+        };
+    }
+    
+    AUDIT_SETTINGS.with(|settings| {
+        settings.borrow().clone()
+    })
+}
+
+#[query]
+fn get_audit_statistics() -> BTreeMap<String, u64> {
+    let caller = ic_cdk::caller();
+    
+    // Check if caller is authorized auditor
+    if !is_authorized_auditor(&caller) {
+        ic_cdk::println!("Unauthorized audit statistics access attempt from: {}", caller);
+        return BTreeMap::new();
+    }
+    
+    // Log audit access
+    log_audit_access(caller, "get_audit_statistics", "statistics".to_string());
+    
+    let mut stats = BTreeMap::new();
+    
+    AUDIT_ENTRIES.with(|entries| {
+        let entries_map = entries.borrow();
+        stats.insert("total_entries".to_string(), entries_map.len() as u64);
+        
+        let compliance_entries = entries_map.values()
+            .filter(|e| e.compliance_relevant)
+            .count();
+        stats.insert("compliance_entries".to_string(), compliance_entries as u64);
+        
+        // Count by event type
+        let mut event_type_counts = BTreeMap::new();
+        for entry in entries_map.values() {
+            let event_type_str = format!("{:?}", entry.event_type);
+            *event_type_counts.entry(event_type_str).or_insert(0) += 1;
+        }
+        
+        for (event_type, count) in event_type_counts {
+            stats.insert(format!("event_type_{}", event_type), count);
+        }
+    });
+    
+    COMPLIANCE_REPORTS.with(|reports| {
+        stats.insert("compliance_reports".to_string(), reports.borrow().len() as u64);
+    });
+    
+    stats
+}
+
+// === Alert Subscription Functions ===
+
+#[update]
+async fn subscribe_to_alerts(
+    subscriber_canister_id: Principal,
+    event_filter: AuditQuery,
+    min_severity: AlertSeverity,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "subscribe_to_alerts")?;
+    
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
+    }
+    
+    let subscription_id = Uuid::new_v4().to_string();
+    
+    ALERT_SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow_mut().insert(subscription_id.clone(), AlertSubscription {
+            id: subscription_id.clone(),
+            subscriber_canister_id,
+            event_filter,
+            min_severity: min_severity.clone(),
+        });
+    });
+    
+    let _audit_entry = log_audit_event(
+        EventType::SystemConfiguration,
+        ResourceType::System,
+        subscription_id.clone(),
+        "subscribe_to_alerts".to_string(),
+        format!("Subscribed {} to {:?} and above alerts", subscriber_canister_id, min_severity),
+        None,
+        true,
+        None,
+    )?;
+    
+    Ok(subscription_id)
+}
+
+#[update]
+async fn unsubscribe_from_alerts(subscription_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "unsubscribe_from_alerts")?;
+    
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("audit access"));
+    }
+    
+    let removed = ALERT_SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow_mut().remove(&subscription_id)
+    });
+    
+    let subscription = match removed {
+        Some(s) => s,
+        None => return Err(CustodyError::not_found("Alert subscription", subscription_id)),
+    };
+    
+    let _audit_entry = log_audit_event(
+        EventType::SystemConfiguration,
+        ResourceType::System,
+        subscription_id,
+        "unsubscribe_from_alerts".to_string(),
+        format!("Unsubscribed {} from alerts", subscription.subscriber_canister_id),
+        None,
+        true,
+        None,
+    )?;
+    
+    Ok("Unsubscribed from alerts".to_string())
+}
+
+#[query]
+fn list_alert_subscriptions() -> Vec<AlertSubscription> {
+    let caller = ic_cdk::caller();
+    
+    if !is_authorized_auditor(&caller) {
+        ic_cdk::println!("Unauthorized alert subscription access attempt from: {}", caller);
+        return Vec::new();
+    }
+    
+    ALERT_SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow().values().cloned().collect()
+    })
+}
+
+fn subscription_matches(subscription: &AlertSubscription, entry: &AuditEntry) -> bool {
+    entry_severity(entry) >= subscription.min_severity && matches_query(entry, &subscription.event_filter)
+}
+
+fn dispatch_alerts(entry: &AuditEntry) {
+    let subscribers: Vec<Principal> = ALERT_SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow()
+            .values()
+            .filter(|subscription| subscription_matches(subscription, entry))
+            .map(|subscription| subscription.subscriber_canister_id)
+            .collect()
+    });
+    
+    for subscriber in subscribers {
+        ic_cdk::spawn(notify_subscriber(subscriber, entry.clone()));
+    }
+}
+
+async fn notify_subscriber(subscriber: Principal, entry: AuditEntry) {
+    let result: Result<(), _> = ic_cdk::call(subscriber, "receive_audit_alert", (entry,)).await;
+    
+    if let Err((code, msg)) = result {
+        ic_cdk::println!("Failed to deliver audit alert to {}: {:?} {}", subscriber, code, msg);
+    }
+}
+
+// === Merkle Mountain Range ===
+//
+// Proofs are lists of "L:<hash>" / "R:<hash>" steps. Starting from the entry
+// hash, each step hashes the running value with the sibling on the given side,
+// so folding the whole proof yields the Merkle root.
+
+fn hash_merkle_pair(left: &str, right: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(left.as_bytes());
+    hasher.update(right.as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// Bags the peaks right to left into a single root hash.
+fn bag_merkle_peaks(peaks: &[String]) -> String {
+    let mut peaks_iter = peaks.iter().rev();
+    let mut root = match peaks_iter.next() {
+        Some(peak) => peak.clone(),
+        None => return String::new(),
+    };
+    for peak in peaks_iter {
+        root = hash_merkle_pair(peak, &root);
+    }
+    root
+}
+
+/// Pushes a leaf onto a list of peaks, merging peaks of equal height. Returns
+/// the left siblings that were merged with the new leaf, bottom up.
+fn push_merkle_leaf(peaks: &mut Vec<String>, heights: &mut Vec<u32>, leaf: String) -> Vec<String> {
+    let mut merged_with = Vec::new();
+    let mut node = leaf;
+    let mut height = 0;
+    
+    while heights.last() == Some(&height) {
+        let left = peaks.pop().unwrap_or_default();
+        heights.pop();
+        node = hash_merkle_pair(&left, &node);
+        merged_with.push(left);
+        height += 1;
+    }
+    
+    peaks.push(node);
+    heights.push(height);
+    merged_with
+}
+
+fn append_merkle_leaf(leaf_hash: &str) -> (u64, Vec<String>) {
+    let leaf_index = MERKLE_NODES.with(|nodes| {
+        let mut nodes = nodes.borrow_mut();
+        if nodes.is_empty() {
+            nodes.push(Vec::new());
+        }
+        nodes[0].push(leaf_hash.to_string());
+        
+        // Record every internal node created by the merge
+        let mut index = nodes[0].len() - 1;
+        let mut height = 0;
+        while index % 2 == 1 {
+            let parent = hash_merkle_pair(&nodes[height][index - 1], &nodes[height][index]);
+            if nodes.len() <= height + 1 {
+                nodes.push(Vec::new());
+            }
+            nodes[height + 1].push(parent);
+            index /= 2;
+            height += 1;
+        }
+        
+        nodes[0].len() as u64 - 1
+    });
+    
+    let proof = MERKLE_PEAKS.with(|peaks| {
+        let mut peaks = peaks.borrow_mut();
+        let mut heights = merkle_peak_heights(leaf_index);
+        let merged_with = push_merkle_leaf(&mut peaks, &mut heights, leaf_hash.to_string());
+        
+        // The new leaf always sits in the rightmost peak
+        let mut proof: Vec<String> = merged_with.into_iter().map(|h| format!("L:{}", h)).collect();
+        for peak in peaks[..peaks.len() - 1].iter().rev() {
+            proof.push(format!("L:{}", peak));
+        }
+        proof
+    });
+    
+    (leaf_index, proof)
+}
+
+/// Heights of the peaks of a mountain range holding `leaf_count` leaves, tallest first.
+fn merkle_peak_heights(leaf_count: u64) -> Vec<u32> {
+    (0..64u32).rev().filter(|bit| leaf_count & (1 << bit) != 0).collect()
+}
+
+fn build_merkle_proof(leaf_index: u64, leaf_hash: &str) -> Result<Vec<String>, String> {
+    MERKLE_NODES.with(|nodes| {
+        let nodes = nodes.borrow();
+        let leaf_count = nodes.first().map(|leaves| leaves.len() as u64).unwrap_or(0);
+        
+        if leaf_index >= leaf_count || nodes[0][leaf_index as usize] != leaf_hash {
+            return Err("Entry hash does not match the Merkle leaf".to_string());
+        }
+        
+        // Locate the peak whose subtree contains the leaf
+        let heights = merkle_peak_heights(leaf_count);
+        let mut peak_position = 0;
+        let mut subtree_start = 0u64;
+        for (position, height) in heights.iter().enumerate() {
+            let size = 1u64 << height;
+            if leaf_index < subtree_start + size {
+                peak_position = position;
+                break;
+            }
+            subtree_start += size;
+        }
+        
+        // Climb from the leaf to its peak
+        let mut proof = Vec::new();
+        let mut index = leaf_index as usize;
+        for height in 0..heights[peak_position] as usize {
+            let sibling = &nodes[height][index ^ 1];
+            if index % 2 == 1 {
+                proof.push(format!("L:{}", sibling));
+            } else {
+                proof.push(format!("R:{}", sibling));
+            }
+            index /= 2;
+        }
+        
+        // Then bag in the peaks to the right and left of it
+        let peaks = MERKLE_PEAKS.with(|peaks| peaks.borrow().clone());
+        if peak_position + 1 < peaks.len() {
+            proof.push(format!("R:{}", bag_merkle_peaks(&peaks[peak_position + 1..])));
+        }
+        for peak in peaks[..peak_position].iter().rev() {
+            proof.push(format!("L:{}", peak));
+        }
+        
+        Ok(proof)
+    })
+}
+
+fn fold_merkle_proof(leaf_hash: &str, proof: &[String]) -> Option<String> {
+    let mut current = leaf_hash.to_string();
+    for step in proof {
+        current = match step.split_once(':')? {
+            ("L", sibling) => hash_merkle_pair(sibling, &current),
+            ("R", sibling) => hash_merkle_pair(&current, sibling),
+            _ => return None,
+        };
+    }
+    Some(current)
+}
+
+fn proof_path_from_steps(steps: &[String]) -> Option<Vec<(String, ProofDirection)>> {
+    steps.iter()
+        .map(|step| match step.split_once(':')? {
+            ("L", sibling) => Some((sibling.to_string(), ProofDirection::Left)),
+            ("R", sibling) => Some((sibling.to_string(), ProofDirection::Right)),
+            _ => None,
+        })
+        .collect()
+}
+
+fn fold_proof_path(leaf_hash: &str, proof_path: &[(String, ProofDirection)]) -> String {
+    proof_path.iter().fold(leaf_hash.to_string(), |current, (sibling, direction)| match direction {
+        ProofDirection::Left => hash_merkle_pair(sibling, &current),
+        ProofDirection::Right => hash_merkle_pair(&current, sibling),
+    })
+}
+
+// === Helper Functions ===
+
+fn store_audit_entry(entry: AuditEntry) {
+    let entry_id = entry.id.clone();
+    
+    SORTED_ENTRY_IDS.with(|sorted| {
+        sorted.borrow_mut().insert(entry.merkle_leaf_index, entry.id.clone());
+    });
+    
+    index_audit_entry(&entry);
+    
+    let alerts_enabled = AUDIT_SETTINGS.with(|s| s.borrow().real_time_alerts_enabled);
+    if alerts_enabled {
+        dispatch_alerts(&entry);
+    }
+    
+    AUDIT_ENTRIES.with(|entries| {
+        entries.borrow_mut().insert(entry.id.clone(), entry);
+    });
+    
+    // Signing is an inter-canister call, so the entry is queued for the signing
+    // timer; this also keeps init and the upgrade hooks free of calls
+    let signatures_enabled = AUDIT_SETTINGS.with(|s| s.borrow().digital_signatures_enabled);
+    if signatures_enabled {
+        UNSIGNED_ENTRIES.with(|queue| queue.borrow_mut().push_back(entry_id));
+    }
+}
+
+fn index_audit_entry(entry: &AuditEntry) {
+    ACTOR_INDEX.with(|index| {
+        index.borrow_mut().entry(entry.actor).or_default().insert(entry.id.clone());
+    });
+    
+    RESOURCE_INDEX.with(|index| {
+        index.borrow_mut().entry(entry.resource_id.clone()).or_default().insert(entry.id.clone());
+    });
+    
+    if let Some(ref correlation_id) = entry.correlation_id {
+        CORRELATION_INDEX.with(|index| {
+            index.borrow_mut().entry(correlation_id.clone()).or_default().insert(entry.id.clone());
+        });
+    }
+}
+
+fn unindex_audit_entry(entry: &AuditEntry) {
+    ACTOR_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        if let Some(ids) = index.get_mut(&entry.actor) {
+            ids.remove(&entry.id);
+            if ids.is_empty() {
+                index.remove(&entry.actor);
+            }
+        }
+    });
+    
+    RESOURCE_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        if let Some(ids) = index.get_mut(&entry.resource_id) {
+            ids.remove(&entry.id);
+            if ids.is_empty() {
+                index.remove(&entry.resource_id);
+            }
+        }
+    });
+    
+    if let Some(ref correlation_id) = entry.correlation_id {
+        CORRELATION_INDEX.with(|index| {
+            let mut index = index.borrow_mut();
+            if let Some(ids) = index.get_mut(correlation_id) {
+                ids.remove(&entry.id);
+                if ids.is_empty() {
+                    index.remove(correlation_id);
+                }
+            }
+        });
+    }
+}
+
+fn audit_signing_key() -> EcdsaKeyId {
+    EcdsaKeyId {
+        curve: EcdsaCurve::Secp256k1,
+        name: ECDSA_KEY_NAME.to_string(),
+    }
+}
+
+fn audit_signing_derivation_path() -> Vec<Vec<u8>> {
+    vec![b"audit_entries".to_vec()]
+}
+
+fn start_signing_scheduler() {
+    ic_cdk_timers::set_timer_interval(SIGNING_INTERVAL, || ic_cdk::spawn(sign_queued_entries()));
+}
+
+async fn sign_queued_entries() {
+    let batch: Vec<String> = UNSIGNED_ENTRIES.with(|queue| {
+        let mut queue = queue.borrow_mut();
+        let batch_size = queue.len().min(MAX_SIGNATURES_PER_BATCH);
+        queue.drain(..batch_size).collect()
+    });
+    
+    for entry_id in batch {
+        if let Err(e) = sign_audit_entry(entry_id.clone()).await {
+            ic_cdk::println!("Signing audit entry {} failed: {}", entry_id, e);
+        }
+    }
+}
+
+async fn sign_audit_entry(entry_id: String) -> Result<String, String> {
+    let entry_hash = AUDIT_ENTRIES.with(|entries| {
+        entries.borrow().get(&entry_id).map(|entry| entry.hash.clone())
+    }).ok_or_else(|| "Audit entry not found".to_string())?;
+    
+    let signature = sign_hash(&entry_hash).await?;
+    
+    AUDIT_ENTRIES.with(|entries| {
+        if let Some(entry) = entries.borrow_mut().get_mut(&entry_id) {
+            entry.digital_signature = Some(signature.clone());
+        }
+    });
+    
+    Ok(signature)
+}
+
+async fn sign_hash(hash: &str) -> Result<String, String> {
+    let (response,) = sign_with_ecdsa(SignWithEcdsaArgument {
+        message_hash: hex::decode(hash)?,
+        derivation_path: audit_signing_derivation_path(),
+        key_id: audit_signing_key(),
+    })
+    .await
+    .map_err(|(code, msg)| format!("sign_with_ecdsa failed: {:?} {}", code, msg))?;
+    
+    Ok(hex::encode(&response.signature))
+}
+
+// Query endpoints can't call the auth canister, so they only see auditor
+// roles it has confirmed within the last minute
+fn is_authorized_auditor(principal: &Principal) -> bool {
+    AUDITORS.with(|auditors| {
+        auditors.borrow().contains_key(principal)
+    }) || has_cached_role(principal, "auditor")
+}
+
+async fn authorize_auditor(principal: Principal) -> bool {
+    is_authorized_auditor(&principal) || has_role(principal, "auditor").await
 }
 
 fn log_audit_access(actor: Principal, method: &str, resource_id: String) {
@@ -776,9 +2318,117 @@ fn log_audit_access(actor: Principal, method: &str, resource_id: String) {
         true,
     );
     
-    AUDIT_ENTRIES.with(|entries| {
-        entries.borrow_mut().insert(access_entry.id.clone(), access_entry);
-    });
+    store_audit_entry(access_entry);
+}
+
+// === Cycle Monitoring Functions ===
+
+#[query]
+fn check_cycle_balance() -> u64 {
+    cycles::check_cycle_balance()
+}
+
+#[query]
+fn get_cycle_stats() -> CycleStats {
+    cycles::get_cycle_stats()
+}
+
+#[query]
+fn get_memory_stats() -> MemoryStats {
+    memory::get_memory_stats()
+}
+
+fn notify_high_memory(stats: &MemoryStats) {
+    memory::log_memory_alert(stats);
+    
+    let entry = create_audit_entry(
+        EventType::SystemConfiguration,
+        ic_cdk::id(),
+        ResourceType::System,
+        ic_cdk::id().to_string(),
+        "high_memory_usage".to_string(),
+        format!(
+            "Heap utilization {}%: {} heap bytes, {} stable bytes",
+            stats.heap_utilization_percent, stats.heap_bytes, stats.stable_bytes,
+        ),
+        AuditMetadata::default(),
+        true,
+    );
+    
+    store_audit_entry(entry);
+}
+
+#[update]
+async fn set_cycle_alert_threshold(threshold: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_cycle_alert_threshold")?;
+    
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("set_cycle_alert_threshold"));
+    }
+    
+    cycles::set_cycle_alert_threshold(threshold);
+    
+    Ok("Cycle alert threshold updated successfully".to_string())
+}
+
+#[update]
+async fn set_cycle_alert_canister(alert_canister: Option<Principal>) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_cycle_alert_canister")?;
+    
+    if !authorize_auditor(caller).await {
+        return Err(CustodyError::unauthorized("set_cycle_alert_canister"));
+    }
+    
+    cycles::set_cycle_alert_canister(alert_canister);
+    
+    Ok("Cycle alert canister updated successfully".to_string())
+}
+
+// Receives low balance alerts forwarded by the other canisters' cycle monitors
+#[update]
+fn record_cycle_alert(canister_id: String, balance: u64, threshold: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "record_cycle_alert")?;
+    
+    // A canister may only report its own balance
+    if caller.to_string() != canister_id {
+        return Err(CustodyError::unauthorized("record_cycle_alert"));
+    }
+    
+    Ok(store_cycle_alert(caller, canister_id, balance, threshold))
+}
+
+fn notify_low_cycles(balance: u64, threshold: u64) {
+    store_cycle_alert(ic_cdk::id(), ic_cdk::id().to_string(), balance, threshold);
+    cycles::forward_cycle_alert(balance, threshold);
+}
+
+fn store_cycle_alert(actor: Principal, canister_id: String, balance: u64, threshold: u64) -> String {
+    let mut additional_context = BTreeMap::new();
+    additional_context.insert("severity".to_string(), "high".to_string());
+    
+    let metadata = AuditMetadata {
+        canister_id: Some(canister_id.clone()),
+        additional_context,
+        ..AuditMetadata::default()
+    };
+    
+    let entry = create_audit_entry(
+        EventType::SystemConfiguration,
+        actor,
+        ResourceType::System,
+        canister_id,
+        "low_cycle_balance".to_string(),
+        format!("Cycle balance {} is below alert threshold {}", balance, threshold),
+        metadata,
+        true,
+    );
+    
+    let entry_id = entry.id.clone();
+    store_audit_entry(entry);
+    entry_id
 }
 
 #[query]
@@ -786,5 +2436,283 @@ fn health_check() -> String {
     "Audit Trail canister is healthy".to_string()
 }
 
+const INTERFACE_VERSION: InterfaceVersion = (1, 0, 0);
+
+#[query]
+fn get_candid_interface_version() -> u32 {
+    INTERFACE_VERSION.0
+}
+
+#[query]
+fn get_interface_metadata() -> CandidVersion {
+    CandidVersion::new(INTERFACE_VERSION, &__export_service())
+}
+
 // Export Candid interface
 ic_cdk::export_candid!();
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    fn sample_entry() -> AuditEntry {
+        AuditEntry {
+            id: "AUDIT_1".to_string(),
+            timestamp: 1_000,
+            event_type: EventType::TransactionApproved,
+            actor: Principal::from_slice(&[1; 29]),
+            resource_type: ResourceType::Transaction,
+            resource_id: "TX_1".to_string(),
+            action: "approve".to_string(),
+            details: String::new(),
+            metadata: AuditMetadata {
+                ip_address: None,
+                user_agent: None,
+                session_id: None,
+                request_id: None,
+                canister_id: None,
+                method_name: None,
+                before_state: None,
+                after_state: None,
+                error_code: None,
+                additional_context: BTreeMap::new(),
+            },
+            hash: String::new(),
+            previous_hash: None,
+            compliance_relevant: true,
+            retention_until: None,
+            merkle_leaf_index: 0,
+            merkle_proof: Vec::new(),
+            digital_signature: None,
+            correlation_id: None,
+        }
+    }
+    
+    fn empty_query() -> AuditQuery {
+        AuditQuery {
+            event_types: None,
+            resource_types: None,
+            actors: None,
+            resource_ids: None,
+            start_time: None,
+            end_time: None,
+            compliance_relevant_only: false,
+            limit: None,
+            offset: None,
+        }
+    }
+    
+    #[test]
+    fn test_matches_query_filters() {
+        let entry = sample_entry();
+        assert!(matches_query(&entry, &empty_query()));
+        
+        let mut query = empty_query();
+        query.event_types = Some(vec![EventType::TransactionApproved]);
+        assert!(matches_query(&entry, &query));
+        query.event_types = Some(vec![EventType::AccountCreation]);
+        assert!(!matches_query(&entry, &query));
+        
+        let mut query = empty_query();
+        query.resource_types = Some(vec![ResourceType::Transaction]);
+        assert!(matches_query(&entry, &query));
+        query.resource_types = Some(vec![ResourceType::CustodyAccount]);
+        assert!(!matches_query(&entry, &query));
+        
+        let mut query = empty_query();
+        query.actors = Some(vec![entry.actor]);
+        assert!(matches_query(&entry, &query));
+        query.actors = Some(vec![Principal::from_slice(&[2; 29])]);
+        assert!(!matches_query(&entry, &query));
+        
+        let mut query = empty_query();
+        query.resource_ids = Some(vec!["TX_1".to_string()]);
+        assert!(matches_query(&entry, &query));
+        query.resource_ids = Some(vec!["TX_2".to_string()]);
+        assert!(!matches_query(&entry, &query));
+        
+        let mut query = empty_query();
+        query.start_time = Some(1_000);
+        query.end_time = Some(1_000);
+        assert!(matches_query(&entry, &query));
+        query.start_time = Some(1_001);
+        assert!(!matches_query(&entry, &query));
+        query.start_time = None;
+        query.end_time = Some(999);
+        assert!(!matches_query(&entry, &query));
+        
+        let mut query = empty_query();
+        query.compliance_relevant_only = true;
+        assert!(matches_query(&entry, &query));
+        let mut routine = sample_entry();
+        routine.compliance_relevant = false;
+        assert!(!matches_query(&routine, &query));
+    }
+    
+    #[test]
+    fn test_proof_path_folds_to_merkle_root() {
+        let leaves: Vec<String> = (0..5u8).map(|i| format!("{:064x}", i)).collect();
+        for leaf in &leaves {
+            append_merkle_leaf(leaf);
+        }
+        
+        for (index, leaf) in leaves.iter().enumerate() {
+            let steps = build_merkle_proof(index as u64, leaf).unwrap();
+            let proof_path = proof_path_from_steps(&steps).unwrap();
+            assert_eq!(fold_proof_path(leaf, &proof_path), get_merkle_root());
+        }
+        
+        let steps = build_merkle_proof(2, &leaves[2]).unwrap();
+        let proof_path = proof_path_from_steps(&steps).unwrap();
+        assert_ne!(fold_proof_path(&leaves[3], &proof_path), get_merkle_root());
+    }
+    
+    #[test]
+    fn test_actor_and_resource_indexes() {
+        let actor = Principal::from_slice(&[4; 29]);
+        // IDs sort differently from the order the entries were appended in
+        for (id, resource_id, leaf) in [("IDX_1", "TX_A", 2), ("IDX_2", "TX_B", 0), ("IDX_3", "TX_A", 1)] {
+            let mut entry = sample_entry();
+            entry.id = id.to_string();
+            entry.merkle_leaf_index = leaf;
+            entry.actor = actor;
+            entry.resource_id = resource_id.to_string();
+            index_audit_entry(&entry);
+            AUDIT_ENTRIES.with(|entries| entries.borrow_mut().insert(entry.id.clone(), entry));
+        }
+        
+        let ids = ACTOR_INDEX.with(|index| index.borrow().get(&actor).cloned().unwrap());
+        let page: Vec<String> = entries_from_index(&ids, 2, None).into_iter().map(|e| e.id).collect();
+        assert_eq!(page, vec!["IDX_2", "IDX_3"]);
+        let page: Vec<String> = entries_from_index(&ids, 2, Some("IDX_3".to_string())).into_iter().map(|e| e.id).collect();
+        assert_eq!(page, vec!["IDX_1"]);
+        
+        let mut query = empty_query();
+        query.actors = Some(vec![actor]);
+        query.resource_ids = Some(vec!["TX_A".to_string()]);
+        assert_eq!(indexed_candidates(&query).unwrap().len(), 2);
+        assert_eq!(collect_audit_entries(&query).len(), 2);
+        
+        let first = AUDIT_ENTRIES.with(|entries| entries.borrow().get("IDX_1").cloned().unwrap());
+        unindex_audit_entry(&first);
+        assert_eq!(indexed_candidates(&query).unwrap().len(), 1);
+    }
+    
+    #[test]
+    fn test_derive_correlation_id() {
+        let mut metadata = AuditMetadata::default();
+        assert_eq!(derive_correlation_id(&ResourceType::Transaction, "TX_1", &metadata), Some("TX_1".to_string()));
+        assert_eq!(derive_correlation_id(&ResourceType::CustodyAccount, "ACC_1", &metadata), None);
+        
+        metadata.request_id = Some("REQ_1".to_string());
+        assert_eq!(derive_correlation_id(&ResourceType::Transaction, "TX_1", &metadata), Some("REQ_1".to_string()));
+        
+        metadata.additional_context.insert("correlation_id".to_string(), "TX_ORIGIN".to_string());
+        assert_eq!(derive_correlation_id(&ResourceType::Transaction, "TX_1", &metadata), Some("TX_ORIGIN".to_string()));
+    }
+    
+    #[test]
+    fn test_apply_request_context() {
+        let canister = Principal::from_slice(&[1]);
+        let user = Principal::from_slice(&[2]);
+        
+        let mut metadata = AuditMetadata::default();
+        assert_eq!(apply_request_context(canister, &mut metadata, None), canister);
+        assert_eq!(metadata.canister_id, None);
+        
+        let context = RequestContext {
+            original_caller: user,
+            session_id: "SESSION_1".to_string(),
+            correlation_id: "TX_ORIGIN".to_string(),
+            initiated_at: 0,
+        };
+        
+        // An untrusted caller can't attribute entries to someone else
+        assert_eq!(apply_request_context(canister, &mut metadata, Some(context.clone())), canister);
+        assert_eq!(metadata.canister_id, None);
+        
+        context::set_trusted_canister(canister, true);
+        assert_eq!(apply_request_context(canister, &mut metadata, Some(context)), user);
+        assert_eq!(metadata.canister_id, Some(canister.to_text()));
+        assert_eq!(metadata.session_id.as_deref(), Some("SESSION_1"));
+        assert_eq!(derive_correlation_id(&ResourceType::CustodyAccount, "ACC_1", &metadata), Some("TX_ORIGIN".to_string()));
+    }
+    
+    #[test]
+    fn test_export_root_hash_covers_order_and_metadata() {
+        let mut first = sample_entry();
+        first.hash = "aa".to_string();
+        let mut second = sample_entry();
+        second.hash = "bb".to_string();
+        
+        let root = calculate_export_root_hash(&[first.clone(), second.clone()]);
+        assert_ne!(root, calculate_export_root_hash(&[second.clone(), first.clone()]));
+        
+        // Metadata and correlation IDs are covered as well as the entry hash
+        let mut relabelled = second.clone();
+        relabelled.correlation_id = Some("TX_OTHER".to_string());
+        assert_ne!(root, calculate_export_root_hash(&[first.clone(), relabelled]));
+        
+        let mut annotated = second;
+        annotated.metadata.session_id = Some("SESSION_2".to_string());
+        assert_ne!(root, calculate_export_root_hash(&[first, annotated]));
+    }
+    
+    #[test]
+    fn test_subscription_matches_filter_and_severity() {
+        let mut subscription = AlertSubscription {
+            id: "SUB_1".to_string(),
+            subscriber_canister_id: Principal::from_slice(&[3; 29]),
+            event_filter: empty_query(),
+            min_severity: AlertSeverity::High,
+        };
+        
+        let mut entry = sample_entry();
+        assert!(!subscription_matches(&subscription, &entry));
+        
+        entry.metadata.additional_context.insert("severity".to_string(), "Critical".to_string());
+        assert!(subscription_matches(&subscription, &entry));
+        
+        subscription.event_filter.event_types = Some(vec![EventType::EmergencyAction]);
+        assert!(!subscription_matches(&subscription, &entry));
+    }
+    
+    #[test]
+    fn test_build_report_export_aggregates_entries() {
+        let report = ComplianceReport {
+            id: "REPORT_1".to_string(),
+            report_type: ReportType::Regulatory,
+            period_start: 0,
+            period_end: 10_000,
+            generated_at: 10_000,
+            generated_by: Principal::from_slice(&[1; 29]),
+            entries_count: 3,
+            summary: String::new(),
+            hash: "abc".to_string(),
+            digital_signature: None,
+        };
+        
+        let mut risky = sample_entry();
+        risky.id = "AUDIT_2".to_string();
+        risky.timestamp = 500;
+        risky.metadata.additional_context.insert("risk_level".to_string(), "High".to_string());
+        risky.metadata.additional_context.insert("account_id".to_string(), "ACC_1".to_string());
+        
+        let mut kyc = sample_entry();
+        kyc.id = "AUDIT_3".to_string();
+        kyc.event_type = EventType::KycUpdate;
+        kyc.action = "approve_kyc_profile".to_string();
+        
+        let export = build_report_export(&report, vec![sample_entry(), risky, kyc], 20_000);
+        
+        assert_eq!(export.entries_by_event_type.get("TransactionApproved"), Some(&2));
+        assert_eq!(export.entries_by_event_type.get("KycUpdate"), Some(&1));
+        assert_eq!(export.high_risk_transactions.len(), 1);
+        assert_eq!(export.high_risk_transactions[0].entry_id, "AUDIT_2");
+        assert_eq!(export.flagged_accounts, vec!["ACC_1".to_string()]);
+        assert_eq!(export.kyc_approved_count, 1);
+        assert_eq!(export.sar_count, 0);
+        assert_eq!(export.entries[0].id, "AUDIT_2");
+        assert_eq!(export.report_metadata.report_type, "Regulatory");
+    }
+}
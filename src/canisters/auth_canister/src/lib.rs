@@ -0,0 +1,170 @@
+use candid::{CandidType, Principal};
+use ic_cdk_macros::{init, post_upgrade, query, update};
+use serde::{Deserialize, Serialize};
+use shared::{check_rate_limit, CustodyError};
+use shared::interface::{CandidVersion, InterfaceVersion};
+use shared::memory::{self, MemoryStats};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+// Principals holding `role_name` on the canister identified by `canister_id`
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct Role {
+    pub canister_id: String,
+    pub role_name: String,
+    pub principals: BTreeSet<Principal>,
+}
+
+thread_local! {
+    // (canister_id, role_name) -> role
+    static ROLES: RefCell<BTreeMap<(String, String), Role>> = const { RefCell::new(BTreeMap::new()) };
+    static AUTH_ADMINS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+#[init]
+fn init() {
+    ic_cdk::println!("Auth canister initialized");
+    
+    memory::start_memory_monitor(memory::log_memory_alert);
+    
+    // Initialize with deployer as admin
+    AUTH_ADMINS.with(|admins| {
+        admins.borrow_mut().insert(ic_cdk::caller());
+    });
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    memory::start_memory_monitor(memory::log_memory_alert);
+}
+
+// === Role Management Functions ===
+
+#[update]
+fn grant_role(canister_id: String, role_name: String, principal: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "grant_role")?;
+    
+    if !is_auth_admin(&caller) {
+        return Err(CustodyError::unauthorized("grant_role"));
+    }
+    
+    if canister_id.is_empty() {
+        return Err(CustodyError::invalid_input("canister_id", "cannot be empty"));
+    }
+    
+    if role_name.is_empty() {
+        return Err(CustodyError::invalid_input("role_name", "cannot be empty"));
+    }
+    
+    ROLES.with(|roles| {
+        roles.borrow_mut()
+            .entry((canister_id.clone(), role_name.clone()))
+            .or_insert_with(|| Role {
+                canister_id,
+                role_name,
+                principals: BTreeSet::new(),
+            })
+            .principals
+            .insert(principal);
+    });
+    
+    Ok("Role granted successfully".to_string())
+}
+
+#[update]
+fn revoke_role(canister_id: String, role_name: String, principal: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "revoke_role")?;
+    
+    if !is_auth_admin(&caller) {
+        return Err(CustodyError::unauthorized("revoke_role"));
+    }
+    
+    let key = (canister_id, role_name);
+    
+    ROLES.with(|roles| {
+        let mut roles = roles.borrow_mut();
+        let role = match roles.get_mut(&key) {
+            Some(role) => role,
+            None => return Err(CustodyError::not_found("Role", format!("{}:{}", key.0, key.1))),
+        };
+        
+        if !role.principals.remove(&principal) {
+            return Err(CustodyError::not_found("Role holder", principal.to_string()));
+        }
+        
+        if role.principals.is_empty() {
+            roles.remove(&key);
+        }
+        
+        Ok("Role revoked successfully".to_string())
+    })
+}
+
+#[update]
+fn add_auth_admin(admin: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_auth_admin")?;
+    
+    if !is_auth_admin(&caller) {
+        return Err(CustodyError::unauthorized("add_auth_admin"));
+    }
+    
+    AUTH_ADMINS.with(|admins| {
+        admins.borrow_mut().insert(admin);
+    });
+    
+    Ok("Admin added successfully".to_string())
+}
+
+// === Query Functions ===
+
+#[query]
+fn has_role(principal: Principal, canister_id: String, role_name: String) -> bool {
+    ROLES.with(|roles| {
+        roles.borrow()
+            .get(&(canister_id, role_name))
+            .map(|role| role.principals.contains(&principal))
+            .unwrap_or(false)
+    })
+}
+
+#[query]
+fn get_roles(canister_id: String) -> Vec<Role> {
+    ROLES.with(|roles| {
+        roles.borrow()
+            .values()
+            .filter(|role| role.canister_id == canister_id)
+            .cloned()
+            .collect()
+    })
+}
+
+fn is_auth_admin(principal: &Principal) -> bool {
+    AUTH_ADMINS.with(|admins| admins.borrow().contains(principal))
+}
+
+#[query]
+fn get_memory_stats() -> MemoryStats {
+    memory::get_memory_stats()
+}
+
+#[query]
+fn health_check() -> String {
+    "Auth canister is healthy".to_string()
+}
+
+const INTERFACE_VERSION: InterfaceVersion = (1, 0, 0);
+
+#[query]
+fn get_candid_interface_version() -> u32 {
+    INTERFACE_VERSION.0
+}
+
+#[query]
+fn get_interface_metadata() -> CandidVersion {
+    CandidVersion::new(INTERFACE_VERSION, &__export_service())
+}
+
+ic_cdk::export_candid!();
@@ -1,6 +1,22 @@
-use candid::CandidType;
-use ic_cdk_macros::{init, query, update};
+use candid::{CandidType, Principal};
+use ic_cdk::api::management_canister::bitcoin::{
+    bitcoin_get_current_fee_percentiles, bitcoin_get_utxos, bitcoin_send_transaction,
+    BitcoinNetwork, GetCurrentFeePercentilesRequest, GetUtxosRequest, SendTransactionRequest,
+    UtxoFilter,
+};
+use ic_cdk::api::management_canister::ecdsa::{
+    ecdsa_public_key, EcdsaCurve, EcdsaKeyId, EcdsaPublicKeyArgument,
+};
+use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
+use ripemd::Ripemd160;
 use serde::{Deserialize, Serialize};
+use shared::{check_rate_limit, CustodyError};
+use shared::hex;
+use shared::interface::{CandidVersion, InterfaceVersion};
+use shared::memory::{self, MemoryStats};
+use sha2::{Digest, Sha256};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
 
 #[derive(CandidType, Serialize, Deserialize)]
 pub struct BitcoinAddress {
@@ -8,25 +24,668 @@ pub struct BitcoinAddress {
     pub balance: u64,
 }
 
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct Utxo {
+    pub txid: String,
+    pub vout: u32,
+    pub value_satoshis: u64,
+    pub confirmations: u32,
+    pub address: String,
+    pub account_id: String,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    pub slow_satoshis_per_vbyte: u64,
+    pub normal_satoshis_per_vbyte: u64,
+    pub fast_satoshis_per_vbyte: u64,
+    pub retrieved_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum TransactionPriority {
+    Slow,
+    Normal,
+    Fast,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum BroadcastStatus {
+    Pending,
+    Confirmed(u32),
+    Failed(String),
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BroadcastRecord {
+    pub txid: String,
+    pub raw_tx_hex: String,
+    pub account_id: String,
+    pub broadcast_at: u64,
+    pub confirmations: u32,
+    pub status: BroadcastStatus,
+}
+
+// Configuration carried across upgrades in stable memory
+#[derive(CandidType, Serialize, Deserialize)]
+struct UpgradeConfig {
+    network: BitcoinNetwork,
+    ecdsa_key_name: String,
+    administrators: BTreeSet<Principal>,
+    broadcasters: BTreeSet<Principal>,
+    confirmation_threshold: u32,
+}
+
+// Threshold ECDSA key used to derive deposit addresses unless init names
+// another ("dfx_test_key" on a local replica, "test_key_1" on testnet)
+const DEFAULT_ECDSA_KEY_NAME: &str = "key_1";
+
+const HARDENED: u32 = 0x8000_0000;
+
+const FEE_CACHE_TTL_NANOS: u64 = 10 * 60 * 1_000_000_000;
+
+// Virtual size of a P2WPKH transaction with 1 input and 2 outputs
+const P2WPKH_1_IN_2_OUT_VBYTES: u64 = 141;
+
+thread_local! {
+    static NETWORK: RefCell<BitcoinNetwork> = const { RefCell::new(BitcoinNetwork::Mainnet) };
+    static ECDSA_KEY_NAME: RefCell<String> = RefCell::new(DEFAULT_ECDSA_KEY_NAME.to_string());
+    static DEPOSIT_ADDRESSES: RefCell<BTreeMap<String, String>> = const { RefCell::new(BTreeMap::new()) };
+    // Keyed by "txid:vout"
+    static UTXOS: RefCell<BTreeMap<String, Utxo>> = const { RefCell::new(BTreeMap::new()) };
+    // Last fee estimate and the time it was cached
+    static CACHED_FEE_ESTIMATE: RefCell<Option<(FeeEstimate, u64)>> = const { RefCell::new(None) };
+    static PENDING_BROADCASTS: RefCell<BTreeMap<String, BroadcastRecord>> = const { RefCell::new(BTreeMap::new()) };
+    static CONFIRMATION_THRESHOLD: RefCell<u32> = const { RefCell::new(6) };
+    static ADMINISTRATORS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+    // Canisters besides the administrators allowed to broadcast, such as custody_core
+    static BROADCASTERS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+}
+
 #[init]
-fn init() {
+fn init(network: Option<BitcoinNetwork>, ecdsa_key_name: Option<String>) {
     ic_cdk::println!("BTC Integration canister initialized");
+    
+    memory::start_memory_monitor(memory::log_memory_alert);
+    
+    // Initialize with deployer as administrator
+    ADMINISTRATORS.with(|admins| {
+        admins.borrow_mut().insert(ic_cdk::caller());
+    });
+    
+    apply_network_config(network, ecdsa_key_name);
+}
+
+#[pre_upgrade]
+fn pre_upgrade() {
+    let config = UpgradeConfig {
+        network: NETWORK.with(|n| *n.borrow()),
+        ecdsa_key_name: ECDSA_KEY_NAME.with(|k| k.borrow().clone()),
+        administrators: ADMINISTRATORS.with(|a| a.borrow().clone()),
+        broadcasters: BROADCASTERS.with(|b| b.borrow().clone()),
+        confirmation_threshold: CONFIRMATION_THRESHOLD.with(|t| *t.borrow()),
+    };
+    if let Err(e) = ic_cdk::storage::stable_save((config,)) {
+        ic_cdk::trap(&format!("Failed to save configuration: {}", e));
+    }
+}
+
+#[post_upgrade]
+fn post_upgrade(network: Option<BitcoinNetwork>, ecdsa_key_name: Option<String>) {
+    memory::start_memory_monitor(memory::log_memory_alert);
+    
+    match ic_cdk::storage::stable_restore::<(UpgradeConfig,)>() {
+        Ok((config,)) => {
+            NETWORK.with(|n| *n.borrow_mut() = config.network);
+            ECDSA_KEY_NAME.with(|k| *k.borrow_mut() = config.ecdsa_key_name);
+            ADMINISTRATORS.with(|a| *a.borrow_mut() = config.administrators);
+            BROADCASTERS.with(|b| *b.borrow_mut() = config.broadcasters);
+            CONFIRMATION_THRESHOLD.with(|t| *t.borrow_mut() = config.confirmation_threshold);
+        }
+        Err(e) => {
+            // Upgrading from a version that saved nothing; keep the upgrader as admin
+            ic_cdk::println!("No saved configuration restored: {}", e);
+            ADMINISTRATORS.with(|admins| {
+                admins.borrow_mut().insert(ic_cdk::caller());
+            });
+        }
+    }
+    
+    // Upgrade arguments, when given, override the saved configuration
+    apply_network_config(network, ecdsa_key_name);
+}
+
+fn apply_network_config(network: Option<BitcoinNetwork>, ecdsa_key_name: Option<String>) {
+    if let Some(network) = network {
+        NETWORK.with(|n| *n.borrow_mut() = network);
+    }
+    
+    if let Some(key_name) = ecdsa_key_name {
+        ECDSA_KEY_NAME.with(|k| *k.borrow_mut() = key_name);
+    }
+}
+
+// === Address Functions ===
+
+#[update]
+async fn generate_address(account_id: String) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "generate_address")?;
+    
+    derive_deposit_address(account_id).await
 }
 
 #[update]
-fn generate_address(account_id: String) -> String {
-    format!("bc1q{}example", &account_id[..8])
+async fn get_deposit_address(account_id: String) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "get_deposit_address")?;
+    
+    derive_deposit_address(account_id).await
+}
+
+async fn derive_deposit_address(account_id: String) -> Result<String, CustodyError> {
+    if account_id.is_empty() {
+        return Err(CustodyError::invalid_input("account_id", "cannot be empty"));
+    }
+    
+    // Avoid a threshold key call when the address was already derived
+    let cached = DEPOSIT_ADDRESSES.with(|addresses| {
+        addresses.borrow().get(&account_id).cloned()
+    });
+    
+    if let Some(address) = cached {
+        return Ok(address);
+    }
+    
+    let (response,) = ecdsa_public_key(EcdsaPublicKeyArgument {
+        canister_id: None,
+        derivation_path: account_derivation_path(&account_id),
+        key_id: EcdsaKeyId {
+            curve: EcdsaCurve::Secp256k1,
+            name: ECDSA_KEY_NAME.with(|k| k.borrow().clone()),
+        },
+    })
+    .await
+    .map_err(|(code, msg)| CustodyError::InternalError(format!("ecdsa_public_key failed: {:?} {}", code, msg)))?;
+    
+    let network = NETWORK.with(|n| *n.borrow());
+    let address = p2wpkh_address(&response.public_key, network)?;
+    
+    DEPOSIT_ADDRESSES.with(|addresses| {
+        addresses.borrow_mut().insert(account_id, address.clone());
+    });
+    
+    Ok(address)
 }
 
 #[query]
-fn get_balance(address: String) -> u64 {
+fn get_balance(_address: String) -> u64 {
     // Mock balance - in production, integrate with Bitcoin network
     100_000_000 // 1 BTC
 }
 
+// === UTXO Functions ===
+
+#[update]
+async fn refresh_utxos(account_id: String) -> Result<Vec<Utxo>, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "refresh_utxos")?;
+    
+    let address = derive_deposit_address(account_id.clone()).await?;
+    let fetched = fetch_address_utxos(&address, &account_id).await?;
+    
+    // Reconcile: drop spent outputs for this account, then add or update the current set
+    let current_keys: BTreeSet<String> = fetched.iter()
+        .map(|u| utxo_key(&u.txid, u.vout))
+        .collect();
+    
+    UTXOS.with(|utxos| {
+        let mut utxos = utxos.borrow_mut();
+        utxos.retain(|key, utxo| utxo.account_id != account_id || current_keys.contains(key));
+        for utxo in &fetched {
+            utxos.insert(utxo_key(&utxo.txid, utxo.vout), utxo.clone());
+        }
+    });
+    
+    Ok(fetched)
+}
+
+#[query]
+fn select_utxos(account_id: String, target_amount: u64) -> Result<Vec<Utxo>, CustodyError> {
+    if target_amount == 0 {
+        return Err(CustodyError::invalid_input("target_amount", "must be greater than zero"));
+    }
+    
+    let mut available: Vec<Utxo> = UTXOS.with(|utxos| {
+        utxos.borrow()
+            .values()
+            .filter(|u| u.account_id == account_id)
+            .cloned()
+            .collect()
+    });
+    
+    // A single output covering the target is always the fewest inputs; pick the
+    // smallest such output to keep change low
+    if let Some(single) = available.iter()
+        .filter(|u| u.value_satoshis >= target_amount)
+        .min_by_key(|u| u.value_satoshis)
+    {
+        return Ok(vec![single.clone()]);
+    }
+    
+    // Otherwise take the largest outputs first
+    available.sort_by_key(|u| std::cmp::Reverse(u.value_satoshis));
+    
+    let mut selected = Vec::new();
+    let mut total = 0u64;
+    
+    for utxo in available {
+        total = total.saturating_add(utxo.value_satoshis);
+        selected.push(utxo);
+        if total >= target_amount {
+            return Ok(selected);
+        }
+    }
+    
+    Err(CustodyError::InsufficientBalance { available: total, required: target_amount })
+}
+
+// === Broadcast Functions ===
+
+#[update]
+async fn broadcast_transaction(
+    raw_tx_hex: String,
+    account_id: String,
+    expected_txid: String,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "broadcast_transaction")?;
+    
+    if !is_administrator(caller) && !BROADCASTERS.with(|b| b.borrow().contains(&caller)) {
+        return Err(CustodyError::unauthorized("broadcast_transaction"));
+    }
+    
+    let transaction = hex::decode(&raw_tx_hex)
+        .map_err(|e| CustodyError::invalid_input("raw_tx_hex", e))?;
+    
+    let expected_txid = expected_txid.to_ascii_lowercase();
+    let txid = compute_txid(&transaction)?;
+    if txid != expected_txid {
+        return Err(CustodyError::invalid_input(
+            "expected_txid",
+            format!("transaction has txid {}, expected {}", txid, expected_txid),
+        ));
+    }
+    
+    // Only a failed broadcast may be retried; anything else keeps its record
+    let existing = PENDING_BROADCASTS.with(|broadcasts| {
+        broadcasts.borrow().get(&txid).map(|record| record.status.clone())
+    });
+    if let Some(status @ (BroadcastStatus::Pending | BroadcastStatus::Confirmed(_))) = existing {
+        return Err(CustodyError::status_conflict(format!("{:?}", status), "Failed"));
+    }
+    
+    let network = NETWORK.with(|n| *n.borrow());
+    
+    let status = match bitcoin_send_transaction(SendTransactionRequest { transaction, network }).await {
+        Ok(()) => BroadcastStatus::Pending,
+        Err((code, msg)) => BroadcastStatus::Failed(format!("{:?} {}", code, msg)),
+    };
+    
+    let record = BroadcastRecord {
+        txid: expected_txid.clone(),
+        raw_tx_hex,
+        account_id,
+        broadcast_at: ic_cdk::api::time(),
+        confirmations: 0,
+        status: status.clone(),
+    };
+    
+    PENDING_BROADCASTS.with(|broadcasts| {
+        broadcasts.borrow_mut().insert(expected_txid.clone(), record);
+    });
+    
+    match status {
+        BroadcastStatus::Failed(reason) => Err(CustodyError::InternalError(format!("bitcoin_send_transaction failed: {}", reason))),
+        _ => Ok(expected_txid),
+    }
+}
+
+#[update]
+async fn check_transaction_confirmations(txid: String) -> Result<u32, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "check_transaction_confirmations")?;
+    
+    let record = PENDING_BROADCASTS.with(|broadcasts| {
+        broadcasts.borrow().get(&txid).cloned()
+    });
+    
+    let record = match record {
+        Some(r) => r,
+        None => return Err(CustodyError::not_found("Broadcast", txid)),
+    };
+    
+    if let BroadcastStatus::Failed(reason) = record.status {
+        return Err(CustodyError::status_conflict(format!("Failed({})", reason), "Pending or Confirmed"));
+    }
+    
+    // Confirmation depth is read from the transaction's change output at the account address
+    let address = derive_deposit_address(record.account_id.clone()).await?;
+    let utxos = fetch_address_utxos(&address, &record.account_id).await?;
+    
+    let confirmations = utxos.iter()
+        .filter(|u| u.txid == txid)
+        .map(|u| u.confirmations)
+        .max()
+        .unwrap_or(0);
+    
+    let threshold = CONFIRMATION_THRESHOLD.with(|t| *t.borrow());
+    
+    PENDING_BROADCASTS.with(|broadcasts| {
+        if let Some(record) = broadcasts.borrow_mut().get_mut(&txid) {
+            record.confirmations = confirmations;
+            if confirmations >= threshold {
+                record.status = BroadcastStatus::Confirmed(confirmations);
+            }
+        }
+    });
+    
+    Ok(confirmations)
+}
+
+#[query]
+fn get_broadcast(txid: String) -> Option<BroadcastRecord> {
+    PENDING_BROADCASTS.with(|broadcasts| {
+        broadcasts.borrow().get(&txid).cloned()
+    })
+}
+
+#[update]
+fn set_broadcaster(canister: Principal, allowed: bool) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_broadcaster")?;
+    
+    if !is_administrator(caller) {
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    BROADCASTERS.with(|broadcasters| {
+        let mut broadcasters = broadcasters.borrow_mut();
+        if allowed {
+            broadcasters.insert(canister);
+        } else {
+            broadcasters.remove(&canister);
+        }
+    });
+    
+    Ok("Broadcaster updated successfully".to_string())
+}
+
+#[update]
+fn set_confirmation_threshold(threshold: u32) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_confirmation_threshold")?;
+    
+    if !is_administrator(caller) {
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    if threshold == 0 {
+        return Err(CustodyError::invalid_input("threshold", "must be at least 1"));
+    }
+    
+    CONFIRMATION_THRESHOLD.with(|t| {
+        *t.borrow_mut() = threshold;
+    });
+    
+    Ok("Confirmation threshold updated successfully".to_string())
+}
+
+// === Fee Functions ===
+
+#[update]
+async fn get_fee_estimate() -> Result<FeeEstimate, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "get_fee_estimate")?;
+    
+    let current_time = ic_cdk::api::time();
+    
+    let cached = CACHED_FEE_ESTIMATE.with(|cache| cache.borrow().clone());
+    if let Some((estimate, cached_at)) = cached {
+        if current_time.saturating_sub(cached_at) < FEE_CACHE_TTL_NANOS {
+            return Ok(estimate);
+        }
+    }
+    
+    let network = NETWORK.with(|n| *n.borrow());
+    
+    let (percentiles,) = bitcoin_get_current_fee_percentiles(GetCurrentFeePercentilesRequest { network })
+        .await
+        .map_err(|(code, msg)| CustodyError::InternalError(format!("bitcoin_get_current_fee_percentiles failed: {:?} {}", code, msg)))?;
+    
+    // Percentiles are in millisatoshi per vbyte; regtest returns none
+    let estimate = if percentiles.is_empty() {
+        FeeEstimate {
+            retrieved_at: current_time,
+            ..default_fee_estimate()
+        }
+    } else {
+        let rate_at = |percentile: usize| {
+            let index = (percentiles.len() - 1) * percentile / 100;
+            std::cmp::max(percentiles[index] / 1000, 1)
+        };
+        FeeEstimate {
+            slow_satoshis_per_vbyte: rate_at(25),
+            normal_satoshis_per_vbyte: rate_at(50),
+            fast_satoshis_per_vbyte: rate_at(75),
+            retrieved_at: current_time,
+        }
+    };
+    
+    CACHED_FEE_ESTIMATE.with(|cache| {
+        *cache.borrow_mut() = Some((estimate.clone(), current_time));
+    });
+    
+    Ok(estimate)
+}
+
+#[query]
+fn estimate_withdrawal_fee(_amount: u64, priority: TransactionPriority) -> u64 {
+    // The fee depends on transaction size, not the amount sent
+    let estimate = CACHED_FEE_ESTIMATE.with(|cache| {
+        cache.borrow().as_ref().map(|(estimate, _)| estimate.clone())
+    }).unwrap_or_else(default_fee_estimate);
+    
+    let rate = match priority {
+        TransactionPriority::Slow => estimate.slow_satoshis_per_vbyte,
+        TransactionPriority::Normal => estimate.normal_satoshis_per_vbyte,
+        TransactionPriority::Fast => estimate.fast_satoshis_per_vbyte,
+    };
+    
+    rate * P2WPKH_1_IN_2_OUT_VBYTES
+}
+
+// === Helper Functions ===
+
+fn default_fee_estimate() -> FeeEstimate {
+    FeeEstimate {
+        slow_satoshis_per_vbyte: 1,
+        normal_satoshis_per_vbyte: 2,
+        fast_satoshis_per_vbyte: 5,
+        retrieved_at: 0,
+    }
+}
+
+async fn fetch_address_utxos(address: &str, account_id: &str) -> Result<Vec<Utxo>, CustodyError> {
+    let network = NETWORK.with(|n| *n.borrow());
+    
+    let mut fetched = Vec::new();
+    let mut filter = None;
+    
+    loop {
+        let (response,) = bitcoin_get_utxos(GetUtxosRequest {
+            address: address.to_string(),
+            network,
+            filter,
+        })
+        .await
+        .map_err(|(code, msg)| CustodyError::InternalError(format!("bitcoin_get_utxos failed: {:?} {}", code, msg)))?;
+        
+        for utxo in response.utxos {
+            fetched.push(Utxo {
+                txid: txid_to_hex(&utxo.outpoint.txid),
+                vout: utxo.outpoint.vout,
+                value_satoshis: utxo.value,
+                confirmations: response.tip_height.saturating_sub(utxo.height) + 1,
+                address: address.to_string(),
+                account_id: account_id.to_string(),
+            });
+        }
+        
+        match response.next_page {
+            Some(page) => filter = Some(UtxoFilter::Page(page)),
+            None => break,
+        }
+    }
+    
+    Ok(fetched)
+}
+
+/// Double SHA-256 of the transaction without its witness data, in display
+/// order. Segwit transactions are stripped back to the legacy serialization.
+fn compute_txid(transaction: &[u8]) -> Result<String, CustodyError> {
+    let mut reader = TxReader { bytes: transaction, position: 0 };
+    
+    let version = reader.take(4)?;
+    let segwit = reader.peek(2) == Some(&[0x00, 0x01][..]);
+    if segwit {
+        reader.take(2)?;
+    }
+    
+    let body_start = reader.position;
+    let input_count = reader.var_int()?;
+    for _ in 0..input_count {
+        reader.take(36)?;
+        let script_length = reader.var_int()?;
+        reader.take(script_length)?;
+        reader.take(4)?;
+    }
+    let output_count = reader.var_int()?;
+    for _ in 0..output_count {
+        reader.take(8)?;
+        let script_length = reader.var_int()?;
+        reader.take(script_length)?;
+    }
+    let body = &transaction[body_start..reader.position];
+    
+    if segwit {
+        for _ in 0..input_count {
+            let item_count = reader.var_int()?;
+            for _ in 0..item_count {
+                let item_length = reader.var_int()?;
+                reader.take(item_length)?;
+            }
+        }
+    }
+    
+    let lock_time = reader.take(4)?;
+    if reader.position != transaction.len() {
+        return Err(CustodyError::invalid_input("raw_tx_hex", "trailing bytes after transaction"));
+    }
+    
+    let mut hasher = Sha256::new();
+    hasher.update(version);
+    hasher.update(body);
+    hasher.update(lock_time);
+    Ok(txid_to_hex(&Sha256::digest(hasher.finalize())))
+}
+
+struct TxReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> TxReader<'a> {
+    fn peek(&self, length: usize) -> Option<&'a [u8]> {
+        self.bytes.get(self.position..self.position.checked_add(length)?)
+    }
+    
+    fn take(&mut self, length: usize) -> Result<&'a [u8], CustodyError> {
+        let taken = self.peek(length)
+            .ok_or_else(|| CustodyError::invalid_input("raw_tx_hex", "truncated transaction"))?;
+        self.position += length;
+        Ok(taken)
+    }
+    
+    fn var_int(&mut self) -> Result<usize, CustodyError> {
+        let value = match self.take(1)?[0] {
+            0xfd => u16::from_le_bytes(self.take(2)?.try_into().unwrap()) as u64,
+            0xfe => u32::from_le_bytes(self.take(4)?.try_into().unwrap()) as u64,
+            0xff => u64::from_le_bytes(self.take(8)?.try_into().unwrap()),
+            n => n as u64,
+        };
+        usize::try_from(value)
+            .map_err(|_| CustodyError::invalid_input("raw_tx_hex", "length out of range"))
+    }
+}
+
+fn is_administrator(principal: Principal) -> bool {
+    ADMINISTRATORS.with(|admins| admins.borrow().contains(&principal))
+}
+
+fn utxo_key(txid: &str, vout: u32) -> String {
+    format!("{}:{}", txid, vout)
+}
+
+/// The Bitcoin API returns txids in internal byte order; display order is reversed.
+fn txid_to_hex(txid: &[u8]) -> String {
+    txid.iter().rev().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// m/44'/0' as big-endian hardened indices, followed by the account id itself.
+/// Threshold ECDSA takes arbitrary bytes as path components, so every account
+/// keeps the same address across upgrades without an index to persist.
+fn account_derivation_path(account_id: &str) -> Vec<Vec<u8>> {
+    vec![
+        (44 | HARDENED).to_be_bytes().to_vec(),
+        HARDENED.to_be_bytes().to_vec(),
+        account_id.as_bytes().to_vec(),
+    ]
+}
+
+fn p2wpkh_address(public_key: &[u8], network: BitcoinNetwork) -> Result<String, CustodyError> {
+    if public_key.len() != 33 {
+        return Err(CustodyError::InternalError("Expected a 33-byte compressed public key".to_string()));
+    }
+    
+    let sha = Sha256::digest(public_key);
+    let witness_program = Ripemd160::digest(sha);
+    
+    let hrp = match network {
+        BitcoinNetwork::Mainnet => bech32::hrp::BC,
+        BitcoinNetwork::Testnet => bech32::hrp::TB,
+        BitcoinNetwork::Regtest => bech32::hrp::BCRT,
+    };
+    
+    bech32::segwit::encode_v0(hrp, &witness_program)
+        .map_err(|e| CustodyError::InternalError(format!("Failed to encode address: {}", e)))
+}
+
+#[query]
+fn get_memory_stats() -> MemoryStats {
+    memory::get_memory_stats()
+}
+
 #[query]
 fn health_check() -> String {
     "BTC Integration canister is healthy".to_string()
 }
 
+const INTERFACE_VERSION: InterfaceVersion = (1, 0, 0);
+
+#[query]
+fn get_candid_interface_version() -> u32 {
+    INTERFACE_VERSION.0
+}
+
+#[query]
+fn get_interface_metadata() -> CandidVersion {
+    CandidVersion::new(INTERFACE_VERSION, &__export_service())
+}
+
 ic_cdk::export_candid!();
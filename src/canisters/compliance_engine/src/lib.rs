@@ -1,8 +1,17 @@
 use candid::{CandidType, Principal};
 use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use shared::cycles::{self, CycleStats};
+use shared::auth::{has_role, set_auth_canister};
+use shared::events::{self, CanisterEvent, KycApprovedEvent, SarFiledEvent, TransactionExecutedEvent};
+use shared::context::{self, verified_request_context};
+use shared::{check_rate_limit, inject_request_context, CustodyError, RequestContext};
+use shared::interface::{CandidVersion, InterfaceVersion};
+use shared::memory::{self, MemoryStats};
 use std::collections::{BTreeMap, BTreeSet};
 use std::cell::RefCell;
+use std::time::Duration;
 use uuid::Uuid;
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
@@ -19,13 +28,15 @@ pub struct KycProfile {
     pub aml_status: AmlStatus,
     pub created_at: u64,
     pub last_updated: u64,
+    // Set on approval; the approval lapses KYC_VALIDITY_DAYS later
+    pub approved_at: Option<u64>,
     pub documents: Vec<Document>,
     pub sanctions_check: Option<SanctionsCheck>,
     pub pep_check: Option<PepCheck>,
     pub adverse_media_check: Option<AdverseMediaCheck>,
 }
 
-#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
 pub enum EntityType {
     Individual,
     Corporation,
@@ -45,7 +56,8 @@ pub enum VerificationLevel {
     Government,
 }
 
-#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+// Variants are ordered from least to most risky
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum RiskLevel {
     Low,
     Medium,
@@ -84,9 +96,10 @@ pub struct Document {
     pub verified_at: Option<u64>,
     pub verification_status: DocumentStatus,
     pub metadata: String,
+    pub document_access_level: DocumentAccessLevel,
 }
 
-#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
 pub enum DocumentType {
     IdentityDocument,
     ProofOfAddress,
@@ -108,6 +121,20 @@ pub enum DocumentStatus {
     Expired,
 }
 
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum DocumentAccessLevel {
+    Public,
+    ComplianceOnly,
+    SeniorComplianceOnly,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum OfficerRole {
+    Junior,
+    Senior,
+    Admin,
+}
+
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct SanctionsCheck {
     pub checked_at: u64,
@@ -126,7 +153,8 @@ pub enum SanctionsResult {
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct SanctionsMatch {
     pub list_name: String,
-    pub match_score: f64,
+    /// 0-100, where 100 is an exact match on every token of the listed name
+    pub match_score: u8,
     pub matched_text: String,
     pub reference: String,
 }
@@ -150,7 +178,8 @@ pub struct PepMatch {
     pub name: String,
     pub position: String,
     pub country: String,
-    pub match_score: f64,
+    /// Basis points, 10000 = 100%
+    pub match_score: u32,
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
@@ -173,7 +202,8 @@ pub struct MediaArticle {
     pub title: String,
     pub source: String,
     pub date: u64,
-    pub relevance_score: f64,
+    /// Basis points, 10000 = 100%
+    pub relevance_score: u32,
     pub sentiment: String,
 }
 
@@ -191,6 +221,9 @@ pub struct TransactionMonitoring {
     pub reviewed_by: Option<Principal>,
     pub reviewed_at: Option<u64>,
     pub notes: Option<String>,
+    // User behind the transaction, not the canister that reported it
+    pub initiated_by: Principal,
+    pub correlation_id: String,
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
@@ -240,6 +273,98 @@ pub enum SarStatus {
     Closed,
 }
 
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum DeadlineType {
+    SarFiling,
+    KycRenewal,
+    DocumentRenewal,
+    AmlReview,
+    RegulatorResponse,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum DeadlineStatus {
+    Open,
+    Completed,
+    Overdue,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct ComplianceDeadline {
+    pub id: String,
+    pub deadline_type: DeadlineType,
+    pub due_at: u64,
+    // SAR, KYC profile or document the obligation belongs to
+    pub related_id: String,
+    pub responsible_officer: Option<Principal>,
+    pub status: DeadlineStatus,
+    pub reminder_sent: bool,
+}
+
+// Subset of the audit_trail canister's EventType and ResourceType variants
+// used when escalating deadlines and recording approvals and filings
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum AuditEventType {
+    ComplianceCheck,
+    KycUpdate,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum AuditResourceType {
+    KycProfile,
+    ComplianceReport,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct SanctionsEntry {
+    pub name: String,
+    pub aliases: Vec<String>,
+    pub entity_type: String,
+    pub identifiers: Vec<String>,
+    pub program: String,
+    pub effective_date: u64,
+}
+
+// State of a profile before a change, kept so earlier states can be audited
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct KycProfileVersion {
+    pub version: u32,
+    pub profile_snapshot: KycProfile,
+    pub changed_at: u64,
+    pub changed_by: Principal,
+    pub change_reason: String,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct JurisdictionRule {
+    pub jurisdiction: String,
+    pub base_risk: RiskLevel,
+    // Empty permits every entity type
+    pub allowed_entity_types: Vec<EntityType>,
+    pub required_document_types: Vec<DocumentType>,
+    pub enhanced_due_diligence: bool,
+    pub max_transaction_amount: Option<u64>,
+}
+
+// Arguments of create_kyc_profile, for batch onboarding
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct KycCreateRequest {
+    pub principal: Principal,
+    pub entity_type: EntityType,
+    pub legal_name: String,
+    pub jurisdiction: String,
+    pub registration_number: Option<String>,
+}
+
+// How a token of a sanctioned name is compared with a token of a legal name
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum MatchMode {
+    Exact,
+    Substring,
+    // Tokens within this many single-character edits of each other match
+    FuzzyLevenshtein(u32),
+}
+
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct ComplianceSettings {
     pub auto_kyc_enabled: bool,
@@ -251,15 +376,29 @@ pub struct ComplianceSettings {
     pub sar_threshold: u64,
     pub kyc_renewal_days: u32,
     pub document_retention_days: u32,
+    pub sanctions_match_mode: MatchMode,
 }
 
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+// SARs must be filed within 30 days of the activity being detected
+const SAR_FILING_DAYS: u64 = 30;
+// Open deadlines get a reminder this many days before they fall due
+const DEADLINE_REMINDER_DAYS: u64 = 7;
+const DEADLINE_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+// Approved profiles must be re-approved after a year
+const KYC_VALIDITY_DAYS: u64 = 365;
+
 thread_local! {
-    static KYC_PROFILES: RefCell<BTreeMap<String, KycProfile>> = RefCell::new(BTreeMap::new());
-    static PRINCIPAL_TO_KYC: RefCell<BTreeMap<Principal, String>> = RefCell::new(BTreeMap::new());
-    static TRANSACTION_MONITORING: RefCell<BTreeMap<String, TransactionMonitoring>> = RefCell::new(BTreeMap::new());
-    static SAR_REPORTS: RefCell<BTreeMap<String, SuspiciousActivityReport>> = RefCell::new(BTreeMap::new());
-    static COMPLIANCE_OFFICERS: RefCell<BTreeSet<Principal>> = RefCell::new(BTreeSet::new());
-    static COMPLIANCE_SETTINGS: RefCell<ComplianceSettings> = RefCell::new(ComplianceSettings {
+    static KYC_PROFILES: RefCell<BTreeMap<String, KycProfile>> = const { RefCell::new(BTreeMap::new()) };
+    static PRINCIPAL_TO_KYC: RefCell<BTreeMap<Principal, String>> = const { RefCell::new(BTreeMap::new()) };
+    // Oldest first, capped at MAX_KYC_HISTORY_VERSIONS per profile
+    static KYC_HISTORY: RefCell<BTreeMap<String, Vec<KycProfileVersion>>> = const { RefCell::new(BTreeMap::new()) };
+    static TRANSACTION_MONITORING: RefCell<BTreeMap<String, TransactionMonitoring>> = const { RefCell::new(BTreeMap::new()) };
+    static SAR_REPORTS: RefCell<BTreeMap<String, SuspiciousActivityReport>> = const { RefCell::new(BTreeMap::new()) };
+    static COMPLIANCE_OFFICERS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+    // Officers without an entry are treated as Junior
+    static COMPLIANCE_OFFICER_ROLES: RefCell<BTreeMap<Principal, OfficerRole>> = const { RefCell::new(BTreeMap::new()) };
+    static COMPLIANCE_SETTINGS: RefCell<ComplianceSettings> = const { RefCell::new(ComplianceSettings {
         auto_kyc_enabled: false,
         sanctions_screening_enabled: true,
         pep_screening_enabled: true,
@@ -269,19 +408,36 @@ thread_local! {
         sar_threshold: 10_000_000_000, // 100 BTC
         kyc_renewal_days: 365,
         document_retention_days: 2555, // 7 years
-    });
-    static SANCTIONED_ENTITIES: RefCell<BTreeSet<String>> = RefCell::new(BTreeSet::new());
-    static HIGH_RISK_JURISDICTIONS: RefCell<BTreeSet<String>> = RefCell::new(BTreeSet::new());
+        sanctions_match_mode: MatchMode::FuzzyLevenshtein(1),
+    }) };
+    static SANCTIONED_ENTITIES: RefCell<BTreeSet<String>> = const { RefCell::new(BTreeSet::new()) };
+    // Keyed by sanctions_entry_key of the entry name
+    static SANCTIONS_DATABASE: RefCell<BTreeMap<String, SanctionsEntry>> = const { RefCell::new(BTreeMap::new()) };
+    // list name -> keys of the entries imported from it
+    static SANCTIONS_LISTS: RefCell<BTreeMap<String, BTreeSet<String>>> = const { RefCell::new(BTreeMap::new()) };
+    static HIGH_RISK_JURISDICTIONS: RefCell<BTreeSet<String>> = const { RefCell::new(BTreeSet::new()) };
+    static JURISDICTION_RULES: RefCell<BTreeMap<String, JurisdictionRule>> = const { RefCell::new(BTreeMap::new()) };
+    static COMPLIANCE_CALENDAR: RefCell<BTreeMap<String, ComplianceDeadline>> = const { RefCell::new(BTreeMap::new()) };
+    static AUDIT_TRAIL_CANISTER: RefCell<Option<Principal>> = const { RefCell::new(None) };
+    static EVENT_BUS_CANISTER: RefCell<Option<Principal>> = const { RefCell::new(None) };
 }
 
 #[init]
-fn init() {
+fn init(auth_canister: Option<Principal>) {
     ic_cdk::println!("Compliance Engine canister initialized");
     
+    set_auth_canister(auth_canister);
+    cycles::start_cycle_monitor(cycles::forward_cycle_alert);
+    memory::start_memory_monitor(notify_high_memory);
+    start_deadline_monitor();
+    
     // Initialize with deployer as compliance officer
     COMPLIANCE_OFFICERS.with(|officers| {
         officers.borrow_mut().insert(ic_cdk::caller());
     });
+    COMPLIANCE_OFFICER_ROLES.with(|roles| {
+        roles.borrow_mut().insert(ic_cdk::caller(), OfficerRole::Admin);
+    });
     
     // Initialize high-risk jurisdictions (simplified list)
     HIGH_RISK_JURISDICTIONS.with(|jurisdictions| {
@@ -300,43 +456,78 @@ fn pre_upgrade() {
 }
 
 #[post_upgrade]
-fn post_upgrade() {
+fn post_upgrade(auth_canister: Option<Principal>) {
     // Stable storage restoration would go here
+    set_auth_canister(auth_canister);
+    cycles::start_cycle_monitor(cycles::forward_cycle_alert);
+    memory::start_memory_monitor(notify_high_memory);
+    start_deadline_monitor();
 }
 
 // === KYC Management Functions ===
 
 #[update]
-fn create_kyc_profile(
+async fn create_kyc_profile(
     principal: Principal,
     entity_type: EntityType,
     legal_name: String,
     jurisdiction: String,
     registration_number: Option<String>,
-) -> Result<String, String> {
+) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "create_kyc_profile")?;
     
     // Check if caller is compliance officer
-    let is_compliance_officer = COMPLIANCE_OFFICERS.with(|officers| {
-        officers.borrow().contains(&caller)
-    });
+    let is_compliance_officer = check_compliance_officer(caller).await;
     
     if !is_compliance_officer {
-        return Err("Only compliance officers can create KYC profiles".to_string());
+        return Err(CustodyError::unauthorized("create_kyc_profile"));
     }
     
+    create_kyc_profile_internal(KycCreateRequest {
+        principal,
+        entity_type,
+        legal_name,
+        jurisdiction,
+        registration_number,
+    })
+}
+
+// Shared by the single and batch endpoints once the caller has been checked
+fn create_kyc_profile_internal(request: KycCreateRequest) -> Result<String, CustodyError> {
+    let KycCreateRequest {
+        principal,
+        entity_type,
+        legal_name,
+        jurisdiction,
+        registration_number,
+    } = request;
+    
     // Check if KYC profile already exists
     let existing_kyc = PRINCIPAL_TO_KYC.with(|map| {
         map.borrow().get(&principal).cloned()
     });
     
     if existing_kyc.is_some() {
-        return Err("KYC profile already exists for this principal".to_string());
+        return Err(CustodyError::status_conflict("KYC profile exists", "no KYC profile"));
     }
     
     // Validate inputs
     if legal_name.is_empty() || jurisdiction.is_empty() {
-        return Err("Legal name and jurisdiction are required".to_string());
+        return Err(CustodyError::invalid_input("legal_name", "legal name and jurisdiction are required"));
+    }
+    
+    let entity_type_allowed = JURISDICTION_RULES.with(|rules| {
+        rules.borrow().get(&jurisdiction).is_none_or(|rule| {
+            rule.allowed_entity_types.is_empty() || rule.allowed_entity_types.contains(&entity_type)
+        })
+    });
+    
+    if !entity_type_allowed {
+        return Err(CustodyError::invalid_input(
+            "entity_type",
+            format!("{:?} is not permitted in {}", entity_type, jurisdiction),
+        ));
     }
     
     let kyc_id = Uuid::new_v4().to_string();
@@ -358,6 +549,7 @@ fn create_kyc_profile(
         aml_status: AmlStatus::NotChecked,
         created_at: current_time,
         last_updated: current_time,
+        approved_at: None,
         documents: Vec::new(),
         sanctions_check: None,
         pep_check: None,
@@ -382,28 +574,47 @@ fn create_kyc_profile(
     Ok(kyc_id)
 }
 
+/// Creates each profile in order and reports every result, so one bad
+/// request doesn't stop the rest of the batch
 #[update]
-fn add_kyc_document(
+async fn batch_create_kyc_profiles(
+    requests: Vec<KycCreateRequest>,
+) -> Result<Vec<Result<String, CustodyError>>, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "batch_create_kyc_profiles")?;
+    
+    if !check_compliance_officer(caller).await {
+        return Err(CustodyError::unauthorized("batch_create_kyc_profiles"));
+    }
+    
+    check_batch_size(requests.len())?;
+    
+    Ok(requests.into_iter().map(create_kyc_profile_internal).collect())
+}
+
+#[update]
+async fn add_kyc_document(
     kyc_id: String,
     document_type: DocumentType,
     name: String,
     hash: String,
     metadata: String,
-) -> Result<String, String> {
+    access_level: Option<DocumentAccessLevel>,
+) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_kyc_document")?;
     
     // Check if caller is compliance officer
-    let is_compliance_officer = COMPLIANCE_OFFICERS.with(|officers| {
-        officers.borrow().contains(&caller)
-    });
+    let is_compliance_officer = check_compliance_officer(caller).await;
     
     if !is_compliance_officer {
-        return Err("Only compliance officers can add documents".to_string());
+        return Err(CustodyError::unauthorized("add_kyc_document"));
     }
     
     let document_id = Uuid::new_v4().to_string();
     let current_time = ic_cdk::api::time();
     
+    let document_access_level = access_level.unwrap_or_else(|| default_access_level(&document_type));
     let document = Document {
         id: document_id.clone(),
         document_type,
@@ -413,32 +624,33 @@ fn add_kyc_document(
         verified_at: None,
         verification_status: DocumentStatus::Pending,
         metadata,
+        document_access_level,
     };
     
     KYC_PROFILES.with(|profiles| {
         let mut profiles_map = profiles.borrow_mut();
         match profiles_map.get_mut(&kyc_id) {
             Some(profile) => {
+                record_kyc_version(profile.clone(), caller, "Document added".to_string());
                 profile.documents.push(document);
                 profile.last_updated = current_time;
                 Ok("Document added successfully".to_string())
             },
-            None => Err("KYC profile not found".to_string()),
+            None => Err(CustodyError::not_found("KYC profile", kyc_id.clone())),
         }
     })
 }
 
 #[update]
-fn verify_kyc_document(kyc_id: String, document_id: String, approved: bool) -> Result<String, String> {
+async fn verify_kyc_document(kyc_id: String, document_id: String, approved: bool) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "verify_kyc_document")?;
     
     // Check if caller is compliance officer
-    let is_compliance_officer = COMPLIANCE_OFFICERS.with(|officers| {
-        officers.borrow().contains(&caller)
-    });
+    let is_compliance_officer = check_compliance_officer(caller).await;
     
     if !is_compliance_officer {
-        return Err("Only compliance officers can verify documents".to_string());
+        return Err(CustodyError::unauthorized("verify_kyc_document"));
     }
     
     let current_time = ic_cdk::api::time();
@@ -447,7 +659,10 @@ fn verify_kyc_document(kyc_id: String, document_id: String, approved: bool) -> R
         let mut profiles_map = profiles.borrow_mut();
         match profiles_map.get_mut(&kyc_id) {
             Some(profile) => {
+                let snapshot = profile.clone();
                 if let Some(document) = profile.documents.iter_mut().find(|d| d.id == document_id) {
+                    let reason = if approved { "Document verified" } else { "Document rejected" };
+                    record_kyc_version(snapshot, caller, format!("{}: {}", reason, document_id));
                     document.verified_at = Some(current_time);
                     document.verification_status = if approved {
                         DocumentStatus::Verified
@@ -457,30 +672,30 @@ fn verify_kyc_document(kyc_id: String, document_id: String, approved: bool) -> R
                     profile.last_updated = current_time;
                     Ok("Document verification updated".to_string())
                 } else {
-                    Err("Document not found".to_string())
+                    Err(CustodyError::not_found("Document", document_id.clone()))
                 }
             },
-            None => Err("KYC profile not found".to_string()),
+            None => Err(CustodyError::not_found("KYC profile", kyc_id.clone())),
         }
     })
 }
 
 #[update]
-fn approve_kyc_profile(kyc_id: String, verification_level: VerificationLevel) -> Result<String, String> {
+async fn approve_kyc_profile(kyc_id: String, verification_level: VerificationLevel) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "approve_kyc_profile")?;
     
     // Check if caller is compliance officer
-    let is_compliance_officer = COMPLIANCE_OFFICERS.with(|officers| {
-        officers.borrow().contains(&caller)
-    });
+    let is_compliance_officer = check_compliance_officer(caller).await;
     
     if !is_compliance_officer {
-        return Err("Only compliance officers can approve KYC profiles".to_string());
+        return Err(CustodyError::unauthorized("approve_kyc_profile"));
     }
     
     let current_time = ic_cdk::api::time();
+    let level = format!("{:?}", verification_level);
     
-    KYC_PROFILES.with(|profiles| {
+    let principal = KYC_PROFILES.with(|profiles| {
         let mut profiles_map = profiles.borrow_mut();
         match profiles_map.get_mut(&kyc_id) {
             Some(profile) => {
@@ -490,18 +705,62 @@ fn approve_kyc_profile(kyc_id: String, verification_level: VerificationLevel) ->
                     .count();
                 
                 if verified_docs == 0 {
-                    return Err("No verified documents found".to_string());
+                    return Err(CustodyError::status_conflict("no verified documents", "verified documents"));
+                }
+                
+                if let Some(rule) = JURISDICTION_RULES.with(|rules| rules.borrow().get(&profile.jurisdiction).cloned()) {
+                    let missing: Vec<String> = rule.required_document_types.iter()
+                        .filter(|required| {
+                            !profile.documents.iter().any(|d| {
+                                &d.document_type == *required && d.verification_status == DocumentStatus::Verified
+                            })
+                        })
+                        .map(|required| format!("{:?}", required))
+                        .collect();
+                    
+                    if !missing.is_empty() {
+                        return Err(CustodyError::status_conflict(
+                            format!("missing verified {}", missing.join(", ")),
+                            "all required documents verified",
+                        ));
+                    }
+                    
+                    if rule.enhanced_due_diligence && matches!(verification_level, VerificationLevel::Basic) {
+                        return Err(CustodyError::invalid_input(
+                            "verification_level",
+                            "jurisdiction requires enhanced due diligence",
+                        ));
+                    }
                 }
                 
+                record_kyc_version(profile.clone(), caller, "Profile approved".to_string());
                 profile.kyc_status = KycStatus::Approved;
                 profile.verification_level = verification_level;
                 profile.last_updated = current_time;
+                profile.approved_at = Some(current_time);
                 
-                Ok("KYC profile approved successfully".to_string())
+                Ok(profile.principal)
             },
-            None => Err("KYC profile not found".to_string()),
+            None => Err(CustodyError::not_found("KYC profile", kyc_id.clone())),
         }
-    })
+    })?;
+    
+    // Counted as an approval in audit_trail's compliance report exports
+    ic_cdk::spawn(log_audit_event(
+        AuditEventType::KycUpdate,
+        AuditResourceType::KycProfile,
+        kyc_id.clone(),
+        "approve_kyc_profile",
+        format!("KYC profile for {} approved at {} verification", principal, level),
+        RequestContext::new(caller, kyc_id.clone()),
+    ));
+    
+    if let Some(event_bus) = EVENT_BUS_CANISTER.with(|c| *c.borrow()) {
+        let event = KycApprovedEvent { kyc_id, principal, verification_level: level };
+        ic_cdk::spawn(events::publish_event(event_bus, events::TOPIC_KYC_APPROVED, event));
+    }
+    
+    Ok("KYC profile approved successfully".to_string())
 }
 
 // === AML Screening Functions ===
@@ -514,46 +773,60 @@ async fn perform_sanctions_screening_async(kyc_id: String, legal_name: String) {
     }
 }
 
-async fn perform_sanctions_screening(kyc_id: String, legal_name: String) -> Result<String, String> {
+async fn perform_sanctions_screening(kyc_id: String, legal_name: String) -> Result<String, CustodyError> {
     // Simplified sanctions screening (in production, integrate with external API)
     let current_time = ic_cdk::api::time();
+    let mode = COMPLIANCE_SETTINGS.with(|s| s.borrow().sanctions_match_mode.clone());
+    let legal_tokens = name_tokens(&legal_name);
     
     // Check against internal sanctioned entities list
-    let is_sanctioned = SANCTIONED_ENTITIES.with(|entities| {
-        entities.borrow().iter().any(|entity| {
-            legal_name.to_lowercase().contains(&entity.to_lowercase())
-        })
+    let mut matches: Vec<SanctionsMatch> = SANCTIONED_ENTITIES.with(|entities| {
+        entities.borrow()
+            .iter()
+            .filter_map(|entity| {
+                let match_score = name_match_score(&name_tokens(entity), &legal_tokens, &mode)?;
+                Some(SanctionsMatch {
+                    list_name: "Internal Sanctions List".to_string(),
+                    match_score,
+                    matched_text: entity.clone(),
+                    reference: "INTERNAL_001".to_string(),
+                })
+            })
+            .collect()
     });
     
+    // Check the imported lists against every name an entity is known by
+    matches.extend(screen_sanctions_database(&legal_tokens, &mode));
+    
+    // Anything short of a full match is left for an officer to review
+    let top_match_score = matches.iter().map(|m| m.match_score).max();
+    let result = match top_match_score {
+        Some(FULL_MATCH_SCORE) => SanctionsResult::DirectMatch,
+        Some(_) => SanctionsResult::PotentialMatch,
+        None => SanctionsResult::Clear,
+    };
+    let is_sanctioned = matches!(result, SanctionsResult::DirectMatch);
+    
+    let mut lists_checked = vec!["Internal Sanctions List".to_string()];
+    lists_checked.extend(SANCTIONS_LISTS.with(|lists| lists.borrow().keys().cloned().collect::<Vec<_>>()));
+    
     let sanctions_check = SanctionsCheck {
         checked_at: current_time,
-        result: if is_sanctioned {
-            SanctionsResult::DirectMatch
-        } else {
-            SanctionsResult::Clear
-        },
-        lists_checked: vec!["OFAC SDN".to_string(), "EU Sanctions".to_string()],
-        matches: if is_sanctioned {
-            vec![SanctionsMatch {
-                list_name: "Internal Sanctions List".to_string(),
-                match_score: 1.0,
-                matched_text: legal_name.clone(),
-                reference: "INTERNAL_001".to_string(),
-            }]
-        } else {
-            Vec::new()
-        },
+        result: result.clone(),
+        lists_checked,
+        matches,
     };
     
     // Update KYC profile with screening results
     KYC_PROFILES.with(|profiles| {
         let mut profiles_map = profiles.borrow_mut();
         if let Some(profile) = profiles_map.get_mut(&kyc_id) {
+            record_kyc_version(profile.clone(), ic_cdk::caller(), "Sanctions screening".to_string());
             profile.sanctions_check = Some(sanctions_check);
-            profile.aml_status = if is_sanctioned {
-                AmlStatus::Hit
-            } else {
-                AmlStatus::Cleared
+            profile.aml_status = match result {
+                SanctionsResult::DirectMatch => AmlStatus::Hit,
+                SanctionsResult::PotentialMatch => AmlStatus::Review,
+                SanctionsResult::Clear => AmlStatus::Cleared,
             };
             profile.last_updated = current_time;
             
@@ -564,7 +837,56 @@ async fn perform_sanctions_screening(kyc_id: String, legal_name: String) -> Resu
         }
     });
     
-    Ok("Sanctions screening completed".to_string())
+    match top_match_score {
+        Some(score) => Ok(format!(
+            "Sanctions screening completed with match score {}%",
+            score
+        )),
+        None => Ok("Sanctions screening completed".to_string()),
+    }
+}
+
+/// Screens every listed profile; ids without a profile are left out of the result
+#[update]
+async fn batch_sanctions_screen(
+    kyc_ids: Vec<String>,
+) -> Result<BTreeMap<String, SanctionsResult>, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "batch_sanctions_screen")?;
+    
+    if !check_compliance_officer(caller).await {
+        return Err(CustodyError::unauthorized("batch_sanctions_screen"));
+    }
+    
+    check_batch_size(kyc_ids.len())?;
+    
+    let mut results = BTreeMap::new();
+    
+    for kyc_id in kyc_ids {
+        let legal_name = KYC_PROFILES.with(|profiles| {
+            profiles.borrow().get(&kyc_id).map(|profile| profile.legal_name.clone())
+        });
+        
+        let legal_name = match legal_name {
+            Some(name) => name,
+            None => continue,
+        };
+        
+        perform_sanctions_screening(kyc_id.clone(), legal_name).await?;
+        
+        let result = KYC_PROFILES.with(|profiles| {
+            profiles.borrow()
+                .get(&kyc_id)
+                .and_then(|profile| profile.sanctions_check.as_ref())
+                .map(|check| check.result.clone())
+        });
+        
+        if let Some(result) = result {
+            results.insert(kyc_id, result);
+        }
+    }
+    
+    Ok(results)
 }
 
 // === Transaction Monitoring Functions ===
@@ -575,7 +897,37 @@ fn monitor_transaction(
     transaction_id: String,
     amount: u64,
     transaction_type: String,
-) -> Result<String, String> {
+    request_context: Option<RequestContext>,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "monitor_transaction")?;
+    
+    // Only a trusted canister may name another principal as the initiator
+    let request_context = verified_request_context(caller, request_context);
+    let context = inject_request_context(request_context, transaction_id.clone());
+    record_transaction_monitoring(account_id, transaction_id, amount, transaction_type, context)
+}
+
+// Shared by monitor_transaction and transaction.executed events. A
+// transaction reported both ways keeps its first monitoring record
+fn record_transaction_monitoring(
+    account_id: String,
+    transaction_id: String,
+    amount: u64,
+    transaction_type: String,
+    context: RequestContext,
+) -> Result<String, CustodyError> {
+    let existing = TRANSACTION_MONITORING.with(|tm| {
+        tm.borrow()
+            .values()
+            .find(|m| m.account_id == account_id && m.transaction_id == transaction_id)
+            .map(|m| m.id.clone())
+    });
+    
+    if let Some(monitoring_id) = existing {
+        return Ok(monitoring_id);
+    }
+    
     let current_time = ic_cdk::api::time();
     
     // Calculate risk score
@@ -605,6 +957,8 @@ fn monitor_transaction(
         reviewed_by: None,
         reviewed_at: None,
         notes: None,
+        initiated_by: context.original_caller,
+        correlation_id: context.correlation_id,
     };
     
     TRANSACTION_MONITORING.with(|tm| {
@@ -621,7 +975,7 @@ fn monitor_transaction(
     Ok(monitoring_id)
 }
 
-fn create_sar_draft(account_id: &str, transaction_id: &str, amount: u64) -> Result<String, String> {
+fn create_sar_draft(account_id: &str, transaction_id: &str, amount: u64) -> Result<String, CustodyError> {
     let sar_id = Uuid::new_v4().to_string();
     let current_time = ic_cdk::api::time();
     
@@ -644,25 +998,31 @@ fn create_sar_draft(account_id: &str, transaction_id: &str, amount: u64) -> Resu
         sars.borrow_mut().insert(sar_id.clone(), sar);
     });
     
+    schedule_deadline(
+        DeadlineType::SarFiling,
+        current_time.saturating_add(SAR_FILING_DAYS * NANOS_PER_DAY),
+        sar_id.clone(),
+        None,
+    );
+    
     Ok(sar_id)
 }
 
 #[update]
-fn file_sar_report(sar_id: String, narrative: String) -> Result<String, String> {
+async fn file_sar_report(sar_id: String, narrative: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "file_sar_report")?;
     
     // Check if caller is compliance officer
-    let is_compliance_officer = COMPLIANCE_OFFICERS.with(|officers| {
-        officers.borrow().contains(&caller)
-    });
+    let is_compliance_officer = check_compliance_officer(caller).await;
     
     if !is_compliance_officer {
-        return Err("Only compliance officers can file SAR reports".to_string());
+        return Err(CustodyError::unauthorized("file_sar_report"));
     }
     
     let current_time = ic_cdk::api::time();
     
-    SAR_REPORTS.with(|sars| {
+    let (account_id, reference_number) = SAR_REPORTS.with(|sars| {
         let mut sars_map = sars.borrow_mut();
         match sars_map.get_mut(&sar_id) {
             Some(sar) => {
@@ -670,20 +1030,324 @@ fn file_sar_report(sar_id: String, narrative: String) -> Result<String, String>
                 sar.narrative = narrative;
                 sar.filed_at = current_time;
                 sar.filed_by = caller;
-                Ok("SAR report filed successfully".to_string())
+                Ok((sar.account_id.clone(), sar.reference_number.clone()))
             },
-            None => Err("SAR report not found".to_string()),
+            None => Err(CustodyError::not_found("SAR report", sar_id.clone())),
+        }
+    })?;
+    
+    complete_deadlines_for(&sar_id, DeadlineType::SarFiling);
+    
+    // Counted as a filed SAR in audit_trail's compliance report exports
+    ic_cdk::spawn(log_audit_event(
+        AuditEventType::ComplianceCheck,
+        AuditResourceType::ComplianceReport,
+        sar_id.clone(),
+        "file_sar_report",
+        format!("SAR {} filed for account {}", reference_number, account_id),
+        RequestContext::new(caller, sar_id.clone()),
+    ));
+    
+    if let Some(event_bus) = EVENT_BUS_CANISTER.with(|c| *c.borrow()) {
+        let event = SarFiledEvent { sar_id, account_id, reference_number };
+        ic_cdk::spawn(events::publish_event(event_bus, events::TOPIC_SAR_FILED, event));
+    }
+    
+    Ok("SAR report filed successfully".to_string())
+}
+
+// === Event Functions ===
+
+/// Called by the event bus for each event on a topic this canister subscribes
+/// to. Subscribing to transaction.executed monitors custody transactions
+/// without custody_core having to be configured with this canister's ID
+#[update]
+fn handle_event(event: CanisterEvent) {
+    if EVENT_BUS_CANISTER.with(|c| *c.borrow()) != Some(ic_cdk::caller()) {
+        ic_cdk::trap("handle_event may only be called by the configured event bus");
+    }
+    
+    if event.topic != events::TOPIC_TRANSACTION_EXECUTED {
+        return;
+    }
+    
+    // Any registered publisher can use any topic on the bus, so only
+    // canisters trusted to report transactions are listened to here
+    if !context::is_trusted_canister(&event.publisher) {
+        ic_cdk::println!("Ignoring {} event from untrusted publisher {}", event.topic, event.publisher);
+        return;
+    }
+    
+    let executed: TransactionExecutedEvent = match candid::decode_one(&event.payload) {
+        Ok(executed) => executed,
+        Err(e) => {
+            ic_cdk::println!("Malformed {} event from {}: {}", event.topic, event.publisher, e);
+            return;
+        }
+    };
+    
+    let context = RequestContext::new(executed.initiated_by, executed.transaction_id.clone());
+    if let Err(e) = record_transaction_monitoring(
+        executed.account_id,
+        executed.transaction_id.clone(),
+        executed.amount,
+        executed.transaction_type,
+        context,
+    ) {
+        ic_cdk::println!("Monitoring of transaction {} failed: {}", executed.transaction_id, e);
+    }
+}
+
+// === Compliance Calendar Functions ===
+
+#[update]
+async fn create_compliance_deadline(
+    deadline_type: DeadlineType,
+    due_at: u64,
+    related_id: String,
+    responsible_officer: Option<Principal>,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "create_compliance_deadline")?;
+    
+    if !check_compliance_officer(caller).await {
+        return Err(CustodyError::unauthorized("create_compliance_deadline"));
+    }
+    
+    if due_at <= ic_cdk::api::time() {
+        return Err(CustodyError::invalid_input("due_at", "must be in the future"));
+    }
+    
+    Ok(schedule_deadline(deadline_type, due_at, related_id, responsible_officer))
+}
+
+#[update]
+async fn complete_compliance_deadline(deadline_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "complete_compliance_deadline")?;
+    
+    if !check_compliance_officer(caller).await {
+        return Err(CustodyError::unauthorized("complete_compliance_deadline"));
+    }
+    
+    COMPLIANCE_CALENDAR.with(|calendar| {
+        match calendar.borrow_mut().get_mut(&deadline_id) {
+            Some(deadline) if deadline.status == DeadlineStatus::Completed => {
+                Err(CustodyError::status_conflict("Completed", "Open or Overdue"))
+            },
+            Some(deadline) => {
+                deadline.status = DeadlineStatus::Completed;
+                Ok("Compliance deadline completed".to_string())
+            },
+            None => Err(CustodyError::not_found("Compliance deadline", deadline_id.clone())),
         }
     })
 }
 
+/// Open and overdue deadlines falling due within `days_ahead` days, soonest
+/// first. Overdue deadlines are always included.
+#[query]
+fn get_upcoming_deadlines(days_ahead: u32) -> Vec<ComplianceDeadline> {
+    let horizon = ic_cdk::api::time().saturating_add(days_ahead as u64 * NANOS_PER_DAY);
+    
+    let mut deadlines: Vec<ComplianceDeadline> = COMPLIANCE_CALENDAR.with(|calendar| {
+        calendar.borrow()
+            .values()
+            .filter(|d| d.status != DeadlineStatus::Completed && d.due_at <= horizon)
+            .cloned()
+            .collect()
+    });
+    deadlines.sort_by_key(|d| d.due_at);
+    deadlines
+}
+
+fn schedule_deadline(
+    deadline_type: DeadlineType,
+    due_at: u64,
+    related_id: String,
+    responsible_officer: Option<Principal>,
+) -> String {
+    let deadline_id = Uuid::new_v4().to_string();
+    let deadline = ComplianceDeadline {
+        id: deadline_id.clone(),
+        deadline_type,
+        due_at,
+        related_id,
+        responsible_officer,
+        status: DeadlineStatus::Open,
+        reminder_sent: false,
+    };
+    
+    COMPLIANCE_CALENDAR.with(|calendar| {
+        calendar.borrow_mut().insert(deadline_id.clone(), deadline);
+    });
+    
+    deadline_id
+}
+
+fn complete_deadlines_for(related_id: &str, deadline_type: DeadlineType) {
+    COMPLIANCE_CALENDAR.with(|calendar| {
+        for deadline in calendar.borrow_mut().values_mut() {
+            if deadline.related_id == related_id
+                && deadline.deadline_type == deadline_type
+                && deadline.status != DeadlineStatus::Completed
+            {
+                deadline.status = DeadlineStatus::Completed;
+            }
+        }
+    });
+}
+
+/// Timers don't survive upgrades, so call this from both init and post_upgrade.
+fn start_deadline_monitor() {
+    ic_cdk_timers::set_timer_interval(DEADLINE_CHECK_INTERVAL, check_overdue_deadlines);
+}
+
+/// Marks open deadlines past their due date as Overdue and escalates them to the
+/// audit trail, and sends a one-off reminder for those falling due soon
+fn check_overdue_deadlines() {
+    let current_time = ic_cdk::api::time();
+    let reminder_horizon = current_time.saturating_add(DEADLINE_REMINDER_DAYS * NANOS_PER_DAY);
+    
+    let (overdue, reminders) = COMPLIANCE_CALENDAR.with(|calendar| {
+        let mut overdue = Vec::new();
+        let mut reminders = Vec::new();
+        
+        for deadline in calendar.borrow_mut().values_mut() {
+            if deadline.status != DeadlineStatus::Open {
+                continue;
+            }
+            
+            if deadline.due_at <= current_time {
+                deadline.status = DeadlineStatus::Overdue;
+                overdue.push(deadline.clone());
+            } else if deadline.due_at <= reminder_horizon && !deadline.reminder_sent {
+                deadline.reminder_sent = true;
+                reminders.push(deadline.clone());
+            }
+        }
+        
+        (overdue, reminders)
+    });
+    
+    for deadline in overdue {
+        ic_cdk::spawn(log_deadline_audit_event(deadline, "deadline_escalated"));
+    }
+    for deadline in reminders {
+        ic_cdk::spawn(log_deadline_audit_event(deadline, "deadline_reminder"));
+    }
+}
+
+async fn log_deadline_audit_event(deadline: ComplianceDeadline, action: &'static str) {
+    let details = format!(
+        "{:?} deadline for {} due at {} (responsible officer: {})",
+        deadline.deadline_type,
+        deadline.related_id,
+        deadline.due_at,
+        deadline.responsible_officer.map_or("unassigned".to_string(), |officer| officer.to_text()),
+    );
+    let context = RequestContext::new(ic_cdk::id(), deadline.id.clone());
+    
+    log_audit_event(
+        AuditEventType::ComplianceCheck,
+        AuditResourceType::ComplianceReport,
+        deadline.id,
+        action,
+        details,
+        context,
+    ).await;
+}
+
+// Sends a compliance-relevant entry to the audit_trail canister, if one is set
+async fn log_audit_event(
+    event_type: AuditEventType,
+    resource_type: AuditResourceType,
+    resource_id: String,
+    action: &str,
+    details: String,
+    context: RequestContext,
+) {
+    let audit_canister = match AUDIT_TRAIL_CANISTER.with(|c| *c.borrow()) {
+        Some(canister) => canister,
+        None => return,
+    };
+    
+    let result: Result<(Result<String, CustodyError>,), _> = ic_cdk::call(
+        audit_canister,
+        "log_audit_event",
+        (
+            event_type,
+            resource_type,
+            resource_id.clone(),
+            action.to_string(),
+            details,
+            None::<()>,
+            true,
+            Some(context),
+        ),
+    ).await;
+    
+    match result {
+        Ok((Ok(_),)) => {},
+        Ok((Err(e),)) => ic_cdk::println!("Audit trail rejected {} for {}: {}", action, resource_id, e),
+        Err((code, msg)) => ic_cdk::println!("{} for {} failed: {:?} {}", action, resource_id, code, msg),
+    }
+}
+
 // === Query Functions ===
 
+/// Documents above the caller's clearance are left out of the returned profile
 #[query]
 fn get_kyc_profile(kyc_id: String) -> Option<KycProfile> {
-    KYC_PROFILES.with(|profiles| {
+    let profile = KYC_PROFILES.with(|profiles| {
         profiles.borrow().get(&kyc_id).cloned()
-    })
+    })?;
+    
+    Some(redact_documents(profile, ic_cdk::caller()))
+}
+
+#[query]
+fn get_kyc_profile_history(kyc_id: String) -> Vec<KycProfileVersion> {
+    let caller = ic_cdk::caller();
+    let versions = KYC_HISTORY.with(|history| {
+        history.borrow().get(&kyc_id).cloned().unwrap_or_default()
+    });
+    
+    versions
+        .into_iter()
+        .map(|mut version| {
+            version.profile_snapshot = redact_documents(version.profile_snapshot, caller);
+            version
+        })
+        .collect()
+}
+
+/// Returns the profile as it was at `timestamp`, or None if the profile didn't
+/// exist yet or that state has been pruned from the history
+#[query]
+fn get_kyc_profile_at(kyc_id: String, timestamp: u64) -> Option<KycProfile> {
+    let current = KYC_PROFILES.with(|profiles| {
+        profiles.borrow().get(&kyc_id).cloned()
+    })?;
+    
+    if timestamp < current.created_at {
+        return None;
+    }
+    
+    // The first change after the timestamp holds the state in effect at it
+    let next_change = KYC_HISTORY.with(|history| {
+        history.borrow()
+            .get(&kyc_id)
+            .and_then(|versions| versions.iter().find(|v| v.changed_at > timestamp).cloned())
+    });
+    
+    let profile = match next_change {
+        Some(version) if version.profile_snapshot.last_updated <= timestamp => version.profile_snapshot,
+        Some(_) => return None,
+        None => current,
+    };
+    
+    Some(redact_documents(profile, ic_cdk::caller()))
 }
 
 #[query]
@@ -692,13 +1356,15 @@ fn get_kyc_by_principal(principal: Principal) -> Option<KycProfile> {
         map.borrow().get(&principal).cloned()
     })?;
     
-    KYC_PROFILES.with(|profiles| {
+    let profile = KYC_PROFILES.with(|profiles| {
         profiles.borrow().get(&kyc_id).cloned()
-    })
+    })?;
+    
+    Some(redact_documents(profile, ic_cdk::caller()))
 }
 
 #[query]
-fn check_compliance_status(principal: Principal) -> Result<String, String> {
+fn check_compliance_status(principal: Principal) -> Result<String, CustodyError> {
     let kyc_id = PRINCIPAL_TO_KYC.with(|map| {
         map.borrow().get(&principal).cloned()
     });
@@ -711,19 +1377,27 @@ fn check_compliance_status(principal: Principal) -> Result<String, String> {
             
             match profile {
                 Some(p) => match p.kyc_status {
+                    KycStatus::Approved if is_kyc_expired(&p, ic_cdk::api::time()) => {
+                        Err(CustodyError::status_conflict("Expired", "Approved"))
+                    },
                     KycStatus::Approved => Ok("Compliant".to_string()),
                     KycStatus::Pending => Ok("Pending KYC".to_string()),
-                    KycStatus::Rejected => Err("KYC Rejected".to_string()),
-                    KycStatus::Suspended => Err("Account Suspended".to_string()),
+                    KycStatus::Rejected => Err(CustodyError::status_conflict("Rejected", "Approved")),
+                    KycStatus::Suspended => Err(CustodyError::status_conflict("Suspended", "Approved")),
                     _ => Ok("Under Review".to_string()),
                 },
-                None => Err("KYC profile not found".to_string()),
+                None => Err(CustodyError::not_found("KYC profile", id)),
             }
         },
-        None => Err("No KYC profile exists".to_string()),
+        None => Err(CustodyError::not_found("KYC profile", principal.to_text())),
     }
 }
 
+fn is_kyc_expired(profile: &KycProfile, now: u64) -> bool {
+    profile.approved_at
+        .is_some_and(|approved_at| now.saturating_sub(approved_at) > KYC_VALIDITY_DAYS * NANOS_PER_DAY)
+}
+
 #[query]
 fn get_transaction_monitoring(monitoring_id: String) -> Option<TransactionMonitoring> {
     TRANSACTION_MONITORING.with(|tm| {
@@ -759,16 +1433,15 @@ fn get_compliance_settings() -> ComplianceSettings {
 // === Admin Functions ===
 
 #[update]
-fn add_compliance_officer(officer: Principal) -> Result<String, String> {
+async fn add_compliance_officer(officer: Principal) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_compliance_officer")?;
     
     // Check if caller is already a compliance officer
-    let is_compliance_officer = COMPLIANCE_OFFICERS.with(|officers| {
-        officers.borrow().contains(&caller)
-    });
+    let is_compliance_officer = check_compliance_officer(caller).await;
     
     if !is_compliance_officer {
-        return Err("Only compliance officers can add new officers".to_string());
+        return Err(CustodyError::unauthorized("add_compliance_officer"));
     }
     
     COMPLIANCE_OFFICERS.with(|officers| {
@@ -779,16 +1452,100 @@ fn add_compliance_officer(officer: Principal) -> Result<String, String> {
 }
 
 #[update]
-fn update_compliance_settings(new_settings: ComplianceSettings) -> Result<String, String> {
+async fn set_audit_trail_canister(canister_id: Principal) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_audit_trail_canister")?;
     
-    // Check if caller is compliance officer
-    let is_compliance_officer = COMPLIANCE_OFFICERS.with(|officers| {
-        officers.borrow().contains(&caller)
+    if !check_compliance_officer(caller).await {
+        return Err(CustodyError::unauthorized("set_audit_trail_canister"));
+    }
+    
+    AUDIT_TRAIL_CANISTER.with(|c| {
+        *c.borrow_mut() = Some(canister_id);
+    });
+    
+    Ok("Audit trail canister set successfully".to_string())
+}
+
+/// Sets the event bus that kyc.approved and sar.filed are published to and
+/// that handle_event accepts deliveries from
+#[update]
+async fn set_event_bus_canister(canister_id: Option<Principal>) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_event_bus_canister")?;
+    
+    if !check_compliance_officer(caller).await {
+        return Err(CustodyError::unauthorized("set_event_bus_canister"));
+    }
+    
+    EVENT_BUS_CANISTER.with(|c| {
+        *c.borrow_mut() = canister_id;
+    });
+    
+    Ok("Event bus canister set successfully".to_string())
+}
+
+/// Allows or stops a canister reporting transactions on behalf of its users,
+/// either directly or as transaction.executed events
+#[update]
+async fn set_trusted_canister(canister: Principal, trusted: bool) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_trusted_canister")?;
+    
+    if !check_compliance_officer(caller).await {
+        return Err(CustodyError::unauthorized("set_trusted_canister"));
+    }
+    
+    context::set_trusted_canister(canister, trusted);
+    
+    Ok("Trusted canister updated successfully".to_string())
+}
+
+#[query]
+fn list_trusted_canisters() -> Vec<Principal> {
+    context::trusted_canisters()
+}
+
+/// Sets the officer's document clearance, registering them as a compliance
+/// officer if they aren't one yet
+#[update]
+fn grant_officer_role(officer: Principal, role: OfficerRole) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "grant_officer_role")?;
+    
+    if officer_role(caller) != Some(OfficerRole::Admin) {
+        return Err(CustodyError::unauthorized("grant_officer_role"));
+    }
+    
+    COMPLIANCE_OFFICERS.with(|officers| {
+        officers.borrow_mut().insert(officer);
+    });
+    COMPLIANCE_OFFICER_ROLES.with(|roles| {
+        roles.borrow_mut().insert(officer, role.clone());
     });
     
+    Ok(format!("Officer role {:?} granted to {}", role, officer))
+}
+
+#[update]
+async fn update_compliance_settings(new_settings: ComplianceSettings) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "update_compliance_settings")?;
+    
+    // Check if caller is compliance officer
+    let is_compliance_officer = check_compliance_officer(caller).await;
+    
     if !is_compliance_officer {
-        return Err("Only compliance officers can update settings".to_string());
+        return Err(CustodyError::unauthorized("update_compliance_settings"));
+    }
+    
+    if let MatchMode::FuzzyLevenshtein(max_distance) = new_settings.sanctions_match_mode {
+        if max_distance == 0 || max_distance > MAX_FUZZY_DISTANCE {
+            return Err(CustodyError::invalid_input(
+                "sanctions_match_mode",
+                format!("fuzzy match distance must be between 1 and {}", MAX_FUZZY_DISTANCE),
+            ));
+        }
     }
     
     COMPLIANCE_SETTINGS.with(|settings| {
@@ -799,16 +1556,15 @@ fn update_compliance_settings(new_settings: ComplianceSettings) -> Result<String
 }
 
 #[update]
-fn add_sanctioned_entity(entity: String) -> Result<String, String> {
+async fn add_sanctioned_entity(entity: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_sanctioned_entity")?;
     
     // Check if caller is compliance officer
-    let is_compliance_officer = COMPLIANCE_OFFICERS.with(|officers| {
-        officers.borrow().contains(&caller)
-    });
+    let is_compliance_officer = check_compliance_officer(caller).await;
     
     if !is_compliance_officer {
-        return Err("Only compliance officers can add sanctioned entities".to_string());
+        return Err(CustodyError::unauthorized("add_sanctioned_entity"));
     }
     
     SANCTIONED_ENTITIES.with(|entities| {
@@ -818,19 +1574,412 @@ fn add_sanctioned_entity(entity: String) -> Result<String, String> {
     Ok("Sanctioned entity added successfully".to_string())
 }
 
+/// Replaces the entries previously imported under `list_name` with `entries`
+/// and returns the number of entries imported
+#[update]
+async fn import_sanctions_list(list_name: String, entries: Vec<SanctionsEntry>) -> Result<u64, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "import_sanctions_list")?;
+    
+    // Check if caller is compliance officer
+    let is_compliance_officer = check_compliance_officer(caller).await;
+    
+    if !is_compliance_officer {
+        return Err(CustodyError::unauthorized("import_sanctions_list"));
+    }
+    
+    if list_name.is_empty() {
+        return Err(CustodyError::invalid_input("list_name", "cannot be empty"));
+    }
+    
+    if entries.iter().any(|entry| normalize_sanctions_name(&entry.name).is_empty()) {
+        return Err(CustodyError::invalid_input("entries", "every entry needs a name"));
+    }
+    
+    let keyed: BTreeMap<String, SanctionsEntry> = entries.into_iter()
+        .map(|entry| (sanctions_entry_key(&entry.name), entry))
+        .collect();
+    
+    // Size the database as it will be once the old list is replaced
+    let resulting_size = SANCTIONS_DATABASE.with(|db| {
+        let db = db.borrow();
+        let orphaned = orphaned_sanctions_keys(&list_name);
+        db.keys()
+            .filter(|key| !orphaned.contains(*key))
+            .chain(keyed.keys().filter(|key| !db.contains_key(*key) || orphaned.contains(*key)))
+            .count()
+    });
+    
+    if resulting_size > MAX_SANCTIONS_ENTRIES {
+        return Err(CustodyError::LimitExceeded {
+            limit: MAX_SANCTIONS_ENTRIES as u64,
+            actual: resulting_size as u64,
+        });
+    }
+    
+    remove_sanctions_list(&list_name);
+    
+    let imported = keyed.len() as u64;
+    
+    SANCTIONS_LISTS.with(|lists| {
+        lists.borrow_mut().insert(list_name.clone(), keyed.keys().cloned().collect());
+    });
+    
+    SANCTIONS_DATABASE.with(|db| {
+        db.borrow_mut().extend(keyed);
+    });
+    
+    ic_cdk::println!("Imported {} entries into sanctions list {}", imported, list_name);
+    Ok(imported)
+}
+
+#[update]
+async fn clear_sanctions_list(list_name: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "clear_sanctions_list")?;
+    
+    // Check if caller is compliance officer
+    let is_compliance_officer = check_compliance_officer(caller).await;
+    
+    if !is_compliance_officer {
+        return Err(CustodyError::unauthorized("clear_sanctions_list"));
+    }
+    
+    if !SANCTIONS_LISTS.with(|lists| lists.borrow().contains_key(&list_name)) {
+        return Err(CustodyError::not_found("Sanctions list", list_name));
+    }
+    
+    remove_sanctions_list(&list_name);
+    
+    Ok("Sanctions list cleared successfully".to_string())
+}
+
+#[update]
+async fn set_jurisdiction_rule(rule: JurisdictionRule) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_jurisdiction_rule")?;
+    
+    // Check if caller is compliance officer
+    let is_compliance_officer = check_compliance_officer(caller).await;
+    
+    if !is_compliance_officer {
+        return Err(CustodyError::unauthorized("set_jurisdiction_rule"));
+    }
+    
+    if rule.jurisdiction.is_empty() {
+        return Err(CustodyError::invalid_input("jurisdiction", "cannot be empty"));
+    }
+    
+    JURISDICTION_RULES.with(|rules| {
+        rules.borrow_mut().insert(rule.jurisdiction.clone(), rule);
+    });
+    
+    Ok("Jurisdiction rule updated successfully".to_string())
+}
+
+#[query]
+fn get_jurisdiction_rule(jurisdiction: String) -> Option<JurisdictionRule> {
+    JURISDICTION_RULES.with(|rules| rules.borrow().get(&jurisdiction).cloned())
+}
+
 // === Helper Functions ===
 
+// Falls back to the auth canister for principals not registered locally
+async fn check_compliance_officer(principal: Principal) -> bool {
+    COMPLIANCE_OFFICERS.with(|officers| officers.borrow().contains(&principal))
+        || has_role(principal, "compliance_officer").await
+}
+
+// Queries can't reach the auth canister, so only locally registered officers
+// get document clearance
+fn officer_role(principal: Principal) -> Option<OfficerRole> {
+    if !COMPLIANCE_OFFICERS.with(|officers| officers.borrow().contains(&principal)) {
+        return None;
+    }
+    
+    let role = COMPLIANCE_OFFICER_ROLES.with(|roles| roles.borrow().get(&principal).cloned());
+    Some(role.unwrap_or(OfficerRole::Junior))
+}
+
+fn can_view_document(level: &DocumentAccessLevel, role: Option<&OfficerRole>) -> bool {
+    match level {
+        DocumentAccessLevel::Public => true,
+        DocumentAccessLevel::ComplianceOnly => role.is_some(),
+        DocumentAccessLevel::SeniorComplianceOnly => {
+            matches!(role, Some(OfficerRole::Senior | OfficerRole::Admin))
+        }
+    }
+}
+
+fn redact_documents(mut profile: KycProfile, viewer: Principal) -> KycProfile {
+    let role = officer_role(viewer);
+    profile.documents.retain(|document| can_view_document(&document.document_access_level, role.as_ref()));
+    profile
+}
+
+// Financial records are restricted to senior officers unless the uploader says otherwise
+fn default_access_level(document_type: &DocumentType) -> DocumentAccessLevel {
+    match document_type {
+        DocumentType::TaxDocument | DocumentType::BankStatement => DocumentAccessLevel::SeniorComplianceOnly,
+        _ => DocumentAccessLevel::ComplianceOnly,
+    }
+}
+
+const MAX_SANCTIONS_ENTRIES: usize = 50_000;
+const FULL_MATCH_SCORE: u8 = 100;
+// Fuzzy alias matches are a weaker identification than the primary name;
+// an exact alias match still counts in full
+const ALIAS_MATCH_PERCENT: u32 = 90;
+const MAX_FUZZY_DISTANCE: u32 = 3;
+// One edit away from a two or three letter token is almost any other short
+// token, so those only match exactly
+const MIN_FUZZY_TOKEN_LENGTH: usize = 4;
+
+// Lowercases and collapses whitespace so formatting differences between
+// lists don't hide a match
+fn normalize_sanctions_name(name: &str) -> String {
+    name.split_whitespace()
+        .map(|part| part.to_lowercase())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Lowercase alphanumeric words, so punctuation like "Acme, Ltd." doesn't
+// keep a token from matching
+fn name_tokens(name: &str) -> Vec<String> {
+    name.to_lowercase()
+        .split(|c: char| !c.is_alphanumeric())
+        .filter(|token| !token.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn levenshtein_distance(a: &str, b: &str) -> usize {
+    let a: Vec<char> = a.chars().collect();
+    let b: Vec<char> = b.chars().collect();
+    
+    // Two rows of the edit distance matrix
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    
+    for (i, ca) in a.iter().enumerate() {
+        current[0] = i + 1;
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    
+    previous[b.len()]
+}
+
+// 0-100 similarity of a sanctioned-name token to a legal-name token
+fn token_match_score(sanctioned: &str, candidate: &str, mode: &MatchMode) -> u32 {
+    if sanctioned == candidate {
+        return FULL_MATCH_SCORE as u32;
+    }
+    
+    let sanctioned_len = sanctioned.chars().count();
+    let candidate_len = candidate.chars().count();
+    
+    match mode {
+        MatchMode::Exact => 0,
+        MatchMode::Substring if candidate.contains(sanctioned) => {
+            (FULL_MATCH_SCORE as usize * sanctioned_len / candidate_len) as u32
+        },
+        MatchMode::Substring => 0,
+        MatchMode::FuzzyLevenshtein(max_distance) => {
+            if sanctioned_len.min(candidate_len) < MIN_FUZZY_TOKEN_LENGTH {
+                return 0;
+            }
+            
+            let distance = levenshtein_distance(sanctioned, candidate);
+            if distance > *max_distance as usize {
+                return 0;
+            }
+            
+            let longest = sanctioned_len.max(candidate_len);
+            (FULL_MATCH_SCORE as usize * (longest - distance) / longest) as u32
+        },
+    }
+}
+
+// Every token of the sanctioned name has to match some token of the legal
+// name, so a common word shared by both is not a hit on its own. The score
+// averages each sanctioned token's best match.
+fn name_match_score(sanctioned_tokens: &[String], legal_tokens: &[String], mode: &MatchMode) -> Option<u8> {
+    if sanctioned_tokens.is_empty() {
+        return None;
+    }
+    
+    let mut total = 0;
+    for sanctioned in sanctioned_tokens {
+        let best = legal_tokens.iter()
+            .map(|candidate| token_match_score(sanctioned, candidate, mode))
+            .max()
+            .unwrap_or(0);
+        if best == 0 {
+            return None;
+        }
+        total += best;
+    }
+    
+    Some((total / sanctioned_tokens.len() as u32) as u8)
+}
+
+fn sanctions_entry_key(name: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(normalize_sanctions_name(name).as_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+// Keys imported under `list_name` that no other list also imported
+fn orphaned_sanctions_keys(list_name: &str) -> BTreeSet<String> {
+    SANCTIONS_LISTS.with(|lists| {
+        let lists = lists.borrow();
+        let mut keys = lists.get(list_name).cloned().unwrap_or_default();
+        for (name, other_keys) in lists.iter() {
+            if name != list_name {
+                keys.retain(|key| !other_keys.contains(key));
+            }
+        }
+        keys
+    })
+}
+
+fn remove_sanctions_list(list_name: &str) {
+    let orphaned = orphaned_sanctions_keys(list_name);
+    
+    SANCTIONS_DATABASE.with(|db| {
+        let mut db = db.borrow_mut();
+        for key in &orphaned {
+            db.remove(key);
+        }
+    });
+    
+    SANCTIONS_LISTS.with(|lists| {
+        lists.borrow_mut().remove(list_name);
+    });
+}
+
+fn screen_sanctions_database(legal_tokens: &[String], mode: &MatchMode) -> Vec<SanctionsMatch> {
+    let matched: Vec<(String, SanctionsMatch)> = SANCTIONS_DATABASE.with(|db| {
+        db.borrow()
+            .iter()
+            .filter_map(|(key, entry)| {
+                // Primary names are a stronger match than fuzzy alias matches
+                let primary = name_match_score(&name_tokens(&entry.name), legal_tokens, mode)
+                    .map(|score| (entry.name.clone(), score));
+                let best_alias = entry.aliases.iter()
+                    .filter_map(|alias| {
+                        let score = name_match_score(&name_tokens(alias), legal_tokens, mode)?;
+                        if score == FULL_MATCH_SCORE {
+                            return Some((alias.clone(), score));
+                        }
+                        Some((alias.clone(), (score as u32 * ALIAS_MATCH_PERCENT / 100) as u8))
+                    })
+                    .max_by_key(|(_, score)| *score);
+                
+                let (matched_text, match_score) = match (primary, best_alias) {
+                    (Some(primary), Some(alias)) if alias.1 > primary.1 => alias,
+                    (Some(primary), _) => primary,
+                    (None, alias) => alias?,
+                };
+
+                Some((key.clone(), SanctionsMatch {
+                    list_name: String::new(),
+                    match_score,
+                    matched_text,
+                    reference: entry.identifiers.first().cloned().unwrap_or_else(|| entry.program.clone()),
+                }))
+            })
+            .collect()
+    });
+    
+    SANCTIONS_LISTS.with(|lists| {
+        let lists = lists.borrow();
+        matched.into_iter()
+            .map(|(key, mut sanctions_match)| {
+                sanctions_match.list_name = lists.iter()
+                    .find(|(_, keys)| keys.contains(&key))
+                    .map(|(name, _)| name.clone())
+                    .unwrap_or_default();
+                sanctions_match
+            })
+            .collect()
+    })
+}
+
+const MAX_KYC_HISTORY_VERSIONS: usize = 100;
+
+// Call with the profile as it was before a mutation
+fn record_kyc_version(snapshot: KycProfile, changed_by: Principal, change_reason: String) {
+    KYC_HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        let versions = history.entry(snapshot.id.clone()).or_default();
+        let version = versions.last().map_or(1, |v| v.version + 1);
+        
+        versions.push(KycProfileVersion {
+            version,
+            profile_snapshot: snapshot,
+            changed_at: ic_cdk::api::time(),
+            changed_by,
+            change_reason,
+        });
+        
+        if versions.len() > MAX_KYC_HISTORY_VERSIONS {
+            let excess = versions.len() - MAX_KYC_HISTORY_VERSIONS;
+            versions.drain(..excess);
+        }
+    });
+}
+
+// Keeps batch calls well inside the per-message instruction limit
+const MAX_BATCH_SIZE: usize = 50;
+
+fn check_batch_size(size: usize) -> Result<(), CustodyError> {
+    if size == 0 {
+        return Err(CustodyError::invalid_input("batch", "cannot be empty"));
+    }
+    
+    if size > MAX_BATCH_SIZE {
+        return Err(CustodyError::LimitExceeded {
+            limit: MAX_BATCH_SIZE as u64,
+            actual: size as u64,
+        });
+    }
+    
+    Ok(())
+}
+
 fn calculate_initial_risk(jurisdiction: &str, entity_type: &EntityType) -> RiskLevel {
     // Check high-risk jurisdictions
     let is_high_risk_jurisdiction = HIGH_RISK_JURISDICTIONS.with(|jurisdictions| {
         jurisdictions.borrow().contains(jurisdiction)
     });
     
+    let jurisdiction_risk = JURISDICTION_RULES.with(|rules| {
+        rules.borrow().get(jurisdiction).map(|rule| rule.base_risk.clone())
+    });
+    
+    let entity_risk = entity_type_risk(entity_type);
+    
+    // A jurisdiction rule can raise the risk but never lower it below the
+    // entity type's own risk
+    let risk = match jurisdiction_risk {
+        Some(base_risk) => base_risk.max(entity_risk),
+        None => entity_risk,
+    };
+    
     if is_high_risk_jurisdiction {
-        return RiskLevel::High;
+        risk.max(RiskLevel::High)
+    } else {
+        risk
     }
-    
-    // Risk based on entity type
+}
+
+fn entity_type_risk(entity_type: &EntityType) -> RiskLevel {
     match entity_type {
         EntityType::Individual => RiskLevel::Low,
         EntityType::Corporation => RiskLevel::Medium,
@@ -843,7 +1992,7 @@ fn calculate_initial_risk(jurisdiction: &str, entity_type: &EntityType) -> RiskL
     }
 }
 
-fn calculate_transaction_risk(account_id: &str, amount: u64, transaction_type: &str) -> u8 {
+fn calculate_transaction_risk(_account_id: &str, amount: u64, transaction_type: &str) -> u8 {
     let mut risk_score = 0u8;
     
     // Risk based on amount
@@ -868,7 +2017,7 @@ fn calculate_transaction_risk(account_id: &str, amount: u64, transaction_type: &
     std::cmp::min(risk_score, 10)
 }
 
-fn determine_compliance_flags(account_id: &str, amount: u64, transaction_type: &str) -> Vec<ComplianceFlag> {
+fn determine_compliance_flags(_account_id: &str, amount: u64, transaction_type: &str) -> Vec<ComplianceFlag> {
     let mut flags = Vec::new();
     
     if amount > 10_000_000_000 { // 100 BTC
@@ -884,10 +2033,74 @@ fn determine_compliance_flags(account_id: &str, amount: u64, transaction_type: &
     flags
 }
 
+// === Cycle Monitoring Functions ===
+
+#[query]
+fn check_cycle_balance() -> u64 {
+    cycles::check_cycle_balance()
+}
+
+#[query]
+fn get_cycle_stats() -> CycleStats {
+    cycles::get_cycle_stats()
+}
+
+#[query]
+fn get_memory_stats() -> MemoryStats {
+    memory::get_memory_stats()
+}
+
+fn notify_high_memory(stats: &MemoryStats) {
+    match AUDIT_TRAIL_CANISTER.with(|c| *c.borrow()) {
+        Some(audit_canister) => ic_cdk::spawn(memory::audit_memory_alert(audit_canister, stats.clone())),
+        None => memory::log_memory_alert(stats),
+    }
+}
+
+#[update]
+async fn set_cycle_alert_threshold(threshold: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_cycle_alert_threshold")?;
+    
+    if !check_compliance_officer(caller).await {
+        return Err(CustodyError::unauthorized("set_cycle_alert_threshold"));
+    }
+    
+    cycles::set_cycle_alert_threshold(threshold);
+    
+    Ok("Cycle alert threshold updated successfully".to_string())
+}
+
+#[update]
+async fn set_cycle_alert_canister(alert_canister: Option<Principal>) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_cycle_alert_canister")?;
+    
+    if !check_compliance_officer(caller).await {
+        return Err(CustodyError::unauthorized("set_cycle_alert_canister"));
+    }
+    
+    cycles::set_cycle_alert_canister(alert_canister);
+    
+    Ok("Cycle alert canister updated successfully".to_string())
+}
+
 #[query]
 fn health_check() -> String {
     "Compliance Engine canister is healthy".to_string()
 }
 
+const INTERFACE_VERSION: InterfaceVersion = (1, 0, 0);
+
+#[query]
+fn get_candid_interface_version() -> u32 {
+    INTERFACE_VERSION.0
+}
+
+#[query]
+fn get_interface_metadata() -> CandidVersion {
+    CandidVersion::new(INTERFACE_VERSION, &__export_service())
+}
+
 // Export Candid interface
 ic_cdk::export_candid!();
@@ -1,15 +1,28 @@
-use candid::{CandidType, Principal};
+use candid::{CandidType, Nat, Principal};
 use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, BTreeSet};
+use sha2::{Digest, Sha256};
+use shared::cycles::{self, CycleStats};
+use shared::events::{self, AccountFrozenEvent, TransactionExecutedEvent};
+use shared::auth::{has_role, set_auth_canister};
+use shared::{CustodyError, RequestContext};
+use shared::interface::{CandidVersion, InterfaceVersion};
+use shared::memory::{self, MemoryStats};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 use std::cell::RefCell;
+use std::ops::Bound;
+use std::thread::LocalKey;
 use uuid::Uuid;
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct CustodyAccount {
     pub id: String,
     pub owner: Principal,
+    // Free text chosen by the owner; display only
     pub institution_name: String,
+    // Institution whose operators handle the account, assigned by an
+    // institution operator opening it or by an emergency contact
+    pub institution_id: Option<String>,
     pub account_type: AccountType,
     pub status: AccountStatus,
     pub created_at: u64,
@@ -18,6 +31,21 @@ pub struct CustodyAccount {
     pub authorized_users: BTreeSet<Principal>,
     pub required_approvals: u8,
     pub compliance_status: ComplianceStatus,
+    // When compliance_status was last confirmed with the compliance_engine canister
+    pub last_compliance_check_at: Option<u64>,
+    // When set, withdrawals and transfers may only go to these addresses
+    pub withdrawal_whitelist: Option<BTreeSet<String>>,
+    pub withdrawal_blacklist: BTreeSet<String>,
+    // ICRC-1 token holdings; `balance` above remains BTC/ckBTC in satoshis
+    pub multi_asset_balances: Vec<AssetBalance>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AssetBalance {
+    pub token_canister_id: Principal,
+    pub token_symbol: String,
+    pub amount: u64,
+    pub decimals: u8,
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
@@ -60,6 +88,28 @@ pub struct Transaction {
     pub executed_at: Option<u64>,
     pub compliance_checked: bool,
     pub risk_score: u8,
+    pub requires_risk_review: bool,
+    pub risk_reviewers: BTreeSet<Principal>,
+    pub required_risk_reviews: u8,
+    // Trust withdrawals also need the trust's notary to witness them
+    pub notary: Option<Principal>,
+    pub notary_approved: bool,
+    // ICRC-1 ledger the amount is denominated in; None for BTC
+    pub token_canister_id: Option<Principal>,
+    // Free-text note shown on statements
+    pub memo: Option<String>,
+    // Correspondent banking reference (wire code, SWIFT ref); unique and indexed
+    pub external_reference: Option<String>,
+    pub rejected_at: Option<u64>,
+    pub rejection_reason: Option<String>,
+    // Signed transaction btc_integration broadcasts for a BTC withdrawal
+    pub bitcoin_transaction: Option<SignedBitcoinTransaction>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct SignedBitcoinTransaction {
+    pub raw_tx_hex: String,
+    pub txid: String,
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
@@ -74,11 +124,223 @@ pub enum TransactionType {
 pub enum TransactionStatus {
     Pending,
     Approved,
+    Executing,
     Executed,
     Rejected,
     Cancelled,
 }
 
+// Decision returned by the risk_management canister's evaluate_transaction
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum RiskDecision {
+    Allow,
+    ReviewRequired,
+    DualReviewRequired,
+    Block(String),
+}
+
+// ICRC-1/ICRC-2 ledger types, limited to what the transfer calls use
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+struct Icrc1Account {
+    owner: Principal,
+    subaccount: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+struct Icrc1TransferArg {
+    from_subaccount: Option<Vec<u8>>,
+    to: Icrc1Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+struct Icrc2TransferFromArgs {
+    spender_subaccount: Option<Vec<u8>>,
+    from: Icrc1Account,
+    to: Icrc1Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+enum Icrc1TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+enum Icrc2TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// Subset of the audit_trail canister's EventType and ResourceType variants
+// used when logging to it
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum AuditEventType {
+    AccountClosure,
+    TransactionExecuted,
+    ComplianceCheck,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum AuditResourceType {
+    CustodyAccount,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AccountClosure {
+    pub account_id: String,
+    pub destination_address: String,
+    pub closure_reason: String,
+    pub owner_approved: bool,
+    pub operator_approval: Option<Principal>,
+    pub requested_at: u64,
+    pub closed_at: Option<u64>,
+    pub sweep_transaction_id: Option<String>,
+    pub audit_entry_id: Option<String>,
+    // Closed accounts are kept for 7 years per regulatory requirement
+    pub retain_until: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum WhitelistAction {
+    Add,
+    Remove,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OperatorPermission {
+    ApproveAccounts,
+    FreezeAccounts,
+    ViewTransactions,
+    ApproveTransactions,
+}
+
+// Operator limited to the accounts of one institution, matched against
+// CustodyAccount.institution_id
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct InstitutionOperator {
+    pub operator: Principal,
+    pub institution_id: String,
+    pub permissions: BTreeSet<OperatorPermission>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum DelegationPermission {
+    InitiateTransactions,
+    // Approve on the delegator's behalf; counts as the delegator's approval
+    ApproveSelf,
+    // Read account statements and analytics
+    ViewOnly,
+}
+
+// Hot key acting for an account owner whose master key stays in cold storage.
+// Covers every account the delegator owns.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct Delegation {
+    pub delegatee: Principal,
+    pub delegated_by: Principal,
+    pub permissions: BTreeSet<DelegationPermission>,
+    pub valid_until: u64,
+    pub max_amount_per_tx: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct TrustAccountDetails {
+    pub trustee: Principal,
+    pub beneficiaries: BTreeSet<Principal>,
+    pub notary: Option<Principal>,
+    pub trust_deed_hash: String,
+    pub distribution_schedule: Option<String>,
+}
+
+// Initiator's appeal of a rejected transaction. The original stays rejected;
+// the appeal runs as a sibling transaction needing one more approval, one of
+// which must be an operator's sign-off.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct DisputeRecord {
+    pub transaction_id: String,
+    pub escalated_transaction_id: String,
+    pub disputed_by: Principal,
+    pub dispute_reason: String,
+    pub escalated_to: Option<Principal>,
+    pub resolution: Option<String>,
+    pub resolved_at: Option<u64>,
+}
+
+// Owner-requested whitelist change, applied once an operator approves it
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct WhitelistChange {
+    pub id: String,
+    pub account_id: String,
+    pub address: String,
+    pub action: WhitelistAction,
+    pub requested_by: Principal,
+    pub requested_at: u64,
+    pub approved_by: Option<Principal>,
+    pub approved_at: Option<u64>,
+    pub audit_entry_id: Option<String>,
+}
+
+// Status the compliance_engine canister assigns to a monitored transaction
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum MonitoringStatus {
+    Clear,
+    Review,
+    Escalated,
+    SarFiled,
+    Blocked,
+}
+
+// Freezing takes one emergency contact; unfreezing takes min_quorum of them
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct EmergencyContactConfig {
+    pub contacts: BTreeSet<Principal>,
+    pub min_quorum: u8,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct FreezeProposal {
+    pub account_id: String,
+    pub approvals: BTreeSet<Principal>,
+}
+
+// Subset of the compliance_engine canister's TransactionMonitoring record
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct TransactionMonitoringStatus {
+    pub id: String,
+    pub status: MonitoringStatus,
+}
+
+// Canisters custody_core calls out to, supplied at install and upgrade
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct IntegrationConfig {
+    pub compliance_canister: Option<Principal>,
+    pub compliance_check_timeout_ns: u64,
+    // BTC withdrawals are broadcast through btc_integration when set, and only
+    // move the internal ledger otherwise
+    pub btc_integration_canister: Option<Principal>,
+}
+
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct CustodySettings {
     pub min_balance_threshold: u64,
@@ -89,26 +351,79 @@ pub struct CustodySettings {
 }
 
 thread_local! {
-    static CUSTODY_ACCOUNTS: RefCell<BTreeMap<String, CustodyAccount>> = RefCell::new(BTreeMap::new());
-    static TRANSACTIONS: RefCell<BTreeMap<String, Transaction>> = RefCell::new(BTreeMap::new());
-    static CUSTODY_SETTINGS: RefCell<CustodySettings> = RefCell::new(CustodySettings {
+    static CUSTODY_ACCOUNTS: RefCell<BTreeMap<String, CustodyAccount>> = const { RefCell::new(BTreeMap::new()) };
+    static TRANSACTIONS: RefCell<BTreeMap<String, Transaction>> = const { RefCell::new(BTreeMap::new()) };
+    static CUSTODY_SETTINGS: RefCell<CustodySettings> = const { RefCell::new(CustodySettings {
         min_balance_threshold: 100_000_000, // 1 BTC in satoshis
         max_transaction_limit: 10_000_000_000, // 100 BTC in satoshis
         emergency_freeze_enabled: true,
         auto_compliance_check: true,
         risk_threshold: 7,
-    });
-    static AUTHORIZED_OPERATORS: RefCell<BTreeSet<Principal>> = RefCell::new(BTreeSet::new());
-    static EMERGENCY_CONTACTS: RefCell<BTreeSet<Principal>> = RefCell::new(BTreeSet::new());
+    }) };
+    static AUTHORIZED_OPERATORS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+    static INSTITUTION_OPERATORS: RefCell<BTreeMap<Principal, InstitutionOperator>> = const { RefCell::new(BTreeMap::new()) };
+    static EMERGENCY_CONFIG: RefCell<EmergencyContactConfig> = const { RefCell::new(EmergencyContactConfig {
+        contacts: BTreeSet::new(),
+        min_quorum: 1,
+    }) };
+    static COMPLIANCE_OFFICERS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+    static RISK_MANAGEMENT_CANISTER: RefCell<Option<Principal>> = const { RefCell::new(None) };
+    static AUDIT_TRAIL_CANISTER: RefCell<Option<Principal>> = const { RefCell::new(None) };
+    static EVENT_BUS_CANISTER: RefCell<Option<Principal>> = const { RefCell::new(None) };
+    static INTEGRATION_CONFIG: RefCell<IntegrationConfig> = const { RefCell::new(IntegrationConfig {
+        compliance_canister: None,
+        compliance_check_timeout_ns: DEFAULT_COMPLIANCE_CHECK_TIMEOUT_NANOS,
+        btc_integration_canister: None,
+    }) };
+    static ACCOUNT_CLOSURES: RefCell<BTreeMap<String, AccountClosure>> = const { RefCell::new(BTreeMap::new()) };
+    static WHITELIST_CHANGES: RefCell<BTreeMap<String, WhitelistChange>> = const { RefCell::new(BTreeMap::new()) };
+    static TRUST_DETAILS: RefCell<BTreeMap<String, TrustAccountDetails>> = const { RefCell::new(BTreeMap::new()) };
+    // "caller:key" -> (result, recorded_at) for retried update calls; no
+    // result yet while the original call is still in flight
+    static IDEMPOTENCY_CACHE: RefCell<BTreeMap<String, (Option<String>, u64)>> = const { RefCell::new(BTreeMap::new()) };
+    // (recorded_at, key) in the order IDEMPOTENCY_CACHE entries were written
+    static IDEMPOTENCY_EXPIRY: RefCell<VecDeque<(u64, String)>> = const { RefCell::new(VecDeque::new()) };
+    // request content hash -> (transaction id, recorded_at) for retries sent
+    // without a key; no id yet while the original call is still in flight
+    static DEDUP_CACHE: RefCell<BTreeMap<String, (Option<String>, u64)>> = const { RefCell::new(BTreeMap::new()) };
+    // (recorded_at, key) in the order DEDUP_CACHE entries were written
+    static DEDUP_EXPIRY: RefCell<VecDeque<(u64, String)>> = const { RefCell::new(VecDeque::new()) };
+    // external_reference -> transaction id
+    static REFERENCE_INDEX: RefCell<BTreeMap<String, String>> = const { RefCell::new(BTreeMap::new()) };
+    // rejected transaction id -> its dispute
+    static DISPUTES: RefCell<BTreeMap<String, DisputeRecord>> = const { RefCell::new(BTreeMap::new()) };
+    // account id -> contacts who approved unfreezing it so far
+    static PENDING_UNFREEZE: RefCell<BTreeMap<String, FreezeProposal>> = const { RefCell::new(BTreeMap::new()) };
+    // delegatee -> delegations granted to it by account owners
+    static DELEGATIONS: RefCell<BTreeMap<Principal, Vec<Delegation>>> = const { RefCell::new(BTreeMap::new()) };
+    // Set while an upgrade is being prepared; new update calls are turned away
+    static UPGRADE_PENDING: RefCell<bool> = const { RefCell::new(false) };
+    // Spawned tasks that have not finished yet
+    static IN_FLIGHT_COUNT: RefCell<u64> = const { RefCell::new(0) };
 }
 
+const IDEMPOTENCY_TTL_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+const DEDUP_TTL_NANOS: u64 = 5 * 60 * 1_000_000_000;
+const DEFAULT_COMPLIANCE_CHECK_TIMEOUT_NANOS: u64 = 30 * 1_000_000_000;
+const CLOSED_ACCOUNT_RETENTION_NANOS: u64 = 7 * 365 * 24 * 60 * 60 * 1_000_000_000;
+// A closure request the co-approver hasn't confirmed within a week lapses
+const CLOSURE_REQUEST_TTL_NANOS: u64 = 7 * 24 * 60 * 60 * 1_000_000_000;
+const MAX_MEMO_LENGTH: usize = 256;
+const DISPUTE_WINDOW_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+const COMPLIANCE_CHECK_CACHE_NANOS: u64 = 15 * 60 * 1_000_000_000;
+
 #[init]
-fn init() {
+fn init(auth_canister: Option<Principal>, integration_config: Option<IntegrationConfig>) {
     ic_cdk::println!("Custody Core canister initialized");
     
+    set_auth_canister(auth_canister);
+    set_integration_config(integration_config);
+    cycles::start_cycle_monitor(cycles::forward_cycle_alert);
+    memory::start_memory_monitor(notify_high_memory);
+    
     // Initialize with deployer as emergency contact
-    EMERGENCY_CONTACTS.with(|contacts| {
-        contacts.borrow_mut().insert(ic_cdk::caller());
+    EMERGENCY_CONFIG.with(|config| {
+        config.borrow_mut().contacts.insert(ic_cdk::caller());
     });
 }
 
@@ -118,8 +433,12 @@ fn pre_upgrade() {
 }
 
 #[post_upgrade]
-fn post_upgrade() {
+fn post_upgrade(auth_canister: Option<Principal>, integration_config: Option<IntegrationConfig>) {
     // Stable storage restoration would go here
+    set_auth_canister(auth_canister);
+    set_integration_config(integration_config);
+    cycles::start_cycle_monitor(cycles::forward_cycle_alert);
+    memory::start_memory_monitor(notify_high_memory);
 }
 
 // === Account Management Functions ===
@@ -129,25 +448,32 @@ fn create_custody_account(
     institution_name: String,
     account_type: AccountType,
     required_approvals: u8,
-) -> Result<String, String> {
+) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "create_custody_account")?;
     
     // Validate input
     if institution_name.is_empty() {
-        return Err("Institution name cannot be empty".to_string());
+        return Err(CustodyError::invalid_input("institution_name", "cannot be empty"));
     }
     
     if required_approvals == 0 || required_approvals > 10 {
-        return Err("Required approvals must be between 1 and 10".to_string());
+        return Err(CustodyError::invalid_input("required_approvals", "must be between 1 and 10"));
     }
     
     let account_id = Uuid::new_v4().to_string();
     let current_time = ic_cdk::api::time();
     
+    // Accounts opened by an institution operator belong to its institution
+    let institution_id = INSTITUTION_OPERATORS.with(|ops| {
+        ops.borrow().get(&caller).map(|op| op.institution_id.clone())
+    });
+    
     let account = CustodyAccount {
         id: account_id.clone(),
         owner: caller,
         institution_name,
+        institution_id,
         account_type,
         status: AccountStatus::PendingApproval,
         created_at: current_time,
@@ -156,6 +482,10 @@ fn create_custody_account(
         authorized_users: BTreeSet::from([caller]),
         required_approvals,
         compliance_status: ComplianceStatus::PendingKyc,
+        last_compliance_check_at: None,
+        withdrawal_whitelist: None,
+        withdrawal_blacklist: BTreeSet::new(),
+        multi_asset_balances: Vec::new(),
     };
     
     CUSTODY_ACCOUNTS.with(|accounts| {
@@ -167,60 +497,272 @@ fn create_custody_account(
 }
 
 #[update]
-fn approve_custody_account(account_id: String) -> Result<String, String> {
+async fn approve_custody_account(account_id: String, idempotency_key: Option<String>) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "approve_custody_account")?;
     
-    // Check if caller is authorized operator
-    let is_authorized = AUTHORIZED_OPERATORS.with(|ops| {
-        ops.borrow().contains(&caller)
-    });
+    let idempotency_key = idempotency_key.map(|key| idempotency_cache_key(&caller, &key));
+    if let Some(cached) = reserve_idempotency_key(idempotency_key.as_deref())? {
+        return Ok(cached);
+    }
+    
+    let result = activate_custody_account(caller, account_id).await;
+    
+    record_idempotent_result(idempotency_key, &result);
+    result
+}
+
+async fn activate_custody_account(caller: Principal, account_id: String) -> Result<String, CustodyError> {
+    let account = match CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().get(&account_id).cloned()) {
+        Some(acc) => acc,
+        None => return Err(CustodyError::not_found("Account", account_id)),
+    };
+    
+    // Check if caller is an operator for the account's institution
+    let is_authorized = is_account_operator(caller, &account, OperatorPermission::ApproveAccounts).await;
     
     if !is_authorized {
-        return Err("Unauthorized operator".to_string());
+        return Err(CustodyError::unauthorized("approve_custody_account"));
+    }
+    
+    // Only new accounts are approved here; a frozen account goes through the
+    // emergency unfreeze quorum instead
+    if account.status != AccountStatus::PendingApproval {
+        return Err(CustodyError::status_conflict(format!("{:?}", account.status), "PendingApproval"));
     }
     
+    // The owner must pass KYC before the account can be activated
+    let compliance_status = screen_account_owner(&account).await?;
+    
     CUSTODY_ACCOUNTS.with(|accounts| {
         let mut accounts_map = accounts.borrow_mut();
         match accounts_map.get_mut(&account_id) {
             Some(account) => {
+                // Re-checked because the account may have changed during screening
+                if account.status != AccountStatus::PendingApproval {
+                    return Err(CustodyError::status_conflict(format!("{:?}", account.status), "PendingApproval"));
+                }
                 account.status = AccountStatus::Active;
-                account.compliance_status = ComplianceStatus::Compliant;
+                account.compliance_status = compliance_status;
                 Ok("Account approved successfully".to_string())
             },
-            None => Err("Account not found".to_string()),
+            None => Err(CustodyError::not_found("Account", account_id.clone())),
         }
     })
 }
 
+// Government and institutional accounts may open while their KYC review is
+// still pending; every other account type needs an approved profile first
+fn accepts_pending_kyc(account_type: &AccountType) -> bool {
+    matches!(account_type, AccountType::GovernmentCustody | AccountType::InstitutionalCustody)
+}
+
+// Compliance gate for account activation. A passing answer from the last
+// COMPLIANCE_CHECK_CACHE_NANOS is reused instead of calling out again.
+async fn screen_account_owner(account: &CustodyAccount) -> Result<ComplianceStatus, CustodyError> {
+    let now = ic_cdk::api::time();
+    let acceptable = |status: &ComplianceStatus| match status {
+        ComplianceStatus::Compliant => true,
+        ComplianceStatus::PendingKyc => accepts_pending_kyc(&account.account_type),
+        _ => false,
+    };
+    
+    let cached = account.last_compliance_check_at
+        .is_some_and(|checked_at| now.saturating_sub(checked_at) < COMPLIANCE_CHECK_CACHE_NANOS);
+    if cached && acceptable(&account.compliance_status) {
+        return Ok(account.compliance_status.clone());
+    }
+    
+    let (compliance_status, failure) = match fetch_compliance_status(account.owner).await {
+        Ok(status) if status == "Compliant" => (ComplianceStatus::Compliant, None),
+        Ok(status) if status == "Pending KYC" => (ComplianceStatus::PendingKyc, Some(status)),
+        Ok(status) => (ComplianceStatus::RequiresReview, Some(status)),
+        Err(CustodyError::InternalError(msg)) => return Err(CustodyError::InternalError(msg)),
+        Err(e) => (ComplianceStatus::NonCompliant, Some(e.to_string())),
+    };
+    
+    // Only answers from the compliance canister are recorded; an unreachable
+    // canister leaves the previous result in place
+    CUSTODY_ACCOUNTS.with(|accounts| {
+        if let Some(acc) = accounts.borrow_mut().get_mut(&account.id) {
+            acc.compliance_status = compliance_status.clone();
+            acc.last_compliance_check_at = Some(now);
+        }
+    });
+    
+    if acceptable(&compliance_status) {
+        return Ok(compliance_status);
+    }
+    
+    Err(CustodyError::status_conflict(
+        format!("Compliance check failed: {}", failure.unwrap_or_default()),
+        "Compliant",
+    ))
+}
+
 #[update]
-fn add_authorized_user(account_id: String, user: Principal) -> Result<String, String> {
+fn add_authorized_user(
+    account_id: String,
+    user: Principal,
+    idempotency_key: Option<String>,
+) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_authorized_user")?;
     
-    CUSTODY_ACCOUNTS.with(|accounts| {
+    let idempotency_key = idempotency_key.map(|key| idempotency_cache_key(&caller, &key));
+    if let Some(cached) = reserve_idempotency_key(idempotency_key.as_deref())? {
+        return Ok(cached);
+    }
+    
+    let result = CUSTODY_ACCOUNTS.with(|accounts| {
         let mut accounts_map = accounts.borrow_mut();
         match accounts_map.get_mut(&account_id) {
             Some(account) => {
                 if account.owner != caller {
-                    return Err("Only account owner can add authorized users".to_string());
+                    return Err(CustodyError::unauthorized("add_authorized_user"));
                 }
                 account.authorized_users.insert(user);
                 Ok("User authorized successfully".to_string())
             },
-            None => Err("Account not found".to_string()),
+            None => Err(CustodyError::not_found("Account", account_id.clone())),
         }
-    })
+    });
+    
+    record_idempotent_result(idempotency_key, &result);
+    result
 }
 
 // === Transaction Functions ===
 
 #[update]
-fn initiate_transaction(
+async fn initiate_transaction(
     account_id: String,
     transaction_type: TransactionType,
     amount: u64,
     recipient: Option<String>,
-) -> Result<String, String> {
+    idempotency_key: Option<String>,
+    memo: Option<String>,
+    external_reference: Option<String>,
+) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "initiate_transaction")?;
+    
+    let idempotency_key = idempotency_key.map(|key| idempotency_cache_key(&caller, &key));
+    if let Some(cached) = reserve_idempotency_key(idempotency_key.as_deref())? {
+        return Ok(cached);
+    }
+    
+    // A request with an idempotency key is deduplicated by the key alone, so
+    // two keyed payments with the same content both go through
+    let dedup_key = match idempotency_key {
+        Some(_) => None,
+        None => Some(dedup_cache_key(
+            &caller,
+            &account_id,
+            amount,
+            &transaction_type,
+            recipient.as_deref(),
+            external_reference.as_deref(),
+        )),
+    };
+    if let Some(dedup_key) = &dedup_key {
+        if let Some(transaction_id) = reserve_dedup_key(dedup_key)? {
+            return Ok(transaction_id);
+        }
+    }
+
+    let result = initiate_transaction_internal(
+        account_id,
+        transaction_type,
+        amount,
+        recipient,
+        None,
+        memo,
+        external_reference,
+    ).await;
+    
+    record_idempotent_result(idempotency_key, &result);
+    if let Some(dedup_key) = dedup_key {
+        record_deduplicated_transaction(dedup_key, &result);
+    }
+    result
+}
+
+#[query]
+fn find_transaction_by_reference(reference: String) -> Option<Transaction> {
+    let transaction_id = REFERENCE_INDEX.with(|index| index.borrow().get(&reference).cloned())?;
+    TRANSACTIONS.with(|txns| txns.borrow().get(&transaction_id).cloned())
+}
+
+fn validate_transaction_memo(memo: Option<&str>) -> Result<(), CustodyError> {
+    if memo.is_some_and(|memo| memo.chars().count() > MAX_MEMO_LENGTH) {
+        return Err(CustodyError::invalid_input("memo", format!("must be at most {} characters", MAX_MEMO_LENGTH)));
+    }
+    
+    Ok(())
+}
+
+fn check_external_reference(reference: Option<&str>) -> Result<(), CustodyError> {
+    let Some(reference) = reference else {
+        return Ok(());
+    };
+    
+    if reference.trim().is_empty() {
+        return Err(CustodyError::invalid_input("external_reference", "must not be empty"));
+    }
+    
+    if REFERENCE_INDEX.with(|index| index.borrow().contains_key(reference)) {
+        return Err(CustodyError::invalid_input("external_reference", "is already used by another transaction"));
+    }
+    
+    Ok(())
+}
+
+// Shared by the public endpoint and the multisig/scheduled paths, which have
+// already been rate limited under their own method names
+async fn initiate_transaction_internal(
+    account_id: String,
+    transaction_type: TransactionType,
+    amount: u64,
+    recipient: Option<String>,
+    token_canister_id: Option<Principal>,
+    memo: Option<String>,
+    external_reference: Option<String>,
+) -> Result<String, CustodyError> {
+    initiate_transaction_as(
+        ic_cdk::caller(),
+        account_id,
+        transaction_type,
+        amount,
+        recipient,
+        token_canister_id,
+        memo,
+        external_reference,
+    ).await
+}
+
+// Initiates on behalf of `initiator`, who must be authorized on the account
+// exactly as a direct caller would be
+#[allow(clippy::too_many_arguments)]
+async fn initiate_transaction_as(
+    initiator: Principal,
+    account_id: String,
+    transaction_type: TransactionType,
+    amount: u64,
+    recipient: Option<String>,
+    token_canister_id: Option<Principal>,
+    memo: Option<String>,
+    external_reference: Option<String>,
+) -> Result<String, CustodyError> {
+    let caller = initiator;
+    
+    // New transactions grow state, so they wait until memory is back in hand
+    if memory::is_memory_critical() {
+        return Err(CustodyError::status_conflict("memory critically high", "memory below alert threshold"));
+    }
+    
+    validate_transaction_memo(memo.as_deref())?;
+    check_external_reference(external_reference.as_deref())?;
     
     // Validate account and authorization
     let account = CUSTODY_ACCOUNTS.with(|accounts| {
@@ -229,34 +771,56 @@ fn initiate_transaction(
     
     let account = match account {
         Some(acc) => acc,
-        None => return Err("Account not found".to_string()),
+        None => return Err(CustodyError::not_found("Account", account_id)),
     };
     
-    if !account.authorized_users.contains(&caller) {
-        return Err("Unauthorized user".to_string());
-    }
+    // A delegate signs as its delegator, so the owner and their hot key
+    // together only ever count as one approval
+    let signer = if account.authorized_users.contains(&caller) {
+        caller
+    } else {
+        let delegation = active_delegation(caller, account.owner, DelegationPermission::InitiateTransactions)
+            .ok_or_else(|| CustodyError::unauthorized("initiate_transaction"))?;
+        check_delegated_amount(&delegation, amount)?;
+        delegation.delegated_by
+    };
     
     if account.status != AccountStatus::Active {
-        return Err("Account is not active".to_string());
+        return Err(CustodyError::status_conflict(format!("{:?}", account.status), "Active"));
     }
     
-    // Check balance for withdrawals and transfers
+    // Check balance and destination for withdrawals and transfers
     match transaction_type {
         TransactionType::Withdrawal | TransactionType::Transfer => {
-            if account.balance < amount {
-                return Err("Insufficient balance".to_string());
+            let available = match token_canister_id {
+                Some(token_canister_id) => asset_balance(&account, token_canister_id),
+                None => account.balance.saturating_sub(account.reserved_balance),
+            };
+            
+            if available < amount {
+                return Err(CustodyError::InsufficientBalance {
+                    available,
+                    required: amount,
+                });
             }
+            check_withdrawal_destination(&account, recipient.as_deref())?;
         },
         _ => {}
     }
     
+    let notary = trust_withdrawal_notary(&account, &transaction_type)?;
+    
     // Check transaction limits
     let max_limit = CUSTODY_SETTINGS.with(|settings| {
         settings.borrow().max_transaction_limit
     });
     
-    if amount > max_limit {
-        return Err("Transaction exceeds maximum limit".to_string());
+    // The limit is in satoshis, so it only applies to BTC
+    if token_canister_id.is_none() && amount > max_limit {
+        return Err(CustodyError::LimitExceeded {
+            limit: max_limit,
+            actual: amount,
+        });
     }
     
     let transaction_id = Uuid::new_v4().to_string();
@@ -265,6 +829,17 @@ fn initiate_transaction(
     // Calculate risk score (simplified)
     let risk_score = calculate_risk_score(&transaction_type, amount, &account);
     
+    // Let the risk management canister block or hold the transaction
+    let required_risk_reviews = match evaluate_transaction_risk(&account, &transaction_id, &transaction_type, amount, &recipient).await? {
+        RiskDecision::Allow => 0,
+        RiskDecision::ReviewRequired => 1,
+        RiskDecision::DualReviewRequired => 2,
+        RiskDecision::Block(reason) => return Err(CustodyError::RiskBlocked { reason }),
+    };
+    
+    // Another transaction may have claimed the reference meanwhile
+    check_external_reference(external_reference.as_deref())?;
+    
     let transaction = Transaction {
         id: transaction_id.clone(),
         account_id: account_id.clone(),
@@ -273,40 +848,69 @@ fn initiate_transaction(
         recipient,
         status: TransactionStatus::Pending,
         initiated_by: caller,
-        approvals: BTreeSet::from([caller]),
+        approvals: BTreeSet::from([signer]),
         required_approvals: account.required_approvals,
         created_at: current_time,
         executed_at: None,
         compliance_checked: false,
         risk_score,
+        requires_risk_review: required_risk_reviews > 0,
+        risk_reviewers: BTreeSet::new(),
+        required_risk_reviews,
+        notary,
+        notary_approved: false,
+        token_canister_id,
+        memo,
+        external_reference,
+        rejected_at: None,
+        rejection_reason: None,
+        bitcoin_transaction: None,
     };
     
+    // Re-check the account, which may have changed during the risk call, and
+    // reserve in the same borrow so concurrent initiations can't both spend
+    // the same balance
+    CUSTODY_ACCOUNTS.with(|accounts| {
+        let mut accounts_map = accounts.borrow_mut();
+        let account = accounts_map.get_mut(&account_id)
+            .filter(|acc| acc.status == AccountStatus::Active)
+            .ok_or_else(|| CustodyError::status_conflict("not active", "Active"))?;
+        
+        if reserves_balance(&transaction) {
+            let available = account.balance.saturating_sub(account.reserved_balance);
+            if available < amount {
+                return Err(CustodyError::InsufficientBalance {
+                    available,
+                    required: amount,
+                });
+            }
+            account.reserved_balance += amount;
+        }
+        Ok(())
+    })?;
+    
+    if let Some(reference) = &transaction.external_reference {
+        REFERENCE_INDEX.with(|index| {
+            index.borrow_mut().insert(reference.clone(), transaction_id.clone());
+        });
+    }
+    
     TRANSACTIONS.with(|txns| {
         txns.borrow_mut().insert(transaction_id.clone(), transaction);
     });
     
-    // Reserve balance for withdrawals/transfers
-    match transaction.transaction_type {
-        TransactionType::Withdrawal | TransactionType::Transfer => {
-            CUSTODY_ACCOUNTS.with(|accounts| {
-                let mut accounts_map = accounts.borrow_mut();
-                if let Some(account) = accounts_map.get_mut(&account_id) {
-                    account.reserved_balance += amount;
-                }
-            });
-        },
-        _ => {}
-    }
-    
     ic_cdk::println!("Transaction initiated: {}", transaction_id);
     Ok(transaction_id)
 }
 
 #[update]
-fn approve_transaction(transaction_id: String) -> Result<String, String> {
+fn approve_transaction(transaction_id: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "approve_transaction")?;
     
-    TRANSACTIONS.with(|txns| {
+    // Spawned futures run straight away, so execution starts only once the
+    // transactions borrow has been released
+    let ready = TRANSACTIONS.with(|txns| {
         let mut txns_map = txns.borrow_mut();
         match txns_map.get_mut(&transaction_id) {
             Some(transaction) => {
@@ -317,30 +921,97 @@ fn approve_transaction(transaction_id: String) -> Result<String, String> {
                 
                 let account = match account {
                     Some(acc) => acc,
-                    None => return Err("Account not found".to_string()),
+                    None => return Err(CustodyError::not_found("Account", transaction.account_id.clone())),
+                };
+                
+                let is_institution_approver = institution_permission(
+                    caller,
+                    &account,
+                    OperatorPermission::ApproveTransactions,
+                ) == Some(true);
+                
+                let is_notary = transaction.notary == Some(caller);
+                
+                let is_direct_approver = account.authorized_users.contains(&caller) || is_institution_approver || is_notary;
+                let delegation = if is_direct_approver {
+                    None
+                } else {
+                    active_delegation(caller, account.owner, DelegationPermission::ApproveSelf)
                 };
                 
-                if !account.authorized_users.contains(&caller) {
-                    return Err("Unauthorized user".to_string());
+                if !is_direct_approver && delegation.is_none() {
+                    return Err(CustodyError::unauthorized("approve_transaction"));
+                }
+                
+                if let Some(delegation) = &delegation {
+                    check_delegated_amount(delegation, transaction.amount)?;
                 }
                 
                 if transaction.status != TransactionStatus::Pending {
-                    return Err("Transaction not in pending status".to_string());
+                    return Err(CustodyError::status_conflict(format!("{:?}", transaction.status), "Pending"));
                 }
                 
-                transaction.approvals.insert(caller);
+                // The notary witnesses the withdrawal rather than counting as an approver;
+                // a delegate approves as its delegator so a hot key adds no extra signature
+                if is_notary {
+                    transaction.notary_approved = true;
+                } else if let Some(delegation) = delegation {
+                    transaction.approvals.insert(delegation.delegated_by);
+                } else {
+                    transaction.approvals.insert(caller);
+                }
                 
-                // Check if we have enough approvals
-                if transaction.approvals.len() >= transaction.required_approvals as usize {
+                // Risk holds wait for a compliance officer, trust withdrawals for the notary
+                let ready = ready_for_execution(transaction);
+                if ready {
                     transaction.status = TransactionStatus::Approved;
-                    ic_cdk::spawn(execute_transaction_async(transaction_id.clone()));
                 }
                 
-                Ok("Transaction approved".to_string())
+                Ok(ready)
             },
-            None => Err("Transaction not found".to_string()),
+            None => Err(CustodyError::not_found("Transaction", transaction_id.clone())),
         }
-    })
+    })?;
+    
+    if ready {
+        spawn_tracked(execute_transaction_async(transaction_id));
+    }
+    
+    Ok("Transaction approved".to_string())
+}
+
+/// Withdraws a pending transaction; only its initiator or the account owner
+/// may cancel it
+#[update]
+fn cancel_transaction(transaction_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "cancel_transaction")?;
+    
+    let transaction = TRANSACTIONS.with(|txns| {
+        let mut txns_map = txns.borrow_mut();
+        let transaction = txns_map.get_mut(&transaction_id)
+            .ok_or_else(|| CustodyError::not_found("Transaction", transaction_id.clone()))?;
+        
+        let owner = CUSTODY_ACCOUNTS.with(|accounts| {
+            accounts.borrow().get(&transaction.account_id).map(|acc| acc.owner)
+        });
+        
+        if transaction.initiated_by != caller && owner != Some(caller) {
+            return Err(CustodyError::unauthorized("cancel_transaction"));
+        }
+        
+        if transaction.status != TransactionStatus::Pending {
+            return Err(CustodyError::status_conflict(format!("{:?}", transaction.status), "Pending"));
+        }
+        
+        transaction.status = TransactionStatus::Cancelled;
+        Ok(transaction.clone())
+    })?;
+    
+    release_reserved_balance(&transaction);
+    
+    ic_cdk::println!("Transaction {} cancelled by {}", transaction_id, caller);
+    Ok("Transaction cancelled".to_string())
 }
 
 async fn execute_transaction_async(transaction_id: String) {
@@ -351,43 +1022,67 @@ async fn execute_transaction_async(transaction_id: String) {
     }
 }
 
-async fn execute_transaction(transaction_id: String) -> Result<String, String> {
+async fn execute_transaction(transaction_id: String) -> Result<String, CustodyError> {
     let transaction = TRANSACTIONS.with(|txns| {
         txns.borrow().get(&transaction_id).cloned()
     });
     
     let transaction = match transaction {
         Some(txn) => txn,
-        None => return Err("Transaction not found".to_string()),
+        None => return Err(CustodyError::not_found("Transaction", transaction_id)),
     };
     
     if transaction.status != TransactionStatus::Approved {
-        return Err("Transaction not approved".to_string());
+        return Err(CustodyError::status_conflict(format!("{:?}", transaction.status), "Approved"));
+    }
+    
+    if transaction.requires_risk_review {
+        return Err(CustodyError::status_conflict("awaiting risk review", "risk review released"));
+    }
+    
+    if transaction.notary.is_some() && !transaction.notary_approved {
+        return Err(CustodyError::status_conflict("awaiting notary approval", "notary approved"));
     }
     
+    // Claim the transaction before the first await so a second execution
+    // cannot start while a ledger call is still in flight
+    set_transaction_status(&transaction_id, TransactionStatus::Executing);
+    
     // Execute the transaction
-    match transaction.transaction_type {
+    let outcome = match transaction.transaction_type {
         TransactionType::Deposit => {
             CUSTODY_ACCOUNTS.with(|accounts| {
                 let mut accounts_map = accounts.borrow_mut();
                 if let Some(account) = accounts_map.get_mut(&transaction.account_id) {
                     account.balance += transaction.amount;
+                    record_balance_snapshot(&account.id, account.balance);
                 }
             });
+            Ok(())
         },
-        TransactionType::Withdrawal | TransactionType::Transfer => {
-            CUSTODY_ACCOUNTS.with(|accounts| {
-                let mut accounts_map = accounts.borrow_mut();
-                if let Some(account) = accounts_map.get_mut(&transaction.account_id) {
-                    account.balance -= transaction.amount;
-                    account.reserved_balance -= transaction.amount;
-                }
-            });
+        TransactionType::Withdrawal | TransactionType::Transfer => match transaction.token_canister_id {
+            Some(token_canister_id) => send_asset_withdrawal(&transaction, token_canister_id).await,
+            None => match INTEGRATION_CONFIG.with(|c| c.borrow().btc_integration_canister) {
+                Some(btc_canister) => broadcast_bitcoin_withdrawal(&transaction, btc_canister).await,
+                None => {
+                    debit_reserved_balance(&transaction.account_id, transaction.amount);
+                    Ok(())
+                },
+            },
         },
         TransactionType::Emergency => {
-            // Emergency transactions require special handling
-            emergency_freeze_account(&transaction.account_id)?;
+            // The transaction already passed its approvals, and caller() here is
+            // not an emergency contact, so this skips the endpoint's auth check
+            emergency_freeze_account_internal(&transaction.account_id).map(|_| ())
         },
+    };
+    
+    // A failed step has already been rolled back (the withdrawal helpers
+    // restore what they debited), so the transaction goes back to Pending with
+    // its approvals and reservation intact for retry_failed_execution
+    if let Err(e) = outcome {
+        set_transaction_status(&transaction_id, TransactionStatus::Pending);
+        return Err(e);
     }
     
     // Update transaction status
@@ -399,64 +1094,1626 @@ async fn execute_transaction(transaction_id: String) -> Result<String, String> {
         }
     });
     
+    resolve_dispute(&transaction_id, "Approved on escalation".to_string(), ic_cdk::api::time());
+    
+    let closes_account = ACCOUNT_CLOSURES.with(|closures| {
+        closures.borrow().get(&transaction.account_id).is_some_and(|closure| {
+            closure.closed_at.is_none() && closure.sweep_transaction_id.as_deref() == Some(transaction_id.as_str())
+        })
+    });
+    if closes_account {
+        spawn_tracked(finalize_account_closure(transaction.account_id.clone()));
+    }
+    
+    let details = format!(
+        "Executed {:?} transaction {} for {} satoshis",
+        transaction.transaction_type,
+        transaction_id,
+        transaction.amount,
+    );
+    let account_id = transaction.account_id.clone();
+    spawn_tracked(async move {
+        log_account_audit_event(AuditEventType::TransactionExecuted, &account_id, "execute_transaction", details).await;
+    });
+    
+    if INTEGRATION_CONFIG.with(|c| c.borrow().compliance_canister.is_some()) {
+        // Monitoring is attributed to whoever initiated the transaction
+        let context = RequestContext::new(transaction.initiated_by, transaction_id.clone());
+        spawn_tracked(notify_compliance(
+            context,
+            transaction_id,
+            transaction.account_id.clone(),
+            transaction.amount,
+            format!("{:?}", transaction.transaction_type),
+        ));
+    }
+    
+    if let Some(event_bus) = EVENT_BUS_CANISTER.with(|c| *c.borrow()) {
+        spawn_tracked(events::publish_event(event_bus, events::TOPIC_TRANSACTION_EXECUTED, TransactionExecutedEvent {
+            account_id: transaction.account_id.clone(),
+            transaction_id: transaction.id.clone(),
+            amount: transaction.amount,
+            transaction_type: format!("{:?}", transaction.transaction_type),
+            initiated_by: transaction.initiated_by,
+        }));
+    }
+    
     Ok("Transaction executed successfully".to_string())
 }
 
-// === Emergency Functions ===
+fn set_transaction_status(transaction_id: &str, status: TransactionStatus) {
+    TRANSACTIONS.with(|txns| {
+        if let Some(txn) = txns.borrow_mut().get_mut(transaction_id) {
+            txn.status = status;
+        }
+    });
+}
 
+/// Attaches the signed Bitcoin transaction a BTC withdrawal is broadcast as.
+/// Only the initiator may attach it, and any other approvals are cleared
+/// since approvers sign off on the exact transaction that goes out
 #[update]
-fn emergency_freeze_account(account_id: String) -> Result<String, String> {
-    let account_id = &account_id;
+fn attach_bitcoin_transaction(transaction_id: String, raw_tx_hex: String, txid: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "attach_bitcoin_transaction")?;
     
-    // Check if caller is emergency contact
-    let is_emergency_contact = EMERGENCY_CONTACTS.with(|contacts| {
-        contacts.borrow().contains(&caller)
-    });
+    if raw_tx_hex.is_empty() {
+        return Err(CustodyError::invalid_input("raw_tx_hex", "cannot be empty"));
+    }
     
-    if !is_emergency_contact {
-        return Err("Unauthorized emergency action".to_string());
+    if txid.len() != 64 || !txid.chars().all(|c| c.is_ascii_hexdigit()) {
+        return Err(CustodyError::invalid_input("txid", "must be 64 hex characters"));
     }
     
-    CUSTODY_ACCOUNTS.with(|accounts| {
-        let mut accounts_map = accounts.borrow_mut();
-        match accounts_map.get_mut(account_id) {
-            Some(account) => {
+    TRANSACTIONS.with(|txns| {
+        let mut txns_map = txns.borrow_mut();
+        let transaction = txns_map.get_mut(&transaction_id)
+            .ok_or_else(|| CustodyError::not_found("Transaction", transaction_id.clone()))?;
+        
+        if transaction.initiated_by != caller {
+            return Err(CustodyError::unauthorized("attach_bitcoin_transaction"));
+        }
+        
+        if !reserves_balance(transaction) {
+            return Err(CustodyError::invalid_input("transaction_id", "not a BTC withdrawal"));
+        }
+        
+        if transaction.status != TransactionStatus::Pending {
+            return Err(CustodyError::status_conflict(format!("{:?}", transaction.status), "Pending"));
+        }
+        
+        transaction.bitcoin_transaction = Some(SignedBitcoinTransaction { raw_tx_hex, txid });
+        transaction.approvals = BTreeSet::from([caller]);
+        transaction.notary_approved = false;
+        Ok(())
+    })?;
+    
+    Ok("Signed transaction attached".to_string())
+}
+
+/// Re-runs a fully approved transaction whose execution failed and left it
+/// Pending. Transactions stuck in Executing are not retried, since their
+/// ledger call may already have gone through
+#[update]
+async fn retry_failed_execution(transaction_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "retry_failed_execution")?;
+    
+    let transaction = TRANSACTIONS.with(|txns| txns.borrow().get(&transaction_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Transaction", transaction_id.clone()))?;
+    
+    let account = CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().get(&transaction.account_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Account", transaction.account_id.clone()))?;
+    
+    if !is_account_operator(caller, &account, OperatorPermission::ApproveTransactions).await {
+        return Err(CustodyError::unauthorized("retry_failed_execution"));
+    }
+    
+    // Re-check the status, which may have changed during the role check
+    TRANSACTIONS.with(|txns| {
+        let mut txns_map = txns.borrow_mut();
+        let transaction = txns_map.get_mut(&transaction_id)
+            .ok_or_else(|| CustodyError::not_found("Transaction", transaction_id.clone()))?;
+        
+        if transaction.status != TransactionStatus::Pending {
+            return Err(CustodyError::status_conflict(format!("{:?}", transaction.status), "Pending"));
+        }
+        
+        if !ready_for_execution(transaction) {
+            return Err(CustodyError::status_conflict("awaiting approvals", "ready for execution"));
+        }
+        
+        transaction.status = TransactionStatus::Approved;
+        Ok(())
+    })?;
+    
+    ic_cdk::println!("Transaction {} execution retried by {}", transaction_id, caller);
+    execute_transaction(transaction_id).await
+}
+
+// Reports an executed transaction to the compliance_engine canister for AML
+// monitoring and freezes the account if the transaction gets escalated
+async fn notify_compliance(
+    context: RequestContext,
+    transaction_id: String,
+    account_id: String,
+    amount: u64,
+    transaction_type: String,
+) {
+    let compliance_canister = match INTEGRATION_CONFIG.with(|c| c.borrow().compliance_canister) {
+        Some(canister) => canister,
+        None => return,
+    };
+    
+    let result: Result<(Result<String, CustodyError>,), _> = ic_cdk::call(
+        compliance_canister,
+        "monitor_transaction",
+        (account_id.clone(), transaction_id.clone(), amount, transaction_type, Some(context)),
+    ).await;
+    
+    let monitoring_id = match result {
+        Ok((Ok(monitoring_id),)) => monitoring_id,
+        Ok((Err(e),)) => {
+            ic_cdk::println!("Compliance monitoring rejected transaction {}: {}", transaction_id, e);
+            return;
+        },
+        Err((code, msg)) => {
+            ic_cdk::println!("Compliance monitoring call failed for {}: {:?} {}", transaction_id, code, msg);
+            return;
+        },
+    };
+    
+    let monitoring: Result<(Option<TransactionMonitoringStatus>,), _> = ic_cdk::call(
+        compliance_canister,
+        "get_transaction_monitoring",
+        (monitoring_id,),
+    ).await;
+    
+    if let Ok((Some(monitoring),)) = monitoring {
+        if monitoring.status == MonitoringStatus::Escalated {
+            match emergency_freeze_account_internal(&account_id) {
+                Ok(_) => ic_cdk::println!("Froze account {} after escalated transaction {}", account_id, transaction_id),
+                Err(e) => ic_cdk::println!("Failed to freeze account {}: {}", account_id, e),
+            }
+        }
+    }
+}
+
+#[update]
+async fn release_risk_review(transaction_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "release_risk_review")?;
+    
+    // Check if caller is compliance officer
+    let is_officer = holds_role(&COMPLIANCE_OFFICERS, caller, "compliance_officer").await;
+    
+    if !is_officer {
+        return Err(CustodyError::unauthorized("release_risk_review"));
+    }
+    
+    // As in approve_transaction, execution is spawned after the borrow ends
+    let ready = TRANSACTIONS.with(|txns| {
+        let mut txns_map = txns.borrow_mut();
+        match txns_map.get_mut(&transaction_id) {
+            Some(transaction) => {
+                if !transaction.requires_risk_review {
+                    return Err(CustodyError::status_conflict("not awaiting risk review", "awaiting risk review"));
+                }
+                
+                if transaction.status != TransactionStatus::Pending {
+                    return Err(CustodyError::status_conflict(format!("{:?}", transaction.status), "Pending"));
+                }
+                
+                if !transaction.risk_reviewers.insert(caller) {
+                    return Err(CustodyError::status_conflict("already reviewed by caller", "not yet reviewed by caller"));
+                }
+                
+                // High scores need sign-off from two different officers
+                if transaction.risk_reviewers.len() < transaction.required_risk_reviews as usize {
+                    return Ok(None);
+                }
+                
+                transaction.requires_risk_review = false;
+                
+                // Proceed if approvals were already collected while on hold
+                let ready = ready_for_execution(transaction);
+                if ready {
+                    transaction.status = TransactionStatus::Approved;
+                }
+                
+                Ok(Some(ready))
+            },
+            None => Err(CustodyError::not_found("Transaction", transaction_id.clone())),
+        }
+    })?;
+    
+    match ready {
+        None => Ok("Risk review recorded; awaiting a second compliance officer".to_string()),
+        Some(ready) => {
+            if ready {
+                spawn_tracked(execute_transaction_async(transaction_id));
+            }
+            Ok("Transaction released from risk review".to_string())
+        }
+    }
+}
+
+async fn evaluate_transaction_risk(
+    account: &CustodyAccount,
+    transaction_id: &str,
+    transaction_type: &TransactionType,
+    amount: u64,
+    recipient: &Option<String>,
+) -> Result<RiskDecision, CustodyError> {
+    let risk_canister = match RISK_MANAGEMENT_CANISTER.with(|c| *c.borrow()) {
+        Some(canister) => canister,
+        // Risk management not configured yet; rely on the local risk score
+        None => return Ok(RiskDecision::Allow),
+    };
+    
+    let result: Result<(RiskDecision,), _> = ic_cdk::call(
+        risk_canister,
+        "evaluate_transaction",
+        (
+            account.id.clone(),
+            account.account_type.clone(),
+            transaction_type.clone(),
+            amount,
+            recipient.clone(),
+            Some(transaction_id.to_string()),
+        ),
+    ).await;
+    
+    match result {
+        Ok((decision,)) => Ok(decision),
+        Err((code, msg)) => Err(CustodyError::InternalError(format!("Risk evaluation failed: {:?} {}", code, msg))),
+    }
+}
+
+// === Emergency Functions ===
+
+#[update]
+async fn emergency_freeze_account(account_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "emergency_freeze_account")?;
+    
+    // Institution operators may freeze their own institution's accounts
+    let can_freeze = CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow()
+            .get(&account_id)
+            .and_then(|acc| institution_permission(caller, acc, OperatorPermission::FreezeAccounts))
+    }) == Some(true);
+    
+    // The risk management canister freezes accounts past its auto-freeze threshold
+    let is_risk_canister = RISK_MANAGEMENT_CANISTER.with(|c| *c.borrow()) == Some(caller);
+    
+    // Otherwise the caller must be an emergency contact
+    let may_freeze = can_freeze
+        || is_risk_canister
+        || is_emergency_contact(caller).await;
+    
+    if !may_freeze {
+        return Err(CustodyError::unauthorized("emergency action"));
+    }
+    
+    emergency_freeze_account_internal(&account_id)
+}
+
+// Freezes without an authorization check, for callers inside this canister
+fn emergency_freeze_account_internal(account_id: &str) -> Result<String, CustodyError> {
+    let result = CUSTODY_ACCOUNTS.with(|accounts| {
+        let mut accounts_map = accounts.borrow_mut();
+        match accounts_map.get_mut(account_id) {
+            Some(account) => {
+                if account.status == AccountStatus::Closed {
+                    return Err(CustodyError::status_conflict("Closed", "Active"));
+                }
                 account.status = AccountStatus::Frozen;
+                
+                // A fresh freeze needs fresh unfreeze approvals
+                PENDING_UNFREEZE.with(|pending| pending.borrow_mut().remove(account_id));
                 Ok("Account frozen successfully".to_string())
             },
-            None => Err("Account not found".to_string()),
+            None => Err(CustodyError::not_found("Account", account_id)),
         }
-    })
+    })?;
+    
+    if let Some(event_bus) = EVENT_BUS_CANISTER.with(|c| *c.borrow()) {
+        let event = AccountFrozenEvent { account_id: account_id.to_string() };
+        spawn_tracked(events::publish_event(event_bus, events::TOPIC_ACCOUNT_FROZEN, event));
+    }
+    
+    Ok(result)
+}
+
+#[update]
+async fn emergency_unfreeze_account(account_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "emergency_unfreeze_account")?;
+    
+    if !is_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("emergency action"));
+    }
+    
+    let status = CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow().get(&account_id).map(|acc| acc.status.clone())
+    }).ok_or_else(|| CustodyError::not_found("Account", account_id.clone()))?;
+    
+    if status != AccountStatus::Frozen {
+        return Err(CustodyError::status_conflict(format!("{:?}", status), "Frozen"));
+    }
+    
+    // A single compromised contact must not be able to unfreeze on its own
+    let min_quorum = EMERGENCY_CONFIG.with(|config| config.borrow().min_quorum);
+    let approvals = PENDING_UNFREEZE.with(|pending| {
+        let mut pending = pending.borrow_mut();
+        let proposal = pending.entry(account_id.clone()).or_insert_with(|| FreezeProposal {
+            account_id: account_id.clone(),
+            approvals: BTreeSet::new(),
+        });
+        proposal.approvals.insert(caller);
+        proposal.approvals.len()
+    });
+    
+    if approvals < min_quorum as usize {
+        return Ok(format!("Unfreeze approved ({}/{} approvals)", approvals, min_quorum));
+    }
+    
+    PENDING_UNFREEZE.with(|pending| pending.borrow_mut().remove(&account_id));
+    CUSTODY_ACCOUNTS.with(|accounts| {
+        if let Some(account) = accounts.borrow_mut().get_mut(&account_id) {
+            account.status = AccountStatus::Active;
+        }
+    });
+    
+    Ok("Account unfrozen successfully".to_string())
+}
+
+#[query]
+fn get_unfreeze_proposal(account_id: String) -> Option<FreezeProposal> {
+    PENDING_UNFREEZE.with(|pending| pending.borrow().get(&account_id).cloned())
+}
+
+#[update]
+async fn add_emergency_contact(contact: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_emergency_contact")?;
+    
+    if !is_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("add_emergency_contact"));
+    }
+    
+    if contact == Principal::anonymous() {
+        return Err(CustodyError::invalid_input("contact", "cannot be anonymous"));
+    }
+    
+    EMERGENCY_CONFIG.with(|config| config.borrow_mut().contacts.insert(contact));
+    
+    Ok("Emergency contact added successfully".to_string())
+}
+
+#[update]
+async fn remove_emergency_contact(contact: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "remove_emergency_contact")?;
+    
+    if !is_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("remove_emergency_contact"));
+    }
+    
+    // Keep enough contacts to reach the quorum, or frozen accounts could never be released
+    EMERGENCY_CONFIG.with(|config| {
+        let mut config = config.borrow_mut();
+        if !config.contacts.contains(&contact) {
+            return Err(CustodyError::not_found("Emergency contact", contact.to_text()));
+        }
+        if config.contacts.len() <= config.min_quorum as usize {
+            return Err(CustodyError::LimitExceeded {
+                limit: config.min_quorum as u64,
+                actual: config.contacts.len() as u64 - 1,
+            });
+        }
+        config.contacts.remove(&contact);
+        Ok(())
+    })?;
+    
+    // Approvals from a removed contact no longer count towards an unfreeze
+    PENDING_UNFREEZE.with(|pending| {
+        for proposal in pending.borrow_mut().values_mut() {
+            proposal.approvals.remove(&contact);
+        }
+    });
+    
+    Ok("Emergency contact removed successfully".to_string())
+}
+
+#[update]
+async fn set_emergency_quorum(min_quorum: u8) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_emergency_quorum")?;
+    
+    if !is_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("set_emergency_quorum"));
+    }
+    
+    EMERGENCY_CONFIG.with(|config| {
+        let mut config = config.borrow_mut();
+        if min_quorum == 0 || min_quorum as usize > config.contacts.len() {
+            return Err(CustodyError::invalid_input(
+                "min_quorum",
+                format!("must be between 1 and the {} registered contacts", config.contacts.len()),
+            ));
+        }
+        config.min_quorum = min_quorum;
+        Ok(())
+    })?;
+    
+    Ok(format!("Emergency quorum set to {}", min_quorum))
+}
+
+#[query]
+fn get_emergency_config() -> EmergencyContactConfig {
+    EMERGENCY_CONFIG.with(|config| config.borrow().clone())
+}
+
+// === Account Closure Functions ===
+
+/// Closing an account needs both the owner and an operator to call this with
+/// the same destination within a week. The first call records the request.
+/// The second closes an empty account straight away; otherwise it initiates
+/// a sweep of the remaining balance as an ordinary withdrawal, and the account
+/// closes once that transaction executes.
+#[update]
+async fn close_account(
+    account_id: String,
+    destination_address: String,
+    closure_reason: String,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "close_account")?;
+    
+    let account = CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow().get(&account_id).cloned()
+    });
+    
+    let account = match account {
+        Some(acc) => acc,
+        None => return Err(CustodyError::not_found("Account", account_id)),
+    };
+    
+    let is_owner = account.owner == caller;
+    let is_operator = !is_owner
+        && is_account_operator(caller, &account, OperatorPermission::ApproveAccounts).await;
+    
+    if !is_owner && !is_operator {
+        return Err(CustodyError::unauthorized("close_account"));
+    }
+    
+    if closure_reason.trim().is_empty() {
+        return Err(CustodyError::invalid_input("closure_reason", "cannot be empty"));
+    }
+    
+    // State may have changed while waiting on the auth canister
+    let account = CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow().get(&account_id).cloned()
+    });
+    
+    let account = match account {
+        Some(acc) => acc,
+        None => return Err(CustodyError::not_found("Account", account_id)),
+    };
+    
+    // Frozen accounts stay frozen, and unapproved ones have nothing to sweep
+    if account.status != AccountStatus::Active {
+        return Err(CustodyError::status_conflict(format!("{:?}", account.status), "Active"));
+    }
+    
+    let has_open_transactions = TRANSACTIONS.with(|txns| {
+        txns.borrow().values().any(|txn| {
+            txn.account_id == account_id
+                && matches!(txn.status, TransactionStatus::Pending | TransactionStatus::Approved | TransactionStatus::Executing)
+        })
+    });
+    
+    if has_open_transactions {
+        return Err(CustodyError::status_conflict("pending transactions", "no pending transactions"));
+    }
+    
+    if account.reserved_balance != 0 {
+        return Err(CustodyError::status_conflict(
+            format!("{} reserved", account.reserved_balance),
+            "0 reserved",
+        ));
+    }
+    
+    // Only satoshis are swept; tokens have to be withdrawn to a principal first
+    if let Some(asset) = account.multi_asset_balances.iter().find(|a| a.amount > 0) {
+        return Err(CustodyError::status_conflict(
+            format!("{} {} held", asset.amount, asset.token_symbol),
+            "no token balances",
+        ));
+    }
+    
+    if account.balance > 0 {
+        if destination_address.trim().is_empty() {
+            return Err(CustodyError::invalid_input("destination_address", "required to sweep the remaining balance"));
+        }
+        check_withdrawal_destination(&account, Some(&destination_address))?;
+    }
+    
+    let now = ic_cdk::api::time();
+    
+    let closure = ACCOUNT_CLOSURES.with(|closures| {
+        let mut closures = closures.borrow_mut();
+        
+        // A lapsed request, or one whose sweep didn't go through, starts over
+        // so both parties have to approve again
+        let stale = closures.get(&account_id).is_some_and(|closure| {
+            closure.closed_at.is_none()
+                && (closure.sweep_transaction_id.is_some()
+                    || now.saturating_sub(closure.requested_at) > CLOSURE_REQUEST_TTL_NANOS)
+        });
+        if stale {
+            closures.remove(&account_id);
+        }
+        
+        let closure = closures.entry(account_id.clone()).or_insert_with(|| AccountClosure {
+            account_id: account_id.clone(),
+            destination_address: destination_address.clone(),
+            closure_reason: closure_reason.clone(),
+            owner_approved: false,
+            operator_approval: None,
+            requested_at: now,
+            closed_at: None,
+            sweep_transaction_id: None,
+            audit_entry_id: None,
+            retain_until: None,
+        });
+        
+        // The co-approver must confirm the destination the first party chose
+        if closure.destination_address != destination_address {
+            return Err(CustodyError::invalid_input(
+                "destination_address",
+                "does not match the pending closure request",
+            ));
+        }
+        
+        if is_owner {
+            closure.owner_approved = true;
+        } else {
+            closure.operator_approval = Some(caller);
+        }
+        
+        Ok(closure.clone())
+    })?;
+    
+    if !closure.owner_approved {
+        return Ok("Closure approved by operator, awaiting account owner".to_string());
+    }
+    
+    if closure.operator_approval.is_none() {
+        return Ok("Closure requested, awaiting operator approval".to_string());
+    }
+    
+    if account.balance == 0 {
+        finalize_account_closure(account_id).await;
+        return Ok("Account closed successfully".to_string());
+    }
+    
+    // The sweep goes through the normal transaction path, so it needs the
+    // account's approvals and passes risk evaluation, limits and any notary
+    let sweep_id = initiate_transaction_as(
+        account.owner,
+        account_id.clone(),
+        TransactionType::Withdrawal,
+        account.balance,
+        Some(closure.destination_address.clone()),
+        None,
+        Some("Account closure sweep".to_string()),
+        None,
+    ).await?;
+    
+    ACCOUNT_CLOSURES.with(|closures| {
+        if let Some(closure) = closures.borrow_mut().get_mut(&account_id) {
+            closure.sweep_transaction_id = Some(sweep_id.clone());
+        }
+    });
+    
+    // The approving operator signs the sweep as well; execution is spawned
+    // once the borrow ends, since spawned futures start running immediately
+    let ready = TRANSACTIONS.with(|txns| {
+        let mut txns_map = txns.borrow_mut();
+        let Some(sweep) = txns_map.get_mut(&sweep_id) else {
+            return false;
+        };
+        sweep.approvals.extend(closure.operator_approval);
+        let ready = sweep.status == TransactionStatus::Pending && ready_for_execution(sweep);
+        if ready {
+            sweep.status = TransactionStatus::Approved;
+        }
+        ready
+    });
+    
+    if ready {
+        spawn_tracked(execute_transaction_async(sweep_id.clone()));
+    }
+    
+    Ok(format!("Closure approved; the account closes once sweep transaction {} executes", sweep_id))
+}
+
+/// Closes an account whose closure was approved, once it is empty. Called
+/// directly for empty accounts and after the sweep transaction executes.
+async fn finalize_account_closure(account_id: String) {
+    let now = ic_cdk::api::time();
+    
+    // A deposit that landed after the sweep keeps the account open until the
+    // closure request lapses
+    let closed = CUSTODY_ACCOUNTS.with(|accounts| {
+        let mut accounts_map = accounts.borrow_mut();
+        let Some(account) = accounts_map.get_mut(&account_id) else {
+            return false;
+        };
+        if account.balance != 0
+            || account.reserved_balance != 0
+            || account.multi_asset_balances.iter().any(|a| a.amount > 0)
+        {
+            return false;
+        }
+        account.status = AccountStatus::Closed;
+        record_balance_snapshot(&account.id, 0);
+        true
+    });
+    
+    if !closed {
+        ic_cdk::println!("Account {} still holds funds after its closure sweep", account_id);
+        return;
+    }
+    
+    let closure = ACCOUNT_CLOSURES.with(|closures| {
+        closures.borrow_mut().get_mut(&account_id).map(|closure| {
+            closure.closed_at = Some(now);
+            closure.retain_until = Some(now + CLOSED_ACCOUNT_RETENTION_NANOS);
+            closure.clone()
+        })
+    });
+    
+    let Some(closure) = closure else {
+        return;
+    };
+    
+    let swept = closure.sweep_transaction_id.as_ref()
+        .and_then(|id| TRANSACTIONS.with(|txns| txns.borrow().get(id).map(|txn| txn.amount)))
+        .unwrap_or(0);
+    
+    let details = format!(
+        "Account closed: {}. Swept {} satoshis to {} (transaction {})",
+        closure.closure_reason,
+        swept,
+        closure.destination_address,
+        closure.sweep_transaction_id.as_deref().unwrap_or("none"),
+    );
+    
+    // The account is already closed at this point; a failed audit call is
+    // visible as a missing audit_entry_id on the closure record
+    if let Some(audit_entry_id) = log_account_audit_event(
+        AuditEventType::AccountClosure,
+        &account_id,
+        "close_account",
+        details,
+    ).await {
+        ACCOUNT_CLOSURES.with(|closures| {
+            if let Some(closure) = closures.borrow_mut().get_mut(&account_id) {
+                closure.audit_entry_id = Some(audit_entry_id);
+            }
+        });
+    }
+}
+
+async fn log_account_audit_event(
+    event_type: AuditEventType,
+    account_id: &str,
+    action: &str,
+    details: String,
+) -> Option<String> {
+    let audit_canister = AUDIT_TRAIL_CANISTER.with(|c| *c.borrow())?;
+    let context = RequestContext::new(ic_cdk::caller(), account_id);
+    
+    let result: Result<(Result<String, CustodyError>,), _> = ic_cdk::call(
+        audit_canister,
+        "log_audit_event",
+        (
+            event_type,
+            AuditResourceType::CustodyAccount,
+            account_id.to_string(),
+            action.to_string(),
+            details,
+            None::<()>,
+            true,
+            Some(context),
+        ),
+    ).await;
+    
+    match result {
+        Ok((Ok(entry_id),)) => Some(entry_id),
+        Ok((Err(e),)) => {
+            ic_cdk::println!("Audit trail rejected {} on {}: {}", action, account_id, e);
+            None
+        },
+        Err((code, msg)) => {
+            ic_cdk::println!("Audit trail call failed for {} on {}: {:?} {}", action, account_id, code, msg);
+            None
+        },
+    }
+}
+
+// === Multi-Asset Functions ===
+
+/// Pulls ICRC tokens from the caller into the custody canister. icrc1_transfer
+/// can only move the sender's own funds, so the caller must first approve the
+/// custody canister on the token ledger and the deposit uses icrc2_transfer_from.
+#[update]
+async fn deposit_asset(account_id: String, token_canister_id: Principal, amount: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "deposit_asset")?;
+    
+    if amount == 0 {
+        return Err(CustodyError::invalid_input("amount", "must be greater than zero"));
+    }
+    
+    let account = CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().get(&account_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Account", account_id.clone()))?;
+    
+    if !account.authorized_users.contains(&caller) {
+        return Err(CustodyError::unauthorized("deposit_asset"));
+    }
+    
+    if account.status != AccountStatus::Active {
+        return Err(CustodyError::status_conflict(format!("{:?}", account.status), "Active"));
+    }
+    
+    // Look up the token's metadata the first time the account holds it
+    let metadata = match account.multi_asset_balances.iter().find(|a| a.token_canister_id == token_canister_id) {
+        Some(asset) => (asset.token_symbol.clone(), asset.decimals),
+        None => fetch_token_metadata(token_canister_id).await?,
+    };
+    
+    let args = Icrc2TransferFromArgs {
+        spender_subaccount: None,
+        from: Icrc1Account { owner: caller, subaccount: None },
+        to: Icrc1Account { owner: ic_cdk::id(), subaccount: None },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+    
+    let result: Result<(Result<Nat, Icrc2TransferFromError>,), _> =
+        ic_cdk::call(token_canister_id, "icrc2_transfer_from", (args,)).await;
+    
+    let block_index = match result {
+        Ok((Ok(block_index),)) => block_index,
+        Ok((Err(e),)) => return Err(CustodyError::InternalError(format!("Token deposit rejected: {:?}", e))),
+        Err((code, msg)) => return Err(CustodyError::InternalError(format!("Token deposit failed: {:?} {}", code, msg))),
+    };
+    
+    // The tokens have moved, so credit them even if the account changed meanwhile
+    credit_asset(&account_id, token_canister_id, amount, metadata)?;
+    
+    Ok(format!("Asset deposited at block {}", block_index))
+}
+
+/// Queues an ICRC token withdrawal that goes through the same approval,
+/// risk review and notary checks as a BTC withdrawal
+#[update]
+async fn withdraw_asset(
+    account_id: String,
+    token_canister_id: Principal,
+    amount: u64,
+    recipient: Principal,
+) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "withdraw_asset")?;
+    
+    if amount == 0 {
+        return Err(CustodyError::invalid_input("amount", "must be greater than zero"));
+    }
+    
+    initiate_transaction_internal(
+        account_id,
+        TransactionType::Withdrawal,
+        amount,
+        Some(recipient.to_text()),
+        Some(token_canister_id),
+        None,
+        None,
+    ).await
+}
+
+fn asset_balance(account: &CustodyAccount, token_canister_id: Principal) -> u64 {
+    account.multi_asset_balances.iter()
+        .find(|a| a.token_canister_id == token_canister_id)
+        .map_or(0, |a| a.amount)
+}
+
+async fn fetch_token_metadata(token_canister_id: Principal) -> Result<(String, u8), CustodyError> {
+    let symbol: Result<(String,), _> = ic_cdk::call(token_canister_id, "icrc1_symbol", ()).await;
+    let decimals: Result<(u8,), _> = ic_cdk::call(token_canister_id, "icrc1_decimals", ()).await;
+    
+    match (symbol, decimals) {
+        (Ok((symbol,)), Ok((decimals,))) => Ok((symbol, decimals)),
+        (Err((code, msg)), _) | (_, Err((code, msg))) => {
+            Err(CustodyError::InternalError(format!("Token metadata lookup failed: {:?} {}", code, msg)))
+        },
+    }
+}
+
+fn credit_asset(
+    account_id: &str,
+    token_canister_id: Principal,
+    amount: u64,
+    (token_symbol, decimals): (String, u8),
+) -> Result<(), CustodyError> {
+    CUSTODY_ACCOUNTS.with(|accounts| {
+        let mut accounts_map = accounts.borrow_mut();
+        let account = accounts_map.get_mut(account_id)
+            .ok_or_else(|| CustodyError::not_found("Account", account_id))?;
+        
+        match account.multi_asset_balances.iter_mut().find(|a| a.token_canister_id == token_canister_id) {
+            Some(asset) => asset.amount = asset.amount.saturating_add(amount),
+            None => account.multi_asset_balances.push(AssetBalance {
+                token_canister_id,
+                token_symbol,
+                amount,
+                decimals,
+            }),
+        }
+        
+        Ok(())
+    })
+}
+
+// Debits the account before calling the ledger so the same tokens can't be
+// sent twice, and puts them back if the transfer fails. The ledger fee is
+// paid from the shared custody balance, so it is charged to the account too.
+async fn send_asset_withdrawal(transaction: &Transaction, token_canister_id: Principal) -> Result<(), CustodyError> {
+    let recipient = transaction.recipient.as_deref()
+        .and_then(|r| Principal::from_text(r).ok())
+        .ok_or_else(|| CustodyError::invalid_input("recipient", "must be a principal for token withdrawals"))?;
+    
+    let fee = fetch_token_fee(token_canister_id).await?;
+    let debit = transaction.amount.checked_add(fee)
+        .ok_or_else(|| CustodyError::invalid_input("amount", "overflows with the ledger fee"))?;
+    
+    let metadata = CUSTODY_ACCOUNTS.with(|accounts| {
+        let mut accounts_map = accounts.borrow_mut();
+        let account = accounts_map.get_mut(&transaction.account_id)
+            .ok_or_else(|| CustodyError::not_found("Account", transaction.account_id.clone()))?;
+        
+        let asset = account.multi_asset_balances.iter_mut()
+            .find(|a| a.token_canister_id == token_canister_id && a.amount >= debit)
+            .ok_or(CustodyError::InsufficientBalance {
+                available: 0,
+                required: debit,
+            })?;
+        
+        asset.amount -= debit;
+        Ok::<_, CustodyError>((asset.token_symbol.clone(), asset.decimals))
+    })?;
+    
+    let args = Icrc1TransferArg {
+        from_subaccount: None,
+        to: Icrc1Account { owner: recipient, subaccount: None },
+        amount: Nat::from(transaction.amount),
+        // Pinning the fee makes the ledger reject the transfer if it changed
+        // rather than charge more than was debited
+        fee: Some(Nat::from(fee)),
+        memo: None,
+        created_at_time: None,
+    };
+    
+    let result: Result<(Result<Nat, Icrc1TransferError>,), _> =
+        ic_cdk::call(token_canister_id, "icrc1_transfer", (args,)).await;
+    
+    let failure = match result {
+        Ok((Ok(_),)) => return Ok(()),
+        Ok((Err(e),)) => format!("Token withdrawal rejected: {:?}", e),
+        Err((code, msg)) => format!("Token withdrawal failed: {:?} {}", code, msg),
+    };
+    
+    // The refund's own error must not replace the transfer failure, or the
+    // caller would not know the tokens are still owed back to the account
+    match credit_asset(&transaction.account_id, token_canister_id, debit, metadata) {
+        Ok(()) => Err(CustodyError::InternalError(failure)),
+        Err(e) => Err(CustodyError::InternalError(format!("{}; refund of {} failed: {}", failure, debit, e))),
+    }
+}
+
+// Deducts an executed BTC withdrawal from the account's balance and the
+// reservation made when it was initiated
+fn debit_reserved_balance(account_id: &str, amount: u64) {
+    CUSTODY_ACCOUNTS.with(|accounts| {
+        let mut accounts_map = accounts.borrow_mut();
+        if let Some(account) = accounts_map.get_mut(account_id) {
+            account.balance -= amount;
+            account.reserved_balance -= amount;
+            record_balance_snapshot(&account.id, account.balance);
+        }
+    });
+}
+
+// Undoes debit_reserved_balance for a withdrawal whose broadcast failed
+fn restore_reserved_balance(account_id: &str, amount: u64) {
+    CUSTODY_ACCOUNTS.with(|accounts| {
+        let mut accounts_map = accounts.borrow_mut();
+        if let Some(account) = accounts_map.get_mut(account_id) {
+            account.balance += amount;
+            account.reserved_balance += amount;
+            record_balance_snapshot(&account.id, account.balance);
+        }
+    });
+}
+
+// Debits the account before calling btc_integration so the same satoshis
+// can't be sent twice, and restores the balance and reservation if the
+// broadcast is rejected or the call itself fails
+async fn broadcast_bitcoin_withdrawal(transaction: &Transaction, btc_canister: Principal) -> Result<(), CustodyError> {
+    let signed = transaction.bitcoin_transaction.clone()
+        .ok_or_else(|| CustodyError::status_conflict("no signed transaction", "signed transaction attached"))?;
+    
+    debit_reserved_balance(&transaction.account_id, transaction.amount);
+    
+    let result: Result<(Result<String, CustodyError>,), _> = ic_cdk::call(
+        btc_canister,
+        "broadcast_transaction",
+        (signed.raw_tx_hex, transaction.account_id.clone(), signed.txid),
+    ).await;
+    
+    let failure = match result {
+        Ok((Ok(_),)) => return Ok(()),
+        Ok((Err(e),)) => format!("Bitcoin broadcast rejected: {}", e),
+        Err((code, msg)) => format!("Bitcoin broadcast failed: {:?} {}", code, msg),
+    };
+    
+    restore_reserved_balance(&transaction.account_id, transaction.amount);
+    Err(CustodyError::InternalError(failure))
+}
+
+async fn fetch_token_fee(token_canister_id: Principal) -> Result<u64, CustodyError> {
+    let result: Result<(Nat,), _> = ic_cdk::call(token_canister_id, "icrc1_fee", ()).await;
+    match result {
+        Ok((fee,)) => u64::try_from(fee.0)
+            .map_err(|_| CustodyError::InternalError("Token fee does not fit in u64".to_string())),
+        Err((code, msg)) => Err(CustodyError::InternalError(format!("Token fee lookup failed: {:?} {}", code, msg))),
+    }
+}
+
+// === Trust Account Functions ===
+
+#[update]
+async fn set_trust_details(account_id: String, details: TrustAccountDetails) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_trust_details")?;
+    
+    let account = CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().get(&account_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Account", account_id.clone()))?;
+    
+    if !is_account_operator(caller, &account, OperatorPermission::ApproveAccounts).await {
+        return Err(CustodyError::unauthorized("set_trust_details"));
+    }
+    
+    if !matches!(account.account_type, AccountType::TrustCustody) {
+        return Err(CustodyError::status_conflict(format!("{:?}", account.account_type), "TrustCustody"));
+    }
+    
+    if details.trust_deed_hash.is_empty() {
+        return Err(CustodyError::invalid_input("trust_deed_hash", "cannot be empty"));
+    }
+    
+    // Trustees and beneficiaries are distinct roles
+    if details.beneficiaries.contains(&details.trustee) {
+        return Err(CustodyError::invalid_input("beneficiaries", "cannot include the trustee"));
+    }
+    
+    if details.notary.is_some_and(|notary| notary == details.trustee || details.beneficiaries.contains(&notary)) {
+        return Err(CustodyError::invalid_input("notary", "must be independent of the trustee and beneficiaries"));
+    }
+    
+    TRUST_DETAILS.with(|trusts| {
+        trusts.borrow_mut().insert(account_id, details);
+    });
+    
+    Ok("Trust details updated successfully".to_string())
+}
+
+#[query]
+fn get_trust_details(account_id: String) -> Option<TrustAccountDetails> {
+    TRUST_DETAILS.with(|trusts| trusts.borrow().get(&account_id).cloned())
+}
+
+// Withdrawals from a trust must be witnessed by its notary, so a trust
+// without one cannot distribute funds yet
+fn trust_withdrawal_notary(
+    account: &CustodyAccount,
+    transaction_type: &TransactionType,
+) -> Result<Option<Principal>, CustodyError> {
+    if !matches!(account.account_type, AccountType::TrustCustody)
+        || !matches!(transaction_type, TransactionType::Withdrawal)
+    {
+        return Ok(None);
+    }
+    
+    TRUST_DETAILS.with(|trusts| trusts.borrow().get(&account.id).and_then(|t| t.notary))
+        .map(Some)
+        .ok_or_else(|| CustodyError::status_conflict("trust notary not configured", "trust notary configured"))
+}
+
+fn ready_for_execution(transaction: &Transaction) -> bool {
+    transaction.approvals.len() >= transaction.required_approvals as usize
+        && !transaction.requires_risk_review
+        && (transaction.notary.is_none() || transaction.notary_approved)
+        && !awaiting_dispute_sign_off(&transaction.id)
+}
+
+// === Dispute Functions ===
+
+#[update]
+async fn reject_transaction(transaction_id: String, reason: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "reject_transaction")?;
+    
+    if reason.trim().is_empty() {
+        return Err(CustodyError::invalid_input("reason", "must not be empty"));
+    }
+    
+    let transaction = TRANSACTIONS.with(|txns| txns.borrow().get(&transaction_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Transaction", transaction_id.clone()))?;
+    
+    let account = CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().get(&transaction.account_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Account", transaction.account_id.clone()))?;
+    
+    let is_approver = account.authorized_users.contains(&caller)
+        || is_account_operator(caller, &account, OperatorPermission::ApproveTransactions).await;
+    
+    if !is_approver {
+        return Err(CustodyError::unauthorized("reject_transaction"));
+    }
+    
+    let now = ic_cdk::api::time();
+    
+    // Re-check the status, which may have changed during the role check
+    TRANSACTIONS.with(|txns| {
+        let mut txns_map = txns.borrow_mut();
+        let transaction = txns_map.get_mut(&transaction_id)
+            .ok_or_else(|| CustodyError::not_found("Transaction", transaction_id.clone()))?;
+        
+        if transaction.status != TransactionStatus::Pending {
+            return Err(CustodyError::status_conflict(format!("{:?}", transaction.status), "Pending"));
+        }
+        
+        transaction.status = TransactionStatus::Rejected;
+        transaction.rejected_at = Some(now);
+        transaction.rejection_reason = Some(reason.clone());
+        Ok(())
+    })?;
+    
+    release_reserved_balance(&transaction);
+    
+    resolve_dispute(&transaction_id, format!("Rejected on escalation: {}", reason), now);
+    
+    Ok("Transaction rejected".to_string())
+}
+
+/// Appeals a rejection by re-submitting the transaction as a sibling that
+/// needs one more approval than the original, including an operator's
+/// sign-off through `sign_off_dispute`
+#[update]
+async fn dispute_rejection(transaction_id: String, reason: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "dispute_rejection")?;
+    
+    if reason.trim().is_empty() {
+        return Err(CustodyError::invalid_input("reason", "must not be empty"));
+    }
+    
+    let original = TRANSACTIONS.with(|txns| txns.borrow().get(&transaction_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Transaction", transaction_id.clone()))?;
+    
+    if original.initiated_by != caller {
+        return Err(CustodyError::unauthorized("dispute_rejection"));
+    }
+    
+    if original.status != TransactionStatus::Rejected {
+        return Err(CustodyError::status_conflict(format!("{:?}", original.status), "Rejected"));
+    }
+    
+    let now = ic_cdk::api::time();
+    let rejected_at = original.rejected_at.unwrap_or(original.created_at);
+    
+    if now.saturating_sub(rejected_at) > DISPUTE_WINDOW_NANOS {
+        return Err(CustodyError::invalid_input("transaction_id", "rejection is more than 24 hours old"));
+    }
+    
+    // Claim the dispute before the sibling is created so it is only filed once
+    let already_disputed = DISPUTES.with(|disputes| {
+        let mut disputes = disputes.borrow_mut();
+        if disputes.contains_key(&transaction_id) {
+            return true;
+        }
+        
+        disputes.insert(transaction_id.clone(), DisputeRecord {
+            transaction_id: transaction_id.clone(),
+            escalated_transaction_id: String::new(),
+            disputed_by: caller,
+            dispute_reason: reason,
+            escalated_to: None,
+            resolution: None,
+            resolved_at: None,
+        });
+        false
+    });
+    
+    if already_disputed {
+        return Err(CustodyError::status_conflict("already disputed", "not disputed"));
+    }
+    
+    // The original keeps its external reference, so the sibling goes without
+    let escalated_transaction_id = match initiate_transaction_internal(
+        original.account_id.clone(),
+        original.transaction_type.clone(),
+        original.amount,
+        original.recipient.clone(),
+        original.token_canister_id,
+        original.memo.clone(),
+        None,
+    ).await {
+        Ok(id) => id,
+        Err(e) => {
+            DISPUTES.with(|disputes| disputes.borrow_mut().remove(&transaction_id));
+            return Err(e);
+        }
+    };
+    
+    TRANSACTIONS.with(|txns| {
+        if let Some(sibling) = txns.borrow_mut().get_mut(&escalated_transaction_id) {
+            sibling.required_approvals = original.required_approvals.saturating_add(1);
+        }
+    });
+    
+    DISPUTES.with(|disputes| {
+        if let Some(dispute) = disputes.borrow_mut().get_mut(&transaction_id) {
+            dispute.escalated_transaction_id = escalated_transaction_id.clone();
+        }
+    });
+    
+    Ok(escalated_transaction_id)
+}
+
+/// Operator sign-off on a disputed transaction's escalated sibling; counts as
+/// one of its approvals
+#[update]
+async fn sign_off_dispute(transaction_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "sign_off_dispute")?;
+    
+    let dispute = DISPUTES.with(|disputes| disputes.borrow().get(&transaction_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Dispute", transaction_id.clone()))?;
+    
+    if dispute.resolved_at.is_some() {
+        return Err(CustodyError::status_conflict("resolved", "open"));
+    }
+    
+    if dispute.disputed_by == caller {
+        return Err(CustodyError::unauthorized("sign_off_dispute"));
+    }
+    
+    let sibling = TRANSACTIONS.with(|txns| txns.borrow().get(&dispute.escalated_transaction_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Transaction", dispute.escalated_transaction_id.clone()))?;
+    
+    let account = CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().get(&sibling.account_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Account", sibling.account_id.clone()))?;
+    
+    if !is_account_operator(caller, &account, OperatorPermission::ApproveTransactions).await {
+        return Err(CustodyError::unauthorized("sign_off_dispute"));
+    }
+    
+    if TRANSACTIONS.with(|txns| txns.borrow().get(&sibling.id).map(|txn| txn.status.clone()))
+        != Some(TransactionStatus::Pending)
+    {
+        return Err(CustodyError::status_conflict("not pending", "Pending"));
+    }
+    
+    DISPUTES.with(|disputes| {
+        if let Some(dispute) = disputes.borrow_mut().get_mut(&transaction_id) {
+            dispute.escalated_to = Some(caller);
+        }
+    });
+    
+    // Spawned futures start running immediately, so execution waits until
+    // the transactions borrow ends
+    let ready = TRANSACTIONS.with(|txns| {
+        let mut txns_map = txns.borrow_mut();
+        let Some(transaction) = txns_map.get_mut(&sibling.id) else {
+            return false;
+        };
+        transaction.approvals.insert(caller);
+        
+        let ready = ready_for_execution(transaction);
+        if ready {
+            transaction.status = TransactionStatus::Approved;
+        }
+        ready
+    });
+    
+    if ready {
+        spawn_tracked(execute_transaction_async(sibling.id));
+    }
+    
+    Ok("Dispute signed off".to_string())
+}
+
+#[query]
+fn get_dispute(transaction_id: String) -> Option<DisputeRecord> {
+    DISPUTES.with(|disputes| disputes.borrow().get(&transaction_id).cloned())
+}
+
+// Escalated dispute transactions also need an operator's sign-off
+fn awaiting_dispute_sign_off(transaction_id: &str) -> bool {
+    DISPUTES.with(|disputes| {
+        disputes.borrow().values().any(|dispute| {
+            dispute.escalated_transaction_id == transaction_id && dispute.escalated_to.is_none()
+        })
+    })
+}
+
+fn resolve_dispute(escalated_transaction_id: &str, resolution: String, now: u64) {
+    DISPUTES.with(|disputes| {
+        for dispute in disputes.borrow_mut().values_mut() {
+            if dispute.escalated_transaction_id == escalated_transaction_id && dispute.resolved_at.is_none() {
+                dispute.resolution = Some(resolution.clone());
+                dispute.resolved_at = Some(now);
+            }
+        }
+    });
+}
+
+// === Withdrawal Whitelist Functions ===
+
+#[update]
+fn add_to_whitelist(account_id: String, address: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_to_whitelist")?;
+    
+    request_whitelist_change(caller, account_id, address, WhitelistAction::Add)
+}
+
+#[update]
+fn remove_from_whitelist(account_id: String, address: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "remove_from_whitelist")?;
+    
+    request_whitelist_change(caller, account_id, address, WhitelistAction::Remove)
+}
+
+// Records an owner's whitelist change for operator approval and returns its id
+fn request_whitelist_change(
+    caller: Principal,
+    account_id: String,
+    address: String,
+    action: WhitelistAction,
+) -> Result<String, CustodyError> {
+    if address.trim().is_empty() {
+        return Err(CustodyError::invalid_input("address", "cannot be empty"));
+    }
+    
+    let account = CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow().get(&account_id).cloned()
+    });
+    
+    let account = match account {
+        Some(acc) => acc,
+        None => return Err(CustodyError::not_found("Account", account_id)),
+    };
+    
+    if account.owner != caller {
+        return Err(CustodyError::unauthorized("whitelist change"));
+    }
+    
+    if account.status == AccountStatus::Closed {
+        return Err(CustodyError::status_conflict("Closed", "not Closed"));
+    }
+    
+    let whitelisted = account.withdrawal_whitelist
+        .as_ref()
+        .is_some_and(|whitelist| whitelist.contains(&address));
+    
+    match action {
+        WhitelistAction::Add => {
+            if account.withdrawal_blacklist.contains(&address) {
+                return Err(CustodyError::invalid_input("address", "is blacklisted for this account"));
+            }
+            if whitelisted {
+                return Err(CustodyError::invalid_input("address", "is already whitelisted"));
+            }
+        },
+        WhitelistAction::Remove => {
+            if !whitelisted {
+                return Err(CustodyError::not_found("Whitelisted address", address));
+            }
+        },
+    }
+    
+    let change_id = Uuid::new_v4().to_string();
+    let change = WhitelistChange {
+        id: change_id.clone(),
+        account_id,
+        address,
+        action,
+        requested_by: caller,
+        requested_at: ic_cdk::api::time(),
+        approved_by: None,
+        approved_at: None,
+        audit_entry_id: None,
+    };
+    
+    WHITELIST_CHANGES.with(|changes| {
+        changes.borrow_mut().insert(change_id.clone(), change);
+    });
+    
+    Ok(change_id)
+}
+
+#[update]
+async fn approve_whitelist_change(change_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "approve_whitelist_change")?;
+    
+    let change = WHITELIST_CHANGES.with(|changes| changes.borrow().get(&change_id).cloned());
+    
+    let change = match change {
+        Some(change) => change,
+        None => return Err(CustodyError::not_found("Whitelist change", change_id)),
+    };
+    
+    let account = match CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().get(&change.account_id).cloned()) {
+        Some(acc) => acc,
+        None => return Err(CustodyError::not_found("Account", change.account_id)),
+    };
+    
+    let is_operator = is_account_operator(caller, &account, OperatorPermission::ApproveAccounts).await;
+    
+    if !is_operator {
+        return Err(CustodyError::unauthorized("approve_whitelist_change"));
+    }
+    
+    if change.approved_at.is_some() {
+        return Err(CustodyError::status_conflict("Approved", "Pending"));
+    }
+    
+    // The operator approval is the second pair of eyes on the owner's request
+    if change.requested_by == caller {
+        return Err(CustodyError::unauthorized("approve own whitelist change"));
+    }
+    
+    CUSTODY_ACCOUNTS.with(|accounts| {
+        let mut accounts_map = accounts.borrow_mut();
+        let account = match accounts_map.get_mut(&change.account_id) {
+            Some(account) => account,
+            None => return Err(CustodyError::not_found("Account", change.account_id.clone())),
+        };
+        
+        if account.status == AccountStatus::Closed {
+            return Err(CustodyError::status_conflict("Closed", "not Closed"));
+        }
+        
+        match change.action {
+            WhitelistAction::Add => {
+                if account.withdrawal_blacklist.contains(&change.address) {
+                    return Err(CustodyError::invalid_input("address", "is blacklisted for this account"));
+                }
+                account.withdrawal_whitelist
+                    .get_or_insert_with(BTreeSet::new)
+                    .insert(change.address.clone());
+            },
+            WhitelistAction::Remove => {
+                // An emptied whitelist stays in force and blocks all withdrawals
+                if let Some(whitelist) = account.withdrawal_whitelist.as_mut() {
+                    whitelist.remove(&change.address);
+                }
+            },
+        }
+        
+        Ok(())
+    })?;
+    
+    WHITELIST_CHANGES.with(|changes| {
+        if let Some(change) = changes.borrow_mut().get_mut(&change_id) {
+            change.approved_by = Some(caller);
+            change.approved_at = Some(ic_cdk::api::time());
+        }
+    });
+    
+    let details = format!(
+        "Withdrawal whitelist {:?} of {} requested by {}, approved by {}",
+        change.action,
+        change.address,
+        change.requested_by,
+        caller,
+    );
+    
+    if let Some(audit_entry_id) = log_account_audit_event(
+        AuditEventType::ComplianceCheck,
+        &change.account_id,
+        "whitelist_change",
+        details,
+    ).await {
+        WHITELIST_CHANGES.with(|changes| {
+            if let Some(change) = changes.borrow_mut().get_mut(&change_id) {
+                change.audit_entry_id = Some(audit_entry_id);
+            }
+        });
+    }
+    
+    Ok("Whitelist change applied".to_string())
 }
 
 #[update]
-fn emergency_unfreeze_account(account_id: String) -> Result<String, String> {
+async fn add_to_blacklist(account_id: String, address: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_to_blacklist")?;
     
-    // Check if caller is emergency contact
-    let is_emergency_contact = EMERGENCY_CONTACTS.with(|contacts| {
-        contacts.borrow().contains(&caller)
-    });
+    let is_officer = holds_role(&COMPLIANCE_OFFICERS, caller, "compliance_officer").await;
+    
+    if !is_officer {
+        return Err(CustodyError::unauthorized("add_to_blacklist"));
+    }
     
-    if !is_emergency_contact {
-        return Err("Unauthorized emergency action".to_string());
+    if address.trim().is_empty() {
+        return Err(CustodyError::invalid_input("address", "cannot be empty"));
     }
     
     CUSTODY_ACCOUNTS.with(|accounts| {
         let mut accounts_map = accounts.borrow_mut();
         match accounts_map.get_mut(&account_id) {
             Some(account) => {
-                account.status = AccountStatus::Active;
-                Ok("Account unfrozen successfully".to_string())
+                // A blacklisted address can't stay whitelisted
+                if let Some(whitelist) = account.withdrawal_whitelist.as_mut() {
+                    whitelist.remove(&address);
+                }
+                account.withdrawal_blacklist.insert(address.clone());
+                Ok(())
             },
-            None => Err("Account not found".to_string()),
+            None => Err(CustodyError::not_found("Account", account_id.clone())),
+        }
+    })?;
+    
+    log_account_audit_event(
+        AuditEventType::ComplianceCheck,
+        &account_id,
+        "blacklist_address",
+        format!("Withdrawal address {} blacklisted by {}", address, caller),
+    ).await;
+    
+    Ok("Address blacklisted".to_string())
+}
+
+#[query]
+fn get_pending_whitelist_changes(account_id: String) -> Vec<WhitelistChange> {
+    WHITELIST_CHANGES.with(|changes| {
+        changes.borrow()
+            .values()
+            .filter(|change| change.account_id == account_id && change.approved_at.is_none())
+            .cloned()
+            .collect()
+    })
+}
+
+// === Delegation Functions ===
+
+#[update]
+fn grant_delegation(
+    account_id: String,
+    delegatee: Principal,
+    permissions: BTreeSet<DelegationPermission>,
+    valid_until: u64,
+    max_amount: Option<u64>,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "grant_delegation")?;
+    
+    let account = CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow().get(&account_id).cloned()
+    });
+    
+    let account = match account {
+        Some(acc) => acc,
+        None => return Err(CustodyError::not_found("Account", account_id)),
+    };
+    
+    if account.owner != caller {
+        return Err(CustodyError::unauthorized("grant_delegation"));
+    }
+    
+    if delegatee == caller || delegatee == Principal::anonymous() {
+        return Err(CustodyError::invalid_input("delegatee", "must be a separate, authenticated key"));
+    }
+    
+    if permissions.is_empty() {
+        return Err(CustodyError::invalid_input("permissions", "at least one permission is required"));
+    }
+    
+    if valid_until <= ic_cdk::api::time() {
+        return Err(CustodyError::invalid_input("valid_until", "must be in the future"));
+    }
+    
+    if max_amount == Some(0) {
+        return Err(CustodyError::invalid_input("max_amount", "must be greater than zero"));
+    }
+    
+    let delegation = Delegation {
+        delegatee,
+        delegated_by: caller,
+        permissions,
+        valid_until,
+        max_amount_per_tx: max_amount,
+    };
+    
+    // A new grant from the same owner replaces the previous one
+    DELEGATIONS.with(|delegations| {
+        let mut delegations_map = delegations.borrow_mut();
+        let granted = delegations_map.entry(delegatee).or_default();
+        granted.retain(|d| d.delegated_by != caller);
+        granted.push(delegation);
+    });
+    
+    ic_cdk::println!("Delegation granted to {} by {}", delegatee, caller);
+    Ok(format!("Delegation granted to {}", delegatee))
+}
+
+/// Revokes the caller's delegation to `delegatee` immediately, e.g. when the
+/// hot key is compromised
+#[update]
+fn revoke_delegation(delegatee: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "revoke_delegation")?;
+    
+    let revoked = DELEGATIONS.with(|delegations| {
+        let mut delegations_map = delegations.borrow_mut();
+        let Some(granted) = delegations_map.get_mut(&delegatee) else {
+            return false;
+        };
+        
+        let before = granted.len();
+        granted.retain(|d| d.delegated_by != caller);
+        let revoked = granted.len() < before;
+        if granted.is_empty() {
+            delegations_map.remove(&delegatee);
         }
+        revoked
+    });
+    
+    if !revoked {
+        return Err(CustodyError::not_found("Delegation", delegatee.to_string()));
+    }
+    
+    ic_cdk::println!("Delegation to {} revoked by {}", delegatee, caller);
+    Ok(format!("Delegation to {} revoked", delegatee))
+}
+
+#[query]
+fn get_delegations(delegatee: Principal) -> Vec<Delegation> {
+    DELEGATIONS.with(|delegations| {
+        delegations.borrow().get(&delegatee).cloned().unwrap_or_default()
     })
 }
 
 // === Query Functions ===
 
+#[query]
+fn get_account_closure(account_id: String) -> Option<AccountClosure> {
+    ACCOUNT_CLOSURES.with(|closures| {
+        closures.borrow().get(&account_id).cloned()
+    })
+}
+
 #[query]
 fn get_custody_account(account_id: String) -> Option<CustodyAccount> {
     CUSTODY_ACCOUNTS.with(|accounts| {
@@ -465,16 +2722,53 @@ fn get_custody_account(account_id: String) -> Option<CustodyAccount> {
 }
 
 #[query]
-fn get_user_accounts(user: Principal) -> Vec<CustodyAccount> {
+fn get_user_accounts(user: Principal, include_closed: bool) -> Vec<CustodyAccount> {
     CUSTODY_ACCOUNTS.with(|accounts| {
         accounts.borrow()
             .values()
             .filter(|account| account.authorized_users.contains(&user))
+            .filter(|account| include_closed || account.status != AccountStatus::Closed)
             .cloned()
             .collect()
     })
 }
 
+const MAX_ACCOUNT_PAGE_SIZE: u32 = 500;
+
+/// Pages through the balances of every account that is not closed, in
+/// account ID order, resuming after `after_id`. Only the risk management
+/// canister and emergency contacts may list every institution's accounts.
+#[query]
+fn list_account_balances(after_id: Option<String>, limit: u32) -> Result<Vec<AccountBalance>, CustodyError> {
+    let caller = ic_cdk::caller();
+    let is_risk_canister = RISK_MANAGEMENT_CANISTER.with(|c| *c.borrow()) == Some(caller);
+    let is_admin = EMERGENCY_CONFIG.with(|config| config.borrow().contacts.contains(&caller));
+    if !is_risk_canister && !is_admin {
+        return Err(CustodyError::unauthorized("list_account_balances"));
+    }
+    
+    let limit = limit.clamp(1, MAX_ACCOUNT_PAGE_SIZE) as usize;
+    let lower_bound = match after_id {
+        Some(ref after_id) => Bound::Excluded(after_id),
+        None => Bound::Unbounded,
+    };
+    
+    Ok(CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow()
+            .range::<String, _>((lower_bound, Bound::Unbounded))
+            .map(|(_, account)| account)
+            .filter(|account| account.status != AccountStatus::Closed)
+            .take(limit)
+            .map(|account| AccountBalance {
+                id: account.id.clone(),
+                account_type: account.account_type.clone(),
+                balance: account.balance,
+                reserved_balance: account.reserved_balance,
+            })
+            .collect()
+    }))
+}
+
 #[query]
 fn get_transaction(transaction_id: String) -> Option<Transaction> {
     TRANSACTIONS.with(|txns| {
@@ -513,17 +2807,50 @@ fn get_custody_settings() -> CustodySettings {
 
 // === Admin Functions ===
 
+/// Recomputes an account's reserved_balance from its outstanding transactions,
+/// repairing reservations left behind by paths that never released them
 #[update]
-fn add_authorized_operator(operator: Principal) -> Result<String, String> {
+async fn reconcile_reserved_balance(account_id: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "reconcile_reserved_balance")?;
     
-    // Check if caller is emergency contact (admin)
-    let is_admin = EMERGENCY_CONTACTS.with(|contacts| {
-        contacts.borrow().contains(&caller)
+    let account = CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().get(&account_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Account", account_id.clone()))?;
+    
+    if !is_account_operator(caller, &account, OperatorPermission::ApproveAccounts).await {
+        return Err(CustodyError::unauthorized("reconcile_reserved_balance"));
+    }
+    
+    // Approved and in-flight transactions still hold their reservation until they execute
+    let outstanding = TRANSACTIONS.with(|txns| {
+        txns.borrow()
+            .values()
+            .filter(|txn| txn.account_id == account_id
+                && matches!(txn.status, TransactionStatus::Pending | TransactionStatus::Approved | TransactionStatus::Executing)
+                && reserves_balance(txn))
+            .fold(0u64, |total, txn| total.saturating_add(txn.amount))
     });
     
+    let previous = CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow_mut().get_mut(&account_id).map(|acc| {
+            std::mem::replace(&mut acc.reserved_balance, outstanding)
+        })
+    }).ok_or_else(|| CustodyError::not_found("Account", account_id.clone()))?;
+    
+    ic_cdk::println!("Reconciled reserved balance of {}: {} -> {}", account_id, previous, outstanding);
+    Ok(format!("Reserved balance reconciled from {} to {}", previous, outstanding))
+}
+
+#[update]
+async fn add_authorized_operator(operator: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_authorized_operator")?;
+    
+    // Check if caller is emergency contact (admin)
+    let is_admin = is_emergency_contact(caller).await;
+    
     if !is_admin {
-        return Err("Unauthorized admin action".to_string());
+        return Err(CustodyError::unauthorized("admin action"));
     }
     
     AUTHORIZED_OPERATORS.with(|ops| {
@@ -534,27 +2861,396 @@ fn add_authorized_operator(operator: Principal) -> Result<String, String> {
 }
 
 #[update]
-fn update_custody_settings(new_settings: CustodySettings) -> Result<String, String> {
+async fn grant_institution_permission(
+    operator: Principal,
+    institution_id: String,
+    permission: OperatorPermission,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "grant_institution_permission")?;
+    
+    // Check if caller is emergency contact (admin)
+    let is_admin = is_emergency_contact(caller).await;
+    
+    if !is_admin {
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    if institution_id.is_empty() {
+        return Err(CustodyError::invalid_input("institution_id", "cannot be empty"));
+    }
+    
+    INSTITUTION_OPERATORS.with(|ops| {
+        let mut ops = ops.borrow_mut();
+        let entry = ops.entry(operator).or_insert_with(|| InstitutionOperator {
+            operator,
+            institution_id: institution_id.clone(),
+            permissions: BTreeSet::new(),
+        });
+        
+        // An operator acts for a single institution
+        if entry.institution_id != institution_id {
+            return Err(CustodyError::invalid_input(
+                "institution_id",
+                "operator already belongs to another institution",
+            ));
+        }
+        
+        entry.permissions.insert(permission);
+        Ok("Institution permission granted".to_string())
+    })
+}
+
+#[update]
+async fn revoke_institution_permission(
+    operator: Principal,
+    permission: OperatorPermission,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "revoke_institution_permission")?;
+    
+    // Check if caller is emergency contact (admin)
+    let is_admin = is_emergency_contact(caller).await;
+    
+    if !is_admin {
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    INSTITUTION_OPERATORS.with(|ops| {
+        let mut ops = ops.borrow_mut();
+        let entry = match ops.get_mut(&operator) {
+            Some(entry) => entry,
+            None => return Err(CustodyError::not_found("Institution operator", operator.to_string())),
+        };
+        
+        if !entry.permissions.remove(&permission) {
+            return Err(CustodyError::not_found("Permission", format!("{:?}", permission)));
+        }
+        
+        // Operators left without permissions keep their institution scope so
+        // they don't fall back to custodian-wide operator rights
+        Ok("Institution permission revoked".to_string())
+    })
+}
+
+/// Places an account under an institution's operators, or back under
+/// custodian-wide operators with None
+#[update]
+async fn assign_account_institution(account_id: String, institution_id: Option<String>) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "assign_account_institution")?;
+    
+    // Check if caller is emergency contact (admin)
+    let is_admin = is_emergency_contact(caller).await;
+    
+    if !is_admin {
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    if institution_id.as_deref().is_some_and(str::is_empty) {
+        return Err(CustodyError::invalid_input("institution_id", "cannot be empty"));
+    }
+    
+    CUSTODY_ACCOUNTS.with(|accounts| {
+        match accounts.borrow_mut().get_mut(&account_id) {
+            Some(account) => {
+                account.institution_id = institution_id;
+                Ok("Account institution updated".to_string())
+            },
+            None => Err(CustodyError::not_found("Account", account_id.clone())),
+        }
+    })
+}
+
+#[query]
+fn get_institution_operator(operator: Principal) -> Option<InstitutionOperator> {
+    INSTITUTION_OPERATORS.with(|ops| ops.borrow().get(&operator).cloned())
+}
+
+#[update]
+async fn add_compliance_officer(officer: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_compliance_officer")?;
+    
+    // Check if caller is emergency contact (admin)
+    let is_admin = is_emergency_contact(caller).await;
+    
+    if !is_admin {
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    COMPLIANCE_OFFICERS.with(|officers| {
+        officers.borrow_mut().insert(officer);
+    });
+    
+    Ok("Compliance officer added successfully".to_string())
+}
+
+#[update]
+async fn set_risk_management_canister(canister_id: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_risk_management_canister")?;
+    
+    // Check if caller is emergency contact (admin)
+    let is_admin = is_emergency_contact(caller).await;
+    
+    if !is_admin {
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    RISK_MANAGEMENT_CANISTER.with(|c| {
+        *c.borrow_mut() = Some(canister_id);
+    });
+    
+    Ok("Risk management canister configured successfully".to_string())
+}
+
+#[update]
+async fn set_audit_trail_canister(canister_id: Principal) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_audit_trail_canister")?;
     
     // Check if caller is emergency contact (admin)
-    let is_admin = EMERGENCY_CONTACTS.with(|contacts| {
-        contacts.borrow().contains(&caller)
+    let is_admin = is_emergency_contact(caller).await;
+    
+    if !is_admin {
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    AUDIT_TRAIL_CANISTER.with(|c| {
+        *c.borrow_mut() = Some(canister_id);
+    });
+    
+    Ok("Audit trail canister configured successfully".to_string())
+}
+
+/// Sets the event bus that transaction.executed and account.frozen events
+/// are published to. The bus must list this canister as a publisher
+#[update]
+async fn set_event_bus_canister(canister_id: Option<Principal>) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_event_bus_canister")?;
+    
+    if !is_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    EVENT_BUS_CANISTER.with(|c| {
+        *c.borrow_mut() = canister_id;
     });
     
+    Ok("Event bus canister configured successfully".to_string())
+}
+
+#[update]
+async fn update_custody_settings(new_settings: CustodySettings) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "update_custody_settings")?;
+    
+    // Check if caller is emergency contact (admin)
+    let is_admin = is_emergency_contact(caller).await;
+    
     if !is_admin {
-        return Err("Unauthorized admin action".to_string());
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    CUSTODY_SETTINGS.with(|settings| {
+        *settings.borrow_mut() = new_settings;
+    });
+    
+    Ok("Settings updated successfully".to_string())
+}
+
+// === Upgrade Functions ===
+
+/// Closes the upgrade gate so no new work starts, then reports whether the
+/// spawned tasks have drained. An update call cannot block, so the admin
+/// polls until this returns Ok before stopping and upgrading the canister
+#[update]
+async fn begin_upgrade_preparation() -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "begin_upgrade_preparation")?;
+    
+    if !is_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    UPGRADE_PENDING.with(|pending| *pending.borrow_mut() = true);
+    
+    let in_flight = IN_FLIGHT_COUNT.with(|count| *count.borrow());
+    if in_flight > 0 {
+        return Err(CustodyError::status_conflict(
+            format!("{} operations in flight", in_flight),
+            "no operations in flight",
+        ));
+    }
+    
+    ic_cdk::println!("Upgrade preparation complete; requested by {}", caller);
+    Ok("No operations in flight; ready for upgrade".to_string())
+}
+
+/// Reopens the gate after an upgrade, or after an abandoned one. Nothing is
+/// kept in stable storage yet, so a fresh wasm already starts with it open
+#[update]
+async fn finalize_upgrade() -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "finalize_upgrade")?;
+    
+    if !is_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("admin action"));
+    }
+    
+    UPGRADE_PENDING.with(|pending| *pending.borrow_mut() = false);
+    
+    Ok("Upgrade finalized; accepting requests".to_string())
+}
+
+// Every update endpoint starts here, so this is where the upgrade gate turns
+// new requests away; the upgrade endpoints themselves stay reachable
+fn check_rate_limit(caller: Principal, method_name: &str) -> Result<(), CustodyError> {
+    let gated = !matches!(method_name, "begin_upgrade_preparation" | "finalize_upgrade");
+    if gated && UPGRADE_PENDING.with(|pending| *pending.borrow()) {
+        return Err(CustodyError::status_conflict("Upgrade in progress, retry in 60s", "no upgrade in progress"));
+    }
+    
+    shared::check_rate_limit(caller, method_name)
+}
+
+// Holds a task's slot in IN_FLIGHT_COUNT. Dropping it on completion, or when
+// the runtime cleans up after a trapped callback, releases the slot
+struct InFlightGuard;
+
+impl InFlightGuard {
+    fn new() -> Self {
+        IN_FLIGHT_COUNT.with(|count| *count.borrow_mut() += 1);
+        InFlightGuard
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        IN_FLIGHT_COUNT.with(|count| {
+            let mut count = count.borrow_mut();
+            *count = count.saturating_sub(1);
+        });
+    }
+}
+
+fn spawn_tracked<F: std::future::Future<Output = ()> + 'static>(future: F) {
+    let guard = InFlightGuard::new();
+    ic_cdk::spawn(async move {
+        let _guard = guard;
+        future.await;
+    });
+}
+
+// === Helper Functions ===
+
+// Checks the local role set first and only asks the auth canister about
+// principals this canister doesn't already know
+async fn holds_role(
+    local_set: &'static LocalKey<RefCell<BTreeSet<Principal>>>,
+    principal: Principal,
+    role_name: &str,
+) -> bool {
+    local_set.with(|set| set.borrow().contains(&principal)) || has_role(principal, role_name).await
+}
+
+async fn is_emergency_contact(principal: Principal) -> bool {
+    EMERGENCY_CONFIG.with(|config| config.borrow().contacts.contains(&principal))
+        || has_role(principal, "emergency_contact").await
+}
+
+// Whether an institution operator holds `permission` for the account, or
+// None if the principal isn't an institution operator
+fn institution_permission(
+    principal: Principal,
+    account: &CustodyAccount,
+    permission: OperatorPermission,
+) -> Option<bool> {
+    INSTITUTION_OPERATORS.with(|ops| {
+        ops.borrow().get(&principal).map(|op| {
+            account.institution_id.as_deref() == Some(op.institution_id.as_str()) && op.permissions.contains(&permission)
+        })
+    })
+}
+
+// reserved_balance only tracks BTC withdrawals and transfers
+fn reserves_balance(transaction: &Transaction) -> bool {
+    transaction.token_canister_id.is_none() && matches!(
+        transaction.transaction_type,
+        TransactionType::Withdrawal | TransactionType::Transfer
+    )
+}
+
+// Every path that ends a transaction without executing it must release the
+// balance reserved when it was initiated
+fn release_reserved_balance(transaction: &Transaction) {
+    if !reserves_balance(transaction) {
+        return;
+    }
+    
+    CUSTODY_ACCOUNTS.with(|accounts| {
+        if let Some(account) = accounts.borrow_mut().get_mut(&transaction.account_id) {
+            account.reserved_balance = account.reserved_balance.saturating_sub(transaction.amount);
+        }
+    });
+}
+
+// Unexpired delegation from `owner` to `delegatee` that includes `permission`
+fn active_delegation(
+    delegatee: Principal,
+    owner: Principal,
+    permission: DelegationPermission,
+) -> Option<Delegation> {
+    let now = ic_cdk::api::time();
+    DELEGATIONS.with(|delegations| {
+        delegations.borrow().get(&delegatee).and_then(|granted| {
+            granted.iter()
+                .find(|d| d.delegated_by == owner && d.valid_until > now && d.permissions.contains(&permission))
+                .cloned()
+        })
+    })
+}
+
+fn check_delegated_amount(delegation: &Delegation, amount: u64) -> Result<(), CustodyError> {
+    match delegation.max_amount_per_tx {
+        Some(limit) if amount > limit => Err(CustodyError::LimitExceeded { limit, actual: amount }),
+        _ => Ok(()),
+    }
+}
+
+// Institution operators are limited to their own institution's accounts, and
+// an account assigned to an institution is left to that institution's
+// operators. Custodian-wide operators act on unassigned accounts only.
+async fn is_account_operator(
+    principal: Principal,
+    account: &CustodyAccount,
+    permission: OperatorPermission,
+) -> bool {
+    match institution_permission(principal, account, permission) {
+        Some(allowed) => allowed,
+        None => account.institution_id.is_none() && holds_role(&AUTHORIZED_OPERATORS, principal, "operator").await,
+    }
+}
+
+fn check_withdrawal_destination(account: &CustodyAccount, recipient: Option<&str>) -> Result<(), CustodyError> {
+    if let Some(recipient) = recipient {
+        if account.withdrawal_blacklist.contains(recipient) {
+            return Err(CustodyError::invalid_input("recipient", "address is blacklisted for this account"));
+        }
     }
     
-    CUSTODY_SETTINGS.with(|settings| {
-        *settings.borrow_mut() = new_settings;
-    });
+    if let Some(whitelist) = &account.withdrawal_whitelist {
+        match recipient {
+            Some(recipient) if whitelist.contains(recipient) => {},
+            _ => return Err(CustodyError::invalid_input("recipient", "address is not on the account's withdrawal whitelist")),
+        }
+    }
     
-    Ok("Settings updated successfully".to_string())
+    Ok(())
 }
 
-// === Helper Functions ===
-
 fn calculate_risk_score(transaction_type: &TransactionType, amount: u64, account: &CustodyAccount) -> u8 {
     let mut risk_score = 0u8;
     
@@ -567,8 +3263,7 @@ fn calculate_risk_score(transaction_type: &TransactionType, amount: u64, account
     }
     
     // Risk based on amount (as percentage of balance)
-    if account.balance > 0 {
-        let percentage = (amount * 100) / account.balance;
+    if let Some(percentage) = (amount * 100).checked_div(account.balance) {
         if percentage > 50 {
             risk_score += 3;
         } else if percentage > 20 {
@@ -589,18 +3284,224 @@ fn calculate_risk_score(transaction_type: &TransactionType, amount: u64, account
     std::cmp::min(risk_score, 10) // Cap at 10
 }
 
+// Keys are scoped to the caller so two principals can't collide on the same key
+fn idempotency_cache_key(caller: &Principal, key: &str) -> String {
+    format!("{}:{}", caller, key)
+}
+
+// Returns the recorded result for a key still inside the TTL. Otherwise the
+// key is reserved before the caller awaits anything, so a retry arriving
+// while the original call is in flight is turned away instead of running
+// twice. A request without a key is never cached.
+fn reserve_idempotency_key(cache_key: Option<&str>) -> Result<Option<String>, CustodyError> {
+    let Some(cache_key) = cache_key else {
+        return Ok(None);
+    };
+    let now = ic_cdk::api::time();
+    
+    IDEMPOTENCY_CACHE.with(|cache| {
+        IDEMPOTENCY_EXPIRY.with(|expiry| {
+            let mut cache = cache.borrow_mut();
+            let mut expiry = expiry.borrow_mut();
+            prune_expired(&mut cache, &mut expiry, IDEMPOTENCY_TTL_NANOS, now);
+            
+            match cache.get(cache_key) {
+                Some((Some(result), _)) => Ok(Some(result.clone())),
+                Some((None, _)) => Err(CustodyError::status_conflict("in progress", "completed")),
+                None => {
+                    record_cache_entry(&mut cache, &mut expiry, cache_key.to_string(), None, now);
+                    Ok(None)
+                },
+            }
+        })
+    })
+}
+
+// Only successful results are recorded so a failed call can be retried; a
+// failure releases the reservation
+fn record_idempotent_result(cache_key: Option<String>, result: &Result<String, CustodyError>) {
+    let Some(cache_key) = cache_key else {
+        return;
+    };
+    
+    IDEMPOTENCY_CACHE.with(|cache| {
+        IDEMPOTENCY_EXPIRY.with(|expiry| {
+            let mut cache = cache.borrow_mut();
+            match result {
+                Ok(value) => {
+                    let now = ic_cdk::api::time();
+                    record_cache_entry(&mut cache, &mut expiry.borrow_mut(), cache_key, Some(value.clone()), now);
+                },
+                Err(_) => {
+                    cache.remove(&cache_key);
+                },
+            }
+        })
+    });
+}
+
+fn record_cache_entry(
+    cache: &mut BTreeMap<String, (Option<String>, u64)>,
+    expiry: &mut VecDeque<(u64, String)>,
+    key: String,
+    value: Option<String>,
+    now: u64,
+) {
+    expiry.push_back((now, key.clone()));
+    cache.insert(key, (value, now));
+}
+
+// Drops cache entries recorded at least `ttl` ago. Only the expired front of
+// `expiry` is visited, so pruning doesn't scan the whole cache on every call.
+// A key written again since keeps its newer entry.
+fn prune_expired(
+    cache: &mut BTreeMap<String, (Option<String>, u64)>,
+    expiry: &mut VecDeque<(u64, String)>,
+    ttl: u64,
+    now: u64,
+) {
+    while expiry.front().is_some_and(|(recorded_at, _)| now.saturating_sub(*recorded_at) >= ttl) {
+        if let Some((recorded_at, key)) = expiry.pop_front() {
+            if cache.get(&key).is_some_and(|(_, current)| *current == recorded_at) {
+                cache.remove(&key);
+            }
+        }
+    }
+}
+
+// Fingerprints a request by its content, so a client that resends the same
+// call without an idempotency key within DEDUP_TTL_NANOS gets the original
+// transaction back. The external reference is part of the content, keeping
+// two separately referenced payments of the same amount apart
+fn dedup_cache_key(
+    caller: &Principal,
+    account_id: &str,
+    amount: u64,
+    transaction_type: &TransactionType,
+    recipient: Option<&str>,
+    external_reference: Option<&str>,
+) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "{}:{}:{}:{:?}:{:?}:{:?}",
+        caller, account_id, amount, transaction_type, recipient, external_reference,
+    ));
+    format!("{:x}", hasher.finalize())
+}
+
+// Returns the transaction recorded for a fingerprint still inside the TTL,
+// unless it was since cancelled or rejected and a fresh one is wanted.
+// Otherwise the fingerprint is reserved before the caller awaits anything, so
+// a duplicate arriving meanwhile is turned away instead of creating a second
+// transaction.
+fn reserve_dedup_key(dedup_key: &str) -> Result<Option<String>, CustodyError> {
+    let now = ic_cdk::api::time();
+    
+    DEDUP_CACHE.with(|cache| {
+        DEDUP_EXPIRY.with(|expiry| {
+            let mut cache = cache.borrow_mut();
+            let mut expiry = expiry.borrow_mut();
+            prune_expired(&mut cache, &mut expiry, DEDUP_TTL_NANOS, now);
+            
+            match cache.get(dedup_key) {
+                Some((None, _)) => return Err(CustodyError::status_conflict("in progress", "completed")),
+                Some((Some(transaction_id), _)) => {
+                    let live = TRANSACTIONS.with(|txns| {
+                        txns.borrow().get(transaction_id).is_some_and(|txn| {
+                            !matches!(txn.status, TransactionStatus::Cancelled | TransactionStatus::Rejected)
+                        })
+                    });
+                    if live {
+                        return Ok(Some(transaction_id.clone()));
+                    }
+                },
+                None => {},
+            }
+            
+            record_cache_entry(&mut cache, &mut expiry, dedup_key.to_string(), None, now);
+            Ok(None)
+        })
+    })
+}
+
+// Fills in a reservation once the transaction exists, or releases it so a
+// failed call can be retried
+fn record_deduplicated_transaction(dedup_key: String, result: &Result<String, CustodyError>) {
+    DEDUP_CACHE.with(|cache| {
+        DEDUP_EXPIRY.with(|expiry| {
+            let mut cache = cache.borrow_mut();
+            match result {
+                Ok(transaction_id) => {
+                    let now = ic_cdk::api::time();
+                    record_cache_entry(&mut cache, &mut expiry.borrow_mut(), dedup_key, Some(transaction_id.clone()), now);
+                },
+                Err(_) => {
+                    cache.remove(&dedup_key);
+                },
+            }
+        })
+    });
+}
+
 // === Integration Functions ===
 
 #[update]
-async fn check_compliance_status(principal: Principal) -> Result<String, String> {
-    // In production, this would call the compliance canister
-    // For now, we'll simulate a compliance check
-    let compliance_result = "compliant"; // Placeholder
+async fn check_compliance_status(principal: Principal) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "check_compliance_status")?;
+    
+    verify_compliance(principal).await
+}
+
+fn set_integration_config(integration_config: Option<IntegrationConfig>) {
+    if let Some(config) = integration_config {
+        INTEGRATION_CONFIG.with(|c| *c.borrow_mut() = config);
+    }
+}
+
+// Asks the compliance_engine canister for the principal's KYC status. Any
+// failure to get a "Compliant" answer, including an unreachable or slow
+// compliance canister, is an error so the caller cannot proceed.
+async fn verify_compliance(principal: Principal) -> Result<String, CustodyError> {
+    match fetch_compliance_status(principal).await? {
+        status if status == "Compliant" => Ok("Account is compliant".to_string()),
+        status => Err(CustodyError::status_conflict(status, "Compliant")),
+    }
+}
+
+// Raw check_compliance_status answer from the compliance_engine canister.
+// Configuration, transport and timeout failures are InternalErrors.
+async fn fetch_compliance_status(principal: Principal) -> Result<String, CustodyError> {
+    let config = INTEGRATION_CONFIG.with(|c| c.borrow().clone());
+    
+    let compliance_canister = match config.compliance_canister {
+        Some(canister) => canister,
+        None => return Err(CustodyError::InternalError("Compliance canister not configured".to_string())),
+    };
+    
+    let started_at = ic_cdk::api::time();
+    
+    let result: Result<(Result<String, CustodyError>,), _> = ic_cdk::call(
+        compliance_canister,
+        "check_compliance_status",
+        (principal,),
+    ).await;
+    
+    // Calls can't be cancelled, so a late answer is discarded instead
+    let elapsed = ic_cdk::api::time().saturating_sub(started_at);
+    if elapsed > config.compliance_check_timeout_ns {
+        return Err(CustodyError::InternalError(format!(
+            "Compliance check timed out after {} ms",
+            elapsed / 1_000_000
+        )));
+    }
     
-    match compliance_result {
-        "compliant" => Ok("Account is compliant".to_string()),
-        "non_compliant" => Err("Account is not compliant".to_string()),
-        _ => Err("Compliance status unknown".to_string()),
+    match result {
+        Ok((Ok(status),)) => Ok(status),
+        Ok((Err(e),)) => Err(e),
+        Err((code, msg)) => Err(CustodyError::InternalError(format!(
+            "Compliance canister unreachable: {:?} {}",
+            code, msg
+        ))),
     }
 }
 
@@ -610,8 +3511,9 @@ async fn initiate_multisig_transaction(
     transaction_type: TransactionType,
     amount: u64,
     recipient: Option<String>,
-) -> Result<String, String> {
+) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "initiate_multisig_transaction")?;
     
     // Validate account and authorization
     let account = CUSTODY_ACCOUNTS.with(|accounts| {
@@ -620,144 +3522,522 @@ async fn initiate_multisig_transaction(
     
     let account = match account {
         Some(acc) => acc,
-        None => return Err("Account not found".to_string()),
+        None => return Err(CustodyError::not_found("Account", account_id)),
     };
     
     if !account.authorized_users.contains(&caller) {
-        return Err("Unauthorized user".to_string());
+        return Err(CustodyError::unauthorized("initiate_multisig_transaction"));
     }
     
     // Check compliance first
-    match check_compliance_status(caller).await {
-        Ok(_) => {},
-        Err(e) => return Err(format!("Compliance check failed: {}", e)),
-    }
+    verify_compliance(caller).await?;
     
     // If account requires multi-sig approval (more than 1 required approval)
     if account.required_approvals > 1 {
         // Create a multi-sig transaction proposal
         // In production, this would call the multisig wallet canister
-        let tx_id = format!("multisig_{}", ic_cdk::api::time());
-        
         // For now, create a regular transaction that requires approvals
-        return initiate_transaction(account_id, transaction_type, amount, recipient).await;
+        initiate_transaction_internal(account_id, transaction_type, amount, recipient, None, None, None).await
     } else {
         // Single approval required, process directly
-        return initiate_transaction(account_id, transaction_type, amount, recipient).await;
+        initiate_transaction_internal(account_id, transaction_type, amount, recipient, None, None, None).await
+    }
+}
+
+#[update]
+async fn batch_process_transactions(transaction_ids: Vec<String>) -> Result<Vec<String>, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "batch_process_transactions")?;
+    
+    let mut results = Vec::new();
+    
+    for tx_id in transaction_ids {
+        match execute_transaction(tx_id.clone()).await {
+            Ok(result) => results.push(format!("{}: {}", tx_id, result)),
+            Err(e) => results.push(format!("{}: Error - {}", tx_id, e)),
+        }
+    }
+    
+    Ok(results)
+}
+
+#[query]
+fn get_account_summary(account_id: String) -> Result<AccountSummary, CustodyError> {
+    let account = CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow().get(&account_id).cloned()
+    });
+    
+    match account {
+        Some(acc) => {
+            let transactions = get_account_transactions(account_id.clone());
+            let pending_txs = get_pending_transactions(account_id.clone());
+            
+            let summary = AccountSummary {
+                account_id: acc.id,
+                institution_name: acc.institution_name,
+                account_type: acc.account_type,
+                status: acc.status,
+                balance: acc.balance,
+                reserved_balance: acc.reserved_balance,
+                compliance_status: acc.compliance_status,
+                total_transactions: transactions.len() as u64,
+                pending_transactions: pending_txs.len() as u64,
+                last_activity: transactions.iter().map(|t| t.created_at).max().unwrap_or(0),
+            };
+            
+            Ok(summary)
+        },
+        None => Err(CustodyError::not_found("Account", account_id.clone())),
+    }
+}
+
+// An account's balances, as read by risk management's capital and stress reports
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AccountBalance {
+    pub id: String,
+    pub account_type: AccountType,
+    pub balance: u64,
+    pub reserved_balance: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AccountSummary {
+    pub account_id: String,
+    pub institution_name: String,
+    pub account_type: AccountType,
+    pub status: AccountStatus,
+    pub balance: u64,
+    pub reserved_balance: u64,
+    pub compliance_status: ComplianceStatus,
+    pub total_transactions: u64,
+    pub pending_transactions: u64,
+    pub last_activity: u64,
+}
+
+#[query]
+fn get_system_metrics() -> SystemMetrics {
+    let total_accounts = CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().len());
+    let total_transactions = TRANSACTIONS.with(|txns| txns.borrow().len());
+    
+    let active_accounts = CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow()
+            .values()
+            .filter(|acc| acc.status == AccountStatus::Active)
+            .count()
+    });
+    
+    let pending_transactions = TRANSACTIONS.with(|txns| {
+        txns.borrow()
+            .values()
+            .filter(|txn| txn.status == TransactionStatus::Pending)
+            .count()
+    });
+    
+    let total_balance = CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow()
+            .values()
+            .map(|acc| acc.balance)
+            .sum()
+    });
+    
+    let total_reserved = CUSTODY_ACCOUNTS.with(|accounts| {
+        accounts.borrow()
+            .values()
+            .map(|acc| acc.reserved_balance)
+            .sum()
+    });
+    
+    SystemMetrics {
+        total_accounts: total_accounts as u64,
+        active_accounts: active_accounts as u64,
+        total_transactions: total_transactions as u64,
+        pending_transactions: pending_transactions as u64,
+        total_balance,
+        total_reserved,
+        uptime: ic_cdk::api::time(),
+    }
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct SystemMetrics {
+    pub total_accounts: u64,
+    pub active_accounts: u64,
+    pub total_transactions: u64,
+    pub pending_transactions: u64,
+    pub total_balance: u64,
+    pub total_reserved: u64,
+    pub uptime: u64,
+}
+
+// === Statement Functions ===
+
+/// Builds a statement of the executed transactions in `[from_time, to_time]`.
+/// Balances are reconstructed by replaying executed transactions backwards
+/// from the current balance.
+#[update]
+async fn generate_account_statement(
+    account_id: String,
+    from_time: u64,
+    to_time: u64,
+) -> Result<AccountStatement, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "generate_account_statement")?;
+    
+    if from_time > to_time {
+        return Err(CustodyError::invalid_input("from_time", "must not be after to_time"));
+    }
+    
+    let account = match CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().get(&account_id).cloned()) {
+        Some(acc) => acc,
+        None => return Err(CustodyError::not_found("Account", account_id)),
+    };
+    
+    let is_authorized = account.authorized_users.contains(&caller)
+        || active_delegation(caller, account.owner, DelegationPermission::ViewOnly).is_some()
+        || is_account_operator(caller, &account, OperatorPermission::ViewTransactions).await;
+    
+    if !is_authorized {
+        return Err(CustodyError::unauthorized("generate_account_statement"));
+    }
+    
+    // Read the account after the role check so the balance is current
+    let account = match CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().get(&account_id).cloned()) {
+        Some(acc) => acc,
+        None => return Err(CustodyError::not_found("Account", account_id)),
+    };
+    
+    let mut executed: Vec<Transaction> = TRANSACTIONS.with(|txns| {
+        txns.borrow()
+            .values()
+            // Token withdrawals move multi_asset_balances, not the satoshi balance
+            .filter(|txn| {
+                txn.account_id == account_id
+                    && txn.status == TransactionStatus::Executed
+                    && txn.token_canister_id.is_none()
+            })
+            .cloned()
+            .collect()
+    });
+    executed.sort_by_key(|txn| txn.executed_at.unwrap_or(txn.created_at));
+    
+    // Undo everything executed after the period to get the closing balance
+    let mut closing_balance = account.balance as i128;
+    for txn in executed.iter().filter(|txn| txn.executed_at.unwrap_or(txn.created_at) > to_time) {
+        closing_balance -= balance_effect(txn);
+    }
+    
+    let mut opening_balance = closing_balance;
+    let mut total_deposited = 0u64;
+    let mut total_withdrawn = 0u64;
+    let mut total_transferred = 0u64;
+    let mut transactions = Vec::new();
+    
+    for txn in &executed {
+        let executed_at = txn.executed_at.unwrap_or(txn.created_at);
+        if executed_at < from_time || executed_at > to_time {
+            continue;
+        }
+        
+        opening_balance -= balance_effect(txn);
+        match txn.transaction_type {
+            TransactionType::Deposit => total_deposited += txn.amount,
+            TransactionType::Withdrawal => total_withdrawn += txn.amount,
+            TransactionType::Transfer => total_transferred += txn.amount,
+            TransactionType::Emergency => {},
+        }
+        
+        transactions.push(StatementTransaction {
+            transaction_id: txn.id.clone(),
+            transaction_type: txn.transaction_type.clone(),
+            amount: txn.amount,
+            recipient: txn.recipient.clone(),
+            executed_at,
+            memo: txn.memo.clone(),
+            external_reference: txn.external_reference.clone(),
+        });
+    }
+    
+    let mut statement = AccountStatement {
+        id: Uuid::new_v4().to_string(),
+        account_id: account.id,
+        institution_name: account.institution_name,
+        period_start: from_time,
+        period_end: to_time,
+        opening_balance: opening_balance.clamp(0, u64::MAX as i128) as u64,
+        closing_balance: closing_balance.clamp(0, u64::MAX as i128) as u64,
+        total_deposited,
+        total_withdrawn,
+        total_transferred,
+        transaction_count: transactions.len() as u64,
+        transactions,
+        generated_at: ic_cdk::api::time(),
+        statement_hash: String::new(),
+    };
+    statement.statement_hash = calculate_statement_hash(&statement)?;
+    
+    STATEMENTS.with(|statements| {
+        statements.borrow_mut().insert(statement.id.clone(), statement.clone());
+    });
+    
+    Ok(statement)
+}
+
+// Signed change an executed transaction made to the account balance
+fn balance_effect(txn: &Transaction) -> i128 {
+    if txn.token_canister_id.is_some() {
+        return 0;
+    }
+    
+    match txn.transaction_type {
+        TransactionType::Deposit => txn.amount as i128,
+        TransactionType::Withdrawal | TransactionType::Transfer => -(txn.amount as i128),
+        TransactionType::Emergency => 0,
     }
 }
 
+// Hash of the statement serialized with an empty statement_hash field
+fn calculate_statement_hash(statement: &AccountStatement) -> Result<String, CustodyError> {
+    let serialized = serde_json::to_vec(statement)
+        .map_err(|e| CustodyError::InternalError(format!("Failed to serialize statement: {}", e)))?;
+    
+    let mut hasher = Sha256::new();
+    hasher.update(&serialized);
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AccountStatement {
+    pub id: String,
+    pub account_id: String,
+    pub institution_name: String,
+    pub period_start: u64,
+    pub period_end: u64,
+    pub opening_balance: u64,
+    pub closing_balance: u64,
+    pub total_deposited: u64,
+    pub total_withdrawn: u64,
+    pub total_transferred: u64,
+    pub transaction_count: u64,
+    pub transactions: Vec<StatementTransaction>,
+    pub generated_at: u64,
+    pub statement_hash: String,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StatementTransaction {
+    pub transaction_id: String,
+    pub transaction_type: TransactionType,
+    pub amount: u64,
+    pub recipient: Option<String>,
+    pub executed_at: u64,
+    pub memo: Option<String>,
+    pub external_reference: Option<String>,
+}
+
+thread_local! {
+    static STATEMENTS: RefCell<BTreeMap<String, AccountStatement>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+// === Account Analytics Functions ===
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct AccountAnalytics {
+    pub account_id: String,
+    pub period_start: u64,
+    pub period_end: u64,
+    // Time-weighted over the period
+    pub average_balance: u64,
+    pub peak_balance: u64,
+    pub trough_balance: u64,
+    pub total_inflows: u64,
+    pub total_outflows: u64,
+    pub net_change: i64,
+    pub transaction_count_by_type: BTreeMap<String, u64>,
+    // (timestamp, score) from the risk management canister, oldest first
+    pub risk_score_trend: Vec<(u64, u8)>,
+    // Transactions held for risk review or rejected during the period
+    pub compliance_events: u32,
+}
+
+// The fields analytics needs from risk_management's RiskSnapshot
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+struct RiskHistorySnapshot {
+    timestamp: u64,
+    score: u8,
+}
+
+const MAX_BALANCE_SNAPSHOTS: usize = 10_000;
+// risk_management keeps at most this many snapshots per account, and returns
+// only 100 unless asked for more
+const MAX_RISK_HISTORY_SNAPSHOTS: u32 = 1_000;
+
+thread_local! {
+    // account id -> (timestamp, balance after the change), oldest first
+    static BALANCE_SNAPSHOTS: RefCell<BTreeMap<String, VecDeque<(u64, u64)>>> = const { RefCell::new(BTreeMap::new()) };
+    // account id -> balance of the newest snapshot evicted from BALANCE_SNAPSHOTS,
+    // i.e. the balance in effect just before the oldest snapshot still kept
+    static EVICTED_BALANCES: RefCell<BTreeMap<String, u64>> = const { RefCell::new(BTreeMap::new()) };
+}
+
 #[update]
-async fn batch_process_transactions(transaction_ids: Vec<String>) -> Result<Vec<String>, String> {
-    let mut results = Vec::new();
+async fn get_account_analytics(
+    account_id: String,
+    from_time: u64,
+    to_time: u64,
+) -> Result<AccountAnalytics, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "get_account_analytics")?;
     
-    for tx_id in transaction_ids {
-        match execute_transaction(tx_id.clone()).await {
-            Ok(result) => results.push(format!("{}: {}", tx_id, result)),
-            Err(e) => results.push(format!("{}: Error - {}", tx_id, e)),
-        }
+    if from_time > to_time {
+        return Err(CustodyError::invalid_input("from_time", "must not be after to_time"));
     }
     
-    Ok(results)
-}
-
-#[query]
-fn get_account_summary(account_id: String) -> Result<AccountSummary, String> {
-    let account = CUSTODY_ACCOUNTS.with(|accounts| {
-        accounts.borrow().get(&account_id).cloned()
-    });
+    let account = CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().get(&account_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Account", account_id.clone()))?;
     
-    match account {
-        Some(acc) => {
-            let transactions = get_account_transactions(account_id.clone());
-            let pending_txs = get_pending_transactions(account_id.clone());
+    let is_authorized = account.authorized_users.contains(&caller)
+        || active_delegation(caller, account.owner, DelegationPermission::ViewOnly).is_some()
+        || is_account_operator(caller, &account, OperatorPermission::ViewTransactions).await;
+    
+    if !is_authorized {
+        return Err(CustodyError::unauthorized("get_account_analytics"));
+    }
+    
+    let (average_balance, peak_balance, trough_balance) = balance_metrics(&account, from_time, to_time);
+    
+    let mut total_inflows = 0u64;
+    let mut total_outflows = 0u64;
+    let mut transaction_count_by_type = BTreeMap::new();
+    let mut compliance_events = 0u32;
+    
+    TRANSACTIONS.with(|txns| {
+        for txn in txns.borrow().values().filter(|txn| txn.account_id == account_id) {
+            if txn.created_at >= from_time && txn.created_at <= to_time {
+                *transaction_count_by_type.entry(format!("{:?}", txn.transaction_type)).or_insert(0u64) += 1;
+                
+                if txn.required_risk_reviews > 0 || txn.status == TransactionStatus::Rejected {
+                    compliance_events += 1;
+                }
+            }
             
-            let summary = AccountSummary {
-                account_id: acc.id,
-                institution_name: acc.institution_name,
-                account_type: acc.account_type,
-                status: acc.status,
-                balance: acc.balance,
-                reserved_balance: acc.reserved_balance,
-                compliance_status: acc.compliance_status,
-                total_transactions: transactions.len() as u64,
-                pending_transactions: pending_txs.len() as u64,
-                last_activity: transactions.iter().map(|t| t.created_at).max().unwrap_or(0),
-            };
+            // Flows are in BTC, like the balance
+            let executed_in_period = txn.status == TransactionStatus::Executed
+                && txn.token_canister_id.is_none()
+                && txn.executed_at.is_some_and(|at| at >= from_time && at <= to_time);
             
-            Ok(summary)
-        },
-        None => Err("Account not found".to_string()),
-    }
+            if executed_in_period {
+                match balance_effect(txn) {
+                    effect if effect > 0 => total_inflows = total_inflows.saturating_add(txn.amount),
+                    effect if effect < 0 => total_outflows = total_outflows.saturating_add(txn.amount),
+                    _ => {}
+                }
+            }
+        }
+    });
+    
+    let net_change = (total_inflows as i128 - total_outflows as i128).clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+    
+    Ok(AccountAnalytics {
+        account_id: account_id.clone(),
+        period_start: from_time,
+        period_end: to_time,
+        average_balance,
+        peak_balance,
+        trough_balance,
+        total_inflows,
+        total_outflows,
+        net_change,
+        transaction_count_by_type,
+        risk_score_trend: fetch_risk_score_trend(&account_id, from_time, to_time).await,
+        compliance_events,
+    })
 }
 
-#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
-pub struct AccountSummary {
-    pub account_id: String,
-    pub institution_name: String,
-    pub account_type: AccountType,
-    pub status: AccountStatus,
-    pub balance: u64,
-    pub reserved_balance: u64,
-    pub compliance_status: ComplianceStatus,
-    pub total_transactions: u64,
-    pub pending_transactions: u64,
-    pub last_activity: u64,
+fn record_balance_snapshot(account_id: &str, balance: u64) {
+    let now = ic_cdk::api::time();
+    
+    BALANCE_SNAPSHOTS.with(|snapshots| {
+        let mut snapshots = snapshots.borrow_mut();
+        let account_snapshots = snapshots.entry(account_id.to_string()).or_default();
+        account_snapshots.push_back((now, balance));
+        
+        while account_snapshots.len() > MAX_BALANCE_SNAPSHOTS {
+            if let Some((_, evicted_balance)) = account_snapshots.pop_front() {
+                EVICTED_BALANCES.with(|evicted| {
+                    evicted.borrow_mut().insert(account_id.to_string(), evicted_balance);
+                });
+            }
+        }
+    });
 }
 
-#[query]
-fn get_system_metrics() -> SystemMetrics {
-    let total_accounts = CUSTODY_ACCOUNTS.with(|accounts| accounts.borrow().len());
-    let total_transactions = TRANSACTIONS.with(|txns| txns.borrow().len());
+/// (time-weighted average, peak, trough) balance over the period. Before an
+/// account's oldest kept snapshot its balance is taken to be the current
+/// balance if it has never changed, the last evicted snapshot's balance if
+/// older snapshots were dropped, and zero otherwise.
+fn balance_metrics(account: &CustodyAccount, from_time: u64, to_time: u64) -> (u64, u64, u64) {
+    let snapshots = BALANCE_SNAPSHOTS.with(|snapshots| snapshots.borrow().get(&account.id).cloned())
+        .unwrap_or_default();
     
-    let active_accounts = CUSTODY_ACCOUNTS.with(|accounts| {
-        accounts.borrow()
-            .values()
-            .filter(|acc| acc.status == AccountStatus::Active)
-            .count()
-    });
+    let mut balance = if snapshots.is_empty() {
+        account.balance
+    } else {
+        EVICTED_BALANCES.with(|evicted| evicted.borrow().get(&account.id).copied()).unwrap_or(0)
+    };
+    for (timestamp, snapshot_balance) in &snapshots {
+        if *timestamp > from_time {
+            break;
+        }
+        balance = *snapshot_balance;
+    }
     
-    let pending_transactions = TRANSACTIONS.with(|txns| {
-        txns.borrow()
-            .values()
-            .filter(|txn| txn.status == TransactionStatus::Pending)
-            .count()
-    });
+    let mut peak = balance;
+    let mut trough = balance;
+    let mut weighted_sum = 0u128;
+    let mut last_time = from_time;
     
-    let total_balance = CUSTODY_ACCOUNTS.with(|accounts| {
-        accounts.borrow()
-            .values()
-            .map(|acc| acc.balance)
-            .sum()
-    });
+    for (timestamp, snapshot_balance) in snapshots.iter().filter(|(t, _)| *t > from_time && *t <= to_time) {
+        weighted_sum += balance as u128 * (timestamp - last_time) as u128;
+        balance = *snapshot_balance;
+        last_time = *timestamp;
+        peak = peak.max(balance);
+        trough = trough.min(balance);
+    }
+    weighted_sum += balance as u128 * (to_time - last_time) as u128;
     
-    let total_reserved = CUSTODY_ACCOUNTS.with(|accounts| {
-        accounts.borrow()
-            .values()
-            .map(|acc| acc.reserved_balance)
-            .sum()
-    });
+    let average = match to_time - from_time {
+        0 => balance,
+        duration => (weighted_sum / duration as u128) as u64,
+    };
     
-    SystemMetrics {
-        total_accounts: total_accounts as u64,
-        active_accounts: active_accounts as u64,
-        total_transactions: total_transactions as u64,
-        pending_transactions: pending_transactions as u64,
-        total_balance,
-        total_reserved,
-        uptime: ic_cdk::api::time(),
-    }
+    (average, peak, trough)
 }
 
-#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
-pub struct SystemMetrics {
-    pub total_accounts: u64,
-    pub active_accounts: u64,
-    pub total_transactions: u64,
-    pub pending_transactions: u64,
-    pub total_balance: u64,
-    pub total_reserved: u64,
-    pub uptime: u64,
+// Analytics are still useful without risk data, so failures yield an empty trend
+async fn fetch_risk_score_trend(account_id: &str, from_time: u64, to_time: u64) -> Vec<(u64, u8)> {
+    let Some(risk_canister) = RISK_MANAGEMENT_CANISTER.with(|c| *c.borrow()) else {
+        return Vec::new();
+    };
+    
+    let result: Result<(Vec<RiskHistorySnapshot>,), _> = ic_cdk::call(
+        risk_canister,
+        "get_risk_history",
+        (account_id.to_string(), Some(MAX_RISK_HISTORY_SNAPSHOTS)),
+    ).await;
+    
+    match result {
+        Ok((history,)) => {
+            let mut trend: Vec<(u64, u8)> = history.into_iter()
+                .filter(|snapshot| snapshot.timestamp >= from_time && snapshot.timestamp <= to_time)
+                .map(|snapshot| (snapshot.timestamp, snapshot.score))
+                .collect();
+            trend.sort_by_key(|(timestamp, _)| *timestamp);
+            trend
+        }
+        Err((code, msg)) => {
+            ic_cdk::println!("Risk history lookup failed: {:?} {}", code, msg);
+            Vec::new()
+        }
+    }
 }
 
 // === Advanced Transaction Processing ===
@@ -769,15 +4049,20 @@ async fn schedule_transaction(
     amount: u64,
     recipient: Option<String>,
     execute_at: u64,
-) -> Result<String, String> {
+    memo: Option<String>,
+    external_reference: Option<String>,
+) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "schedule_transaction")?;
+    
+    validate_transaction_memo(memo.as_deref())?;
     
     // Validate future execution time (must be at least 5 minutes in the future)
     let now = ic_cdk::api::time();
     let min_delay = 5 * 60 * 1_000_000_000; // 5 minutes in nanoseconds
     
     if execute_at <= now + min_delay {
-        return Err("Execution time must be at least 5 minutes in the future".to_string());
+        return Err(CustodyError::invalid_input("execute_at", "must be at least 5 minutes in the future"));
     }
     
     // Create scheduled transaction
@@ -793,6 +4078,8 @@ async fn schedule_transaction(
         created_at: now,
         created_by: caller,
         status: ScheduledTransactionStatus::Pending,
+        memo,
+        external_reference,
     };
     
     SCHEDULED_TRANSACTIONS.with(|scheduled| {
@@ -813,6 +4100,8 @@ pub struct ScheduledTransaction {
     pub created_at: u64,
     pub created_by: Principal,
     pub status: ScheduledTransactionStatus,
+    pub memo: Option<String>,
+    pub external_reference: Option<String>,
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
@@ -823,15 +4112,250 @@ pub enum ScheduledTransactionStatus {
     Failed,
 }
 
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum RecurrenceFrequency {
+    Daily,
+    Weekly,
+    Monthly,
+    // Third month of each calendar quarter: March, June, September, December
+    Quarterly,
+}
+
+/// Calendar rule for a recurring transaction. `day_of_week` is ISO (1 = Monday
+/// .. 7 = Sunday) and is required for weekly schedules. For monthly and
+/// quarterly schedules `day_of_month` is clamped to the month's length, and
+/// when it is None the occurrence falls on the last business day of the month.
+/// `time_of_day_utc` is seconds after midnight UTC.
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RecurringSchedule {
+    pub frequency: RecurrenceFrequency,
+    pub day_of_month: Option<u8>,
+    pub day_of_week: Option<u8>,
+    pub time_of_day_utc: u32,
+    pub max_occurrences: Option<u32>,
+    pub occurrences_executed: u32,
+    // None once max_occurrences is reached
+    pub next_execution_at: Option<u64>,
+}
+
 thread_local! {
-    static SCHEDULED_TRANSACTIONS: RefCell<BTreeMap<String, ScheduledTransaction>> = RefCell::new(BTreeMap::new());
+    static SCHEDULED_TRANSACTIONS: RefCell<BTreeMap<String, ScheduledTransaction>> = const { RefCell::new(BTreeMap::new()) };
+    // Keyed by the id of the series' first ScheduledTransaction, which later
+    // occurrences copy
+    static RECURRING_SCHEDULES: RefCell<BTreeMap<String, RecurringSchedule>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+const NANOS_PER_SECOND: u64 = 1_000_000_000;
+const SECONDS_PER_DAY: u64 = 86_400;
+
+#[update]
+async fn schedule_recurring_transaction(
+    account_id: String,
+    transaction_type: TransactionType,
+    amount: u64,
+    recipient: Option<String>,
+    schedule: RecurringSchedule,
+    memo: Option<String>,
+    external_reference: Option<String>,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "schedule_recurring_transaction")?;
+    
+    validate_recurring_schedule(&schedule)?;
+    validate_transaction_memo(memo.as_deref())?;
+    
+    let now = ic_cdk::api::time();
+    let min_delay = 5 * 60 * NANOS_PER_SECOND;
+    
+    let first_execution = next_occurrence_after(&schedule, now + min_delay)
+        .ok_or_else(|| CustodyError::invalid_input("schedule", "has no upcoming occurrence"))?;
+    
+    let schedule_id = format!("recurring_{}_{}", now, amount);
+    
+    let first_occurrence = ScheduledTransaction {
+        id: schedule_id.clone(),
+        account_id,
+        transaction_type,
+        amount,
+        recipient,
+        execute_at: first_execution,
+        created_at: now,
+        created_by: caller,
+        status: ScheduledTransactionStatus::Pending,
+        memo,
+        external_reference,
+    };
+    
+    let occurrences_executed = 1;
+    let exhausted = schedule.max_occurrences.is_some_and(|max| occurrences_executed >= max);
+    
+    let schedule = RecurringSchedule {
+        occurrences_executed,
+        next_execution_at: if exhausted { None } else { next_occurrence_after(&schedule, first_execution) },
+        ..schedule
+    };
+    
+    SCHEDULED_TRANSACTIONS.with(|scheduled| {
+        scheduled.borrow_mut().insert(schedule_id.clone(), first_occurrence);
+    });
+    RECURRING_SCHEDULES.with(|schedules| {
+        schedules.borrow_mut().insert(schedule_id.clone(), schedule);
+    });
+    
+    Ok(schedule_id)
+}
+
+#[query]
+fn get_recurring_schedule(schedule_id: String) -> Option<RecurringSchedule> {
+    RECURRING_SCHEDULES.with(|schedules| schedules.borrow().get(&schedule_id).cloned())
+}
+
+fn validate_recurring_schedule(schedule: &RecurringSchedule) -> Result<(), CustodyError> {
+    if schedule.time_of_day_utc as u64 >= SECONDS_PER_DAY {
+        return Err(CustodyError::invalid_input("time_of_day_utc", "must be less than 86400 seconds"));
+    }
+    
+    if schedule.day_of_month.is_some_and(|day| !(1..=31).contains(&day)) {
+        return Err(CustodyError::invalid_input("day_of_month", "must be between 1 and 31"));
+    }
+    
+    if schedule.day_of_week.is_some_and(|day| !(1..=7).contains(&day)) {
+        return Err(CustodyError::invalid_input("day_of_week", "must be between 1 (Monday) and 7 (Sunday)"));
+    }
+    
+    if schedule.frequency == RecurrenceFrequency::Weekly && schedule.day_of_week.is_none() {
+        return Err(CustodyError::invalid_input("day_of_week", "is required for weekly schedules"));
+    }
+    
+    if schedule.max_occurrences == Some(0) {
+        return Err(CustodyError::invalid_input("max_occurrences", "must be greater than zero"));
+    }
+    
+    Ok(())
+}
+
+/// Queues a ScheduledTransaction for every occurrence of every recurring
+/// series that has come due, copying the series' first occurrence
+fn queue_due_recurring_transactions(now: u64) {
+    let due_schedules = RECURRING_SCHEDULES.with(|schedules| {
+        schedules.borrow()
+            .iter()
+            .filter(|(_, schedule)| schedule.next_execution_at.is_some_and(|at| at <= now))
+            .map(|(id, schedule)| (id.clone(), schedule.clone()))
+            .collect::<Vec<_>>()
+    });
+    
+    for (schedule_id, mut schedule) in due_schedules {
+        let Some(template) = SCHEDULED_TRANSACTIONS.with(|scheduled| scheduled.borrow().get(&schedule_id).cloned()) else {
+            continue;
+        };
+        
+        while let Some(execute_at) = schedule.next_execution_at.filter(|at| *at <= now) {
+            schedule.occurrences_executed += 1;
+            
+            // External references are unique, so each occurrence gets its own
+            let occurrence = ScheduledTransaction {
+                id: format!("{}_{}", schedule_id, schedule.occurrences_executed),
+                execute_at,
+                created_at: now,
+                status: ScheduledTransactionStatus::Pending,
+                external_reference: template.external_reference.as_ref()
+                    .map(|reference| format!("{}-{}", reference, schedule.occurrences_executed)),
+                ..template.clone()
+            };
+            
+            SCHEDULED_TRANSACTIONS.with(|scheduled| {
+                scheduled.borrow_mut().insert(occurrence.id.clone(), occurrence);
+            });
+            
+            let exhausted = schedule.max_occurrences.is_some_and(|max| schedule.occurrences_executed >= max);
+            schedule.next_execution_at = if exhausted { None } else { next_occurrence_after(&schedule, execute_at) };
+        }
+        
+        RECURRING_SCHEDULES.with(|schedules| {
+            schedules.borrow_mut().insert(schedule_id, schedule);
+        });
+    }
+}
+
+/// First time strictly after `after` that matches the schedule's calendar rule
+fn next_occurrence_after(schedule: &RecurringSchedule, after: u64) -> Option<u64> {
+    let time_of_day = schedule.time_of_day_utc as u64 * NANOS_PER_SECOND;
+    let first_day = after / NANOS_PER_SECOND / SECONDS_PER_DAY;
+    
+    // A quarter-end occurrence is always within roughly four months
+    (first_day..first_day + 400)
+        .filter(|day| schedule_matches_day(schedule, *day))
+        .map(|day| day * SECONDS_PER_DAY * NANOS_PER_SECOND + time_of_day)
+        .find(|at| *at > after)
+}
+
+fn schedule_matches_day(schedule: &RecurringSchedule, day: u64) -> bool {
+    let (year, month, day_of_month) = civil_from_days(day);
+    
+    let target_day_of_month = || {
+        let month_length = days_in_month(year, month);
+        match schedule.day_of_month {
+            Some(target) => (target as u32).min(month_length),
+            None => {
+                // Step back from the month's last day over any weekend
+                let last_day = day + (month_length - day_of_month) as u64;
+                match iso_weekday(last_day) {
+                    6 => month_length - 1,
+                    7 => month_length - 2,
+                    _ => month_length,
+                }
+            }
+        }
+    };
+    
+    match schedule.frequency {
+        RecurrenceFrequency::Daily => true,
+        RecurrenceFrequency::Weekly => schedule.day_of_week == Some(iso_weekday(day)),
+        RecurrenceFrequency::Monthly => day_of_month == target_day_of_month(),
+        RecurrenceFrequency::Quarterly => month % 3 == 0 && day_of_month == target_day_of_month(),
+    }
+}
+
+// 1970-01-01 was a Thursday
+fn iso_weekday(days_since_epoch: u64) -> u8 {
+    ((days_since_epoch + 3) % 7 + 1) as u8
+}
+
+/// (year, month, day) for a count of days since 1970-01-01, using Howard
+/// Hinnant's civil_from_days algorithm
+fn civil_from_days(days_since_epoch: u64) -> (u64, u32, u32) {
+    let z = days_since_epoch + 719_468;
+    let era = z / 146_097;
+    let day_of_era = z - era * 146_097;
+    let year_of_era = (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let shifted_month = (5 * day_of_year + 2) / 153;
+    let day = (day_of_year - (153 * shifted_month + 2) / 5 + 1) as u32;
+    let month = if shifted_month < 10 { shifted_month + 3 } else { shifted_month - 9 } as u32;
+    let year = year_of_era + era * 400 + u64::from(month <= 2);
+    
+    (year, month, day)
+}
+
+fn days_in_month(year: u64, month: u32) -> u32 {
+    match month {
+        4 | 6 | 9 | 11 => 30,
+        2 if year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400)) => 29,
+        2 => 28,
+        _ => 31,
+    }
 }
 
 #[update]
-async fn process_scheduled_transactions() -> Result<Vec<String>, String> {
+async fn process_scheduled_transactions() -> Result<Vec<String>, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "process_scheduled_transactions")?;
+    
     let now = ic_cdk::api::time();
     let mut results = Vec::new();
     
+    queue_due_recurring_transactions(now);
+    
     let ready_transactions = SCHEDULED_TRANSACTIONS.with(|scheduled| {
         scheduled.borrow()
             .values()
@@ -841,11 +4365,14 @@ async fn process_scheduled_transactions() -> Result<Vec<String>, String> {
     });
     
     for scheduled_tx in ready_transactions {
-        match initiate_transaction(
+        match initiate_transaction_internal(
             scheduled_tx.account_id.clone(),
             scheduled_tx.transaction_type.clone(),
             scheduled_tx.amount,
             scheduled_tx.recipient.clone(),
+            None,
+            scheduled_tx.memo.clone(),
+            scheduled_tx.external_reference.clone(),
         ).await {
             Ok(tx_id) => {
                 // Update scheduled transaction status
@@ -873,11 +4400,75 @@ async fn process_scheduled_transactions() -> Result<Vec<String>, String> {
     Ok(results)
 }
 
+// === Cycle Monitoring Functions ===
+
+#[query]
+fn check_cycle_balance() -> u64 {
+    cycles::check_cycle_balance()
+}
+
+#[query]
+fn get_cycle_stats() -> CycleStats {
+    cycles::get_cycle_stats()
+}
+
+#[query]
+fn get_memory_stats() -> MemoryStats {
+    memory::get_memory_stats()
+}
+
+fn notify_high_memory(stats: &MemoryStats) {
+    match AUDIT_TRAIL_CANISTER.with(|c| *c.borrow()) {
+        Some(audit_canister) => spawn_tracked(memory::audit_memory_alert(audit_canister, stats.clone())),
+        None => memory::log_memory_alert(stats),
+    }
+}
+
+#[update]
+async fn set_cycle_alert_threshold(threshold: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_cycle_alert_threshold")?;
+    
+    if !is_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("set_cycle_alert_threshold"));
+    }
+    
+    cycles::set_cycle_alert_threshold(threshold);
+    
+    Ok("Cycle alert threshold updated successfully".to_string())
+}
+
+#[update]
+async fn set_cycle_alert_canister(alert_canister: Option<Principal>) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_cycle_alert_canister")?;
+    
+    if !is_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("set_cycle_alert_canister"));
+    }
+    
+    cycles::set_cycle_alert_canister(alert_canister);
+    
+    Ok("Cycle alert canister updated successfully".to_string())
+}
+
 #[query]
 fn health_check() -> String {
     "Custody Core canister is healthy and ready for institutional operations".to_string()
 }
 
+const INTERFACE_VERSION: InterfaceVersion = (1, 0, 0);
+
+#[query]
+fn get_candid_interface_version() -> u32 {
+    INTERFACE_VERSION.0
+}
+
+#[query]
+fn get_interface_metadata() -> CandidVersion {
+    CandidVersion::new(INTERFACE_VERSION, &__export_service())
+}
+
 #[query]
 fn get_version_info() -> String {
     "Institutional Custody Core v1.0.0 - Production Ready".to_string()
@@ -23,6 +23,10 @@ mod tests {
             authorized_users: BTreeSet::from([test_principal(id)]),
             required_approvals,
             compliance_status: ComplianceStatus::Compliant,
+            last_compliance_check_at: None,
+            withdrawal_whitelist: None,
+            withdrawal_blacklist: BTreeSet::new(),
+            multi_asset_balances: Vec::new(),
         }
     }
 
@@ -54,6 +58,16 @@ mod tests {
             executed_at: None,
             compliance_checked: false,
             risk_score: 5,
+            requires_risk_review: false,
+            risk_reviewers: BTreeSet::new(),
+            required_risk_reviews: 0,
+            notary: None,
+            notary_approved: false,
+            token_canister_id: None,
+            memo: None,
+            external_reference: None,
+            rejected_at: None,
+            rejection_reason: None,
         };
 
         assert_eq!(transaction.amount, 500000);
@@ -128,6 +142,16 @@ mod tests {
             executed_at: None,
             compliance_checked: false,
             risk_score: 6,
+            requires_risk_review: false,
+            risk_reviewers: BTreeSet::new(),
+            required_risk_reviews: 0,
+            notary: None,
+            notary_approved: false,
+            token_canister_id: None,
+            memo: None,
+            external_reference: None,
+            rejected_at: None,
+            rejection_reason: None,
         };
 
         // Add first approval
@@ -241,6 +265,16 @@ mod tests {
                 executed_at: None,
                 compliance_checked: false,
                 risk_score: 3,
+                requires_risk_review: false,
+                risk_reviewers: BTreeSet::new(),
+                required_risk_reviews: 0,
+                notary: None,
+                notary_approved: false,
+                token_canister_id: None,
+                memo: None,
+                external_reference: None,
+                rejected_at: None,
+                rejection_reason: None,
             }
         };
 
@@ -288,6 +322,8 @@ mod tests {
             created_at: 1234567890,
             created_by: test_principal(1),
             status: ScheduledTransactionStatus::Pending,
+            memo: None,
+            external_reference: None,
         };
 
         assert_eq!(scheduled_tx.status, ScheduledTransactionStatus::Pending);
@@ -314,6 +350,16 @@ mod tests {
                     executed_at: Some(1234567891),
                     compliance_checked: true,
                     risk_score: 2,
+                    requires_risk_review: false,
+                    risk_reviewers: BTreeSet::new(),
+                    required_risk_reviews: 0,
+                    notary: None,
+                    notary_approved: false,
+                    token_canister_id: None,
+                    memo: None,
+                    external_reference: None,
+                    rejected_at: None,
+                    rejection_reason: None,
                 }
             }
         ];
@@ -0,0 +1,299 @@
+use candid::Principal;
+use ic_cdk_macros::{init, post_upgrade, query, update};
+use shared::auth::{has_role, set_auth_canister};
+use shared::events::{CanisterEvent, EVENT_HANDLER_METHOD};
+use shared::{check_rate_limit, CustodyError};
+use shared::interface::{CandidVersion, InterfaceVersion};
+use shared::memory::{self, MemoryStats};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+const MAX_TOPIC_LENGTH: usize = 64;
+
+thread_local! {
+    // topic -> canisters that receive its events
+    static SUBSCRIPTIONS: RefCell<BTreeMap<String, BTreeSet<Principal>>> = const { RefCell::new(BTreeMap::new()) };
+    // Canisters allowed to publish; anyone else could forge events
+    static PUBLISHERS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+    // topic -> canisters allowed to subscribe themselves; topics like
+    // sar.filed carry data that not every canister should receive
+    static SUBSCRIBER_ALLOWLISTS: RefCell<BTreeMap<String, BTreeSet<Principal>>> = const { RefCell::new(BTreeMap::new()) };
+    static BUS_OPERATORS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+    static EVENT_SEQUENCE: RefCell<u64> = const { RefCell::new(0) };
+}
+
+#[init]
+fn init(auth_canister: Option<Principal>) {
+    ic_cdk::println!("Event Bus canister initialized");
+    
+    set_auth_canister(auth_canister);
+    memory::start_memory_monitor(memory::log_memory_alert);
+    
+    // Initialize with deployer as operator
+    BUS_OPERATORS.with(|operators| {
+        operators.borrow_mut().insert(ic_cdk::caller());
+    });
+}
+
+#[post_upgrade]
+fn post_upgrade(auth_canister: Option<Principal>) {
+    set_auth_canister(auth_canister);
+    memory::start_memory_monitor(memory::log_memory_alert);
+}
+
+// === Publishing Functions ===
+
+/// Fans the event out to every subscriber of its topic. The publisher and
+/// timestamp are set here so subscribers can trust them
+#[update]
+fn publish(event: CanisterEvent) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "publish")?;
+    
+    if !PUBLISHERS.with(|publishers| publishers.borrow().contains(&caller)) {
+        return Err(CustodyError::unauthorized("publish"));
+    }
+    
+    validate_topic(&event.topic)?;
+    
+    let event = CanisterEvent {
+        publisher: caller,
+        published_at: ic_cdk::api::time(),
+        ..event
+    };
+    
+    let event_id = EVENT_SEQUENCE.with(|sequence| {
+        let mut sequence = sequence.borrow_mut();
+        *sequence += 1;
+        format!("EVT-{}", *sequence)
+    });
+    
+    let subscribers: Vec<Principal> = SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow()
+            .get(&event.topic)
+            .map(|subscribers| subscribers.iter().copied().collect())
+            .unwrap_or_default()
+    });
+    
+    // Each subscriber gets its own call so a slow or failing one doesn't
+    // hold up delivery to the rest
+    for subscriber in subscribers {
+        ic_cdk::spawn(deliver_event(subscriber, event.clone(), event_id.clone()));
+    }
+    
+    Ok(event_id)
+}
+
+async fn deliver_event(subscriber: Principal, event: CanisterEvent, event_id: String) {
+    let result: Result<(), _> = ic_cdk::call(subscriber, EVENT_HANDLER_METHOD, (event,)).await;
+    
+    if let Err((code, msg)) = result {
+        ic_cdk::println!("Delivery of {} to {} failed: {:?} {}", event_id, subscriber, code, msg);
+    }
+}
+
+// === Subscription Functions ===
+
+/// Subscribes a canister to a topic. Operators may subscribe any canister;
+/// other canisters may subscribe themselves to topics they are allowed on
+#[update]
+async fn subscribe(topic: String, subscriber_canister_id: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "subscribe")?;
+    
+    let self_subscribe_allowed = caller == subscriber_canister_id && is_allowed_subscriber(&topic, caller);
+    if !self_subscribe_allowed && !is_bus_operator(caller).await {
+        return Err(CustodyError::unauthorized("subscribe"));
+    }
+    
+    validate_topic(&topic)?;
+    
+    let added = SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow_mut()
+            .entry(topic.clone())
+            .or_default()
+            .insert(subscriber_canister_id)
+    });
+    
+    if !added {
+        return Err(CustodyError::status_conflict("already subscribed", "not subscribed"));
+    }
+    
+    Ok(format!("Subscribed to {}", topic))
+}
+
+#[update]
+async fn unsubscribe(topic: String, subscriber_canister_id: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "unsubscribe")?;
+    
+    if caller != subscriber_canister_id && !is_bus_operator(caller).await {
+        return Err(CustodyError::unauthorized("unsubscribe"));
+    }
+    
+    let removed = SUBSCRIPTIONS.with(|subscriptions| {
+        let mut subscriptions = subscriptions.borrow_mut();
+        let Some(subscribers) = subscriptions.get_mut(&topic) else {
+            return false;
+        };
+        
+        let removed = subscribers.remove(&subscriber_canister_id);
+        if subscribers.is_empty() {
+            subscriptions.remove(&topic);
+        }
+        removed
+    });
+    
+    if !removed {
+        return Err(CustodyError::not_found("Subscription", format!("{}:{}", topic, subscriber_canister_id)));
+    }
+    
+    Ok(format!("Unsubscribed from {}", topic))
+}
+
+fn is_allowed_subscriber(topic: &str, principal: Principal) -> bool {
+    SUBSCRIBER_ALLOWLISTS.with(|allowlists| {
+        allowlists.borrow()
+            .get(topic)
+            .is_some_and(|allowed| allowed.contains(&principal))
+    })
+}
+
+fn validate_topic(topic: &str) -> Result<(), CustodyError> {
+    if topic.is_empty() || topic.len() > MAX_TOPIC_LENGTH {
+        return Err(CustodyError::invalid_input(
+            "topic",
+            format!("must be between 1 and {} characters", MAX_TOPIC_LENGTH),
+        ));
+    }
+    
+    if !topic.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '.' || c == '_') {
+        return Err(CustodyError::invalid_input("topic", "may only contain lowercase letters, digits, '.' and '_'"));
+    }
+    
+    Ok(())
+}
+
+// === Admin Functions ===
+
+#[update]
+async fn add_publisher(publisher: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_publisher")?;
+    
+    if !is_bus_operator(caller).await {
+        return Err(CustodyError::unauthorized("add_publisher"));
+    }
+    
+    PUBLISHERS.with(|publishers| {
+        publishers.borrow_mut().insert(publisher);
+    });
+    
+    Ok("Publisher added successfully".to_string())
+}
+
+#[update]
+async fn remove_publisher(publisher: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "remove_publisher")?;
+    
+    if !is_bus_operator(caller).await {
+        return Err(CustodyError::unauthorized("remove_publisher"));
+    }
+    
+    let removed = PUBLISHERS.with(|publishers| publishers.borrow_mut().remove(&publisher));
+    if !removed {
+        return Err(CustodyError::not_found("Publisher", publisher.to_string()));
+    }
+    
+    Ok("Publisher removed successfully".to_string())
+}
+
+/// Allows or stops a canister subscribing itself to a topic. Existing
+/// subscriptions are left alone; unsubscribe removes them
+#[update]
+async fn set_allowed_subscriber(topic: String, subscriber_canister_id: Principal, allowed: bool) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_allowed_subscriber")?;
+    
+    if !is_bus_operator(caller).await {
+        return Err(CustodyError::unauthorized("set_allowed_subscriber"));
+    }
+    
+    validate_topic(&topic)?;
+    
+    SUBSCRIBER_ALLOWLISTS.with(|allowlists| {
+        let mut allowlists = allowlists.borrow_mut();
+        if allowed {
+            allowlists.entry(topic).or_default().insert(subscriber_canister_id);
+        } else if let Some(allowed) = allowlists.get_mut(&topic) {
+            allowed.remove(&subscriber_canister_id);
+            if allowed.is_empty() {
+                allowlists.remove(&topic);
+            }
+        }
+    });
+    
+    Ok("Subscriber allowlist updated successfully".to_string())
+}
+
+// === Query Functions ===
+
+#[query]
+fn get_subscribers(topic: String) -> Vec<Principal> {
+    SUBSCRIPTIONS.with(|subscriptions| {
+        subscriptions.borrow()
+            .get(&topic)
+            .map(|subscribers| subscribers.iter().copied().collect())
+            .unwrap_or_default()
+    })
+}
+
+#[query]
+fn list_topics() -> Vec<String> {
+    SUBSCRIPTIONS.with(|subscriptions| subscriptions.borrow().keys().cloned().collect())
+}
+
+#[query]
+fn get_allowed_subscribers(topic: String) -> Vec<Principal> {
+    SUBSCRIBER_ALLOWLISTS.with(|allowlists| {
+        allowlists.borrow()
+            .get(&topic)
+            .map(|allowed| allowed.iter().copied().collect())
+            .unwrap_or_default()
+    })
+}
+
+#[query]
+fn list_publishers() -> Vec<Principal> {
+    PUBLISHERS.with(|publishers| publishers.borrow().iter().copied().collect())
+}
+
+async fn is_bus_operator(principal: Principal) -> bool {
+    BUS_OPERATORS.with(|operators| operators.borrow().contains(&principal))
+        || has_role(principal, "operator").await
+}
+
+#[query]
+fn get_memory_stats() -> MemoryStats {
+    memory::get_memory_stats()
+}
+
+#[query]
+fn health_check() -> String {
+    "Event Bus canister is healthy".to_string()
+}
+
+const INTERFACE_VERSION: InterfaceVersion = (1, 0, 0);
+
+#[query]
+fn get_candid_interface_version() -> u32 {
+    INTERFACE_VERSION.0
+}
+
+#[query]
+fn get_interface_metadata() -> CandidVersion {
+    CandidVersion::new(INTERFACE_VERSION, &__export_service())
+}
+
+ic_cdk::export_candid!();
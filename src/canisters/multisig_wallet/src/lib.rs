@@ -1,7 +1,14 @@
 use candid::{CandidType, Principal};
 use ic_cdk_macros::{init, post_upgrade, pre_upgrade, query, update};
 use serde::{Deserialize, Serialize};
-use std::collections::{BTreeMap, BTreeSet};
+use shared::cycles::{self, CycleStats};
+use shared::hex;
+use shared::auth::{has_cached_role, has_role, set_auth_canister};
+use shared::{check_rate_limit, CustodyError, RequestContext};
+use shared::interface::{CandidVersion, InterfaceVersion};
+use shared::memory::{self, MemoryStats};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+use std::ops::Bound;
 use std::cell::RefCell;
 use uuid::Uuid;
 use sha2::{Sha256, Digest};
@@ -13,6 +20,11 @@ pub struct MultisigWallet {
     pub owners: BTreeSet<Principal>,
     pub threshold: u8,
     pub balance: u64,
+    // Token balances keyed by token symbol or ledger canister id; `balance`
+    // holds the native BTC balance
+    pub asset_balances: BTreeMap<String, u64>,
+    // Treasury wallet that covers native shortfalls up to the policy's parent_funding_limit
+    pub parent_wallet_id: Option<String>,
     pub created_at: u64,
     pub wallet_type: WalletType,
     pub status: WalletStatus,
@@ -22,6 +34,16 @@ pub struct MultisigWallet {
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct WalletMetadata {
+    pub label: String,
+    pub description: String,
+    pub tags: BTreeSet<String>,
+    pub project_code: Option<String>,
+    pub cost_center: Option<String>,
+    pub created_by_org: String,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
 pub enum WalletType {
     CorporateOperational,
     CorporateTreasury,
@@ -31,7 +53,7 @@ pub enum WalletType {
     InstitutionalHot,
 }
 
-#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
 pub enum WalletStatus {
     Active,
     Frozen,
@@ -57,6 +79,20 @@ pub struct MultisigTransaction {
     pub gas_limit: Option<u64>,
     pub nonce: Option<u64>,
     pub priority: TransactionPriority,
+    pub expires_at: Option<u64>,
+    pub cancellation_reason: Option<String>,
+    // Network fee charged on top of `amount`; reconciled at broadcast
+    pub estimated_fee: u64,
+    // None for native BTC, otherwise a key of the wallet's asset_balances
+    pub asset_id: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BatchTransactionRequest {
+    pub to: String,
+    pub amount: u64,
+    pub data: Vec<u8>,
+    pub priority: TransactionPriority,
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
@@ -67,6 +103,16 @@ pub enum TransactionPriority {
     Emergency,
 }
 
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct FeeEstimate {
+    pub base_fee: u64,
+    pub priority_fee: u64,
+    pub total_fee: u64,
+    // What the recipient receives; the fee is debited from the wallet on top
+    pub net_amount: u64,
+    pub fee_rate_sat_per_vbyte: u64,
+}
+
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
 pub struct WalletPolicy {
     pub require_confirmation_delay: bool,
@@ -76,6 +122,101 @@ pub struct WalletPolicy {
     pub emergency_freeze_threshold: u64,
     pub allowed_destinations: Option<BTreeSet<String>>,
     pub restricted_destinations: BTreeSet<String>,
+    pub transaction_timeout_hours: u32,
+    // Most that may be executed within any window; 0 disables the check
+    pub velocity_window_minutes: u32,
+    pub velocity_limit: u64,
+    // Total the parent wallet's owners authorized it to fund this wallet with;
+    // only set_parent_wallet changes it
+    pub parent_funding_limit: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum OwnerChangeAction {
+    Add(Principal),
+    Remove(Principal),
+    ChangeThreshold(u8),
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct OwnerChangeProposal {
+    pub id: String,
+    pub wallet_id: String,
+    pub action: OwnerChangeAction,
+    pub proposed_by: Principal,
+    pub confirmations: BTreeSet<Principal>,
+    pub created_at: u64,
+    pub executed: bool,
+}
+
+/// Configuration changes that need the wallet's threshold of owners
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum WalletChange {
+    // Lets `sub_wallet_id` draw up to `funding_limit` from this wallet
+    FundSubWallet { sub_wallet_id: String, funding_limit: u64 },
+    // Replaces the wallet's policy; `reason` goes into the policy history
+    UpdatePolicy { policy: WalletPolicy, reason: String },
+    // Gives `guardian` recovery rights once the owners are inactive for `delay_seconds`
+    AddGuardian { guardian: Principal, delay_seconds: u64 },
+    RemoveGuardian { guardian: Principal },
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct WalletChangeProposal {
+    pub id: String,
+    pub wallet_id: String,
+    pub change: WalletChange,
+    pub proposed_by: Principal,
+    pub confirmations: BTreeSet<Principal>,
+    pub created_at: u64,
+    pub executed: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct TypeChangeProposal {
+    pub id: String,
+    pub wallet_id: String,
+    pub current_type: WalletType,
+    pub new_type: WalletType,
+    pub reason: String,
+    pub proposed_by: Principal,
+    pub confirmations: BTreeSet<Principal>,
+    pub created_at: u64,
+    // Set once every owner has confirmed; the cooling-off period runs from then
+    pub executable_at: Option<u64>,
+    pub executed: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct GuardianRecord {
+    pub guardian: Principal,
+    pub added_at: u64,
+    // How long the owners must be inactive before this guardian can start a recovery
+    pub activation_delay_seconds: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RecoveryProposal {
+    pub id: String,
+    pub wallet_id: String,
+    pub new_owners: BTreeSet<Principal>,
+    pub new_threshold: u8,
+    pub initiated_by: Principal,
+    pub confirmations: BTreeSet<Principal>,
+    pub created_at: u64,
+    // Set once every guardian has confirmed
+    pub executable_at: Option<u64>,
+    pub executed: bool,
+    pub cancelled: bool,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct PolicyVersion {
+    pub version: u32,
+    pub policy: WalletPolicy,
+    pub changed_at: u64,
+    pub changed_by: Principal,
+    pub change_reason: String,
 }
 
 #[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
@@ -98,26 +239,126 @@ pub enum AuditAction {
     TransactionSubmitted,
     TransactionConfirmed,
     TransactionRejected,
+    TransactionCancelled,
     TransactionExecuted,
     WalletFrozen,
     WalletUnfrozen,
     PolicyUpdated,
     EmergencyAction,
+    VelocityLimitExceeded,
+    GuardianAdded,
+    GuardianRemoved,
+    RecoveryInitiated,
+    RecoveryConfirmed,
+    RecoveryCancelled,
+    RecoveryExecuted,
+    MetadataUpdated,
+    AssetDeposited,
+    InternalFunding,
+}
+
+// Subset of the audit_trail canister's EventType and ResourceType variants
+// used when sending it alerts
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum AuditEventType {
+    EmergencyAction,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum AuditResourceType {
+    MultisigWallet,
 }
 
+// Mirror of the audit_trail canister's AuditMetadata
+#[derive(Clone, Debug, Default, CandidType, Serialize, Deserialize)]
+pub struct AuditMetadata {
+    pub ip_address: Option<String>,
+    pub user_agent: Option<String>,
+    pub session_id: Option<String>,
+    pub request_id: Option<String>,
+    pub canister_id: Option<String>,
+    pub method_name: Option<String>,
+    pub before_state: Option<String>,
+    pub after_state: Option<String>,
+    pub error_code: Option<String>,
+    pub additional_context: BTreeMap<String, String>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct WalletHealth {
+    pub wallet_id: String,
+    pub status: WalletStatus,
+    pub owner_count: u32,
+    pub threshold: u32,
+    pub pending_transaction_count: u32,
+    // Highlights transactions that may be stuck awaiting confirmations
+    pub oldest_pending_age_seconds: Option<u64>,
+    pub daily_utilization_percent: u8,
+    pub balance: u64,
+    pub daily_limit: u64,
+    pub last_transaction_at: Option<u64>,
+    pub audit_log_count: u64,
+}
+
+// (priority level, created_at, transaction id); Emergency is level 0 so it sorts
+// first, and the id keeps batch transactions sharing a timestamp apart
+type ExecutionQueueKey = (u8, u64, String);
+
 thread_local! {
-    static WALLETS: RefCell<BTreeMap<String, MultisigWallet>> = RefCell::new(BTreeMap::new());
-    static TRANSACTIONS: RefCell<BTreeMap<String, MultisigTransaction>> = RefCell::new(BTreeMap::new());
-    static WALLET_POLICIES: RefCell<BTreeMap<String, WalletPolicy>> = RefCell::new(BTreeMap::new());
-    static AUDIT_LOGS: RefCell<BTreeMap<String, WalletAuditLog>> = RefCell::new(BTreeMap::new());
-    static EMERGENCY_CONTACTS: RefCell<BTreeSet<Principal>> = RefCell::new(BTreeSet::new());
-    static GLOBAL_FROZEN: RefCell<bool> = RefCell::new(false);
+    static WALLETS: RefCell<BTreeMap<String, MultisigWallet>> = const { RefCell::new(BTreeMap::new()) };
+    static TRANSACTIONS: RefCell<BTreeMap<String, MultisigTransaction>> = const { RefCell::new(BTreeMap::new()) };
+    static WALLET_POLICIES: RefCell<BTreeMap<String, WalletPolicy>> = const { RefCell::new(BTreeMap::new()) };
+    static WALLET_POLICY_HISTORY: RefCell<BTreeMap<String, Vec<PolicyVersion>>> = const { RefCell::new(BTreeMap::new()) };
+    static AUDIT_LOGS: RefCell<BTreeMap<String, WalletAuditLog>> = const { RefCell::new(BTreeMap::new()) };
+    static OWNER_CHANGE_PROPOSALS: RefCell<BTreeMap<String, OwnerChangeProposal>> = const { RefCell::new(BTreeMap::new()) };
+    static TYPE_CHANGE_PROPOSALS: RefCell<BTreeMap<String, TypeChangeProposal>> = const { RefCell::new(BTreeMap::new()) };
+    static WALLET_CHANGE_PROPOSALS: RefCell<BTreeMap<String, WalletChangeProposal>> = const { RefCell::new(BTreeMap::new()) };
+    static EMERGENCY_CONTACTS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+    static GLOBAL_FROZEN: RefCell<bool> = const { RefCell::new(false) };
+    static GLOBAL_FREEZE_SNAPSHOT: RefCell<BTreeSet<String>> = const { RefCell::new(BTreeSet::new()) };
+    // wallet_id -> (executed_at, amount), oldest first
+    static RECENT_EXECUTIONS: RefCell<BTreeMap<String, VecDeque<(u64, u64)>>> = const { RefCell::new(BTreeMap::new()) };
+    static AUDIT_TRAIL_CANISTER: RefCell<Option<Principal>> = const { RefCell::new(None) };
+    static WALLET_GUARDIANS: RefCell<BTreeMap<String, Vec<GuardianRecord>>> = const { RefCell::new(BTreeMap::new()) };
+    static RECOVERY_PROPOSALS: RefCell<BTreeMap<String, RecoveryProposal>> = const { RefCell::new(BTreeMap::new()) };
+    // Hex-encoded Ed25519 or secp256k1 keys owners sign offline confirmations with
+    static OWNER_PUBLIC_KEYS: RefCell<BTreeMap<Principal, String>> = const { RefCell::new(BTreeMap::new()) };
+    // Sub-wallet id -> total drawn from its parent under the current funding limit
+    static PARENT_FUNDING_DRAWN: RefCell<BTreeMap<String, u64>> = const { RefCell::new(BTreeMap::new()) };
+    // Transactions that reached threshold, in execution order -> wallet id
+    static EXECUTION_QUEUE: RefCell<BTreeMap<ExecutionQueueKey, String>> = const { RefCell::new(BTreeMap::new()) };
+    static WALLET_METADATA: RefCell<BTreeMap<String, WalletMetadata>> = const { RefCell::new(BTreeMap::new()) };
+    // tag -> ids of the wallets carrying it
+    static TAG_INDEX: RefCell<BTreeMap<String, BTreeSet<String>>> = const { RefCell::new(BTreeMap::new()) };
+    // project code -> ids of the wallets assigned to it
+    static PROJECT_INDEX: RefCell<BTreeMap<String, BTreeSet<String>>> = const { RefCell::new(BTreeMap::new()) };
 }
 
+// Cooling-off period between the last owner confirmation and a wallet type change
+const TYPE_CHANGE_COOLING_OFF_NANOS: u64 = 72 * 60 * 60 * 1_000_000_000;
+
+// Mandatory wait between the last guardian confirmation and the ownership change
+const RECOVERY_TIMELOCK_NANOS: u64 = 48 * 60 * 60 * 1_000_000_000;
+
+// Executions are kept for at least a day regardless of the velocity window
+const MIN_EXECUTION_RETENTION_NANOS: u64 = 24 * 60 * 60 * 1_000_000_000;
+const MAX_RECENT_EXECUTIONS: usize = 1_000;
+
+// Size of a typical P2WPKH spend with one input and two outputs
+const P2WPKH_1_IN_2_OUT_VBYTES: u64 = 141;
+const BASE_FEE_RATE_SAT_PER_VBYTE: u64 = 1;
+
+const MAX_WALLET_TAGS: usize = 32;
+const MAX_METADATA_FIELD_LENGTH: usize = 256;
+
 #[init]
-fn init() {
+fn init(auth_canister: Option<Principal>) {
     ic_cdk::println!("Multisig Wallet canister initialized");
     
+    set_auth_canister(auth_canister);
+    cycles::start_cycle_monitor(cycles::forward_cycle_alert);
+    memory::start_memory_monitor(notify_high_memory);
+    
     // Initialize with deployer as emergency contact
     EMERGENCY_CONTACTS.with(|contacts| {
         contacts.borrow_mut().insert(ic_cdk::caller());
@@ -130,8 +371,11 @@ fn pre_upgrade() {
 }
 
 #[post_upgrade]
-fn post_upgrade() {
+fn post_upgrade(auth_canister: Option<Principal>) {
     // Stable storage restoration would go here
+    set_auth_canister(auth_canister);
+    cycles::start_cycle_monitor(cycles::forward_cycle_alert);
+    memory::start_memory_monitor(notify_high_memory);
 }
 
 // === Wallet Management Functions ===
@@ -143,30 +387,32 @@ fn create_multisig_wallet(
     threshold: u8,
     wallet_type: WalletType,
     daily_limit: u64,
-) -> Result<String, String> {
+) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "create_multisig_wallet")?;
     
     // Validate inputs
     if name.is_empty() {
-        return Err("Wallet name cannot be empty".to_string());
+        return Err(CustodyError::invalid_input("name", "cannot be empty"));
     }
     
     if owners.is_empty() || owners.len() > 20 {
-        return Err("Must have 1-20 owners".to_string());
+        return Err(CustodyError::invalid_input("owners", "must have 1-20 owners"));
     }
     
     if threshold == 0 || threshold as usize > owners.len() {
-        return Err("Invalid threshold".to_string());
+        return Err(CustodyError::invalid_input("threshold", "must be between 1 and the number of owners"));
     }
     
     let owners_set: BTreeSet<Principal> = owners.into_iter().collect();
     if !owners_set.contains(&caller) {
-        return Err("Creator must be an owner".to_string());
+        return Err(CustodyError::invalid_input("owners", "creator must be an owner"));
     }
     
     let wallet_id = Uuid::new_v4().to_string();
     let current_time = ic_cdk::api::time();
     let current_day = current_time / (24 * 60 * 60 * 1_000_000_000);
+    let owner_count = owners_set.len();
     
     let wallet = MultisigWallet {
         id: wallet_id.clone(),
@@ -174,6 +420,8 @@ fn create_multisig_wallet(
         owners: owners_set,
         threshold,
         balance: 0,
+        asset_balances: BTreeMap::new(),
+        parent_wallet_id: None,
         created_at: current_time,
         wallet_type: wallet_type.clone(),
         status: WalletStatus::Active,
@@ -183,28 +431,22 @@ fn create_multisig_wallet(
     };
     
     // Create default policy
-    let policy = WalletPolicy {
-        require_confirmation_delay: matches!(wallet_type, WalletType::CorporateTreasury | WalletType::InstitutionalCold),
-        confirmation_delay_hours: match wallet_type {
-            WalletType::CorporateTreasury | WalletType::InstitutionalCold => 24,
-            WalletType::GovernmentEmergency => 1,
-            _ => 0,
-        },
-        max_single_transaction: match wallet_type {
-            WalletType::InstitutionalHot => 1_000_000_000, // 10 BTC
-            WalletType::CorporateOperational => 500_000_000, // 5 BTC
-            _ => 10_000_000_000, // 100 BTC
-        },
-        require_dual_approval_above: daily_limit / 10,
-        emergency_freeze_threshold: daily_limit * 2,
-        allowed_destinations: None,
-        restricted_destinations: BTreeSet::new(),
-    };
+    let policy = default_wallet_policy(&wallet_type, daily_limit);
     
     WALLETS.with(|wallets| {
         wallets.borrow_mut().insert(wallet_id.clone(), wallet);
     });
     
+    WALLET_POLICY_HISTORY.with(|history| {
+        history.borrow_mut().insert(wallet_id.clone(), vec![PolicyVersion {
+            version: 1,
+            policy: policy.clone(),
+            changed_at: current_time,
+            changed_by: caller,
+            change_reason: "Initial wallet policy".to_string(),
+        }]);
+    });
+    
     WALLET_POLICIES.with(|policies| {
         policies.borrow_mut().insert(wallet_id.clone(), policy);
     });
@@ -217,7 +459,7 @@ fn create_multisig_wallet(
         action: AuditAction::WalletCreated,
         actor: caller,
         timestamp: current_time,
-        details: format!("Wallet '{}' created with {} owners, threshold {}", name, owners_set.len(), threshold),
+        details: format!("Wallet '{}' created with {} owners, threshold {}", name, owner_count, threshold),
         transaction_id: None,
     };
     
@@ -230,530 +472,2263 @@ fn create_multisig_wallet(
 }
 
 #[update]
-fn add_wallet_owner(wallet_id: String, new_owner: Principal) -> Result<String, String> {
+fn propose_owner_change(
+    wallet_id: String,
+    action: OwnerChangeAction,
+) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "propose_owner_change")?;
     
-    WALLETS.with(|wallets| {
-        let mut wallets_map = wallets.borrow_mut();
-        match wallets_map.get_mut(&wallet_id) {
-            Some(wallet) => {
-                if !wallet.owners.contains(&caller) {
-                    return Err("Only owners can add new owners".to_string());
-                }
-                
-                if wallet.owners.len() >= 20 {
-                    return Err("Maximum 20 owners allowed".to_string());
-                }
-                
-                if wallet.owners.contains(&new_owner) {
-                    return Err("Principal is already an owner".to_string());
-                }
-                
-                wallet.owners.insert(new_owner);
-                
-                // Log the action
-                log_audit_action(&wallet_id, AuditAction::OwnerAdded, caller, 
-                    format!("Added owner: {}", new_owner), None);
-                
-                Ok("Owner added successfully".to_string())
-            },
-            None => Err("Wallet not found".to_string()),
-        }
-    })
+    let wallet = WALLETS.with(|wallets| {
+        wallets.borrow().get(&wallet_id).cloned()
+    });
+    
+    let wallet = match wallet {
+        Some(w) => w,
+        None => return Err(CustodyError::not_found("Wallet", wallet_id.clone())),
+    };
+    
+    if !wallet.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("propose_owner_change"));
+    }
+    
+    validate_owner_change(&wallet, &action)?;
+    
+    let proposal_id = Uuid::new_v4().to_string();
+    let mut proposal = OwnerChangeProposal {
+        id: proposal_id.clone(),
+        wallet_id: wallet_id.clone(),
+        action: action.clone(),
+        proposed_by: caller,
+        confirmations: BTreeSet::from([caller]),
+        created_at: ic_cdk::api::time(),
+        executed: false,
+    };
+    
+    // Single-signature wallets execute the change straight away. It is applied
+    // before anything is recorded so a failure leaves no proposal behind.
+    if wallet.threshold <= 1 {
+        apply_owner_change(&proposal)?;
+        proposal.executed = true;
+    }
+    
+    OWNER_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow_mut().insert(proposal_id.clone(), proposal.clone());
+    });
+    
+    log_audit_action(&wallet_id, owner_change_audit_action(&action), caller, 
+        format!("Proposed {} (proposal {})", describe_owner_change(&action), proposal_id), None);
+    
+    if proposal.executed {
+        log_owner_change_executed(&proposal, caller);
+    }
+    
+    Ok(proposal_id)
 }
 
 #[update]
-fn remove_wallet_owner(wallet_id: String, owner_to_remove: Principal) -> Result<String, String> {
+fn confirm_owner_change(proposal_id: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "confirm_owner_change")?;
     
-    WALLETS.with(|wallets| {
-        let mut wallets_map = wallets.borrow_mut();
-        match wallets_map.get_mut(&wallet_id) {
-            Some(wallet) => {
-                if !wallet.owners.contains(&caller) {
-                    return Err("Only owners can remove owners".to_string());
-                }
-                
-                if !wallet.owners.contains(&owner_to_remove) {
-                    return Err("Principal is not an owner".to_string());
-                }
-                
-                if wallet.owners.len() <= wallet.threshold as usize {
-                    return Err("Cannot remove owner below threshold".to_string());
-                }
-                
-                wallet.owners.remove(&owner_to_remove);
-                
-                // Log the action
-                log_audit_action(&wallet_id, AuditAction::OwnerRemoved, caller, 
-                    format!("Removed owner: {}", owner_to_remove), None);
-                
-                Ok("Owner removed successfully".to_string())
+    let proposal = OWNER_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow().get(&proposal_id).cloned()
+    });
+    
+    let proposal = match proposal {
+        Some(p) => p,
+        None => return Err(CustodyError::not_found("Proposal", proposal_id)),
+    };
+    
+    if proposal.executed {
+        return Err(CustodyError::status_conflict("executed", "pending"));
+    }
+    
+    let wallet = WALLETS.with(|wallets| {
+        wallets.borrow().get(&proposal.wallet_id).cloned()
+    });
+    
+    let wallet = match wallet {
+        Some(w) => w,
+        None => return Err(CustodyError::not_found("Wallet", proposal.wallet_id.clone())),
+    };
+    
+    if !wallet.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("confirm_owner_change"));
+    }
+    
+    if proposal.confirmations.contains(&caller) {
+        return Err(CustodyError::status_conflict("confirmed by caller", "not confirmed by caller"));
+    }
+    
+    let confirmations = OWNER_CHANGE_PROPOSALS.with(|proposals| {
+        let mut proposals_map = proposals.borrow_mut();
+        match proposals_map.get_mut(&proposal_id) {
+            Some(p) => {
+                p.confirmations.insert(caller);
+                // Only confirmations from current owners count towards the threshold
+                p.confirmations.iter().filter(|c| wallet.owners.contains(c)).count()
             },
-            None => Err("Wallet not found".to_string()),
+            None => 0,
         }
-    })
+    });
+    
+    log_audit_action(&proposal.wallet_id, owner_change_audit_action(&proposal.action), caller, 
+        format!("Confirmed {} (proposal {})", describe_owner_change(&proposal.action), proposal_id), None);
+    
+    if confirmations >= wallet.threshold as usize {
+        execute_owner_change(&proposal_id, caller)
+    } else {
+        Ok(format!("Ownership change confirmed ({}/{})", confirmations, wallet.threshold))
+    }
 }
 
+/// Proposes a new policy for the wallet. It takes effect once the wallet's
+/// threshold of owners has confirmed it through `confirm_wallet_change`;
+/// returns the proposal ID.
 #[update]
-fn change_threshold(wallet_id: String, new_threshold: u8) -> Result<String, String> {
+fn update_wallet_policy(wallet_id: String, new_policy: WalletPolicy, reason: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "update_wallet_policy")?;
     
-    WALLETS.with(|wallets| {
-        let mut wallets_map = wallets.borrow_mut();
-        match wallets_map.get_mut(&wallet_id) {
-            Some(wallet) => {
-                if !wallet.owners.contains(&caller) {
-                    return Err("Only owners can change threshold".to_string());
-                }
-                
-                if new_threshold == 0 || new_threshold as usize > wallet.owners.len() {
-                    return Err("Invalid threshold".to_string());
-                }
-                
-                let old_threshold = wallet.threshold;
-                wallet.threshold = new_threshold;
-                
-                // Log the action
-                log_audit_action(&wallet_id, AuditAction::ThresholdChanged, caller, 
-                    format!("Changed threshold from {} to {}", old_threshold, new_threshold), None);
-                
-                Ok("Threshold changed successfully".to_string())
-            },
-            None => Err("Wallet not found".to_string()),
-        }
-    })
+    if reason.is_empty() {
+        return Err(CustodyError::invalid_input("reason", "required for policy changes"));
+    }
+    
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(&wallet_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Wallet", wallet_id.clone()))?;
+    
+    if !wallet.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("update_wallet_policy"));
+    }
+    
+    propose_wallet_change(&wallet, WalletChange::UpdatePolicy { policy: new_policy, reason }, caller)
 }
 
-// === Transaction Functions ===
+// Applies a confirmed UpdatePolicy change
+fn apply_wallet_policy(
+    wallet_id: &str,
+    new_policy: WalletPolicy,
+    reason: &str,
+    actor: Principal,
+) -> Result<String, CustodyError> {
+    // The funding limit is the parent owners' authorization, not this wallet's
+    let mut new_policy = new_policy;
+    new_policy.parent_funding_limit = parent_funding_limit(wallet_id);
+    
+    let current_time = ic_cdk::api::time();
+    
+    // Every policy that has ever been in effect is kept in the history so the
+    // policy active at any point in time can be reconstructed for auditors
+    let version = WALLET_POLICY_HISTORY.with(|history| {
+        let mut history_map = history.borrow_mut();
+        let versions = history_map.entry(wallet_id.to_string()).or_default();
+        let version = versions.last().map(|v| v.version).unwrap_or(0) + 1;
+        versions.push(PolicyVersion {
+            version,
+            policy: new_policy.clone(),
+            changed_at: current_time,
+            changed_by: actor,
+            change_reason: reason.to_string(),
+        });
+        version
+    });
+    
+    WALLET_POLICIES.with(|policies| {
+        policies.borrow_mut().insert(wallet_id.to_string(), new_policy);
+    });
+    
+    Ok(format!("Policy updated to version {}", version))
+}
 
+/// Wallet type changes need every owner, not just the threshold, and only
+/// take effect after a cooling-off period
 #[update]
-fn submit_transaction(
-    wallet_id: String,
-    to: String,
-    amount: u64,
-    data: Vec<u8>,
-    priority: TransactionPriority,
-) -> Result<String, String> {
+fn propose_wallet_type_change(wallet_id: String, new_type: WalletType, reason: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "propose_wallet_type_change")?;
     
-    // Check global freeze
-    let is_frozen = GLOBAL_FROZEN.with(|frozen| *frozen.borrow());
-    if is_frozen {
-        return Err("Global freeze is active".to_string());
+    if reason.is_empty() {
+        return Err(CustodyError::invalid_input("reason", "required for wallet type changes"));
     }
     
-    // Validate wallet and ownership
-    let wallet = WALLETS.with(|wallets| {
-        wallets.borrow().get(&wallet_id).cloned()
-    });
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(&wallet_id).cloned());
     
     let wallet = match wallet {
         Some(w) => w,
-        None => return Err("Wallet not found".to_string()),
+        None => return Err(CustodyError::not_found("Wallet", wallet_id)),
     };
     
     if !wallet.owners.contains(&caller) {
-        return Err("Only wallet owners can submit transactions".to_string());
+        return Err(CustodyError::unauthorized("propose_wallet_type_change"));
     }
     
-    if wallet.status != WalletStatus::Active {
-        return Err("Wallet is not active".to_string());
+    if wallet.wallet_type == new_type {
+        return Err(CustodyError::invalid_input("new_type", "wallet already has this type"));
     }
     
-    // Check wallet policy
-    let policy = WALLET_POLICIES.with(|policies| {
-        policies.borrow().get(&wallet_id).cloned()
+    let has_open_proposal = TYPE_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow()
+            .values()
+            .any(|p| p.wallet_id == wallet_id && !p.executed)
     });
     
-    if let Some(policy) = policy {
-        if amount > policy.max_single_transaction {
-            return Err("Transaction exceeds maximum allowed amount".to_string());
-        }
-        
-        if policy.restricted_destinations.contains(&to) {
-            return Err("Destination is restricted".to_string());
-        }
-        
-        if let Some(ref allowed) = policy.allowed_destinations {
-            if !allowed.contains(&to) {
-                return Err("Destination is not in allowed list".to_string());
-            }
-        }
+    if has_open_proposal {
+        return Err(CustodyError::status_conflict("type change pending", "no pending type change"));
     }
     
-    // Check daily limit
-    let current_day = ic_cdk::api::time() / (24 * 60 * 60 * 1_000_000_000);
-    let updated_wallet = WALLETS.with(|wallets| {
-        let mut wallets_map = wallets.borrow_mut();
-        if let Some(wallet) = wallets_map.get_mut(&wallet_id) {
-            if wallet.last_reset_day < current_day {
-                wallet.daily_spent = 0;
-                wallet.last_reset_day = current_day;
-            }
-            
-            if wallet.daily_spent + amount > wallet.daily_limit {
-                return Err("Transaction exceeds daily limit".to_string());
-            }
-            
-            Ok(wallet.clone())
-        } else {
-            Err("Wallet not found".to_string())
-        }
-    })?;
-    
-    let transaction_id = Uuid::new_v4().to_string();
+    let proposal_id = Uuid::new_v4().to_string();
     let current_time = ic_cdk::api::time();
-    
-    let transaction = MultisigTransaction {
-        id: transaction_id.clone(),
+    let mut proposal = TypeChangeProposal {
+        id: proposal_id.clone(),
         wallet_id: wallet_id.clone(),
-        to,
-        amount,
-        data,
+        current_type: wallet.wallet_type.clone(),
+        new_type: new_type.clone(),
+        reason: reason.clone(),
+        proposed_by: caller,
         confirmations: BTreeSet::from([caller]),
-        rejections: BTreeSet::new(),
-        executed: false,
-        rejected: false,
         created_at: current_time,
-        executed_at: None,
-        transaction_hash: None,
-        gas_price: None,
-        gas_limit: None,
-        nonce: None,
-        priority,
+        executable_at: None,
+        executed: false,
     };
+    start_type_change_cooling_off(&mut proposal, &wallet, current_time);
     
-    TRANSACTIONS.with(|txns| {
-        txns.borrow_mut().insert(transaction_id.clone(), transaction);
+    TYPE_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow_mut().insert(proposal_id.clone(), proposal);
     });
     
-    // Log the action
-    log_audit_action(&wallet_id, AuditAction::TransactionSubmitted, caller, 
-        format!("Submitted transaction for {} satoshis", amount), Some(transaction_id.clone()));
-    
-    // Check if transaction can be auto-executed
-    if updated_wallet.threshold == 1 {
-        ic_cdk::spawn(execute_transaction_async(transaction_id.clone()));
-    }
+    log_audit_action(&wallet_id, AuditAction::PolicyUpdated, caller,
+        format!("Proposed wallet type change from {:?} to {:?} (proposal {}): {}",
+            wallet.wallet_type, new_type, proposal_id, reason), None);
     
-    ic_cdk::println!("Transaction submitted: {}", transaction_id);
-    Ok(transaction_id)
+    Ok(proposal_id)
 }
 
 #[update]
-fn confirm_transaction(transaction_id: String) -> Result<String, String> {
+fn confirm_wallet_type_change(proposal_id: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "confirm_wallet_type_change")?;
     
-    let result = TRANSACTIONS.with(|txns| {
-        let mut txns_map = txns.borrow_mut();
-        match txns_map.get_mut(&transaction_id) {
-            Some(transaction) => {
-                if transaction.executed || transaction.rejected {
-                    return Err("Transaction already finalized".to_string());
-                }
-                
-                // Check if caller is owner of the wallet
-                let wallet = WALLETS.with(|wallets| {
-                    wallets.borrow().get(&transaction.wallet_id).cloned()
-                });
-                
-                let wallet = match wallet {
-                    Some(w) => w,
-                    None => return Err("Wallet not found".to_string()),
-                };
-                
-                if !wallet.owners.contains(&caller) {
-                    return Err("Only wallet owners can confirm transactions".to_string());
-                }
-                
-                if transaction.rejections.contains(&caller) {
-                    return Err("Cannot confirm after rejecting".to_string());
-                }
-                
-                transaction.confirmations.insert(caller);
-                
-                // Check if we have enough confirmations
-                let threshold_met = transaction.confirmations.len() >= wallet.threshold as usize;
-                
-                // Log the action
-                log_audit_action(&transaction.wallet_id, AuditAction::TransactionConfirmed, caller, 
-                    format!("Confirmed transaction {}", transaction_id), Some(transaction_id.clone()));
-                
-                if threshold_met {
-                    ic_cdk::spawn(execute_transaction_async(transaction_id.clone()));
-                    Ok("Transaction confirmed and will be executed".to_string())
-                } else {
-                    Ok(format!("Transaction confirmed ({}/{})", transaction.confirmations.len(), wallet.threshold))
-                }
-            },
-            None => Err("Transaction not found".to_string()),
-        }
+    let proposal = TYPE_CHANGE_PROPOSALS.with(|proposals| proposals.borrow().get(&proposal_id).cloned());
+    
+    let mut proposal = match proposal {
+        Some(p) => p,
+        None => return Err(CustodyError::not_found("Type change proposal", proposal_id)),
+    };
+    
+    if proposal.executed {
+        return Err(CustodyError::status_conflict("executed", "pending"));
+    }
+    
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(&proposal.wallet_id).cloned());
+    
+    let wallet = match wallet {
+        Some(w) => w,
+        None => return Err(CustodyError::not_found("Wallet", proposal.wallet_id.clone())),
+    };
+    
+    if !wallet.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("confirm_wallet_type_change"));
+    }
+    
+    if !proposal.confirmations.insert(caller) {
+        return Err(CustodyError::status_conflict("confirmed by caller", "not confirmed by caller"));
+    }
+    
+    start_type_change_cooling_off(&mut proposal, &wallet, ic_cdk::api::time());
+    
+    TYPE_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow_mut().insert(proposal_id.clone(), proposal.clone());
     });
     
-    result
+    log_audit_action(&proposal.wallet_id, AuditAction::PolicyUpdated, caller,
+        format!("Confirmed wallet type change to {:?} (proposal {})", proposal.new_type, proposal_id), None);
+    
+    match proposal.executable_at {
+        Some(executable_at) => Ok(format!("All owners confirmed, executable at {}", executable_at)),
+        None => {
+            let confirmed = proposal.confirmations.iter().filter(|c| wallet.owners.contains(c)).count();
+            Ok(format!("Wallet type change confirmed ({}/{})", confirmed, wallet.owners.len()))
+        },
+    }
 }
 
 #[update]
-fn reject_transaction(transaction_id: String) -> Result<String, String> {
+fn execute_wallet_type_change(proposal_id: String) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "execute_wallet_type_change")?;
     
-    TRANSACTIONS.with(|txns| {
-        let mut txns_map = txns.borrow_mut();
-        match txns_map.get_mut(&transaction_id) {
-            Some(transaction) => {
-                if transaction.executed || transaction.rejected {
-                    return Err("Transaction already finalized".to_string());
-                }
-                
-                // Check if caller is owner of the wallet
-                let wallet = WALLETS.with(|wallets| {
-                    wallets.borrow().get(&transaction.wallet_id).cloned()
-                });
-                
-                let wallet = match wallet {
-                    Some(w) => w,
-                    None => return Err("Wallet not found".to_string()),
-                };
-                
-                if !wallet.owners.contains(&caller) {
-                    return Err("Only wallet owners can reject transactions".to_string());
-                }
-                
-                transaction.rejections.insert(caller);
-                transaction.confirmations.remove(&caller);
-                
-                // Check if transaction should be rejected
-                let max_rejections = wallet.owners.len() - wallet.threshold as usize + 1;
-                if transaction.rejections.len() >= max_rejections {
-                    transaction.rejected = true;
-                }
-                
-                // Log the action
-                log_audit_action(&transaction.wallet_id, AuditAction::TransactionRejected, caller, 
-                    format!("Rejected transaction {}", transaction_id), Some(transaction_id.clone()));
-                
-                Ok("Transaction rejected".to_string())
-            },
-            None => Err("Transaction not found".to_string()),
-        }
-    })
-}
-
-async fn execute_transaction_async(transaction_id: String) {
-    let result = execute_transaction(transaction_id.clone()).await;
-    match result {
-        Ok(_) => ic_cdk::println!("Transaction {} executed successfully", transaction_id),
-        Err(e) => ic_cdk::println!("Transaction {} execution failed: {}", transaction_id, e),
-    }
-}
-
-async fn execute_transaction(transaction_id: String) -> Result<String, String> {
-    let transaction = TRANSACTIONS.with(|txns| {
-        txns.borrow().get(&transaction_id).cloned()
-    });
+    let proposal = TYPE_CHANGE_PROPOSALS.with(|proposals| proposals.borrow().get(&proposal_id).cloned());
     
-    let mut transaction = match transaction {
-        Some(txn) => txn,
-        None => return Err("Transaction not found".to_string()),
+    let mut proposal = match proposal {
+        Some(p) => p,
+        None => return Err(CustodyError::not_found("Type change proposal", proposal_id)),
     };
     
-    if transaction.executed || transaction.rejected {
-        return Err("Transaction already finalized".to_string());
+    if proposal.executed {
+        return Err(CustodyError::status_conflict("executed", "pending"));
     }
     
-    // Get wallet info
-    let wallet = WALLETS.with(|wallets| {
-        wallets.borrow().get(&transaction.wallet_id).cloned()
-    });
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(&proposal.wallet_id).cloned());
     
     let wallet = match wallet {
         Some(w) => w,
-        None => return Err("Wallet not found".to_string()),
+        None => return Err(CustodyError::not_found("Wallet", proposal.wallet_id.clone())),
     };
     
-    if transaction.confirmations.len() < wallet.threshold as usize {
-        return Err("Insufficient confirmations".to_string());
+    if !wallet.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("execute_wallet_type_change"));
     }
     
-    // Check wallet balance
-    if wallet.balance < transaction.amount {
-        return Err("Insufficient wallet balance".to_string());
+    // Owners may have changed since the cooling-off period started
+    let current_time = ic_cdk::api::time();
+    start_type_change_cooling_off(&mut proposal, &wallet, current_time);
+    
+    match proposal.executable_at {
+        None => return Err(CustodyError::status_conflict("awaiting owner confirmations", "confirmed by all owners")),
+        Some(executable_at) if executable_at > current_time => {
+            return Err(CustodyError::status_conflict(
+                format!("cooling off until {}", executable_at),
+                "cooling-off period elapsed",
+            ));
+        },
+        Some(_) => {},
     }
     
-    // Update wallet balance and daily spent
     WALLETS.with(|wallets| {
-        let mut wallets_map = wallets.borrow_mut();
-        if let Some(wallet) = wallets_map.get_mut(&transaction.wallet_id) {
-            wallet.balance -= transaction.amount;
-            wallet.daily_spent += transaction.amount;
+        if let Some(wallet) = wallets.borrow_mut().get_mut(&proposal.wallet_id) {
+            wallet.wallet_type = proposal.new_type.clone();
         }
     });
     
-    // Mark transaction as executed
-    transaction.executed = true;
-    transaction.executed_at = Some(ic_cdk::api::time());
+    // The previous policy stays in the version history
+    let mut new_policy = default_wallet_policy(&proposal.new_type, wallet.daily_limit);
+    new_policy.parent_funding_limit = parent_funding_limit(&proposal.wallet_id);
+    let change_reason = format!("Wallet type changed from {:?} to {:?}: {}",
+        proposal.current_type, proposal.new_type, proposal.reason);
     
-    // Generate transaction hash (simplified)
-    let mut hasher = Sha256::new();
-    hasher.update(format!("{}{}{}", transaction.to, transaction.amount, transaction.created_at));
-    let hash_result = hasher.finalize();
-    transaction.transaction_hash = Some(format!("{:x}", hash_result));
+    let version = WALLET_POLICY_HISTORY.with(|history| {
+        let mut history_map = history.borrow_mut();
+        let versions = history_map.entry(proposal.wallet_id.clone()).or_default();
+        let version = versions.last().map(|v| v.version).unwrap_or(0) + 1;
+        versions.push(PolicyVersion {
+            version,
+            policy: new_policy.clone(),
+            changed_at: current_time,
+            changed_by: caller,
+            change_reason: change_reason.clone(),
+        });
+        version
+    });
     
-    TRANSACTIONS.with(|txns| {
-        txns.borrow_mut().insert(transaction_id.clone(), transaction);
+    WALLET_POLICIES.with(|policies| {
+        policies.borrow_mut().insert(proposal.wallet_id.clone(), new_policy);
     });
     
-    // Log execution
-    log_audit_action(&wallet.id, AuditAction::TransactionExecuted, ic_cdk::caller(), 
-        format!("Executed transaction for {} satoshis", transaction.amount), Some(transaction_id));
+    TYPE_CHANGE_PROPOSALS.with(|proposals| {
+        if let Some(p) = proposals.borrow_mut().get_mut(&proposal_id) {
+            p.executed = true;
+            p.executable_at = proposal.executable_at;
+        }
+    });
     
-    Ok("Transaction executed successfully".to_string())
+    log_audit_action(&proposal.wallet_id, AuditAction::PolicyUpdated, caller,
+        format!("{} (policy version {})", change_reason, version), None);
+    
+    Ok(format!("Wallet type changed to {:?}", proposal.new_type))
 }
 
-// === Emergency Functions ===
+// === Wallet Metadata Functions ===
 
+/// Replaces the wallet's label, tags and project assignment. Tags are
+/// trimmed and lowercased so searches are case-insensitive.
 #[update]
-fn emergency_freeze_wallet(wallet_id: String) -> Result<String, String> {
+fn set_wallet_metadata(wallet_id: String, metadata: WalletMetadata) -> Result<String, CustodyError> {
     let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_wallet_metadata")?;
     
-    // Check if caller is emergency contact
-    let is_emergency_contact = EMERGENCY_CONTACTS.with(|contacts| {
-        contacts.borrow().contains(&caller)
-    });
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(&wallet_id).cloned());
     
-    let is_wallet_owner = WALLETS.with(|wallets| {
-        if let Some(wallet) = wallets.borrow().get(&wallet_id) {
-            wallet.owners.contains(&caller)
-        } else {
-            false
-        }
-    });
+    let wallet = match wallet {
+        Some(w) => w,
+        None => return Err(CustodyError::not_found("Wallet", wallet_id)),
+    };
     
-    if !is_emergency_contact && !is_wallet_owner {
-        return Err("Unauthorized emergency action".to_string());
+    if !wallet.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("set_wallet_metadata"));
     }
     
-    WALLETS.with(|wallets| {
-        let mut wallets_map = wallets.borrow_mut();
-        match wallets_map.get_mut(&wallet_id) {
-            Some(wallet) => {
-                wallet.status = WalletStatus::Frozen;
-                
-                // Log emergency action
-                log_audit_action(&wallet_id, AuditAction::WalletFrozen, caller, 
-                    "Emergency freeze activated".to_string(), None);
-                
-                Ok("Wallet frozen successfully".to_string())
-            },
-            None => Err("Wallet not found".to_string()),
-        }
-    })
-}
-
-#[update]
-fn emergency_unfreeze_wallet(wallet_id: String) -> Result<String, String> {
-    let caller = ic_cdk::caller();
+    let metadata = normalize_wallet_metadata(metadata)?;
     
-    // Check if caller is emergency contact
-    let is_emergency_contact = EMERGENCY_CONTACTS.with(|contacts| {
-        contacts.borrow().contains(&caller)
+    let previous = WALLET_METADATA.with(|store| {
+        store.borrow_mut().insert(wallet_id.clone(), metadata.clone())
     });
     
-    if !is_emergency_contact {
-        return Err("Only emergency contacts can unfreeze wallets".to_string());
+    if let Some(previous) = &previous {
+        TAG_INDEX.with(|index| {
+            let mut index = index.borrow_mut();
+            for tag in &previous.tags {
+                remove_from_index(&mut index, tag, &wallet_id);
+            }
+        });
+        if let Some(project_code) = &previous.project_code {
+            PROJECT_INDEX.with(|index| remove_from_index(&mut index.borrow_mut(), project_code, &wallet_id));
+        }
     }
     
-    WALLETS.with(|wallets| {
-        let mut wallets_map = wallets.borrow_mut();
-        match wallets_map.get_mut(&wallet_id) {
-            Some(wallet) => {
-                wallet.status = WalletStatus::Active;
-                
-                // Log emergency action
-                log_audit_action(&wallet_id, AuditAction::WalletUnfrozen, caller, 
-                    "Emergency unfreeze activated".to_string(), None);
-                
-                Ok("Wallet unfrozen successfully".to_string())
-            },
-            None => Err("Wallet not found".to_string()),
+    TAG_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        for tag in &metadata.tags {
+            index.entry(tag.clone()).or_default().insert(wallet_id.clone());
         }
-    })
-}
-
-#[update]
-fn global_emergency_freeze() -> Result<String, String> {
-    let caller = ic_cdk::caller();
-    
-    // Check if caller is emergency contact
-    let is_emergency_contact = EMERGENCY_CONTACTS.with(|contacts| {
-        contacts.borrow().contains(&caller)
     });
-    
-    if !is_emergency_contact {
-        return Err("Unauthorized emergency action".to_string());
+    if let Some(project_code) = &metadata.project_code {
+        PROJECT_INDEX.with(|index| {
+            index.borrow_mut().entry(project_code.clone()).or_default().insert(wallet_id.clone());
+        });
     }
     
-    GLOBAL_FROZEN.with(|frozen| {
-        *frozen.borrow_mut() = true;
-    });
+    log_audit_action(&wallet_id, AuditAction::MetadataUpdated, caller,
+        format!("Metadata set: label '{}', {} tag(s)", metadata.label, metadata.tags.len()), None);
     
-    Ok("Global emergency freeze activated".to_string())
+    Ok("Wallet metadata updated successfully".to_string())
 }
 
-// === Query Functions ===
-
 #[query]
-fn get_wallet(wallet_id: String) -> Option<MultisigWallet> {
-    WALLETS.with(|wallets| {
-        wallets.borrow().get(&wallet_id).cloned()
-    })
+fn get_wallet_metadata(wallet_id: String) -> Option<WalletMetadata> {
+    WALLET_METADATA.with(|store| store.borrow().get(&wallet_id).cloned())
 }
 
 #[query]
-fn get_user_wallets(user: Principal) -> Vec<MultisigWallet> {
+fn search_wallets_by_tag(tag: String) -> Vec<(String, MultisigWallet)> {
+    let tag = tag.trim().to_lowercase();
+    let wallet_ids = TAG_INDEX.with(|index| index.borrow().get(&tag).cloned().unwrap_or_default());
+    
     WALLETS.with(|wallets| {
-        wallets.borrow()
-            .values()
-            .filter(|wallet| wallet.owners.contains(&user))
-            .cloned()
+        let wallets = wallets.borrow();
+        wallet_ids
+            .into_iter()
+            .filter_map(|id| wallets.get(&id).cloned().map(|wallet| (id, wallet)))
             .collect()
     })
 }
 
 #[query]
-fn get_transaction(transaction_id: String) -> Option<MultisigTransaction> {
-    TRANSACTIONS.with(|txns| {
-        txns.borrow().get(&transaction_id).cloned()
+fn get_wallets_by_project(project_code: String) -> Vec<MultisigWallet> {
+    let wallet_ids = PROJECT_INDEX.with(|index| {
+        index.borrow().get(project_code.trim()).cloned().unwrap_or_default()
+    });
+    
+    WALLETS.with(|wallets| {
+        let wallets = wallets.borrow();
+        wallet_ids.iter().filter_map(|id| wallets.get(id).cloned()).collect()
     })
 }
 
-#[query]
-fn get_wallet_transactions(wallet_id: String) -> Vec<MultisigTransaction> {
-    TRANSACTIONS.with(|txns| {
-        txns.borrow()
-            .values()
-            .filter(|txn| txn.wallet_id == wallet_id)
-            .cloned()
-            .collect()
-    })
+fn normalize_wallet_metadata(metadata: WalletMetadata) -> Result<WalletMetadata, CustodyError> {
+    let label = metadata.label.trim().to_string();
+    if label.is_empty() {
+        return Err(CustodyError::invalid_input("label", "must not be empty"));
+    }
+    
+    let tags: BTreeSet<String> = metadata.tags
+        .iter()
+        .map(|tag| tag.trim().to_lowercase())
+        .filter(|tag| !tag.is_empty())
+        .collect();
+    if tags.len() > MAX_WALLET_TAGS {
+        return Err(CustodyError::LimitExceeded { limit: MAX_WALLET_TAGS as u64, actual: tags.len() as u64 });
+    }
+    
+    let normalized = WalletMetadata {
+        label,
+        description: metadata.description,
+        tags,
+        project_code: metadata.project_code.map(|code| code.trim().to_string()).filter(|code| !code.is_empty()),
+        cost_center: metadata.cost_center,
+        created_by_org: metadata.created_by_org,
+    };
+    
+    let longest = normalized.tags.iter()
+        .chain([&normalized.label, &normalized.description, &normalized.created_by_org])
+        .chain(normalized.project_code.iter())
+        .chain(normalized.cost_center.iter())
+        .map(|field| field.len())
+        .max()
+        .unwrap_or(0);
+    if longest > MAX_METADATA_FIELD_LENGTH {
+        return Err(CustodyError::LimitExceeded { limit: MAX_METADATA_FIELD_LENGTH as u64, actual: longest as u64 });
+    }
+    
+    Ok(normalized)
 }
 
-#[query]
-fn get_pending_transactions(wallet_id: String) -> Vec<MultisigTransaction> {
-    TRANSACTIONS.with(|txns| {
-        txns.borrow()
-            .values()
-            .filter(|txn| txn.wallet_id == wallet_id && !txn.executed && !txn.rejected)
-            .cloned()
+fn remove_from_index(index: &mut BTreeMap<String, BTreeSet<String>>, key: &str, wallet_id: &str) {
+    if let Some(ids) = index.get_mut(key) {
+        ids.remove(wallet_id);
+        if ids.is_empty() {
+            index.remove(key);
+        }
+    }
+}
+
+// === Transaction Functions ===
+
+#[update]
+fn submit_transaction(
+    wallet_id: String,
+    to: String,
+    amount: u64,
+    data: Vec<u8>,
+    priority: TransactionPriority,
+    asset_id: Option<String>,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "submit_transaction")?;
+    
+    let asset_id = asset_id.map(normalize_asset_id).transpose()?;
+    
+    // Check global freeze
+    let is_frozen = GLOBAL_FROZEN.with(|frozen| *frozen.borrow());
+    if is_frozen {
+        return Err(CustodyError::status_conflict("global freeze active", "no global freeze"));
+    }
+    
+    // Validate wallet and ownership
+    let wallet = WALLETS.with(|wallets| {
+        wallets.borrow().get(&wallet_id).cloned()
+    });
+    
+    let wallet = match wallet {
+        Some(w) => w,
+        None => return Err(CustodyError::not_found("Wallet", wallet_id)),
+    };
+    
+    if !wallet.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("submit_transaction"));
+    }
+    
+    // Clear out timed-out transactions before accepting new ones
+    expire_timed_out_transactions(&wallet_id, caller);
+    
+    if wallet.status != WalletStatus::Active {
+        return Err(CustodyError::status_conflict(format!("{:?}", wallet.status), "Active"));
+    }
+    
+    // Check wallet policy
+    let policy = WALLET_POLICIES.with(|policies| {
+        policies.borrow().get(&wallet_id).cloned()
+    });
+    
+    let timeout_hours = policy.as_ref().map(|p| p.transaction_timeout_hours).unwrap_or(0);
+    
+    // Policy limits are denominated in satoshis, so token transfers are only
+    // held to the destination rules, the daily limit and velocity checks
+    // likewise apply to native transfers only
+    if let Some(ref policy) = policy {
+        match asset_id {
+            None => check_transaction_policy(policy, &to, amount)?,
+            Some(_) => check_destination_policy(policy, &to)?,
+        }
+    }
+    
+    // Token transfers settle on the token's ledger and carry no BTC network fee
+    let fee = match asset_id {
+        None => calculate_fee_estimate(amount, &priority).total_fee,
+        Some(_) => 0,
+    };
+    let required = amount.saturating_add(fee);
+    let mut available = available_balance(&wallet, asset_id.as_deref());
+    if asset_id.is_none() {
+        available = available.saturating_add(parent_funding_available(&wallet));
+    }
+    if available < required {
+        return Err(CustodyError::InsufficientBalance {
+            available,
+            required,
+        });
+    }
+    
+    // Check daily limit
+    let current_day = ic_cdk::api::time() / (24 * 60 * 60 * 1_000_000_000);
+    let updated_wallet = WALLETS.with(|wallets| {
+        let mut wallets_map = wallets.borrow_mut();
+        if let Some(wallet) = wallets_map.get_mut(&wallet_id) {
+            if wallet.last_reset_day < current_day {
+                wallet.daily_spent = 0;
+                wallet.last_reset_day = current_day;
+            }
+            
+            if asset_id.is_none() && wallet.daily_spent + amount > wallet.daily_limit {
+                return Err(CustodyError::LimitExceeded {
+                    limit: wallet.daily_limit,
+                    actual: wallet.daily_spent + amount,
+                });
+            }
+            
+            Ok(wallet.clone())
+        } else {
+            Err(CustodyError::not_found("Wallet", wallet_id.clone()))
+        }
+    })?;
+    
+    let transaction_id = Uuid::new_v4().to_string();
+    let current_time = ic_cdk::api::time();
+    let expires_at = transaction_expiry(current_time, timeout_hours);
+    
+    let transaction = MultisigTransaction {
+        id: transaction_id.clone(),
+        wallet_id: wallet_id.clone(),
+        to,
+        amount,
+        data,
+        confirmations: BTreeSet::from([caller]),
+        rejections: BTreeSet::new(),
+        executed: false,
+        rejected: false,
+        created_at: current_time,
+        executed_at: None,
+        transaction_hash: None,
+        gas_price: None,
+        gas_limit: None,
+        nonce: None,
+        priority,
+        expires_at,
+        cancellation_reason: None,
+        estimated_fee: fee,
+        asset_id: asset_id.clone(),
+    };
+    
+    TRANSACTIONS.with(|txns| {
+        txns.borrow_mut().insert(transaction_id.clone(), transaction.clone());
+    });
+    
+    // Log the action
+    let details = match asset_id {
+        None => format!("Submitted transaction for {} satoshis plus {} satoshis estimated fee", amount, fee),
+        Some(ref asset) => format!("Submitted transaction for {} {}", amount, asset),
+    };
+    log_audit_action(&wallet_id, AuditAction::TransactionSubmitted, caller, details, Some(transaction_id.clone()));
+    
+    // Check if transaction can be auto-executed
+    if updated_wallet.threshold == 1 {
+        queue_for_execution(&transaction);
+    }
+    
+    ic_cdk::println!("Transaction submitted: {}", transaction_id);
+    Ok(transaction_id)
+}
+
+#[update]
+fn submit_batch_transactions(
+    wallet_id: String,
+    requests: Vec<BatchTransactionRequest>,
+) -> Result<Vec<String>, Vec<CustodyError>> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "submit_batch_transactions").map_err(|e| vec![e])?;
+    
+    if requests.is_empty() || requests.len() > 100 {
+        return Err(vec![CustodyError::invalid_input("requests", "batch must contain 1-100 transactions")]);
+    }
+    
+    // Check global freeze
+    let is_frozen = GLOBAL_FROZEN.with(|frozen| *frozen.borrow());
+    if is_frozen {
+        return Err(vec![CustodyError::status_conflict("global freeze active", "no global freeze")]);
+    }
+    
+    // Validate wallet and ownership
+    let wallet = WALLETS.with(|wallets| {
+        wallets.borrow().get(&wallet_id).cloned()
+    });
+    
+    let wallet = match wallet {
+        Some(w) => w,
+        None => return Err(vec![CustodyError::not_found("Wallet", wallet_id)]),
+    };
+    
+    if !wallet.owners.contains(&caller) {
+        return Err(vec![CustodyError::unauthorized("submit_batch_transactions")]);
+    }
+    
+    expire_timed_out_transactions(&wallet_id, caller);
+    
+    if !matches!(wallet.status, WalletStatus::Active) {
+        return Err(vec![CustodyError::status_conflict(format!("{:?}", wallet.status), "Active")]);
+    }
+    
+    let policy = WALLET_POLICIES.with(|policies| {
+        policies.borrow().get(&wallet_id).cloned()
+    });
+    
+    let timeout_hours = policy.as_ref().map(|p| p.transaction_timeout_hours).unwrap_or(0);
+    
+    // Validate every request before touching state so the batch is all-or-nothing
+    let mut errors = Vec::new();
+    let mut batch_total: u64 = 0;
+    let mut batch_fees: u64 = 0;
+    for (index, request) in requests.iter().enumerate() {
+        if let Some(ref policy) = policy {
+            if let Err(e) = check_transaction_policy(policy, &request.to, request.amount) {
+                errors.push(CustodyError::invalid_input(&format!("requests[{}]", index), e.to_string()));
+            }
+        }
+        batch_total = batch_total.saturating_add(request.amount);
+        batch_fees = batch_fees.saturating_add(calculate_fee_estimate(request.amount, &request.priority).total_fee);
+    }
+    
+    let batch_required = batch_total.saturating_add(batch_fees);
+    if wallet.balance < batch_required {
+        errors.push(CustodyError::InsufficientBalance {
+            available: wallet.balance,
+            required: batch_required,
+        });
+    }
+    
+    // The daily limit applies to the batch as a whole
+    let current_day = ic_cdk::api::time() / (24 * 60 * 60 * 1_000_000_000);
+    let daily_spent = if wallet.last_reset_day < current_day { 0 } else { wallet.daily_spent };
+    if daily_spent.saturating_add(batch_total) > wallet.daily_limit {
+        errors.push(CustodyError::LimitExceeded {
+            limit: wallet.daily_limit,
+            actual: daily_spent.saturating_add(batch_total),
+        });
+    }
+    
+    if !errors.is_empty() {
+        return Err(errors);
+    }
+    
+    WALLETS.with(|wallets| {
+        if let Some(wallet) = wallets.borrow_mut().get_mut(&wallet_id) {
+            if wallet.last_reset_day < current_day {
+                wallet.daily_spent = 0;
+                wallet.last_reset_day = current_day;
+            }
+        }
+    });
+    
+    let current_time = ic_cdk::api::time();
+    let expires_at = transaction_expiry(current_time, timeout_hours);
+    let mut transaction_ids = Vec::with_capacity(requests.len());
+    
+    for request in requests {
+        let transaction_id = Uuid::new_v4().to_string();
+        let amount = request.amount;
+        let fee = calculate_fee_estimate(amount, &request.priority);
+        
+        let transaction = MultisigTransaction {
+            id: transaction_id.clone(),
+            wallet_id: wallet_id.clone(),
+            to: request.to,
+            amount,
+            data: request.data,
+            confirmations: BTreeSet::from([caller]),
+            rejections: BTreeSet::new(),
+            executed: false,
+            rejected: false,
+            created_at: current_time,
+            executed_at: None,
+            transaction_hash: None,
+            gas_price: None,
+            gas_limit: None,
+            nonce: None,
+            priority: request.priority,
+            expires_at,
+            cancellation_reason: None,
+            estimated_fee: fee.total_fee,
+            asset_id: None,
+        };
+        
+        TRANSACTIONS.with(|txns| {
+            txns.borrow_mut().insert(transaction_id.clone(), transaction.clone());
+        });
+        
+        log_audit_action(&wallet_id, AuditAction::TransactionSubmitted, caller, 
+            format!("Submitted batch transaction for {} satoshis", amount), Some(transaction_id.clone()));
+        
+        if wallet.threshold == 1 {
+            queue_for_execution(&transaction);
+        }
+        
+        transaction_ids.push(transaction_id);
+    }
+    
+    ic_cdk::println!("Batch of {} transactions submitted to wallet {}", transaction_ids.len(), wallet_id);
+    Ok(transaction_ids)
+}
+
+#[update]
+fn confirm_transaction(transaction_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "confirm_transaction")?;
+    
+    let details = format!("Confirmed transaction {}", transaction_id);
+    confirm_transaction_as(transaction_id, caller, "confirm_transaction", details)
+}
+
+fn confirm_transaction_as(
+    transaction_id: String,
+    confirmer: Principal,
+    method: &str,
+    details: String,
+) -> Result<String, CustodyError> {
+    let mut ready = None;
+    
+    let result = TRANSACTIONS.with(|txns| {
+        let mut txns_map = txns.borrow_mut();
+        match txns_map.get_mut(&transaction_id) {
+            Some(transaction) => {
+                if transaction.executed || transaction.rejected {
+                    return Err(CustodyError::status_conflict("finalized", "pending"));
+                }
+                
+                if is_expired(transaction, ic_cdk::api::time()) {
+                    return Err(CustodyError::status_conflict("expired", "pending"));
+                }
+                
+                // Check if caller is owner of the wallet
+                let wallet = WALLETS.with(|wallets| {
+                    wallets.borrow().get(&transaction.wallet_id).cloned()
+                });
+                
+                let wallet = match wallet {
+                    Some(w) => w,
+                    None => return Err(CustodyError::not_found("Wallet", transaction.wallet_id.clone())),
+                };
+                
+                if !wallet.owners.contains(&confirmer) {
+                    return Err(CustodyError::unauthorized(method));
+                }
+                
+                if transaction.rejections.contains(&confirmer) {
+                    return Err(CustodyError::status_conflict("rejected by caller", "not rejected by caller"));
+                }
+                
+                transaction.confirmations.insert(confirmer);
+                
+                // Check if we have enough confirmations
+                let threshold_met = transaction.confirmations.len() >= wallet.threshold as usize;
+                
+                // Log the action
+                log_audit_action(&transaction.wallet_id, AuditAction::TransactionConfirmed, confirmer, 
+                    details, Some(transaction_id.clone()));
+                
+                if threshold_met {
+                    ready = Some(transaction.clone());
+                    Ok("Transaction confirmed and will be executed".to_string())
+                } else {
+                    Ok(format!("Transaction confirmed ({}/{})", transaction.confirmations.len(), wallet.threshold))
+                }
+            },
+            None => Err(CustodyError::not_found("Transaction", transaction_id.clone())),
+        }
+    });
+    
+    // Queued outside the borrow, since an Emergency transaction executes straight away
+    if let Some(transaction) = ready {
+        queue_for_execution(&transaction);
+    }
+    
+    result
+}
+
+#[update]
+fn reject_transaction(transaction_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "reject_transaction")?;
+    
+    TRANSACTIONS.with(|txns| {
+        let mut txns_map = txns.borrow_mut();
+        match txns_map.get_mut(&transaction_id) {
+            Some(transaction) => {
+                if transaction.executed || transaction.rejected {
+                    return Err(CustodyError::status_conflict("finalized", "pending"));
+                }
+                
+                // Check if caller is owner of the wallet
+                let wallet = WALLETS.with(|wallets| {
+                    wallets.borrow().get(&transaction.wallet_id).cloned()
+                });
+                
+                let wallet = match wallet {
+                    Some(w) => w,
+                    None => return Err(CustodyError::not_found("Wallet", transaction.wallet_id.clone())),
+                };
+                
+                if !wallet.owners.contains(&caller) {
+                    return Err(CustodyError::unauthorized("reject_transaction"));
+                }
+                
+                transaction.rejections.insert(caller);
+                transaction.confirmations.remove(&caller);
+                
+                // Check if transaction should be rejected
+                let max_rejections = wallet.owners.len() - wallet.threshold as usize + 1;
+                if transaction.rejections.len() >= max_rejections {
+                    transaction.rejected = true;
+                }
+                
+                // Log the action
+                log_audit_action(&transaction.wallet_id, AuditAction::TransactionRejected, caller, 
+                    format!("Rejected transaction {}", transaction_id), Some(transaction_id.clone()));
+                
+                Ok("Transaction rejected".to_string())
+            },
+            None => Err(CustodyError::not_found("Transaction", transaction_id.clone())),
+        }
+    })
+}
+
+#[update]
+fn cancel_transaction(transaction_id: String, reason: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "cancel_transaction")?;
+    
+    if reason.is_empty() {
+        return Err(CustodyError::invalid_input("reason", "required for cancellation"));
+    }
+    
+    TRANSACTIONS.with(|txns| {
+        let mut txns_map = txns.borrow_mut();
+        match txns_map.get_mut(&transaction_id) {
+            Some(transaction) => {
+                if transaction.executed || transaction.rejected {
+                    return Err(CustodyError::status_conflict("finalized", "pending"));
+                }
+                
+                let is_owner = WALLETS.with(|wallets| {
+                    wallets.borrow()
+                        .get(&transaction.wallet_id)
+                        .map(|wallet| wallet.owners.contains(&caller))
+                        .unwrap_or(false)
+                });
+                
+                if !is_owner {
+                    return Err(CustodyError::unauthorized("cancel_transaction"));
+                }
+                
+                // daily_spent is only debited on execution, so a pending
+                // transaction holds no daily-limit reservation to credit back
+                transaction.rejected = true;
+                transaction.cancellation_reason = Some(reason.clone());
+                
+                log_audit_action(&transaction.wallet_id, AuditAction::TransactionCancelled, caller, 
+                    format!("Cancelled transaction {}: {}", transaction_id, reason), Some(transaction_id.clone()));
+                
+                Ok("Transaction cancelled".to_string())
+            },
+            None => Err(CustodyError::not_found("Transaction", transaction_id.clone())),
+        }
+    })
+}
+
+#[update]
+fn expire_pending_transactions(wallet_id: String) -> Result<Vec<String>, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "expire_pending_transactions")?;
+    
+    let is_owner = WALLETS.with(|wallets| {
+        wallets.borrow()
+            .get(&wallet_id)
+            .map(|wallet| wallet.owners.contains(&caller))
+    });
+    
+    match is_owner {
+        Some(true) => Ok(expire_timed_out_transactions(&wallet_id, caller)),
+        Some(false) => Err(CustodyError::unauthorized("expire_pending_transactions")),
+        None => Err(CustodyError::not_found("Wallet", wallet_id)),
+    }
+}
+
+/// Emergency transactions execute as soon as they reach threshold; everything
+/// else joins the execution queue, which is drained in priority order
+fn queue_for_execution(transaction: &MultisigTransaction) {
+    if matches!(transaction.priority, TransactionPriority::Emergency) {
+        ic_cdk::spawn(execute_transaction_async(transaction.id.clone()));
+        return;
+    }
+    
+    EXECUTION_QUEUE.with(|queue| {
+        queue.borrow_mut().insert(execution_queue_key(transaction), transaction.wallet_id.clone());
+    });
+    ic_cdk::spawn(process_execution_queue());
+}
+
+/// Executes queued transactions head first. A transaction blocked by its
+/// wallet's balance or velocity limit stays queued and holds back the rest of
+/// that wallet's queue, so lower priorities can't spend the funds first. The
+/// same holds for wallets that are not active, so a frozen wallet's queue
+/// waits for the unfreeze instead of being dropped or re-tripping the freeze.
+/// Deposits and unfreezes drain the queue again.
+async fn process_execution_queue() {
+    let mut blocked_wallets = BTreeSet::new();
+    let mut cursor: Option<ExecutionQueueKey> = None;
+    
+    loop {
+        let next = EXECUTION_QUEUE.with(|queue| {
+            let queue = queue.borrow();
+            let mut remaining = match &cursor {
+                Some(cursor) => queue.range((Bound::Excluded(cursor.clone()), Bound::Unbounded)),
+                None => queue.range(..),
+            };
+            remaining
+                .find(|(_, wallet_id)| !blocked_wallets.contains(*wallet_id))
+                .map(|(key, wallet_id)| (key.clone(), wallet_id.clone()))
+        });
+        
+        let (key, wallet_id) = match next {
+            Some(entry) => entry,
+            None => break,
+        };
+        
+        let wallet_active = WALLETS.with(|wallets| {
+            wallets.borrow().get(&wallet_id).map(|wallet| wallet.status == WalletStatus::Active)
+        });
+        if wallet_active == Some(false) {
+            blocked_wallets.insert(wallet_id);
+            cursor = Some(key);
+            continue;
+        }
+        
+        match execute_transaction(key.2.clone()).await {
+            Err(e @ (CustodyError::InsufficientBalance { .. } | CustodyError::VelocityLimitExceeded { .. })) => {
+                ic_cdk::println!("Queued transaction {} is waiting: {}", key.2, e);
+                blocked_wallets.insert(wallet_id);
+            },
+            result => {
+                if let Err(e) = result {
+                    ic_cdk::println!("Dropping queued transaction {}: {}", key.2, e);
+                }
+                EXECUTION_QUEUE.with(|queue| queue.borrow_mut().remove(&key));
+            },
+        }
+        
+        cursor = Some(key);
+    }
+}
+
+fn execution_queue_key(transaction: &MultisigTransaction) -> ExecutionQueueKey {
+    let priority_level = match transaction.priority {
+        TransactionPriority::Emergency => 0,
+        TransactionPriority::High => 1,
+        TransactionPriority::Normal => 2,
+        TransactionPriority::Low => 3,
+    };
+    (priority_level, transaction.created_at, transaction.id.clone())
+}
+
+async fn execute_transaction_async(transaction_id: String) {
+    let result = execute_transaction(transaction_id.clone()).await;
+    match result {
+        Ok(_) => ic_cdk::println!("Transaction {} executed successfully", transaction_id),
+        Err(e) => ic_cdk::println!("Transaction {} execution failed: {}", transaction_id, e),
+    }
+}
+
+async fn execute_transaction(transaction_id: String) -> Result<String, CustodyError> {
+    let transaction = TRANSACTIONS.with(|txns| {
+        txns.borrow().get(&transaction_id).cloned()
+    });
+    
+    let mut transaction = match transaction {
+        Some(txn) => txn,
+        None => return Err(CustodyError::not_found("Transaction", transaction_id)),
+    };
+    
+    if transaction.executed || transaction.rejected {
+        return Err(CustodyError::status_conflict("finalized", "pending"));
+    }
+    
+    if is_expired(&transaction, ic_cdk::api::time()) {
+        return Err(CustodyError::status_conflict("expired", "pending"));
+    }
+    
+    // Get wallet info
+    let wallet = WALLETS.with(|wallets| {
+        wallets.borrow().get(&transaction.wallet_id).cloned()
+    });
+    
+    let wallet = match wallet {
+        Some(w) => w,
+        None => return Err(CustodyError::not_found("Wallet", transaction.wallet_id.clone())),
+    };
+    
+    // A frozen or compromised wallet moves nothing, and a retry against it
+    // must not freeze and alert all over again
+    if wallet.status != WalletStatus::Active {
+        return Err(CustodyError::status_conflict(format!("{:?}", wallet.status), "Active"));
+    }
+    
+    if transaction.confirmations.len() < wallet.threshold as usize {
+        return Err(CustodyError::status_conflict(
+            format!("{} confirmations", transaction.confirmations.len()),
+            format!("{} confirmations", wallet.threshold),
+        ));
+    }
+    
+    // Check wallet balance, including the network fee. A sub-wallet draws a
+    // native shortfall from its parent.
+    let total_debit = transaction.amount.saturating_add(transaction.estimated_fee);
+    let is_native = transaction.asset_id.is_none();
+    let available = available_balance(&wallet, transaction.asset_id.as_deref());
+    let shortfall = total_debit.saturating_sub(available);
+    if shortfall > 0 && (!is_native || parent_funding_available(&wallet) < shortfall) {
+        let funding = if is_native { parent_funding_available(&wallet) } else { 0 };
+        return Err(CustodyError::InsufficientBalance {
+            available: available.saturating_add(funding),
+            required: total_debit,
+        });
+    }
+    
+    let now = ic_cdk::api::time();
+    let policy = WALLET_POLICIES.with(|policies| policies.borrow().get(&wallet.id).cloned())
+        .filter(|_| is_native);
+    
+    // A burst over the velocity limit suggests compromised keys, so the wallet
+    // is frozen rather than just refusing this one transaction
+    if let Some(policy) = policy {
+        if let Err(e) = check_velocity(&wallet.id, &policy, transaction.amount, now) {
+            let details = format!("Velocity limit exceeded by transaction {}: {}", transaction_id, e);
+            freeze_wallet_internal(&wallet.id, ic_cdk::id(), details.clone());
+            log_audit_action(&wallet.id, AuditAction::VelocityLimitExceeded, ic_cdk::id(),
+                details.clone(), Some(transaction_id.clone()));
+            ic_cdk::spawn(send_critical_alert(wallet.id.clone(), details));
+            return Err(e);
+        }
+    }
+    
+    if is_native {
+        RECENT_EXECUTIONS.with(|recent| {
+            let mut recent = recent.borrow_mut();
+            let executions = recent.entry(wallet.id.clone()).or_default();
+            executions.push_back((now, transaction.amount));
+            if executions.len() > MAX_RECENT_EXECUTIONS {
+                executions.pop_front();
+            }
+        });
+    }
+    
+    if shortfall > 0 {
+        fund_from_parent(&wallet, shortfall, &transaction_id);
+    }
+    
+    // Update wallet balance and daily spent
+    WALLETS.with(|wallets| {
+        let mut wallets_map = wallets.borrow_mut();
+        if let Some(wallet) = wallets_map.get_mut(&transaction.wallet_id) {
+            match &transaction.asset_id {
+                None => {
+                    wallet.balance -= total_debit;
+                    wallet.daily_spent += transaction.amount;
+                },
+                Some(asset) => {
+                    if let Some(balance) = wallet.asset_balances.get_mut(asset) {
+                        *balance -= total_debit;
+                    }
+                },
+            }
+        }
+    });
+    
+    // Mark transaction as executed
+    transaction.executed = true;
+    transaction.executed_at = Some(ic_cdk::api::time());
+    
+    // Generate transaction hash (simplified)
+    let mut hasher = Sha256::new();
+    hasher.update(format!("{}{}{}", transaction.to, transaction.amount, transaction.created_at));
+    let hash_result = hasher.finalize();
+    transaction.transaction_hash = Some(format!("{:x}", hash_result));
+    
+    let details = format!("Executed transaction for {} {}", transaction.amount,
+        transaction.asset_id.as_deref().unwrap_or("satoshis"));
+    
+    TRANSACTIONS.with(|txns| {
+        txns.borrow_mut().insert(transaction_id.clone(), transaction);
+    });
+    
+    // Log execution
+    log_audit_action(&wallet.id, AuditAction::TransactionExecuted, ic_cdk::caller(), details, Some(transaction_id));
+    
+    Ok("Transaction executed successfully".to_string())
+}
+
+// === Wallet Change Functions ===
+
+/// Records a change proposed by an owner of `wallet`; the proposer's
+/// confirmation counts. Single-signature wallets apply it straight away.
+fn propose_wallet_change(
+    wallet: &MultisigWallet,
+    change: WalletChange,
+    caller: Principal,
+) -> Result<String, CustodyError> {
+    validate_wallet_change(&wallet.id, &change)?;
+    
+    let proposal_id = Uuid::new_v4().to_string();
+    let mut proposal = WalletChangeProposal {
+        id: proposal_id.clone(),
+        wallet_id: wallet.id.clone(),
+        change,
+        proposed_by: caller,
+        confirmations: BTreeSet::from([caller]),
+        created_at: ic_cdk::api::time(),
+        executed: false,
+    };
+    
+    log_audit_action(&wallet.id, wallet_change_audit_action(&proposal.change), caller,
+        format!("Proposed {} (proposal {})", describe_wallet_change(&proposal.change), proposal_id), None);
+    
+    if wallet.threshold <= 1 {
+        apply_wallet_change(&proposal, caller)?;
+        proposal.executed = true;
+    }
+    
+    WALLET_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow_mut().insert(proposal_id.clone(), proposal);
+    });
+    
+    Ok(proposal_id)
+}
+
+#[update]
+fn confirm_wallet_change(proposal_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "confirm_wallet_change")?;
+    
+    let proposal = WALLET_CHANGE_PROPOSALS.with(|proposals| proposals.borrow().get(&proposal_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Proposal", proposal_id.clone()))?;
+    
+    if proposal.executed {
+        return Err(CustodyError::status_conflict("executed", "pending"));
+    }
+    
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(&proposal.wallet_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Wallet", proposal.wallet_id.clone()))?;
+    
+    if !wallet.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("confirm_wallet_change"));
+    }
+    
+    if proposal.confirmations.contains(&caller) {
+        return Err(CustodyError::status_conflict("confirmed by caller", "not confirmed by caller"));
+    }
+    
+    let mut proposal = proposal;
+    proposal.confirmations.insert(caller);
+    
+    // Only confirmations from current owners count towards the threshold
+    let confirmations = proposal.confirmations.iter().filter(|c| wallet.owners.contains(c)).count();
+    
+    log_audit_action(&proposal.wallet_id, wallet_change_audit_action(&proposal.change), caller,
+        format!("Confirmed {} (proposal {})", describe_wallet_change(&proposal.change), proposal_id), None);
+    
+    // The proposal keeps its confirmations if applying fails, so it can be
+    // confirmed again once whatever blocked it is resolved
+    let result = if confirmations >= wallet.threshold as usize {
+        apply_wallet_change(&proposal, caller).inspect(|_| proposal.executed = true)
+    } else {
+        Ok(format!("Wallet change confirmed ({}/{})", confirmations, wallet.threshold))
+    };
+    
+    WALLET_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow_mut().insert(proposal_id, proposal);
+    });
+    
+    result
+}
+
+// === Sub-Wallet Functions ===
+
+/// Proposes making `wallet_id` a sub-wallet of `parent_id`. The caller must
+/// own both wallets, and the parent's threshold of owners must confirm it,
+/// since `funding_limit` is the total the parent may transfer to cover the
+/// sub-wallet's native shortfalls. Applying it restarts that allowance.
+#[update]
+fn set_parent_wallet(wallet_id: String, parent_id: String, funding_limit: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_parent_wallet")?;
+    
+    let (wallet, parent) = WALLETS.with(|wallets| {
+        let wallets = wallets.borrow();
+        (wallets.get(&wallet_id).cloned(), wallets.get(&parent_id).cloned())
+    });
+    
+    let wallet = wallet.ok_or_else(|| CustodyError::not_found("Wallet", wallet_id.clone()))?;
+    let parent = parent.ok_or_else(|| CustodyError::not_found("Wallet", parent_id.clone()))?;
+    
+    if !wallet.owners.contains(&caller) || !parent.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("set_parent_wallet"));
+    }
+    
+    propose_wallet_change(&parent, WalletChange::FundSubWallet { sub_wallet_id: wallet_id, funding_limit }, caller)
+}
+
+// Applies a confirmed FundSubWallet change of `parent_id`
+fn set_parent_wallet_internal(
+    wallet_id: &str,
+    parent_id: &str,
+    funding_limit: u64,
+    actor: Principal,
+) -> Result<String, CustodyError> {
+    validate_parent_wallet(wallet_id, parent_id)?;
+    
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(wallet_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Wallet", wallet_id))?;
+    
+    let mut policy = WALLET_POLICIES.with(|policies| policies.borrow().get(wallet_id).cloned())
+        .unwrap_or_else(|| default_wallet_policy(&wallet.wallet_type, wallet.daily_limit));
+    policy.parent_funding_limit = funding_limit;
+    
+    let current_time = ic_cdk::api::time();
+    let change_reason = format!("Parent wallet set to {} with funding limit {}", parent_id, funding_limit);
+    
+    let version = WALLET_POLICY_HISTORY.with(|history| {
+        let mut history_map = history.borrow_mut();
+        let versions = history_map.entry(wallet_id.to_string()).or_default();
+        let version = versions.last().map(|v| v.version).unwrap_or(0) + 1;
+        versions.push(PolicyVersion {
+            version,
+            policy: policy.clone(),
+            changed_at: current_time,
+            changed_by: actor,
+            change_reason: change_reason.clone(),
+        });
+        version
+    });
+    
+    WALLET_POLICIES.with(|policies| {
+        policies.borrow_mut().insert(wallet_id.to_string(), policy);
+    });
+    WALLETS.with(|wallets| {
+        if let Some(wallet) = wallets.borrow_mut().get_mut(wallet_id) {
+            wallet.parent_wallet_id = Some(parent_id.to_string());
+        }
+    });
+    PARENT_FUNDING_DRAWN.with(|drawn| {
+        drawn.borrow_mut().remove(wallet_id);
+    });
+    
+    log_audit_action(wallet_id, AuditAction::PolicyUpdated, actor,
+        format!("{} (policy version {})", change_reason, version), None);
+    
+    Ok(format!("Parent wallet set, policy version {}", version))
+}
+
+fn validate_parent_wallet(wallet_id: &str, parent_id: &str) -> Result<(), CustodyError> {
+    if wallet_id == parent_id {
+        return Err(CustodyError::invalid_input("parent_id", "a wallet cannot be its own parent"));
+    }
+    
+    let parent = WALLETS.with(|wallets| wallets.borrow().get(parent_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Wallet", parent_id))?;
+    
+    if WALLETS.with(|wallets| !wallets.borrow().contains_key(wallet_id)) {
+        return Err(CustodyError::not_found("Wallet", wallet_id));
+    }
+    
+    // Walk up from the new parent so the hierarchy can't loop back
+    let mut ancestor = parent.parent_wallet_id.clone();
+    while let Some(id) = ancestor {
+        if id == wallet_id {
+            return Err(CustodyError::invalid_input("parent_id", "would create a wallet hierarchy cycle"));
+        }
+        ancestor = WALLETS.with(|wallets| wallets.borrow().get(&id).and_then(|w| w.parent_wallet_id.clone()));
+    }
+    
+    Ok(())
+}
+
+fn parent_funding_limit(wallet_id: &str) -> u64 {
+    WALLET_POLICIES.with(|policies| {
+        policies.borrow().get(wallet_id).map_or(0, |policy| policy.parent_funding_limit)
+    })
+}
+
+/// How much of a native shortfall the parent can cover right now: its
+/// balance, capped by what is left of the funding limit
+fn parent_funding_available(wallet: &MultisigWallet) -> u64 {
+    let parent = wallet.parent_wallet_id.as_ref()
+        .and_then(|parent_id| WALLETS.with(|wallets| wallets.borrow().get(parent_id).cloned()));
+    
+    let parent = match parent {
+        Some(parent) if parent.status == WalletStatus::Active => parent,
+        _ => return 0,
+    };
+    
+    let drawn = PARENT_FUNDING_DRAWN.with(|drawn| drawn.borrow().get(&wallet.id).copied().unwrap_or(0));
+    parent.balance.min(parent_funding_limit(&wallet.id).saturating_sub(drawn))
+}
+
+// Callers check parent_funding_available first
+fn fund_from_parent(wallet: &MultisigWallet, amount: u64, transaction_id: &str) {
+    let parent_id = match &wallet.parent_wallet_id {
+        Some(parent_id) => parent_id.clone(),
+        None => return,
+    };
+    
+    WALLETS.with(|wallets| {
+        let mut wallets_map = wallets.borrow_mut();
+        if let Some(parent) = wallets_map.get_mut(&parent_id) {
+            parent.balance -= amount;
+        }
+        if let Some(sub_wallet) = wallets_map.get_mut(&wallet.id) {
+            sub_wallet.balance += amount;
+        }
+    });
+    PARENT_FUNDING_DRAWN.with(|drawn| {
+        *drawn.borrow_mut().entry(wallet.id.clone()).or_insert(0) += amount;
+    });
+    
+    let details = format!("Transferred {} satoshis from {} to sub-wallet {} for transaction {}",
+        amount, parent_id, wallet.id, transaction_id);
+    log_audit_action(&parent_id, AuditAction::InternalFunding, ic_cdk::id(), details.clone(),
+        Some(transaction_id.to_string()));
+    log_audit_action(&wallet.id, AuditAction::InternalFunding, ic_cdk::id(), details,
+        Some(transaction_id.to_string()));
+}
+
+// === Asset Functions ===
+
+/// Records an inbound token transfer to the wallet. Owners call this once the
+/// transfer has settled on the token's ledger; native BTC is not an asset_id.
+#[update]
+fn deposit_asset(wallet_id: String, asset_id: String, amount: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "deposit_asset")?;
+    
+    let asset_id = normalize_asset_id(asset_id)?;
+    if amount == 0 {
+        return Err(CustodyError::invalid_input("amount", "must be greater than zero"));
+    }
+    
+    let new_balance = WALLETS.with(|wallets| {
+        let mut wallets_map = wallets.borrow_mut();
+        let wallet = wallets_map.get_mut(&wallet_id)
+            .ok_or_else(|| CustodyError::not_found("Wallet", wallet_id.clone()))?;
+        
+        if !wallet.owners.contains(&caller) {
+            return Err(CustodyError::unauthorized("deposit_asset"));
+        }
+        
+        let balance = wallet.asset_balances.entry(asset_id.clone()).or_insert(0);
+        *balance = balance.checked_add(amount)
+            .ok_or_else(|| CustodyError::invalid_input("amount", "balance would overflow"))?;
+        Ok(*balance)
+    })?;
+    
+    log_audit_action(&wallet_id, AuditAction::AssetDeposited, caller,
+        format!("Deposited {} {}", amount, asset_id), None);
+    ic_cdk::spawn(process_execution_queue());
+    
+    Ok(format!("Deposited {} {}, balance {}", amount, asset_id, new_balance))
+}
+
+fn normalize_asset_id(asset_id: String) -> Result<String, CustodyError> {
+    let asset_id = asset_id.trim().to_string();
+    if asset_id.is_empty() {
+        return Err(CustodyError::invalid_input("asset_id", "must not be empty"));
+    }
+    Ok(asset_id)
+}
+
+fn available_balance(wallet: &MultisigWallet, asset_id: Option<&str>) -> u64 {
+    match asset_id {
+        None => wallet.balance,
+        Some(asset) => wallet.asset_balances.get(asset).copied().unwrap_or(0),
+    }
+}
+
+// === Offline Signature Functions ===
+
+/// Registers the key the caller signs offline confirmations with: a 32-byte
+/// Ed25519 key or a compressed or uncompressed secp256k1 key, hex-encoded
+#[update]
+fn register_owner_key(public_key_hex: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "register_owner_key")?;
+    
+    let owns_wallet = WALLETS.with(|wallets| {
+        wallets.borrow().values().any(|wallet| wallet.owners.contains(&caller))
+    });
+    if !owns_wallet {
+        return Err(CustodyError::unauthorized("register_owner_key"));
+    }
+    
+    let public_key_hex = public_key_hex.trim().to_lowercase();
+    parse_owner_key(&public_key_hex)?;
+    
+    if owner_for_key(&public_key_hex).is_some_and(|owner| owner != caller) {
+        return Err(CustodyError::status_conflict("registered to another owner", "unregistered"));
+    }
+    
+    OWNER_PUBLIC_KEYS.with(|keys| {
+        keys.borrow_mut().insert(caller, public_key_hex);
+    });
+    
+    Ok("Owner key registered successfully".to_string())
+}
+
+/// Confirms a transaction on behalf of the owner whose registered key produced
+/// `signature_hex`. `message_hash` must be the transaction's confirmation hash
+/// from `get_confirmation_message_hash`, so a signature can't be replayed
+/// against another transaction. Secp256k1 signatures are 64-byte compact or
+/// DER encoded ECDSA over the hash; Ed25519 signatures sign the hash bytes.
+#[update]
+fn confirm_with_signature(
+    transaction_id: String,
+    message_hash: String,
+    signature_hex: String,
+    public_key_hex: String,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "confirm_with_signature")?;
+    
+    let public_key_hex = public_key_hex.trim().to_lowercase();
+    let owner = owner_for_key(&public_key_hex)
+        .ok_or_else(|| CustodyError::not_found("Owner key", public_key_hex.clone()))?;
+    
+    let transaction = TRANSACTIONS.with(|txns| txns.borrow().get(&transaction_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Transaction", transaction_id.clone()))?;
+    
+    let expected_hash = confirmation_message_hash(&transaction);
+    if !message_hash.trim().eq_ignore_ascii_case(&expected_hash) {
+        return Err(CustodyError::invalid_input("message_hash", "does not match the transaction's confirmation hash"));
+    }
+    
+    let digest = hex::decode(&expected_hash).map_err(|e| CustodyError::invalid_input("message_hash", e))?;
+    let signature = hex::decode(signature_hex.trim()).map_err(|e| CustodyError::invalid_input("signature_hex", e))?;
+    
+    if !parse_owner_key(&public_key_hex)?.verify(&digest, &signature) {
+        return Err(CustodyError::invalid_input("signature_hex", "signature verification failed"));
+    }
+    
+    let details = format!("Confirmed transaction {} with an offline signature", transaction_id);
+    confirm_transaction_as(transaction_id, owner, "confirm_with_signature", details)
+}
+
+#[query]
+fn get_owner_key(owner: Principal) -> Option<String> {
+    OWNER_PUBLIC_KEYS.with(|keys| keys.borrow().get(&owner).cloned())
+}
+
+/// The hash owners sign offline to confirm the transaction
+#[query]
+fn get_confirmation_message_hash(transaction_id: String) -> Option<String> {
+    TRANSACTIONS.with(|txns| txns.borrow().get(&transaction_id).map(confirmation_message_hash))
+}
+
+enum OwnerKey {
+    Ed25519(ed25519_dalek::VerifyingKey),
+    Secp256k1(secp256k1::PublicKey),
+}
+
+impl OwnerKey {
+    fn verify(&self, digest: &[u8], signature: &[u8]) -> bool {
+        match self {
+            OwnerKey::Ed25519(key) => {
+                ed25519_dalek::Signature::from_slice(signature)
+                    .is_ok_and(|signature| key.verify_strict(digest, &signature).is_ok())
+            },
+            OwnerKey::Secp256k1(key) => {
+                let message = match secp256k1::Message::from_digest_slice(digest) {
+                    Ok(message) => message,
+                    Err(_) => return false,
+                };
+                let signature = secp256k1::ecdsa::Signature::from_compact(signature)
+                    .or_else(|_| secp256k1::ecdsa::Signature::from_der(signature));
+                match signature {
+                    Ok(mut signature) => {
+                        signature.normalize_s();
+                        secp256k1::SECP256K1.verify_ecdsa(&message, &signature, key).is_ok()
+                    },
+                    Err(_) => false,
+                }
+            },
+        }
+    }
+}
+
+fn parse_owner_key(public_key_hex: &str) -> Result<OwnerKey, CustodyError> {
+    let bytes = hex::decode(public_key_hex).map_err(|e| CustodyError::invalid_input("public_key_hex", e))?;
+    
+    let key = match bytes.len() {
+        32 => {
+            let bytes: [u8; 32] = bytes.as_slice().try_into().expect("length checked");
+            ed25519_dalek::VerifyingKey::from_bytes(&bytes).ok().map(OwnerKey::Ed25519)
+        },
+        33 | 65 => secp256k1::PublicKey::from_slice(&bytes).ok().map(OwnerKey::Secp256k1),
+        _ => None,
+    };
+    
+    key.ok_or_else(|| CustodyError::invalid_input("public_key_hex", "not an Ed25519 or secp256k1 public key"))
+}
+
+fn owner_for_key(public_key_hex: &str) -> Option<Principal> {
+    OWNER_PUBLIC_KEYS.with(|keys| {
+        keys.borrow()
+            .iter()
+            .find(|(_, key)| key.as_str() == public_key_hex)
+            .map(|(owner, _)| *owner)
+    })
+}
+
+// Binds the signature to this canister and to every field an owner approves
+fn confirmation_message_hash(transaction: &MultisigTransaction) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(format!(
+        "multisig_wallet:confirm:{}:{}:{}:{}:{}:{}:",
+        ic_cdk::id(),
+        transaction.id,
+        transaction.wallet_id,
+        transaction.to,
+        transaction.amount,
+        transaction.asset_id.as_deref().unwrap_or("BTC"),
+    ));
+    hasher.update(&transaction.data);
+    format!("{:x}", hasher.finalize())
+}
+
+// === Fee Functions ===
+
+#[query]
+fn estimate_transaction_fee(
+    wallet_id: String,
+    to: String,
+    amount: u64,
+    priority: TransactionPriority,
+) -> Result<FeeEstimate, CustodyError> {
+    let caller = ic_cdk::caller();
+    
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(&wallet_id).cloned());
+    
+    let wallet = match wallet {
+        Some(w) => w,
+        None => return Err(CustodyError::not_found("Wallet", wallet_id)),
+    };
+    
+    if !wallet.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("estimate_transaction_fee"));
+    }
+    
+    if to.is_empty() {
+        return Err(CustodyError::invalid_input("to", "destination is required"));
+    }
+    
+    if amount == 0 {
+        return Err(CustodyError::invalid_input("amount", "must be greater than zero"));
+    }
+    
+    Ok(calculate_fee_estimate(amount, &priority))
+}
+
+// === Emergency Functions ===
+
+#[update]
+async fn emergency_freeze_wallet(wallet_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "emergency_freeze_wallet")?;
+    
+    // Check if caller is emergency contact
+    let is_emergency_contact = check_emergency_contact(caller).await;
+    
+    let is_wallet_owner = WALLETS.with(|wallets| {
+        if let Some(wallet) = wallets.borrow().get(&wallet_id) {
+            wallet.owners.contains(&caller)
+        } else {
+            false
+        }
+    });
+    
+    if !is_emergency_contact && !is_wallet_owner {
+        return Err(CustodyError::unauthorized("emergency action"));
+    }
+    
+    if freeze_wallet_internal(&wallet_id, caller, "Emergency freeze activated".to_string()) {
+        Ok("Wallet frozen successfully".to_string())
+    } else {
+        Err(CustodyError::not_found("Wallet", wallet_id))
+    }
+}
+
+// Freezes without an authorization check; returns false if the wallet doesn't exist
+fn freeze_wallet_internal(wallet_id: &str, actor: Principal, details: String) -> bool {
+    let frozen = WALLETS.with(|wallets| {
+        match wallets.borrow_mut().get_mut(wallet_id) {
+            Some(wallet) => {
+                wallet.status = WalletStatus::Frozen;
+                true
+            },
+            None => false,
+        }
+    });
+    
+    if frozen {
+        log_audit_action(wallet_id, AuditAction::WalletFrozen, actor, details, None);
+    }
+    
+    frozen
+}
+
+#[update]
+async fn set_audit_trail_canister(canister_id: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_audit_trail_canister")?;
+    
+    if !check_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("set_audit_trail_canister"));
+    }
+    
+    AUDIT_TRAIL_CANISTER.with(|c| {
+        *c.borrow_mut() = Some(canister_id);
+    });
+    
+    Ok("Audit trail canister set successfully".to_string())
+}
+
+#[update]
+async fn emergency_unfreeze_wallet(wallet_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "emergency_unfreeze_wallet")?;
+    
+    // Check if caller is emergency contact
+    let is_emergency_contact = check_emergency_contact(caller).await;
+    
+    if !is_emergency_contact {
+        return Err(CustodyError::unauthorized("emergency_unfreeze_wallet"));
+    }
+    
+    WALLETS.with(|wallets| {
+        let mut wallets_map = wallets.borrow_mut();
+        match wallets_map.get_mut(&wallet_id) {
+            Some(wallet) => {
+                wallet.status = WalletStatus::Active;
+                
+                // Log emergency action
+                log_audit_action(&wallet_id, AuditAction::WalletUnfrozen, caller, 
+                    "Emergency unfreeze activated".to_string(), None);
+                
+                Ok(())
+            },
+            None => Err(CustodyError::not_found("Wallet", wallet_id.clone())),
+        }
+    })?;
+    
+    ic_cdk::spawn(process_execution_queue());
+    Ok("Wallet unfrozen successfully".to_string())
+}
+
+#[update]
+async fn global_emergency_freeze() -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "global_emergency_freeze")?;
+    
+    // Check if caller is emergency contact
+    let is_emergency_contact = check_emergency_contact(caller).await;
+    
+    if !is_emergency_contact {
+        return Err(CustodyError::unauthorized("emergency action"));
+    }
+    
+    GLOBAL_FROZEN.with(|frozen| {
+        *frozen.borrow_mut() = true;
+    });
+    
+    // Freeze every active wallet individually and remember which ones we
+    // touched so the unfreeze does not reactivate wallets frozen beforehand
+    let frozen_wallets: Vec<String> = WALLETS.with(|wallets| {
+        wallets.borrow_mut()
+            .values_mut()
+            .filter(|wallet| matches!(wallet.status, WalletStatus::Active))
+            .map(|wallet| {
+                wallet.status = WalletStatus::Frozen;
+                wallet.id.clone()
+            })
+            .collect()
+    });
+    
+    for wallet_id in &frozen_wallets {
+        log_audit_action(wallet_id, AuditAction::WalletFrozen, caller, 
+            "Global emergency freeze activated".to_string(), None);
+    }
+    
+    GLOBAL_FREEZE_SNAPSHOT.with(|snapshot| {
+        snapshot.borrow_mut().extend(frozen_wallets.iter().cloned());
+    });
+    
+    Ok(format!("Global emergency freeze activated, {} wallets frozen", frozen_wallets.len()))
+}
+
+#[update]
+async fn global_emergency_unfreeze() -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "global_emergency_unfreeze")?;
+    
+    // Check if caller is emergency contact
+    let is_emergency_contact = check_emergency_contact(caller).await;
+    
+    if !is_emergency_contact {
+        return Err(CustodyError::unauthorized("global_emergency_unfreeze"));
+    }
+    
+    let snapshot = GLOBAL_FREEZE_SNAPSHOT.with(|snapshot| {
+        std::mem::take(&mut *snapshot.borrow_mut())
+    });
+    
+    let mut restored = 0;
+    WALLETS.with(|wallets| {
+        let mut wallets_map = wallets.borrow_mut();
+        for wallet_id in &snapshot {
+            if let Some(wallet) = wallets_map.get_mut(wallet_id) {
+                // Leave wallets that were re-flagged during the freeze alone
+                if matches!(wallet.status, WalletStatus::Frozen) {
+                    wallet.status = WalletStatus::Active;
+                    restored += 1;
+                    log_audit_action(wallet_id, AuditAction::WalletUnfrozen, caller, 
+                        "Global emergency freeze lifted".to_string(), None);
+                }
+            }
+        }
+    });
+    
+    GLOBAL_FROZEN.with(|frozen| {
+        *frozen.borrow_mut() = false;
+    });
+    
+    Ok(format!("Global emergency freeze lifted, {} wallets restored", restored))
+}
+
+// === Guardian Recovery Functions ===
+
+#[update]
+fn add_wallet_guardian(wallet_id: String, guardian: Principal, delay_seconds: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_wallet_guardian")?;
+    
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(&wallet_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Wallet", wallet_id.clone()))?;
+    
+    if !wallet.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("add_wallet_guardian"));
+    }
+    
+    if delay_seconds == 0 {
+        return Err(CustodyError::invalid_input("delay_seconds", "must be greater than zero"));
+    }
+    
+    // Guardians can take over the wallet, so adding one needs the threshold
+    propose_wallet_change(&wallet, WalletChange::AddGuardian { guardian, delay_seconds }, caller)
+}
+
+#[update]
+fn remove_wallet_guardian(wallet_id: String, guardian: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "remove_wallet_guardian")?;
+    
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(&wallet_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Wallet", wallet_id.clone()))?;
+    
+    if !wallet.owners.contains(&caller) {
+        return Err(CustodyError::unauthorized("remove_wallet_guardian"));
+    }
+    
+    propose_wallet_change(&wallet, WalletChange::RemoveGuardian { guardian }, caller)
+}
+
+// Applies a confirmed AddGuardian change
+fn add_guardian_internal(
+    wallet_id: &str,
+    guardian: Principal,
+    delay_seconds: u64,
+) -> Result<String, CustodyError> {
+    WALLET_GUARDIANS.with(|guardians| {
+        guardians.borrow_mut().entry(wallet_id.to_string()).or_default().push(GuardianRecord {
+            guardian,
+            added_at: ic_cdk::api::time(),
+            activation_delay_seconds: delay_seconds,
+        });
+    });
+    
+    Ok("Guardian added successfully".to_string())
+}
+
+// Applies a confirmed RemoveGuardian change
+fn remove_guardian_internal(wallet_id: &str, guardian: Principal) -> Result<String, CustodyError> {
+    WALLET_GUARDIANS.with(|guardians| {
+        if let Some(records) = guardians.borrow_mut().get_mut(wallet_id) {
+            records.retain(|record| record.guardian != guardian);
+        }
+    });
+    
+    Ok("Guardian removed successfully".to_string())
+}
+
+/// Lets a guardian propose new owners once the current owners have been
+/// inactive for the guardian's activation delay
+#[update]
+fn initiate_guardian_recovery(
+    wallet_id: String,
+    new_owners: Vec<Principal>,
+    new_threshold: u8,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "initiate_guardian_recovery")?;
+    
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(&wallet_id).cloned());
+    
+    let wallet = match wallet {
+        Some(w) => w,
+        None => return Err(CustodyError::not_found("Wallet", wallet_id)),
+    };
+    
+    let record = wallet_guardians(&wallet_id)
+        .into_iter()
+        .find(|record| record.guardian == caller);
+    
+    let record = match record {
+        Some(record) => record,
+        None => return Err(CustodyError::unauthorized("initiate_guardian_recovery")),
+    };
+    
+    let now = ic_cdk::api::time();
+    let inactive_nanos = now.saturating_sub(last_owner_activity(&wallet));
+    let required_nanos = record.activation_delay_seconds.saturating_mul(1_000_000_000);
+    
+    if inactive_nanos < required_nanos {
+        return Err(CustodyError::status_conflict(
+            format!("owners active {} seconds ago", inactive_nanos / 1_000_000_000),
+            format!("owners inactive for {} seconds", record.activation_delay_seconds),
+        ));
+    }
+    
+    if new_owners.is_empty() || new_owners.len() > 20 {
+        return Err(CustodyError::invalid_input("new_owners", "must have 1-20 owners"));
+    }
+    
+    let new_owners: BTreeSet<Principal> = new_owners.into_iter().collect();
+    
+    if new_threshold == 0 || new_threshold as usize > new_owners.len() {
+        return Err(CustodyError::invalid_input("new_threshold", "must be between 1 and the number of owners"));
+    }
+    
+    let has_open_recovery = RECOVERY_PROPOSALS.with(|proposals| {
+        proposals.borrow()
+            .values()
+            .any(|p| p.wallet_id == wallet_id && !p.executed && !p.cancelled)
+    });
+    
+    if has_open_recovery {
+        return Err(CustodyError::status_conflict("recovery in progress", "no open recovery"));
+    }
+    
+    let proposal_id = Uuid::new_v4().to_string();
+    let mut proposal = RecoveryProposal {
+        id: proposal_id.clone(),
+        wallet_id: wallet_id.clone(),
+        new_owners,
+        new_threshold,
+        initiated_by: caller,
+        confirmations: BTreeSet::from([caller]),
+        created_at: now,
+        executable_at: None,
+        executed: false,
+        cancelled: false,
+    };
+    start_recovery_timelock(&mut proposal, now);
+    
+    RECOVERY_PROPOSALS.with(|proposals| {
+        proposals.borrow_mut().insert(proposal_id.clone(), proposal);
+    });
+    
+    log_audit_action(&wallet_id, AuditAction::RecoveryInitiated, caller,
+        format!("Guardian recovery {} initiated", proposal_id), None);
+    
+    Ok(proposal_id)
+}
+
+#[update]
+fn confirm_guardian_recovery(proposal_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "confirm_guardian_recovery")?;
+    
+    let now = ic_cdk::api::time();
+    
+    let proposal = RECOVERY_PROPOSALS.with(|proposals| {
+        let mut proposals = proposals.borrow_mut();
+        let proposal = match proposals.get_mut(&proposal_id) {
+            Some(p) => p,
+            None => return Err(CustodyError::not_found("Recovery proposal", proposal_id.clone())),
+        };
+        
+        if proposal.executed || proposal.cancelled {
+            return Err(CustodyError::status_conflict("closed", "open"));
+        }
+        
+        if !wallet_guardians(&proposal.wallet_id).iter().any(|record| record.guardian == caller) {
+            return Err(CustodyError::unauthorized("confirm_guardian_recovery"));
+        }
+        
+        if !proposal.confirmations.insert(caller) {
+            return Err(CustodyError::invalid_input("proposal_id", "already confirmed by caller"));
+        }
+        
+        start_recovery_timelock(proposal, now);
+        Ok(proposal.clone())
+    })?;
+    
+    log_audit_action(&proposal.wallet_id, AuditAction::RecoveryConfirmed, caller,
+        format!("Guardian recovery {} confirmed", proposal_id), None);
+    
+    match proposal.executable_at {
+        Some(executable_at) => Ok(format!("All guardians confirmed, executable at {}", executable_at)),
+        None => Ok("Recovery confirmed, awaiting remaining guardians".to_string()),
+    }
+}
+
+/// Any owner who still has access can stop a recovery
+#[update]
+fn cancel_guardian_recovery(proposal_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "cancel_guardian_recovery")?;
+    
+    let wallet_id = RECOVERY_PROPOSALS.with(|proposals| {
+        let mut proposals = proposals.borrow_mut();
+        let proposal = match proposals.get_mut(&proposal_id) {
+            Some(p) => p,
+            None => return Err(CustodyError::not_found("Recovery proposal", proposal_id.clone())),
+        };
+        
+        if proposal.executed || proposal.cancelled {
+            return Err(CustodyError::status_conflict("closed", "open"));
+        }
+        
+        let is_owner = WALLETS.with(|wallets| {
+            wallets.borrow()
+                .get(&proposal.wallet_id)
+                .is_some_and(|wallet| wallet.owners.contains(&caller))
+        });
+        
+        if !is_owner {
+            return Err(CustodyError::unauthorized("cancel_guardian_recovery"));
+        }
+        
+        proposal.cancelled = true;
+        Ok(proposal.wallet_id.clone())
+    })?;
+    
+    log_audit_action(&wallet_id, AuditAction::RecoveryCancelled, caller,
+        format!("Guardian recovery {} cancelled by owner", proposal_id), None);
+    
+    Ok("Recovery cancelled".to_string())
+}
+
+#[update]
+fn execute_guardian_recovery(proposal_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "execute_guardian_recovery")?;
+    
+    let proposal = RECOVERY_PROPOSALS.with(|proposals| proposals.borrow().get(&proposal_id).cloned());
+    
+    let mut proposal = match proposal {
+        Some(p) => p,
+        None => return Err(CustodyError::not_found("Recovery proposal", proposal_id)),
+    };
+    
+    if !wallet_guardians(&proposal.wallet_id).iter().any(|record| record.guardian == caller) {
+        return Err(CustodyError::unauthorized("execute_guardian_recovery"));
+    }
+    
+    if proposal.executed || proposal.cancelled {
+        return Err(CustodyError::status_conflict("closed", "open"));
+    }
+    
+    // Guardians may have changed since the timelock started
+    let now = ic_cdk::api::time();
+    start_recovery_timelock(&mut proposal, now);
+    
+    match proposal.executable_at {
+        None => return Err(CustodyError::status_conflict("awaiting guardian confirmations", "confirmed by all guardians")),
+        Some(executable_at) if executable_at > now => {
+            return Err(CustodyError::status_conflict(
+                format!("timelocked until {}", executable_at),
+                "timelock elapsed",
+            ));
+        },
+        Some(_) => {},
+    }
+    
+    WALLETS.with(|wallets| {
+        match wallets.borrow_mut().get_mut(&proposal.wallet_id) {
+            Some(wallet) => {
+                wallet.owners = proposal.new_owners.clone();
+                wallet.threshold = proposal.new_threshold;
+                Ok(())
+            },
+            None => Err(CustodyError::not_found("Wallet", proposal.wallet_id.clone())),
+        }
+    })?;
+    
+    RECOVERY_PROPOSALS.with(|proposals| {
+        if let Some(p) = proposals.borrow_mut().get_mut(&proposal_id) {
+            p.executed = true;
+        }
+    });
+    
+    log_audit_action(&proposal.wallet_id, AuditAction::RecoveryExecuted, caller,
+        format!("Guardian recovery {} replaced owners with {} owner(s), threshold {}",
+            proposal_id, proposal.new_owners.len(), proposal.new_threshold), None);
+    
+    Ok("Recovery executed".to_string())
+}
+
+// === Query Functions ===
+
+#[query]
+fn get_wallet(wallet_id: String) -> Option<MultisigWallet> {
+    WALLETS.with(|wallets| {
+        wallets.borrow().get(&wallet_id).cloned()
+    })
+}
+
+#[query]
+fn get_user_wallets(user: Principal) -> Vec<MultisigWallet> {
+    WALLETS.with(|wallets| {
+        wallets.borrow()
+            .values()
+            .filter(|wallet| wallet.owners.contains(&user))
+            .cloned()
+            .collect()
+    })
+}
+
+#[query]
+fn get_transaction(transaction_id: String) -> Option<MultisigTransaction> {
+    TRANSACTIONS.with(|txns| {
+        txns.borrow().get(&transaction_id).cloned()
+    })
+}
+
+#[query]
+fn get_wallet_transactions(wallet_id: String) -> Vec<MultisigTransaction> {
+    TRANSACTIONS.with(|txns| {
+        txns.borrow()
+            .values()
+            .filter(|txn| txn.wallet_id == wallet_id)
+            .cloned()
+            .collect()
+    })
+}
+
+#[query]
+fn get_pending_transactions(wallet_id: String) -> Vec<MultisigTransaction> {
+    TRANSACTIONS.with(|txns| {
+        txns.borrow()
+            .values()
+            .filter(|txn| txn.wallet_id == wallet_id && !txn.executed && !txn.rejected)
+            .cloned()
+            .collect()
+    })
+}
+
+/// Transactions waiting to execute, in the order they will be executed
+#[query]
+fn get_execution_queue(wallet_id: String) -> Vec<MultisigTransaction> {
+    let transaction_ids: Vec<String> = EXECUTION_QUEUE.with(|queue| {
+        queue.borrow()
+            .iter()
+            .filter(|(_, queued_wallet)| **queued_wallet == wallet_id)
+            .map(|(key, _)| key.2.clone())
+            .collect()
+    });
+    
+    let now = ic_cdk::api::time();
+    TRANSACTIONS.with(|txns| {
+        let txns = txns.borrow();
+        transaction_ids
+            .iter()
+            .filter_map(|id| txns.get(id))
+            .filter(|txn| !txn.executed && !txn.rejected && !is_expired(txn, now))
+            .cloned()
+            .collect()
+    })
+}
+
+#[query]
+fn get_wallet_policy(wallet_id: String) -> Option<WalletPolicy> {
+    WALLET_POLICIES.with(|policies| {
+        policies.borrow().get(&wallet_id).cloned()
+    })
+}
+
+#[query]
+fn get_owner_change_proposal(proposal_id: String) -> Option<OwnerChangeProposal> {
+    OWNER_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow().get(&proposal_id).cloned()
+    })
+}
+
+#[query]
+fn get_type_change_proposal(proposal_id: String) -> Option<TypeChangeProposal> {
+    TYPE_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow().get(&proposal_id).cloned()
+    })
+}
+
+#[query]
+fn get_pending_owner_changes(wallet_id: String) -> Vec<OwnerChangeProposal> {
+    OWNER_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow()
+            .values()
+            .filter(|p| p.wallet_id == wallet_id && !p.executed)
+            .cloned()
             .collect()
     })
 }
 
 #[query]
-fn get_wallet_policy(wallet_id: String) -> Option<WalletPolicy> {
-    WALLET_POLICIES.with(|policies| {
-        policies.borrow().get(&wallet_id).cloned()
+fn get_wallet_change_proposal(proposal_id: String) -> Option<WalletChangeProposal> {
+    WALLET_CHANGE_PROPOSALS.with(|proposals| proposals.borrow().get(&proposal_id).cloned())
+}
+
+#[query]
+fn get_pending_wallet_changes(wallet_id: String) -> Vec<WalletChangeProposal> {
+    WALLET_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow()
+            .values()
+            .filter(|p| p.wallet_id == wallet_id && !p.executed)
+            .cloned()
+            .collect()
+    })
+}
+
+#[query]
+fn get_wallet_guardians(wallet_id: String) -> Vec<GuardianRecord> {
+    wallet_guardians(&wallet_id)
+}
+
+#[query]
+fn get_recovery_proposal(proposal_id: String) -> Option<RecoveryProposal> {
+    RECOVERY_PROPOSALS.with(|proposals| {
+        proposals.borrow().get(&proposal_id).cloned()
+    })
+}
+
+#[query]
+fn get_policy_history(wallet_id: String) -> Vec<PolicyVersion> {
+    WALLET_POLICY_HISTORY.with(|history| {
+        history.borrow().get(&wallet_id).cloned().unwrap_or_default()
+    })
+}
+
+#[query]
+fn get_policy_at_time(wallet_id: String, timestamp: u64) -> Option<WalletPolicy> {
+    WALLET_POLICY_HISTORY.with(|history| {
+        history.borrow()
+            .get(&wallet_id)?
+            .iter()
+            .rev()
+            .find(|version| version.changed_at <= timestamp)
+            .map(|version| version.policy.clone())
     })
 }
 
@@ -768,8 +2743,86 @@ fn get_audit_logs(wallet_id: String) -> Vec<WalletAuditLog> {
     })
 }
 
+#[query]
+fn get_wallet_health(wallet_id: String) -> Result<WalletHealth, CustodyError> {
+    let caller = ic_cdk::caller();
+    
+    let wallet = WALLETS.with(|wallets| wallets.borrow().get(&wallet_id).cloned());
+    
+    let wallet = match wallet {
+        Some(wallet) => wallet,
+        None => return Err(CustodyError::not_found("Wallet", wallet_id)),
+    };
+    
+    if !wallet.owners.contains(&caller) && !is_cached_emergency_contact(&caller) {
+        return Err(CustodyError::unauthorized("get_wallet_health"));
+    }
+    
+    Ok(wallet_health(&wallet, ic_cdk::api::time()))
+}
+
+/// Emergency contacts see every wallet, owners only the wallets they own
+#[query]
+fn get_system_wallet_summary() -> Vec<WalletHealth> {
+    let caller = ic_cdk::caller();
+    let sees_all = is_cached_emergency_contact(&caller);
+    let now = ic_cdk::api::time();
+    
+    WALLETS.with(|wallets| {
+        wallets.borrow()
+            .values()
+            .filter(|wallet| sees_all || wallet.owners.contains(&caller))
+            .map(|wallet| wallet_health(wallet, now))
+            .collect()
+    })
+}
+
 // === Helper Functions ===
 
+fn wallet_health(wallet: &MultisigWallet, now: u64) -> WalletHealth {
+    let mut pending_transaction_count = 0u32;
+    let mut oldest_pending_at: Option<u64> = None;
+    let mut last_transaction_at: Option<u64> = None;
+    
+    TRANSACTIONS.with(|txns| {
+        for txn in txns.borrow().values().filter(|txn| txn.wallet_id == wallet.id) {
+            if !txn.executed && !txn.rejected {
+                pending_transaction_count += 1;
+                oldest_pending_at = Some(oldest_pending_at.map_or(txn.created_at, |at| at.min(txn.created_at)));
+            }
+            let activity_at = txn.executed_at.unwrap_or(txn.created_at);
+            last_transaction_at = Some(last_transaction_at.map_or(activity_at, |at| at.max(activity_at)));
+        }
+    });
+    
+    // daily_spent is only reset on the next submission, so a stale day counts as unused
+    let current_day = now / (24 * 60 * 60 * 1_000_000_000);
+    let daily_spent = if wallet.last_reset_day < current_day { 0 } else { wallet.daily_spent };
+    let daily_utilization_percent = if wallet.daily_limit == 0 {
+        0
+    } else {
+        (daily_spent as u128 * 100 / wallet.daily_limit as u128).min(100) as u8
+    };
+    
+    let audit_log_count = AUDIT_LOGS.with(|logs| {
+        logs.borrow().values().filter(|log| log.wallet_id == wallet.id).count() as u64
+    });
+    
+    WalletHealth {
+        wallet_id: wallet.id.clone(),
+        status: wallet.status.clone(),
+        owner_count: wallet.owners.len() as u32,
+        threshold: wallet.threshold as u32,
+        pending_transaction_count,
+        oldest_pending_age_seconds: oldest_pending_at.map(|at| now.saturating_sub(at) / 1_000_000_000),
+        daily_utilization_percent,
+        balance: wallet.balance,
+        daily_limit: wallet.daily_limit,
+        last_transaction_at,
+        audit_log_count,
+    }
+}
+
 fn log_audit_action(
     wallet_id: &str,
     action: AuditAction,
@@ -793,10 +2846,509 @@ fn log_audit_action(
     });
 }
 
+fn validate_wallet_change(wallet_id: &str, change: &WalletChange) -> Result<(), CustodyError> {
+    match change {
+        WalletChange::FundSubWallet { sub_wallet_id, .. } => validate_parent_wallet(sub_wallet_id, wallet_id),
+        WalletChange::UpdatePolicy { .. } => Ok(()),
+        WalletChange::AddGuardian { guardian, .. } => {
+            let is_owner = WALLETS.with(|wallets| {
+                wallets.borrow().get(wallet_id).map(|w| w.owners.contains(guardian)).unwrap_or(false)
+            });
+            if is_owner {
+                return Err(CustodyError::invalid_input("guardian", "owners cannot be guardians"));
+            }
+            if is_guardian(wallet_id, guardian) {
+                return Err(CustodyError::invalid_input("guardian", "already a guardian of this wallet"));
+            }
+            Ok(())
+        },
+        WalletChange::RemoveGuardian { guardian } => {
+            if !is_guardian(wallet_id, guardian) {
+                return Err(CustodyError::not_found("Guardian", guardian.to_string()));
+            }
+            Ok(())
+        },
+    }
+}
+
+fn is_guardian(wallet_id: &str, guardian: &Principal) -> bool {
+    WALLET_GUARDIANS.with(|guardians| {
+        guardians.borrow()
+            .get(wallet_id)
+            .map(|records| records.iter().any(|record| record.guardian == *guardian))
+            .unwrap_or(false)
+    })
+}
+
+fn wallet_change_audit_action(change: &WalletChange) -> AuditAction {
+    match change {
+        WalletChange::FundSubWallet { .. } | WalletChange::UpdatePolicy { .. } => AuditAction::PolicyUpdated,
+        WalletChange::AddGuardian { .. } => AuditAction::GuardianAdded,
+        WalletChange::RemoveGuardian { .. } => AuditAction::GuardianRemoved,
+    }
+}
+
+fn describe_wallet_change(change: &WalletChange) -> String {
+    match change {
+        WalletChange::FundSubWallet { sub_wallet_id, funding_limit } => {
+            format!("funding of up to {} for sub-wallet {}", funding_limit, sub_wallet_id)
+        },
+        WalletChange::UpdatePolicy { reason, .. } => format!("policy update ({})", reason),
+        WalletChange::AddGuardian { guardian, delay_seconds } => {
+            format!("guardian {} with {} second activation delay", guardian, delay_seconds)
+        },
+        WalletChange::RemoveGuardian { guardian } => format!("removal of guardian {}", guardian),
+    }
+}
+
+/// Applies a confirmed wallet change, re-validating it since the wallets may
+/// have changed since it was proposed
+fn apply_wallet_change(proposal: &WalletChangeProposal, actor: Principal) -> Result<String, CustodyError> {
+    let result = match &proposal.change {
+        WalletChange::FundSubWallet { sub_wallet_id, funding_limit } => {
+            set_parent_wallet_internal(sub_wallet_id, &proposal.wallet_id, *funding_limit, actor)?
+        },
+        WalletChange::UpdatePolicy { policy, reason } => {
+            apply_wallet_policy(&proposal.wallet_id, policy.clone(), reason, actor)?
+        },
+        WalletChange::AddGuardian { guardian, delay_seconds } => {
+            validate_wallet_change(&proposal.wallet_id, &proposal.change)?;
+            add_guardian_internal(&proposal.wallet_id, *guardian, *delay_seconds)?
+        },
+        WalletChange::RemoveGuardian { guardian } => {
+            validate_wallet_change(&proposal.wallet_id, &proposal.change)?;
+            remove_guardian_internal(&proposal.wallet_id, *guardian)?
+        },
+    };
+    
+    log_audit_action(&proposal.wallet_id, wallet_change_audit_action(&proposal.change), actor,
+        format!("Executed {} (proposal {})", describe_wallet_change(&proposal.change), proposal.id), None);
+    
+    Ok(result)
+}
+
+fn validate_owner_change(
+    wallet: &MultisigWallet,
+    action: &OwnerChangeAction,
+) -> Result<(), CustodyError> {
+    match action {
+        OwnerChangeAction::Add(target) => {
+            if wallet.owners.len() >= 20 {
+                return Err(CustodyError::LimitExceeded {
+                    limit: 20,
+                    actual: wallet.owners.len() as u64 + 1,
+                });
+            }
+            if wallet.owners.contains(target) {
+                return Err(CustodyError::invalid_input("target", "principal is already an owner"));
+            }
+        },
+        OwnerChangeAction::Remove(target) => {
+            if !wallet.owners.contains(target) {
+                return Err(CustodyError::invalid_input("target", "principal is not an owner"));
+            }
+            if wallet.owners.len() <= wallet.threshold as usize {
+                return Err(CustodyError::invalid_input("target", "cannot remove owner below threshold"));
+            }
+        },
+        OwnerChangeAction::ChangeThreshold(new_threshold) => {
+            if *new_threshold == 0 || *new_threshold as usize > wallet.owners.len() {
+                return Err(CustodyError::invalid_input("threshold", "must be between 1 and the number of owners"));
+            }
+        },
+    }
+    Ok(())
+}
+
+fn owner_change_audit_action(action: &OwnerChangeAction) -> AuditAction {
+    match action {
+        OwnerChangeAction::Add(_) => AuditAction::OwnerAdded,
+        OwnerChangeAction::Remove(_) => AuditAction::OwnerRemoved,
+        OwnerChangeAction::ChangeThreshold(_) => AuditAction::ThresholdChanged,
+    }
+}
+
+fn describe_owner_change(action: &OwnerChangeAction) -> String {
+    match action {
+        OwnerChangeAction::Add(target) => format!("adding owner {}", target),
+        OwnerChangeAction::Remove(target) => format!("removing owner {}", target),
+        OwnerChangeAction::ChangeThreshold(new_threshold) => format!("changing threshold to {}", new_threshold),
+    }
+}
+
+/// Executes a confirmed ownership change proposal
+fn execute_owner_change(proposal_id: &str, actor: Principal) -> Result<String, CustodyError> {
+    let proposal = OWNER_CHANGE_PROPOSALS.with(|proposals| {
+        proposals.borrow().get(proposal_id).cloned()
+    });
+    
+    let proposal = match proposal {
+        Some(p) => p,
+        None => return Err(CustodyError::not_found("Proposal", proposal_id)),
+    };
+    
+    apply_owner_change(&proposal)?;
+    
+    OWNER_CHANGE_PROPOSALS.with(|proposals| {
+        if let Some(p) = proposals.borrow_mut().get_mut(proposal_id) {
+            p.executed = true;
+        }
+    });
+    
+    log_owner_change_executed(&proposal, actor);
+    
+    Ok("Ownership change executed".to_string())
+}
+
+/// Applies an ownership change to its wallet. The wallet state is re-validated
+/// since other proposals may have executed between proposal creation and now.
+fn apply_owner_change(proposal: &OwnerChangeProposal) -> Result<(), CustodyError> {
+    WALLETS.with(|wallets| {
+        let mut wallets_map = wallets.borrow_mut();
+        match wallets_map.get_mut(&proposal.wallet_id) {
+            Some(wallet) => {
+                validate_owner_change(wallet, &proposal.action)?;
+                
+                match proposal.action {
+                    OwnerChangeAction::Add(target) => {
+                        wallet.owners.insert(target);
+                    },
+                    OwnerChangeAction::Remove(target) => {
+                        wallet.owners.remove(&target);
+                    },
+                    OwnerChangeAction::ChangeThreshold(new_threshold) => {
+                        wallet.threshold = new_threshold;
+                    },
+                }
+                Ok(())
+            },
+            None => Err(CustodyError::not_found("Wallet", proposal.wallet_id.clone())),
+        }
+    })
+}
+
+fn log_owner_change_executed(proposal: &OwnerChangeProposal, actor: Principal) {
+    log_audit_action(&proposal.wallet_id, owner_change_audit_action(&proposal.action), actor, 
+        format!("Executed {} (proposal {})", describe_owner_change(&proposal.action), proposal.id), None);
+}
+
+fn check_transaction_policy(policy: &WalletPolicy, to: &str, amount: u64) -> Result<(), CustodyError> {
+    if amount > policy.max_single_transaction {
+        return Err(CustodyError::LimitExceeded {
+            limit: policy.max_single_transaction,
+            actual: amount,
+        });
+    }
+    
+    check_destination_policy(policy, to)
+}
+
+fn check_destination_policy(policy: &WalletPolicy, to: &str) -> Result<(), CustodyError> {
+    if policy.restricted_destinations.contains(to) {
+        return Err(CustodyError::invalid_input("to", "destination is restricted"));
+    }
+    
+    if let Some(ref allowed) = policy.allowed_destinations {
+        if !allowed.contains(to) {
+            return Err(CustodyError::invalid_input("to", "destination is not in allowed list"));
+        }
+    }
+    
+    Ok(())
+}
+
+fn default_wallet_policy(wallet_type: &WalletType, daily_limit: u64) -> WalletPolicy {
+    WalletPolicy {
+        require_confirmation_delay: matches!(wallet_type, WalletType::CorporateTreasury | WalletType::InstitutionalCold),
+        confirmation_delay_hours: match wallet_type {
+            WalletType::CorporateTreasury | WalletType::InstitutionalCold => 24,
+            WalletType::GovernmentEmergency => 1,
+            _ => 0,
+        },
+        max_single_transaction: match wallet_type {
+            WalletType::InstitutionalHot => 1_000_000_000, // 10 BTC
+            WalletType::CorporateOperational => 500_000_000, // 5 BTC
+            _ => 10_000_000_000, // 100 BTC
+        },
+        require_dual_approval_above: daily_limit / 10,
+        emergency_freeze_threshold: daily_limit * 2,
+        allowed_destinations: None,
+        restricted_destinations: BTreeSet::new(),
+        transaction_timeout_hours: match wallet_type {
+            WalletType::GovernmentEmergency => 4,
+            WalletType::CorporateTreasury | WalletType::InstitutionalCold => 168, // 7 days
+            _ => 72,
+        },
+        // Half the daily limit within an hour looks like a drain
+        velocity_window_minutes: 60,
+        velocity_limit: daily_limit / 2,
+        parent_funding_limit: 0,
+    }
+}
+
+// Starts the cooling-off period once every current owner has confirmed, and
+// clears it if an owner added since then hasn't
+fn start_type_change_cooling_off(proposal: &mut TypeChangeProposal, wallet: &MultisigWallet, now: u64) {
+    let all_confirmed = wallet.owners.iter().all(|owner| proposal.confirmations.contains(owner));
+    
+    if !all_confirmed {
+        proposal.executable_at = None;
+    } else if proposal.executable_at.is_none() {
+        proposal.executable_at = Some(now + TYPE_CHANGE_COOLING_OFF_NANOS);
+    }
+}
+
+// The fee depends on transaction size and priority, not the amount sent
+fn calculate_fee_estimate(amount: u64, priority: &TransactionPriority) -> FeeEstimate {
+    let fee_rate_sat_per_vbyte = match priority {
+        TransactionPriority::Low => BASE_FEE_RATE_SAT_PER_VBYTE,
+        TransactionPriority::Normal => 2,
+        TransactionPriority::High => 5,
+        TransactionPriority::Emergency => 10,
+    };
+    
+    let base_fee = BASE_FEE_RATE_SAT_PER_VBYTE * P2WPKH_1_IN_2_OUT_VBYTES;
+    let total_fee = fee_rate_sat_per_vbyte * P2WPKH_1_IN_2_OUT_VBYTES;
+    
+    FeeEstimate {
+        base_fee,
+        priority_fee: total_fee - base_fee,
+        total_fee,
+        net_amount: amount,
+        fee_rate_sat_per_vbyte,
+    }
+}
+
+fn wallet_guardians(wallet_id: &str) -> Vec<GuardianRecord> {
+    WALLET_GUARDIANS.with(|guardians| {
+        guardians.borrow().get(wallet_id).cloned().unwrap_or_default()
+    })
+}
+
+// Owner actions are all audit logged, so the newest log entry by an owner
+// marks their last activity
+fn last_owner_activity(wallet: &MultisigWallet) -> u64 {
+    AUDIT_LOGS.with(|logs| {
+        logs.borrow()
+            .values()
+            .filter(|log| log.wallet_id == wallet.id && wallet.owners.contains(&log.actor))
+            .map(|log| log.timestamp)
+            .max()
+    }).unwrap_or(wallet.created_at).max(wallet.created_at)
+}
+
+// Starts the timelock once every current guardian has confirmed, and clears
+// it if a guardian added since then hasn't
+fn start_recovery_timelock(proposal: &mut RecoveryProposal, now: u64) {
+    let all_confirmed = wallet_guardians(&proposal.wallet_id)
+        .iter()
+        .all(|record| proposal.confirmations.contains(&record.guardian));
+    
+    if !all_confirmed {
+        proposal.executable_at = None;
+    } else if proposal.executable_at.is_none() {
+        proposal.executable_at = Some(now + RECOVERY_TIMELOCK_NANOS);
+    }
+}
+
+// Fails if executing `amount` now would push the total executed within the
+// policy's velocity window over its limit
+fn check_velocity(wallet_id: &str, policy: &WalletPolicy, amount: u64, now: u64) -> Result<(), CustodyError> {
+    if policy.velocity_window_minutes == 0 || policy.velocity_limit == 0 {
+        return Ok(());
+    }
+    
+    let window_nanos = policy.velocity_window_minutes as u64 * 60 * 1_000_000_000;
+    let window_start = now.saturating_sub(window_nanos);
+    let retain_from = now.saturating_sub(window_nanos.max(MIN_EXECUTION_RETENTION_NANOS));
+    
+    let window_total = RECENT_EXECUTIONS.with(|recent| {
+        let mut recent = recent.borrow_mut();
+        let executions = match recent.get_mut(wallet_id) {
+            Some(executions) => executions,
+            None => return 0,
+        };
+        
+        while matches!(executions.front(), Some(&(executed_at, _)) if executed_at < retain_from) {
+            executions.pop_front();
+        }
+        
+        executions.iter()
+            .filter(|(executed_at, _)| *executed_at >= window_start)
+            .map(|(_, amount)| *amount)
+            .fold(0u64, u64::saturating_add)
+    });
+    
+    let actual = window_total.saturating_add(amount);
+    if actual > policy.velocity_limit {
+        return Err(CustodyError::VelocityLimitExceeded {
+            limit: policy.velocity_limit,
+            actual,
+            window_minutes: policy.velocity_window_minutes,
+        });
+    }
+    
+    Ok(())
+}
+
+// Sends a critical-severity entry to the audit_trail canister, if one is set
+async fn send_critical_alert(wallet_id: String, details: String) {
+    let audit_canister = match AUDIT_TRAIL_CANISTER.with(|c| *c.borrow()) {
+        Some(canister) => canister,
+        None => return,
+    };
+    
+    let mut additional_context = BTreeMap::new();
+    additional_context.insert("severity".to_string(), "critical".to_string());
+    let metadata = AuditMetadata {
+        method_name: Some("execute_transaction".to_string()),
+        additional_context,
+        ..Default::default()
+    };
+    let context = RequestContext::new(ic_cdk::caller(), wallet_id.clone());
+    
+    let result: Result<(Result<String, CustodyError>,), _> = ic_cdk::call(
+        audit_canister,
+        "log_audit_event",
+        (
+            AuditEventType::EmergencyAction,
+            AuditResourceType::MultisigWallet,
+            wallet_id.clone(),
+            "velocity_limit_exceeded".to_string(),
+            details,
+            Some(metadata),
+            true,
+            Some(context),
+        ),
+    ).await;
+    
+    match result {
+        Ok((Ok(_),)) => {},
+        Ok((Err(e),)) => ic_cdk::println!("Audit trail rejected alert for wallet {}: {}", wallet_id, e),
+        Err((code, msg)) => ic_cdk::println!("Alert for wallet {} failed: {:?} {}", wallet_id, code, msg),
+    }
+}
+
+fn transaction_expiry(current_time: u64, timeout_hours: u32) -> Option<u64> {
+    if timeout_hours > 0 {
+        Some(current_time + timeout_hours as u64 * 60 * 60 * 1_000_000_000)
+    } else {
+        None
+    }
+}
+
+// For queries, which can't call the auth canister and rely on its cached answers
+fn is_cached_emergency_contact(principal: &Principal) -> bool {
+    EMERGENCY_CONTACTS.with(|contacts| contacts.borrow().contains(principal))
+        || has_cached_role(principal, "emergency_contact")
+}
+
+// Falls back to the auth canister for principals not registered locally
+async fn check_emergency_contact(principal: Principal) -> bool {
+    EMERGENCY_CONTACTS.with(|contacts| contacts.borrow().contains(&principal))
+        || has_role(principal, "emergency_contact").await
+}
+
+fn is_expired(transaction: &MultisigTransaction, now: u64) -> bool {
+    matches!(transaction.expires_at, Some(expires_at) if expires_at <= now)
+}
+
+/// Marks every pending transaction of the wallet whose confirmation window has
+/// elapsed as rejected and returns the IDs of the transactions that were expired.
+fn expire_timed_out_transactions(wallet_id: &str, actor: Principal) -> Vec<String> {
+    let now = ic_cdk::api::time();
+    
+    let expired: Vec<String> = TRANSACTIONS.with(|txns| {
+        txns.borrow_mut()
+            .values_mut()
+            .filter(|txn| txn.wallet_id == wallet_id && !txn.executed && !txn.rejected)
+            .filter(|txn| is_expired(txn, now))
+            .map(|txn| {
+                txn.rejected = true;
+                txn.id.clone()
+            })
+            .collect()
+    });
+    
+    // Transactions only debit the wallet on execution, so there is no
+    // reserved balance to hand back here beyond recording the expiry.
+    for transaction_id in &expired {
+        log_audit_action(wallet_id, AuditAction::TransactionRejected, actor, 
+            format!("Transaction {} expired before reaching threshold", transaction_id), 
+            Some(transaction_id.clone()));
+    }
+    
+    expired
+}
+
+// === Cycle Monitoring Functions ===
+
+#[query]
+fn check_cycle_balance() -> u64 {
+    cycles::check_cycle_balance()
+}
+
+#[query]
+fn get_cycle_stats() -> CycleStats {
+    cycles::get_cycle_stats()
+}
+
+#[query]
+fn get_memory_stats() -> MemoryStats {
+    memory::get_memory_stats()
+}
+
+fn notify_high_memory(stats: &MemoryStats) {
+    match AUDIT_TRAIL_CANISTER.with(|c| *c.borrow()) {
+        Some(audit_canister) => ic_cdk::spawn(memory::audit_memory_alert(audit_canister, stats.clone())),
+        None => memory::log_memory_alert(stats),
+    }
+}
+
+#[update]
+async fn set_cycle_alert_threshold(threshold: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_cycle_alert_threshold")?;
+    
+    if !check_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("set_cycle_alert_threshold"));
+    }
+    
+    cycles::set_cycle_alert_threshold(threshold);
+    
+    Ok("Cycle alert threshold updated successfully".to_string())
+}
+
+#[update]
+async fn set_cycle_alert_canister(alert_canister: Option<Principal>) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_cycle_alert_canister")?;
+    
+    if !check_emergency_contact(caller).await {
+        return Err(CustodyError::unauthorized("set_cycle_alert_canister"));
+    }
+    
+    cycles::set_cycle_alert_canister(alert_canister);
+    
+    Ok("Cycle alert canister updated successfully".to_string())
+}
+
 #[query]
 fn health_check() -> String {
     "Multisig Wallet canister is healthy".to_string()
 }
 
+const INTERFACE_VERSION: InterfaceVersion = (1, 0, 0);
+
+#[query]
+fn get_candid_interface_version() -> u32 {
+    INTERFACE_VERSION.0
+}
+
+#[query]
+fn get_interface_metadata() -> CandidVersion {
+    CandidVersion::new(INTERFACE_VERSION, &__export_service())
+}
+
 // Export Candid interface
 ic_cdk::export_candid!();
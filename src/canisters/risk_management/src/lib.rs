@@ -1,29 +1,1331 @@
-use candid::CandidType;
-use ic_cdk_macros::{init, query, update};
+use candid::{CandidType, Principal};
+use ic_cdk_macros::{init, post_upgrade, query, update};
 use serde::{Deserialize, Serialize};
+use shared::cycles::{self, CycleStats};
+use shared::auth::{has_role, set_auth_canister};
+use shared::{check_rate_limit, CustodyError};
+use shared::interface::{CandidVersion, InterfaceVersion};
+use shared::memory::{self, MemoryStats};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
 
 #[derive(CandidType, Serialize, Deserialize)]
 pub struct RiskAssessment {
     pub score: u8,
     pub factors: Vec<String>,
+    pub decision: RiskDecision,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RiskFactor {
+    pub id: String,
+    pub name: String,
+    pub weight: f32,
+    pub enabled: bool,
+    pub evaluation_fn_key: String,
+}
+
+// Mirrors custody_core's account and compliance enums so callers can pass them straight through
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum AccountType {
+    CorporateCustody,
+    GovernmentCustody,
+    InstitutionalCustody,
+    TrustCustody,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum ComplianceStatus {
+    Compliant,
+    PendingKyc,
+    RequiresReview,
+    NonCompliant,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub enum TransactionType {
+    Deposit,
+    Withdrawal,
+    Transfer,
+    Emergency,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+pub enum RiskLevel {
+    Low,
+    Medium,
+    High,
+    Critical,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CounterpartyProfile {
+    pub address: String,
+    pub risk_level: RiskLevel,
+    pub is_known: bool,
+    pub is_sanctioned: bool,
+    pub jurisdiction: Option<String>,
+    pub last_transacted_at: Option<u64>,
+    pub total_received: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StressScenario {
+    // Change in the BTC price, e.g. -50 for a halving
+    pub price_shock_percent: i8,
+    // Pending withdrawals are multiplied by this
+    pub withdrawal_surge_multiplier: u8,
+    // Share of each balance withdrawn on top of the surge
+    pub liquidity_drain_percent: u8,
+    // Empty means every account type
+    pub affected_account_types: Vec<AccountType>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct StressTestResult {
+    pub accounts_at_risk: Vec<String>,
+    pub estimated_total_exposure: u64,
+    pub capital_shortfall: u64,
+    pub recommended_reserves: u64,
+    // Latest risk score -> number of accounts
+    pub risk_score_distribution: BTreeMap<u8, u32>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RegulatoryCapital {
+    pub total_rwa: u64,
+    pub tier1_capital_required: u64,
+    pub tier2_capital_required: u64,
+    pub actual_capital: u64,
+    // Actual capital over risk-weighted assets, in basis points
+    pub capital_adequacy_ratio: u32,
+    pub last_computed_at: u64,
+    pub breached: bool,
+}
+
+// custody_core's AccountBalance, as read by the capital and stress reports
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct CustodyAccountSummary {
+    id: String,
+    account_type: AccountType,
+    balance: u64,
+    reserved_balance: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum RiskDecision {
+    Allow,
+    ReviewRequired,
+    DualReviewRequired,
+    Block(String),
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RiskLimits {
+    pub review_above: u8,
+    pub block_above: u8,
+    pub override_requires: Principal,
+}
+
+// Scores strictly above each threshold trigger the matching response
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RiskAppetite {
+    pub review_threshold: u8,
+    pub block_threshold: u8,
+    pub auto_freeze_threshold: u8,
+    pub require_dual_officer_above: u8,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RiskContext {
+    pub account_id: String,
+    pub amount: u64,
+    pub account_type: Option<AccountType>,
+    pub jurisdiction: Option<String>,
+    pub kyc_status: Option<ComplianceStatus>,
+    pub counterparty: Option<String>,
+    pub transaction_id: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RiskSnapshot {
+    pub timestamp: u64,
+    pub score: u8,
+    pub factors: Vec<String>,
+    pub triggered_by: String,
+    pub amount: u64,
+    pub counterparty: Option<String>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct BehavioralBaseline {
+    pub account_id: String,
+    pub avg_transaction_amount: u64,
+    pub avg_daily_transaction_count: f32,
+    // UTC hour the account most often transacts in
+    pub typical_transaction_hour: u8,
+    pub typical_counterparties: BTreeSet<String>,
+    pub baseline_computed_at: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum TrendDirection {
+    Increasing,
+    Decreasing,
+    Stable,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct RiskTrend {
+    pub average: f64,
+    pub min: u8,
+    pub max: u8,
+    pub direction: TrendDirection,
+}
+
+const NANOS_PER_HOUR: u64 = 60 * 60 * 1_000_000_000;
+const MAX_RISK_SCORE: f32 = 10.0;
+const MAX_HISTORY_PER_ACCOUNT: usize = 1000;
+const BASELINE_WINDOW_NANOS: u64 = 90 * 24 * NANOS_PER_HOUR;
+const MIN_BASELINE_SAMPLES: usize = 10;
+// Amounts above this multiple of the baseline average are anomalous
+const BASELINE_AMOUNT_MULTIPLIER: u64 = 3;
+// Hours further than this from the typical hour are anomalous
+const BASELINE_HOUR_TOLERANCE: u8 = 4;
+const BEHAVIORAL_ANOMALY_POINTS: f32 = 2.0;
+// Recommended reserves cover the projected shortfall plus this margin
+const STRESS_RESERVE_BUFFER_PERCENT: u64 = 20;
+// Basel III minimums as a percentage of risk-weighted assets
+const TIER1_CAPITAL_PERCENT: u64 = 6;
+const TIER2_CAPITAL_PERCENT: u64 = 2;
+const CAPITAL_HISTORY_RETENTION_NANOS: u64 = 90 * 24 * NANOS_PER_HOUR;
+
+const BUILTIN_EVALUATORS: [&str; 9] = [
+    "amount_size",
+    "account_type",
+    "jurisdiction_risk",
+    "kyc_status",
+    "transaction_velocity",
+    "counterparty_known",
+    "time_of_day",
+    "cumulative_daily_volume",
+    "counterparty_risk",
+];
+
+thread_local! {
+    static RISK_FACTORS: RefCell<BTreeMap<String, RiskFactor>> = const { RefCell::new(BTreeMap::new()) };
+    static RISK_MANAGERS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+    static HIGH_RISK_JURISDICTIONS: RefCell<BTreeSet<String>> = const { RefCell::new(BTreeSet::new()) };
+    static KNOWN_COUNTERPARTIES: RefCell<BTreeSet<String>> = const { RefCell::new(BTreeSet::new()) };
+    // Recent (timestamp, amount) pairs per account, kept for the last 24 hours
+    static ACCOUNT_ACTIVITY: RefCell<BTreeMap<String, Vec<(u64, u64)>>> = const { RefCell::new(BTreeMap::new()) };
+    static RISK_HISTORY: RefCell<BTreeMap<String, VecDeque<RiskSnapshot>>> = const { RefCell::new(BTreeMap::new()) };
+    static RISK_LIMITS: RefCell<BTreeMap<AccountType, RiskLimits>> = const { RefCell::new(BTreeMap::new()) };
+    static RISK_APPETITE: RefCell<BTreeMap<AccountType, RiskAppetite>> = const { RefCell::new(BTreeMap::new()) };
+    static COUNTERPARTY_DB: RefCell<BTreeMap<String, CounterpartyProfile>> = const { RefCell::new(BTreeMap::new()) };
+    static CUSTODY_CANISTER: RefCell<Option<Principal>> = const { RefCell::new(None) };
+    static BEHAVIORAL_BASELINES: RefCell<BTreeMap<String, BehavioralBaseline>> = const { RefCell::new(BTreeMap::new()) };
+    static ACTUAL_CAPITAL: RefCell<u64> = const { RefCell::new(0) };
+    static CAPITAL_HISTORY: RefCell<VecDeque<(u64, RegulatoryCapital)>> = const { RefCell::new(VecDeque::new()) };
 }
 
 #[init]
-fn init() {
+fn init(auth_canister: Option<Principal>) {
     ic_cdk::println!("Risk Management canister initialized");
+    
+    set_auth_canister(auth_canister);
+    cycles::start_cycle_monitor(cycles::forward_cycle_alert);
+    memory::start_memory_monitor(memory::log_memory_alert);
+    
+    // Initialize with deployer as risk manager
+    RISK_MANAGERS.with(|managers| {
+        managers.borrow_mut().insert(ic_cdk::caller());
+    });
+    
+    // The general factors add up to the maximum score of 10; counterparty
+    // risk comes on top so a sanctioned recipient always raises the score
+    let default_factors = [
+        ("amount_size", "Transaction amount", 2.0),
+        ("account_type", "Account type", 1.0),
+        ("jurisdiction_risk", "Jurisdiction risk", 1.5),
+        ("kyc_status", "KYC status", 1.5),
+        ("transaction_velocity", "Transaction velocity", 1.0),
+        ("counterparty_known", "Unknown counterparty", 1.0),
+        ("time_of_day", "Time of day", 0.5),
+        ("cumulative_daily_volume", "Cumulative daily volume", 1.5),
+        ("counterparty_risk", "Counterparty risk", 2.0),
+    ];
+    
+    RISK_FACTORS.with(|factors| {
+        let mut factors = factors.borrow_mut();
+        for (key, name, weight) in default_factors {
+            factors.insert(key.to_string(), RiskFactor {
+                id: key.to_string(),
+                name: name.to_string(),
+                weight,
+                enabled: true,
+                evaluation_fn_key: key.to_string(),
+            });
+        }
+    });
+    
+    // Initialize high-risk jurisdictions (simplified list)
+    HIGH_RISK_JURISDICTIONS.with(|jurisdictions| {
+        let mut j = jurisdictions.borrow_mut();
+        j.insert("North Korea".to_string());
+        j.insert("Iran".to_string());
+        j.insert("Syria".to_string());
+        j.insert("Cuba".to_string());
+        j.insert("Sudan".to_string());
+    });
+    
+    // Default per account type limits; the deployer may override them
+    let default_limits = [
+        (AccountType::GovernmentCustody, 5, 6, 8),
+        (AccountType::TrustCustody, 5, 6, 8),
+        (AccountType::CorporateCustody, 6, 7, 9),
+        (AccountType::InstitutionalCustody, 6, 7, 9),
+    ];
+    
+    for (account_type, review_above, dual_above, block_above) in default_limits {
+        RISK_LIMITS.with(|limits| {
+            limits.borrow_mut().insert(account_type.clone(), RiskLimits {
+                review_above,
+                block_above,
+                override_requires: ic_cdk::caller(),
+            });
+        });
+        
+        RISK_APPETITE.with(|appetite| {
+            appetite.borrow_mut().insert(account_type, RiskAppetite {
+                review_threshold: review_above,
+                block_threshold: block_above,
+                auto_freeze_threshold: 9,
+                require_dual_officer_above: dual_above,
+            });
+        });
+    }
+}
+
+#[post_upgrade]
+fn post_upgrade(auth_canister: Option<Principal>) {
+    set_auth_canister(auth_canister);
+    cycles::start_cycle_monitor(cycles::forward_cycle_alert);
+    memory::start_memory_monitor(memory::log_memory_alert);
 }
 
+// === Risk Assessment Functions ===
+
 #[update]
-fn assess_risk(account_id: String, amount: u64) -> RiskAssessment {
-    let mut score = 0u8;
+fn assess_risk(context: RiskContext) -> RiskAssessment {
+    if let Err(e) = check_rate_limit(ic_cdk::caller(), "assess_risk") {
+        ic_cdk::trap(&e.to_string());
+    }
+    
+    // Anyone can ask for an assessment, but only custody_core's own requests
+    // are recorded against the account or trusted enough to freeze it
+    let from_custody = is_custody_canister(ic_cdk::caller());
+    let mut assessment = run_assessment(&context, from_custody);
+    
+    let may_freeze = from_custody;
+    let account_type = context.account_type.unwrap_or(AccountType::CorporateCustody);
+    assessment.decision = apply_risk_appetite(&context.account_id, &account_type, None, assessment.score, may_freeze);
+    
+    assessment
+}
+
+#[update]
+fn evaluate_transaction(
+    account_id: String,
+    account_type: AccountType,
+    transaction_type: TransactionType,
+    amount: u64,
+    counterparty: Option<String>,
+    transaction_id: Option<String>,
+) -> RiskDecision {
+    if let Err(e) = check_rate_limit(ic_cdk::caller(), "evaluate_transaction") {
+        ic_cdk::trap(&e.to_string());
+    }
+    
+    let context = RiskContext {
+        account_id,
+        amount,
+        account_type: Some(account_type.clone()),
+        jurisdiction: None,
+        kyc_status: None,
+        counterparty,
+        transaction_id,
+    };
+    
+    let from_custody = is_custody_canister(ic_cdk::caller());
+    let assessment = run_assessment(&context, from_custody);
+    
+    apply_risk_appetite(&context.account_id, &account_type, Some(&transaction_type), assessment.score, from_custody)
+}
+
+fn risk_appetite_for(account_type: &AccountType) -> RiskAppetite {
+    RISK_APPETITE.with(|appetite| {
+        appetite.borrow().get(account_type).cloned().unwrap_or(RiskAppetite {
+            review_threshold: 6,
+            block_threshold: 8,
+            auto_freeze_threshold: 9,
+            require_dual_officer_above: 7,
+        })
+    })
+}
+
+// Maps a score onto the account type's risk appetite, freezing the account
+// through custody_core when the score is past the auto-freeze threshold and
+// `may_freeze` is set
+fn apply_risk_appetite(
+    account_id: &str,
+    account_type: &AccountType,
+    transaction_type: Option<&TransactionType>,
+    score: u8,
+    may_freeze: bool,
+) -> RiskDecision {
+    let appetite = risk_appetite_for(account_type);
+    
+    if may_freeze && score > appetite.auto_freeze_threshold {
+        ic_cdk::spawn(freeze_custody_account(account_id.to_string(), score));
+    }
+    
+    if score > appetite.block_threshold {
+        // Incoming funds cannot be refused on-chain, so they are held for review instead
+        if matches!(transaction_type, Some(TransactionType::Deposit)) {
+            return RiskDecision::DualReviewRequired;
+        }
+        return RiskDecision::Block(format!(
+            "Risk score {} exceeds block threshold {}",
+            score, appetite.block_threshold
+        ));
+    }
+    
+    if score > appetite.require_dual_officer_above {
+        return RiskDecision::DualReviewRequired;
+    }
+    
+    if score > appetite.review_threshold {
+        return RiskDecision::ReviewRequired;
+    }
+    
+    RiskDecision::Allow
+}
+
+async fn freeze_custody_account(account_id: String, score: u8) {
+    let custody_canister = match CUSTODY_CANISTER.with(|c| *c.borrow()) {
+        Some(c) => c,
+        None => {
+            ic_cdk::println!("Cannot auto-freeze {} (score {}): custody canister not configured", account_id, score);
+            return;
+        }
+    };
+    
+    let result: Result<(Result<String, CustodyError>,), _> = ic_cdk::call(
+        custody_canister,
+        "emergency_freeze_account",
+        (account_id.clone(),),
+    ).await;
+    
+    match result {
+        Ok((Ok(_),)) => ic_cdk::println!("Auto-froze account {} at risk score {}", account_id, score),
+        Ok((Err(e),)) => ic_cdk::println!("Auto-freeze of {} rejected: {}", account_id, e),
+        Err((code, msg)) => ic_cdk::println!("Auto-freeze of {} failed: {:?} {}", account_id, code, msg),
+    }
+}
+
+// Scores the context. Activity and the snapshot are only recorded when
+// `persist` is set, so other callers can't skew an account's velocity,
+// baseline or history with made-up transactions
+fn run_assessment(context: &RiskContext, persist: bool) -> RiskAssessment {
+    let current_time = ic_cdk::api::time();
+    let mut total = 0.0f32;
     let mut factors = Vec::new();
     
-    if amount > 10_000_000_000 {
-        score += 5;
-        factors.push("Large amount".to_string());
+    let registered: Vec<RiskFactor> = RISK_FACTORS.with(|f| f.borrow().values().cloned().collect());
+    
+    for factor in registered.iter().filter(|f| f.enabled) {
+        let factor_score = match evaluate_factor(&factor.evaluation_fn_key, context, current_time) {
+            Some(s) => s,
+            None => continue,
+        };
+        
+        if factor_score > 0.0 {
+            total += factor_score * factor.weight;
+            factors.push(format!("{} ({:.2})", factor.name, factor_score));
+        }
+    }
+    
+    // Compared against the baseline before this transaction is recorded
+    if let Some(anomaly) = detect_behavioral_anomaly(context, current_time) {
+        total += BEHAVIORAL_ANOMALY_POINTS;
+        factors.push(format!("BehavioralAnomaly ({})", anomaly));
+    }
+    
+    let score = total.clamp(0.0, MAX_RISK_SCORE).round() as u8;
+    
+    if !persist {
+        return RiskAssessment { score, factors, decision: RiskDecision::Allow };
     }
     
-    RiskAssessment { score, factors }
+    record_activity(&context.account_id, context.amount, current_time);
+    
+    if let Some(ref counterparty) = context.counterparty {
+        record_counterparty_activity(counterparty, context.amount, current_time);
+    }
+    
+    let snapshot = RiskSnapshot {
+        timestamp: current_time,
+        score,
+        factors: factors.clone(),
+        triggered_by: context.transaction_id.clone().unwrap_or_else(|| "manual".to_string()),
+        amount: context.amount,
+        counterparty: context.counterparty.clone(),
+    };
+    
+    RISK_HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        let snapshots = history.entry(context.account_id.clone()).or_default();
+        if snapshots.len() >= MAX_HISTORY_PER_ACCOUNT {
+            snapshots.pop_front();
+        }
+        snapshots.push_back(snapshot);
+    });
+    
+    RiskAssessment { score, factors, decision: RiskDecision::Allow }
+}
+
+fn evaluate_factor(key: &str, context: &RiskContext, current_time: u64) -> Option<f32> {
+    let score = match key {
+        // Scaled against 100 BTC in satoshis
+        "amount_size" => context.amount as f32 / 10_000_000_000.0,
+        "account_type" => match context.account_type {
+            Some(AccountType::GovernmentCustody) => 0.2,
+            Some(AccountType::InstitutionalCustody) => 0.3,
+            Some(AccountType::CorporateCustody) => 0.5,
+            Some(AccountType::TrustCustody) => 0.6,
+            None => 0.5,
+        },
+        "jurisdiction_risk" => match &context.jurisdiction {
+            Some(j) if HIGH_RISK_JURISDICTIONS.with(|h| h.borrow().contains(j)) => 1.0,
+            Some(_) => 0.0,
+            None => 0.5,
+        },
+        "kyc_status" => match context.kyc_status {
+            Some(ComplianceStatus::Compliant) => 0.0,
+            Some(ComplianceStatus::PendingKyc) => 0.5,
+            Some(ComplianceStatus::RequiresReview) => 0.7,
+            Some(ComplianceStatus::NonCompliant) => 1.0,
+            None => 0.5,
+        },
+        // Ten or more transactions in the past hour is maximum risk
+        "transaction_velocity" => {
+            let recent = recent_activity(&context.account_id, current_time, NANOS_PER_HOUR);
+            recent.len() as f32 / 10.0
+        }
+        "counterparty_known" => match &context.counterparty {
+            Some(c) if is_known_counterparty(c) => 0.0,
+            Some(_) => 1.0,
+            None => 0.0,
+        },
+        "counterparty_risk" => match &context.counterparty {
+            Some(c) => counterparty_risk_score(c),
+            None => 0.0,
+        },
+        // Activity between 00:00 and 06:00 UTC is unusual for institutions
+        "time_of_day" => {
+            let hour = (current_time / NANOS_PER_HOUR) % 24;
+            match hour {
+                0..=5 => 1.0,
+                22..=23 => 0.5,
+                _ => 0.0,
+            }
+        }
+        // Scaled against 500 BTC moved within 24 hours
+        "cumulative_daily_volume" => {
+            let recent = recent_activity(&context.account_id, current_time, 24 * NANOS_PER_HOUR);
+            let volume: u64 = recent.iter().map(|(_, amount)| amount).sum::<u64>()
+                .saturating_add(context.amount);
+            volume as f32 / 50_000_000_000.0
+        }
+        _ => return None,
+    };
+    
+    Some(score.clamp(0.0, 1.0))
+}
+
+fn recent_activity(account_id: &str, current_time: u64, window: u64) -> Vec<(u64, u64)> {
+    let since = current_time.saturating_sub(window);
+    ACCOUNT_ACTIVITY.with(|activity| {
+        activity.borrow()
+            .get(account_id)
+            .map(|a| a.iter().filter(|(t, _)| *t >= since).cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+fn record_activity(account_id: &str, amount: u64, current_time: u64) {
+    let since = current_time.saturating_sub(24 * NANOS_PER_HOUR);
+    ACCOUNT_ACTIVITY.with(|activity| {
+        let mut activity = activity.borrow_mut();
+        let entries = activity.entry(account_id.to_string()).or_default();
+        entries.retain(|(t, _)| *t >= since);
+        entries.push((current_time, amount));
+    });
+}
+
+// === Risk History Functions ===
+
+#[query]
+fn get_risk_history(account_id: String, limit: Option<u32>) -> Vec<RiskSnapshot> {
+    let limit = limit.unwrap_or(100) as usize;
+    
+    // Most recent snapshots first
+    RISK_HISTORY.with(|history| {
+        history.borrow()
+            .get(&account_id)
+            .map(|snapshots| snapshots.iter().rev().take(limit).cloned().collect())
+            .unwrap_or_default()
+    })
+}
+
+#[query]
+fn get_risk_trend(account_id: String, window_hours: u32) -> RiskTrend {
+    let since = ic_cdk::api::time().saturating_sub(window_hours as u64 * NANOS_PER_HOUR);
+    
+    let scores: Vec<u8> = RISK_HISTORY.with(|history| {
+        history.borrow()
+            .get(&account_id)
+            .map(|snapshots| {
+                snapshots.iter()
+                    .filter(|s| s.timestamp >= since)
+                    .map(|s| s.score)
+                    .collect()
+            })
+            .unwrap_or_default()
+    });
+    
+    if scores.is_empty() {
+        return RiskTrend {
+            average: 0.0,
+            min: 0,
+            max: 0,
+            direction: TrendDirection::Stable,
+        };
+    }
+    
+    let average = mean_score(&scores);
+    
+    // Compare the older and newer halves of the window
+    let (older, newer) = scores.split_at(scores.len() / 2);
+    let direction = if older.is_empty() {
+        TrendDirection::Stable
+    } else {
+        let change = mean_score(newer) - mean_score(older);
+        if change > 0.5 {
+            TrendDirection::Increasing
+        } else if change < -0.5 {
+            TrendDirection::Decreasing
+        } else {
+            TrendDirection::Stable
+        }
+    };
+    
+    RiskTrend {
+        average,
+        min: *scores.iter().min().unwrap_or(&0),
+        max: *scores.iter().max().unwrap_or(&0),
+        direction,
+    }
+}
+
+fn mean_score(scores: &[u8]) -> f64 {
+    scores.iter().map(|s| *s as f64).sum::<f64>() / scores.len() as f64
+}
+
+// === Behavioral Baseline Functions ===
+
+#[update]
+async fn compute_baseline(account_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "compute_baseline")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("compute_baseline"));
+    }
+    
+    store_baseline(&account_id)
+}
+
+/// Refreshes an existing baseline; meant to be called periodically
+#[update]
+async fn update_baseline(account_id: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "update_baseline")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("update_baseline"));
+    }
+    
+    if BEHAVIORAL_BASELINES.with(|b| !b.borrow().contains_key(&account_id)) {
+        return Err(CustodyError::not_found("Behavioral baseline", account_id));
+    }
+    
+    store_baseline(&account_id)
+}
+
+#[query]
+fn get_behavioral_baseline(account_id: String) -> Option<BehavioralBaseline> {
+    BEHAVIORAL_BASELINES.with(|baselines| baselines.borrow().get(&account_id).cloned())
+}
+
+fn store_baseline(account_id: &str) -> Result<String, CustodyError> {
+    let current_time = ic_cdk::api::time();
+    let since = current_time.saturating_sub(BASELINE_WINDOW_NANOS);
+    
+    let snapshots: Vec<RiskSnapshot> = RISK_HISTORY.with(|history| {
+        history.borrow()
+            .get(account_id)
+            .map(|s| s.iter().filter(|s| s.timestamp >= since).cloned().collect())
+            .unwrap_or_default()
+    });
+    
+    let baseline = compute_baseline_from(account_id, &snapshots, current_time)?;
+    
+    BEHAVIORAL_BASELINES.with(|baselines| {
+        baselines.borrow_mut().insert(account_id.to_string(), baseline);
+    });
+    
+    Ok(format!("Baseline computed from {} transactions", snapshots.len()))
+}
+
+fn compute_baseline_from(
+    account_id: &str,
+    snapshots: &[RiskSnapshot],
+    current_time: u64,
+) -> Result<BehavioralBaseline, CustodyError> {
+    if snapshots.len() < MIN_BASELINE_SAMPLES {
+        return Err(CustodyError::invalid_input(
+            "account_id",
+            format!("at least {} transactions are needed for a baseline", MIN_BASELINE_SAMPLES),
+        ));
+    }
+    
+    let total_amount: u128 = snapshots.iter().map(|s| s.amount as u128).sum();
+    
+    // Spread over the days the account has history for, at least one
+    let earliest = snapshots.iter().map(|s| s.timestamp).min().unwrap_or(current_time);
+    let days = (current_time.saturating_sub(earliest) / (24 * NANOS_PER_HOUR)).max(1);
+    
+    let mut hour_counts = [0u32; 24];
+    for snapshot in snapshots {
+        hour_counts[((snapshot.timestamp / NANOS_PER_HOUR) % 24) as usize] += 1;
+    }
+    let typical_transaction_hour = (0..24u8)
+        .max_by_key(|hour| hour_counts[*hour as usize])
+        .unwrap_or(0);
+    
+    Ok(BehavioralBaseline {
+        account_id: account_id.to_string(),
+        avg_transaction_amount: (total_amount / snapshots.len() as u128) as u64,
+        avg_daily_transaction_count: snapshots.len() as f32 / days as f32,
+        typical_transaction_hour,
+        typical_counterparties: snapshots.iter().filter_map(|s| s.counterparty.clone()).collect(),
+        baseline_computed_at: current_time,
+    })
+}
+
+fn detect_behavioral_anomaly(context: &RiskContext, current_time: u64) -> Option<String> {
+    let baseline = BEHAVIORAL_BASELINES.with(|b| b.borrow().get(&context.account_id).cloned())?;
+    
+    if baseline.avg_transaction_amount > 0
+        && context.amount > baseline.avg_transaction_amount.saturating_mul(BASELINE_AMOUNT_MULTIPLIER)
+    {
+        return Some(format!(
+            "amount {} exceeds {}x the average of {}",
+            context.amount, BASELINE_AMOUNT_MULTIPLIER, baseline.avg_transaction_amount
+        ));
+    }
+    
+    let hour = ((current_time / NANOS_PER_HOUR) % 24) as u8;
+    let distance = hour.abs_diff(baseline.typical_transaction_hour);
+    if distance.min(24 - distance) > BASELINE_HOUR_TOLERANCE {
+        return Some(format!(
+            "hour {} is far from the typical hour {}",
+            hour, baseline.typical_transaction_hour
+        ));
+    }
+    
+    None
+}
+
+// === Factor Registry Functions ===
+
+#[update]
+async fn register_risk_factor(factor: RiskFactor) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "register_risk_factor")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("register_risk_factor"));
+    }
+    
+    if factor.id.is_empty() || factor.name.is_empty() {
+        return Err(CustodyError::invalid_input("factor", "ID and name are required"));
+    }
+    
+    if !BUILTIN_EVALUATORS.contains(&factor.evaluation_fn_key.as_str()) {
+        return Err(CustodyError::invalid_input(
+            "evaluation_fn_key",
+            format!("unknown evaluation function {}", factor.evaluation_fn_key),
+        ));
+    }
+    
+    if !factor.weight.is_finite() || factor.weight < 0.0 {
+        return Err(CustodyError::invalid_input("weight", "must be a non-negative number"));
+    }
+    
+    let factor_id = factor.id.clone();
+    
+    RISK_FACTORS.with(|factors| {
+        factors.borrow_mut().insert(factor_id.clone(), factor);
+    });
+    
+    Ok(factor_id)
+}
+
+#[update]
+async fn update_factor_weight(factor_id: String, weight: f32) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "update_factor_weight")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("update_factor_weight"));
+    }
+    
+    if !weight.is_finite() || weight < 0.0 {
+        return Err(CustodyError::invalid_input("weight", "must be a non-negative number"));
+    }
+    
+    RISK_FACTORS.with(|factors| {
+        match factors.borrow_mut().get_mut(&factor_id) {
+            Some(factor) => {
+                factor.weight = weight;
+                Ok("Factor weight updated successfully".to_string())
+            }
+            None => Err(CustodyError::not_found("Risk factor", factor_id.clone())),
+        }
+    })
+}
+
+#[update]
+async fn add_known_counterparty(counterparty: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "add_known_counterparty")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("add_known_counterparty"));
+    }
+    
+    KNOWN_COUNTERPARTIES.with(|counterparties| {
+        counterparties.borrow_mut().insert(counterparty);
+    });
+    
+    Ok("Counterparty added successfully".to_string())
+}
+
+#[update]
+async fn set_risk_limits(account_type: AccountType, new_limits: RiskLimits) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_risk_limits")?;
+    
+    let override_principal = RISK_LIMITS.with(|limits| {
+        limits.borrow().get(&account_type).map(|l| l.override_requires)
+    });
+    
+    if override_principal != Some(caller) && !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("set_risk_limits"));
+    }
+    
+    if new_limits.review_above > new_limits.block_above {
+        return Err(CustodyError::invalid_input("review_above", "cannot exceed block_above"));
+    }
+    
+    // Keep the appetite's review and block thresholds in step with the limits
+    let mut appetite = risk_appetite_for(&account_type);
+    appetite.review_threshold = new_limits.review_above;
+    appetite.block_threshold = new_limits.block_above;
+    appetite.require_dual_officer_above = appetite.require_dual_officer_above
+        .clamp(new_limits.review_above, new_limits.block_above);
+    appetite.auto_freeze_threshold = appetite.auto_freeze_threshold.max(new_limits.block_above);
+    
+    RISK_APPETITE.with(|a| {
+        a.borrow_mut().insert(account_type.clone(), appetite);
+    });
+    
+    RISK_LIMITS.with(|limits| {
+        limits.borrow_mut().insert(account_type, new_limits);
+    });
+    
+    Ok("Risk limits updated successfully".to_string())
+}
+
+#[update]
+async fn set_risk_appetite(account_type: AccountType, appetite: RiskAppetite) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_risk_appetite")?;
+    
+    let override_principal = RISK_LIMITS.with(|limits| {
+        limits.borrow().get(&account_type).map(|l| l.override_requires)
+    });
+    
+    if override_principal != Some(caller) && !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("set_risk_appetite"));
+    }
+    
+    if appetite.review_threshold > appetite.require_dual_officer_above {
+        return Err(CustodyError::invalid_input("review_threshold", "cannot exceed require_dual_officer_above"));
+    }
+    
+    if appetite.require_dual_officer_above > appetite.block_threshold {
+        return Err(CustodyError::invalid_input("require_dual_officer_above", "cannot exceed block_threshold"));
+    }
+    
+    if appetite.block_threshold > appetite.auto_freeze_threshold {
+        return Err(CustodyError::invalid_input("block_threshold", "cannot exceed auto_freeze_threshold"));
+    }
+    
+    RISK_LIMITS.with(|limits| {
+        if let Some(l) = limits.borrow_mut().get_mut(&account_type) {
+            l.review_above = appetite.review_threshold;
+            l.block_above = appetite.block_threshold;
+        }
+    });
+    
+    RISK_APPETITE.with(|a| {
+        a.borrow_mut().insert(account_type, appetite);
+    });
+    
+    Ok("Risk appetite updated successfully".to_string())
+}
+
+#[query]
+fn get_risk_appetite() -> Vec<(AccountType, RiskAppetite)> {
+    RISK_APPETITE.with(|appetite| {
+        appetite.borrow().iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    })
+}
+
+#[query]
+fn get_risk_limits() -> Vec<(AccountType, RiskLimits)> {
+    RISK_LIMITS.with(|limits| {
+        limits.borrow().iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    })
+}
+
+#[query]
+fn get_risk_factors() -> Vec<RiskFactor> {
+    RISK_FACTORS.with(|factors| factors.borrow().values().cloned().collect())
+}
+
+// Local managers are checked first so the auth canister is only asked
+// about principals this canister doesn't already know
+async fn is_risk_manager(principal: Principal) -> bool {
+    RISK_MANAGERS.with(|managers| managers.borrow().contains(&principal))
+        || has_role(principal, "risk_manager").await
+}
+
+// === Stress Testing Functions ===
+
+/// Projects every custody account under a stress scenario. This is an update
+/// call only because it reads balances from custody_core; it changes no state.
+#[update]
+async fn run_stress_test(scenario: StressScenario) -> Result<StressTestResult, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "run_stress_test")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("run_stress_test"));
+    }
+    
+    if scenario.price_shock_percent < -100 {
+        return Err(CustodyError::invalid_input("price_shock_percent", "cannot be below -100"));
+    }
+    
+    if scenario.liquidity_drain_percent > 100 {
+        return Err(CustodyError::invalid_input("liquidity_drain_percent", "cannot exceed 100"));
+    }
+    
+    let custody_canister = configured_custody_canister()?;
+    
+    let accounts = fetch_all_custody_accounts(custody_canister).await?;
+    
+    let mut result = StressTestResult {
+        accounts_at_risk: Vec::new(),
+        estimated_total_exposure: 0,
+        capital_shortfall: 0,
+        recommended_reserves: 0,
+        risk_score_distribution: BTreeMap::new(),
+    };
+    
+    for account in accounts {
+        if !scenario.affected_account_types.is_empty()
+            && !scenario.affected_account_types.contains(&account.account_type)
+        {
+            continue;
+        }
+        
+        let review_above = risk_appetite_for(&account.account_type).review_threshold;
+        
+        let (exposure, shortfall) = project_stressed_account(&account, &scenario);
+        
+        result.estimated_total_exposure = result.estimated_total_exposure.saturating_add(exposure);
+        result.capital_shortfall = result.capital_shortfall.saturating_add(shortfall);
+        
+        // Accounts never assessed here have no score and count only on shortfall
+        let score = RISK_HISTORY.with(|history| {
+            history.borrow().get(&account.id).and_then(|snapshots| snapshots.back()).map(|s| s.score)
+        });
+        if let Some(score) = score {
+            *result.risk_score_distribution.entry(score).or_insert(0) += 1;
+        }
+        
+        if shortfall > 0 || score.is_some_and(|score| score > review_above) {
+            result.accounts_at_risk.push(account.id);
+        }
+    }
+    
+    result.recommended_reserves = result.capital_shortfall
+        .saturating_add(result.capital_shortfall / 100 * STRESS_RESERVE_BUFFER_PERCENT);
+    
+    Ok(result)
+}
+
+// Returns the withdrawal demand on the account and how far it exceeds the
+// balance once the price shock is applied
+fn project_stressed_account(account: &CustodyAccountSummary, scenario: &StressScenario) -> (u64, u64) {
+    let price_factor = (100 + scenario.price_shock_percent as i64).max(0) as u128;
+    let stressed_balance = (account.balance as u128 * price_factor / 100) as u64;
+    
+    let surged_withdrawals = account.reserved_balance.saturating_mul(scenario.withdrawal_surge_multiplier as u64);
+    let drained = (account.balance as u128 * scenario.liquidity_drain_percent as u128 / 100) as u64;
+    let demand = surged_withdrawals.saturating_add(drained);
+    
+    (demand, demand.saturating_sub(stressed_balance))
+}
+
+fn is_custody_canister(principal: Principal) -> bool {
+    CUSTODY_CANISTER.with(|c| *c.borrow()) == Some(principal)
+}
+
+fn configured_custody_canister() -> Result<Principal, CustodyError> {
+    CUSTODY_CANISTER.with(|c| *c.borrow())
+        .ok_or_else(|| CustodyError::status_conflict("custody canister not configured", "custody canister configured"))
+}
+
+// Page size when reading every account from custody_core
+const CUSTODY_ACCOUNT_PAGE_SIZE: u32 = 500;
+
+/// Every open account known to custody_core, read a page per call so the
+/// number of calls grows with the account count divided by the page size
+async fn fetch_all_custody_accounts(custody_canister: Principal) -> Result<Vec<CustodyAccountSummary>, CustodyError> {
+    let mut accounts = Vec::new();
+    let mut after_id: Option<String> = None;
+    
+    loop {
+        let response: Result<(Result<Vec<CustodyAccountSummary>, CustodyError>,), _> = ic_cdk::call(
+            custody_canister,
+            "list_account_balances",
+            (after_id.clone(), CUSTODY_ACCOUNT_PAGE_SIZE),
+        ).await;
+        
+        let page = match response {
+            Ok((Ok(page),)) => page,
+            Ok((Err(e),)) => return Err(e),
+            Err((code, msg)) => return Err(CustodyError::InternalError(format!(
+                "Failed to list accounts: {:?} {}", code, msg
+            ))),
+        };
+        
+        match page.last() {
+            Some(last) => after_id = Some(last.id.clone()),
+            None => break,
+        }
+        accounts.extend(page);
+    }
+    
+    Ok(accounts)
+}
+
+#[update]
+async fn set_custody_canister(canister_id: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_custody_canister")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("set_custody_canister"));
+    }
+    
+    CUSTODY_CANISTER.with(|c| {
+        *c.borrow_mut() = Some(canister_id);
+    });
+    
+    Ok("Custody canister configured successfully".to_string())
+}
+
+// === Regulatory Capital Functions ===
+
+/// Weighs every custody account's balance by its account type and compares
+/// the resulting Basel III minimums with the capital on record
+#[update]
+async fn compute_regulatory_capital() -> Result<RegulatoryCapital, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "compute_regulatory_capital")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("compute_regulatory_capital"));
+    }
+    
+    let custody_canister = configured_custody_canister()?;
+    
+    // Capital is held against every account, not just the ones assessed here
+    let accounts = fetch_all_custody_accounts(custody_canister).await?;
+    
+    let mut total_rwa: u64 = 0;
+    for account in accounts {
+        let weighted = account.balance as u128 * risk_weight_percent(&account.account_type) as u128 / 100;
+        total_rwa = total_rwa.saturating_add(weighted as u64);
+    }
+    
+    let actual_capital = ACTUAL_CAPITAL.with(|c| *c.borrow());
+    let tier1_capital_required = (total_rwa as u128 * TIER1_CAPITAL_PERCENT as u128 / 100) as u64;
+    let tier2_capital_required = (total_rwa as u128 * TIER2_CAPITAL_PERCENT as u128 / 100) as u64;
+    
+    let capital_adequacy_ratio = if total_rwa == 0 {
+        u32::MAX
+    } else {
+        (actual_capital as u128 * 10_000 / total_rwa as u128).min(u32::MAX as u128) as u32
+    };
+    
+    let current_time = ic_cdk::api::time();
+    let capital = RegulatoryCapital {
+        total_rwa,
+        tier1_capital_required,
+        tier2_capital_required,
+        actual_capital,
+        capital_adequacy_ratio,
+        last_computed_at: current_time,
+        breached: actual_capital < tier1_capital_required,
+    };
+    
+    if capital.breached {
+        ic_cdk::println!(
+            "ALERT: actual capital {} is below the tier 1 requirement {}",
+            actual_capital, tier1_capital_required
+        );
+    }
+    
+    CAPITAL_HISTORY.with(|history| {
+        let mut history = history.borrow_mut();
+        let cutoff = current_time.saturating_sub(CAPITAL_HISTORY_RETENTION_NANOS);
+        while history.front().is_some_and(|(computed_at, _)| *computed_at < cutoff) {
+            history.pop_front();
+        }
+        history.push_back((current_time, capital.clone()));
+    });
+    
+    Ok(capital)
+}
+
+#[update]
+async fn set_actual_capital(amount: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_actual_capital")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("set_actual_capital"));
+    }
+    
+    ACTUAL_CAPITAL.with(|c| {
+        *c.borrow_mut() = amount;
+    });
+    
+    Ok("Actual capital updated successfully".to_string())
+}
+
+#[query]
+fn get_capital_history() -> Vec<(u64, RegulatoryCapital)> {
+    CAPITAL_HISTORY.with(|history| history.borrow().iter().cloned().collect())
+}
+
+// Standardised-approach risk weights, as a percentage of the balance
+fn risk_weight_percent(account_type: &AccountType) -> u64 {
+    match account_type {
+        AccountType::GovernmentCustody => 0,
+        AccountType::InstitutionalCustody => 50,
+        AccountType::CorporateCustody => 100,
+        AccountType::TrustCustody => 100,
+    }
+}
+
+// === Counterparty Functions ===
+
+#[update]
+async fn register_counterparty(
+    address: String,
+    risk_level: RiskLevel,
+    jurisdiction: Option<String>,
+) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "register_counterparty")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("register_counterparty"));
+    }
+    
+    if address.is_empty() {
+        return Err(CustodyError::invalid_input("address", "cannot be empty"));
+    }
+    
+    // Re-registering keeps the sanctions flag and transaction history
+    COUNTERPARTY_DB.with(|db| {
+        let mut db = db.borrow_mut();
+        let profile = db.entry(address.clone()).or_insert_with(|| CounterpartyProfile {
+            address: address.clone(),
+            risk_level: RiskLevel::Low,
+            is_known: true,
+            is_sanctioned: false,
+            jurisdiction: None,
+            last_transacted_at: None,
+            total_received: 0,
+        });
+        profile.risk_level = risk_level;
+        profile.is_known = true;
+        profile.jurisdiction = jurisdiction;
+    });
+    
+    Ok("Counterparty registered successfully".to_string())
+}
+
+/// Flags or clears sanctions on a counterparty. Flagging an address with no
+/// profile creates one; clearing needs an existing profile
+#[update]
+async fn set_counterparty_sanctioned(address: String, sanctioned: bool) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_counterparty_sanctioned")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("set_counterparty_sanctioned"));
+    }
+    
+    COUNTERPARTY_DB.with(|db| {
+        let mut db = db.borrow_mut();
+        if !sanctioned && !db.contains_key(&address) {
+            return Err(CustodyError::not_found("Counterparty", address.clone()));
+        }
+        
+        let profile = db.entry(address.clone()).or_insert_with(|| CounterpartyProfile {
+            address: address.clone(),
+            risk_level: RiskLevel::Critical,
+            is_known: false,
+            is_sanctioned: false,
+            jurisdiction: None,
+            last_transacted_at: None,
+            total_received: 0,
+        });
+        profile.is_sanctioned = sanctioned;
+        Ok(())
+    })?;
+    
+    Ok("Counterparty sanctions flag updated".to_string())
+}
+
+#[query]
+fn lookup_counterparty_risk(address: String) -> Option<CounterpartyProfile> {
+    COUNTERPARTY_DB.with(|db| db.borrow().get(&address).cloned())
+}
+
+fn is_known_counterparty(address: &str) -> bool {
+    KNOWN_COUNTERPARTIES.with(|k| k.borrow().contains(address))
+        || COUNTERPARTY_DB.with(|db| db.borrow().get(address).is_some_and(|p| p.is_known))
+}
+
+fn counterparty_risk_score(address: &str) -> f32 {
+    let profile = match COUNTERPARTY_DB.with(|db| db.borrow().get(address).cloned()) {
+        Some(p) => p,
+        None => return 0.0,
+    };
+    
+    if profile.is_sanctioned {
+        return 1.0;
+    }
+    
+    let high_risk_jurisdiction = profile.jurisdiction.as_ref()
+        .is_some_and(|j| HIGH_RISK_JURISDICTIONS.with(|h| h.borrow().contains(j)));
+    if high_risk_jurisdiction {
+        return 1.0;
+    }
+    
+    match profile.risk_level {
+        RiskLevel::Low => 0.0,
+        RiskLevel::Medium => 0.3,
+        RiskLevel::High => 0.7,
+        RiskLevel::Critical => 1.0,
+    }
+}
+
+fn record_counterparty_activity(address: &str, amount: u64, current_time: u64) {
+    COUNTERPARTY_DB.with(|db| {
+        if let Some(profile) = db.borrow_mut().get_mut(address) {
+            profile.last_transacted_at = Some(current_time);
+            profile.total_received = profile.total_received.saturating_add(amount);
+        }
+    });
+}
+
+// === Cycle Monitoring Functions ===
+
+#[query]
+fn check_cycle_balance() -> u64 {
+    cycles::check_cycle_balance()
+}
+
+#[query]
+fn get_cycle_stats() -> CycleStats {
+    cycles::get_cycle_stats()
+}
+
+#[query]
+fn get_memory_stats() -> MemoryStats {
+    memory::get_memory_stats()
+}
+
+#[update]
+async fn set_cycle_alert_threshold(threshold: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_cycle_alert_threshold")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("set_cycle_alert_threshold"));
+    }
+    
+    cycles::set_cycle_alert_threshold(threshold);
+    
+    Ok("Cycle alert threshold updated successfully".to_string())
+}
+
+#[update]
+async fn set_cycle_alert_canister(alert_canister: Option<Principal>) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "set_cycle_alert_canister")?;
+    
+    if !is_risk_manager(caller).await {
+        return Err(CustodyError::unauthorized("set_cycle_alert_canister"));
+    }
+    
+    cycles::set_cycle_alert_canister(alert_canister);
+    
+    Ok("Cycle alert canister updated successfully".to_string())
 }
 
 #[query]
@@ -31,4 +1333,16 @@ fn health_check() -> String {
     "Risk Management canister is healthy".to_string()
 }
 
+const INTERFACE_VERSION: InterfaceVersion = (1, 0, 0);
+
+#[query]
+fn get_candid_interface_version() -> u32 {
+    INTERFACE_VERSION.0
+}
+
+#[query]
+fn get_interface_metadata() -> CandidVersion {
+    CandidVersion::new(INTERFACE_VERSION, &__export_service())
+}
+
 ic_cdk::export_candid!();
@@ -0,0 +1,260 @@
+use candid::{CandidType, Principal};
+use futures::future::join_all;
+use ic_cdk::api::management_canister::main::{canister_status, CanisterIdRecord};
+use ic_cdk_macros::{init, post_upgrade, query, update};
+use serde::{Deserialize, Serialize};
+use shared::auth::{has_role, set_auth_canister};
+use shared::{check_rate_limit, CustodyError};
+use shared::interface::{CandidVersion, InterfaceVersion};
+use shared::memory::{self, MemoryStats};
+use std::cell::RefCell;
+use std::collections::{BTreeMap, BTreeSet};
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize, PartialEq)]
+pub enum HealthStatus {
+    Healthy,
+    Degraded(String),
+    Unreachable,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CanisterHealth {
+    pub canister_id: String,
+    pub last_checked: u64,
+    pub status: HealthStatus,
+    pub response_time_ns: u64,
+    // 0 when the monitor is not a controller and can't read the balance
+    pub cycle_balance: u64,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct SystemHealth {
+    pub all_healthy: bool,
+    pub degraded: Vec<String>,
+}
+
+// Responses slower than this mark the canister as degraded
+const SLOW_RESPONSE_NANOS: u64 = 5 * 1_000_000_000;
+
+// Cycle balances below this mark the canister as degraded
+const LOW_CYCLE_BALANCE: u64 = 1_000_000_000_000;
+
+thread_local! {
+    // canister_id -> display name
+    static MONITORED_CANISTERS: RefCell<BTreeMap<String, String>> = const { RefCell::new(BTreeMap::new()) };
+    static HEALTH_STATES: RefCell<BTreeMap<String, CanisterHealth>> = const { RefCell::new(BTreeMap::new()) };
+    static MONITOR_OPERATORS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+#[init]
+fn init(auth_canister: Option<Principal>) {
+    ic_cdk::println!("System Monitor canister initialized");
+    
+    set_auth_canister(auth_canister);
+    memory::start_memory_monitor(memory::log_memory_alert);
+    
+    // Initialize with deployer as operator
+    MONITOR_OPERATORS.with(|operators| {
+        operators.borrow_mut().insert(ic_cdk::caller());
+    });
+}
+
+#[post_upgrade]
+fn post_upgrade(auth_canister: Option<Principal>) {
+    set_auth_canister(auth_canister);
+    memory::start_memory_monitor(memory::log_memory_alert);
+}
+
+// === Registration Functions ===
+
+#[update]
+async fn register_monitored_canister(id: Principal, name: String) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "register_monitored_canister")?;
+    
+    if !is_monitor_operator(caller).await {
+        return Err(CustodyError::unauthorized("register_monitored_canister"));
+    }
+    
+    if name.is_empty() {
+        return Err(CustodyError::invalid_input("name", "cannot be empty"));
+    }
+    
+    MONITORED_CANISTERS.with(|canisters| {
+        canisters.borrow_mut().insert(id.to_string(), name);
+    });
+    
+    Ok("Canister registered for monitoring".to_string())
+}
+
+#[update]
+async fn unregister_monitored_canister(id: Principal) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "unregister_monitored_canister")?;
+    
+    if !is_monitor_operator(caller).await {
+        return Err(CustodyError::unauthorized("unregister_monitored_canister"));
+    }
+    
+    let canister_id = id.to_string();
+    
+    let removed = MONITORED_CANISTERS.with(|canisters| {
+        canisters.borrow_mut().remove(&canister_id).is_some()
+    });
+    
+    if !removed {
+        return Err(CustodyError::not_found("Monitored canister", canister_id));
+    }
+    
+    HEALTH_STATES.with(|states| {
+        states.borrow_mut().remove(&canister_id);
+    });
+    
+    Ok("Canister removed from monitoring".to_string())
+}
+
+// === Health Check Functions ===
+
+#[update]
+async fn check_all_canisters() -> Vec<CanisterHealth> {
+    if let Err(e) = check_rate_limit(ic_cdk::caller(), "check_all_canisters") {
+        ic_cdk::trap(&e.to_string());
+    }
+    
+    let canister_ids: Vec<String> = MONITORED_CANISTERS.with(|canisters| {
+        canisters.borrow().keys().cloned().collect()
+    });
+    
+    // All canisters are probed concurrently so one slow canister doesn't
+    // delay the others
+    let results = join_all(canister_ids.into_iter().map(probe_canister)).await;
+    
+    HEALTH_STATES.with(|states| {
+        let mut states = states.borrow_mut();
+        for health in &results {
+            states.insert(health.canister_id.clone(), health.clone());
+        }
+    });
+    
+    results
+}
+
+async fn probe_canister(canister_id: String) -> CanisterHealth {
+    let started_at = ic_cdk::api::time();
+    
+    let principal = match Principal::from_text(&canister_id) {
+        Ok(principal) => principal,
+        Err(_) => return unreachable_health(canister_id, started_at),
+    };
+    
+    let response: Result<(String,), _> = ic_cdk::call(principal, "health_check", ()).await;
+    
+    let finished_at = ic_cdk::api::time();
+    let response_time_ns = finished_at.saturating_sub(started_at);
+    
+    if response.is_err() {
+        return unreachable_health(canister_id, finished_at);
+    }
+    
+    // Reading the balance requires the monitor to be a controller of the canister
+    let cycle_balance = match canister_status(CanisterIdRecord { canister_id: principal }).await {
+        Ok((status,)) => Some(u64::try_from(status.cycles.0).unwrap_or(u64::MAX)),
+        Err(_) => None,
+    };
+    
+    let status = if response_time_ns > SLOW_RESPONSE_NANOS {
+        HealthStatus::Degraded(format!("Slow response: {} ms", response_time_ns / 1_000_000))
+    } else if matches!(cycle_balance, Some(balance) if balance < LOW_CYCLE_BALANCE) {
+        HealthStatus::Degraded("Low cycle balance".to_string())
+    } else {
+        HealthStatus::Healthy
+    };
+    
+    CanisterHealth {
+        canister_id,
+        last_checked: finished_at,
+        status,
+        response_time_ns,
+        cycle_balance: cycle_balance.unwrap_or(0),
+    }
+}
+
+fn unreachable_health(canister_id: String, checked_at: u64) -> CanisterHealth {
+    CanisterHealth {
+        canister_id,
+        last_checked: checked_at,
+        status: HealthStatus::Unreachable,
+        response_time_ns: 0,
+        cycle_balance: 0,
+    }
+}
+
+// === Query Functions ===
+
+#[query]
+fn get_canister_health(canister_id: String) -> Option<CanisterHealth> {
+    HEALTH_STATES.with(|states| states.borrow().get(&canister_id).cloned())
+}
+
+#[query]
+fn get_system_health_summary() -> SystemHealth {
+    let names = MONITORED_CANISTERS.with(|canisters| canisters.borrow().clone());
+    
+    let degraded: Vec<String> = HEALTH_STATES.with(|states| {
+        let states = states.borrow();
+        names.iter()
+            .filter(|(canister_id, _)| {
+                // Canisters that have never been checked count as not healthy
+                !matches!(
+                    states.get(*canister_id).map(|health| &health.status),
+                    Some(HealthStatus::Healthy)
+                )
+            })
+            .map(|(_, name)| name.clone())
+            .collect()
+    });
+    
+    SystemHealth {
+        all_healthy: degraded.is_empty(),
+        degraded,
+    }
+}
+
+#[query]
+fn list_monitored_canisters() -> Vec<(String, String)> {
+    MONITORED_CANISTERS.with(|canisters| {
+        canisters.borrow()
+            .iter()
+            .map(|(id, name)| (id.clone(), name.clone()))
+            .collect()
+    })
+}
+
+async fn is_monitor_operator(principal: Principal) -> bool {
+    MONITOR_OPERATORS.with(|operators| operators.borrow().contains(&principal))
+        || has_role(principal, "operator").await
+}
+
+#[query]
+fn get_memory_stats() -> MemoryStats {
+    memory::get_memory_stats()
+}
+
+#[query]
+fn health_check() -> String {
+    "System Monitor canister is healthy".to_string()
+}
+
+const INTERFACE_VERSION: InterfaceVersion = (1, 0, 0);
+
+#[query]
+fn get_candid_interface_version() -> u32 {
+    INTERFACE_VERSION.0
+}
+
+#[query]
+fn get_interface_metadata() -> CandidVersion {
+    CandidVersion::new(INTERFACE_VERSION, &__export_service())
+}
+
+ic_cdk::export_candid!();
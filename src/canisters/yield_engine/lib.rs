@@ -1,13 +1,32 @@
-use candid::{CandidType, Deserialize};
+use candid::{CandidType, Decode, Deserialize, Encode, Nat, Principal};
 use ic_cdk_macros::*;
-use std::collections::HashMap;
+use ic_stable_structures::memory_manager::{MemoryId, MemoryManager, VirtualMemory};
+use ic_stable_structures::storable::Bound;
+use ic_stable_structures::{DefaultMemoryImpl, StableBTreeMap, StableCell, Storable};
+use serde::Serialize;
+use shared::interface::{CandidVersion, InterfaceVersion};
+use shared::memory::{self, MemoryStats};
+use shared::{check_rate_limit, CustodyError};
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::time::Duration;
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
 pub struct YieldStrategy {
     pub name: String,
-    pub apy: f64,
+    pub apy_basis_points: u64, // 100 basis points = 1%
     pub risk_level: u8,
     pub is_active: bool,
+    pub withdrawal_lock_period_seconds: u64,
+    pub max_user_deposit: u64,
+    pub max_total_tvl: u64,
+    pub pause_reason: Option<String>,
+    // Yield stops accruing at this time once the strategy is wound down
+    pub wound_down_at: Option<u64>,
+    // Commitment period; leaving earlier costs the penalty below. Optional
+    // so strategies stored before lock periods existed still decode
+    pub lock_period_days: Option<u32>,
+    pub early_exit_penalty_basis_points: Option<u32>,
 }
 
 #[derive(Clone, Debug, CandidType, Deserialize)]
@@ -16,34 +35,299 @@ pub struct YieldPosition {
     pub amount: u64,
     pub start_time: u64,
     pub accumulated_yield: u64,
+    pub deposit_time: u64,
+    // Roll accrued yield into `amount` on each compounding run. Optional so
+    // positions stored before auto-compounding existed still decode
+    pub auto_compound: Option<bool>,
+    // End of the strategy's commitment period at deposit time
+    pub locked_until: Option<u64>,
+    // Ledger holding the deposited tokens; None for accounting-only deposits
+    pub token_canister_id: Option<Principal>,
 }
 
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct PortfolioAllocation {
+    pub strategy_name: String,
+    pub allocation_percent: u8,
+    // Index into the owner's positions; kept current as positions are removed
+    pub position_index: u32,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct Portfolio {
+    pub id: String,
+    pub owner: String,
+    pub allocations: Vec<PortfolioAllocation>,
+    pub total_deposited: u64,
+    pub created_at: u64,
+}
+
+// The referrer's position on a referred deposit. It accrues a share of the
+// yield the referred amount generates, paid out by claim_referral_bonus
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ReferralBonus {
+    pub referred_user: String,
+    pub strategy: String,
+    pub referred_amount: u64,
+    pub bonus_rate_basis_points: u64,
+    pub start_time: u64,
+    // Settled and not yet claimed; optional so older checkpoints still decode
+    pub accumulated_bonus: Option<u64>,
+    // Ledger the referred deposit was made on, which pays the bonus
+    pub token_canister_id: Option<Principal>,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize, Default)]
+pub struct ReferralStats {
+    pub referred_users: u32,
+    pub total_referred_deposits: u64,
+    // Bonuses settled so far; live accrual is added on top when queried
+    pub earned_bonuses: u64,
+}
+
+// An APY that was in force until `timestamp`, when it was replaced
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct ApySnapshot {
+    pub timestamp: u64,
+    pub apy_basis_points: u64,
+    pub updated_by: Principal,
+}
+
+#[derive(Clone, Debug, CandidType, Deserialize)]
+pub struct CompoundEvent {
+    pub position_id: String,
+    pub timestamp: u64,
+    pub compounded_amount: u64,
+    pub new_principal: u64,
+}
+
+// ICRC-1/ICRC-2 ledger types, limited to what the deposit and transfer calls use
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+struct Icrc1Account {
+    owner: Principal,
+    subaccount: Option<Vec<u8>>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+struct Icrc1TransferArg {
+    from_subaccount: Option<Vec<u8>>,
+    to: Icrc1Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+struct Icrc2AllowanceArgs {
+    account: Icrc1Account,
+    spender: Icrc1Account,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+struct Icrc2Allowance {
+    allowance: Nat,
+    expires_at: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+struct Icrc2TransferFromArgs {
+    spender_subaccount: Option<Vec<u8>>,
+    from: Icrc1Account,
+    to: Icrc1Account,
+    amount: Nat,
+    fee: Option<Nat>,
+    memo: Option<Vec<u8>>,
+    created_at_time: Option<u64>,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+enum Icrc1TransferError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+enum Icrc2TransferFromError {
+    BadFee { expected_fee: Nat },
+    BadBurn { min_burn_amount: Nat },
+    InsufficientFunds { balance: Nat },
+    InsufficientAllowance { allowance: Nat },
+    TooOld,
+    CreatedInFuture { ledger_time: u64 },
+    Duplicate { duplicate_of: Nat },
+    TemporarilyUnavailable,
+    GenericError { error_code: Nat, message: String },
+}
+
+// Positions of one user, stored as a single stable map value
+#[derive(Clone, Debug, CandidType, Deserialize)]
+struct StoredPositions(Vec<YieldPosition>);
+
+#[derive(Clone, Debug, CandidType, Deserialize, Default)]
+struct UpgradeCheckpoint {
+    upgraded_at: u64,
+    yield_admin: Option<Principal>,
+    // Optional so checkpoints written before APY history existed still decode
+    apy_history: Option<BTreeMap<String, VecDeque<ApySnapshot>>>,
+    portfolios: Option<BTreeMap<String, Portfolio>>,
+    next_portfolio_id: Option<u64>,
+    referrals: Option<BTreeMap<String, String>>,
+    referral_bonuses: Option<BTreeMap<String, Vec<ReferralBonus>>>,
+    referral_stats: Option<BTreeMap<String, ReferralStats>>,
+    referral_bonus_rate: Option<u64>,
+    // An older single-total treasury decodes as None, since it can't be
+    // attributed to a ledger
+    penalty_treasury: Option<BTreeMap<Principal, u64>>,
+    canister_token_balances: Option<BTreeMap<Principal, u64>>,
+    compound_history: Option<BTreeMap<String, Vec<CompoundEvent>>>,
+}
+
+impl Storable for YieldStrategy {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode YieldStrategy"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode YieldStrategy")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Storable for StoredPositions {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode positions"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode positions")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+impl Storable for UpgradeCheckpoint {
+    fn to_bytes(&self) -> Cow<'_, [u8]> {
+        Cow::Owned(Encode!(self).expect("Failed to encode checkpoint"))
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        Decode!(bytes.as_ref(), Self).expect("Failed to decode checkpoint")
+    }
+
+    const BOUND: Bound = Bound::Unbounded;
+}
+
+type Memory = VirtualMemory<DefaultMemoryImpl>;
+
+const STRATEGIES_MEMORY_ID: MemoryId = MemoryId::new(0);
+const POSITIONS_MEMORY_ID: MemoryId = MemoryId::new(1);
+const CHECKPOINT_MEMORY_ID: MemoryId = MemoryId::new(2);
+
+const NANOS_PER_DAY: u64 = 24 * 60 * 60 * 1_000_000_000;
+const NANOS_PER_YEAR: u128 = 365 * 24 * 60 * 60 * 1_000_000_000;
+const BASIS_POINTS: u128 = 10_000;
+// Fixed-point scale for the exponent series
+const YIELD_SCALE: u128 = 1_000_000_000_000;
+const MAX_APY_HISTORY: usize = 1000;
+// Referrers earn this share of their referrals' yield unless the admin changes it
+const DEFAULT_REFERRAL_BONUS_RATE: u64 = 500;
+const COMPOUND_INTERVAL: Duration = Duration::from_secs(24 * 60 * 60);
+const YIELD_OVERFLOW: &str = "Yield calculation overflowed";
+
 thread_local! {
     static YIELD_STRATEGIES: std::cell::RefCell<HashMap<String, YieldStrategy>> = std::cell::RefCell::new(HashMap::new());
     static USER_POSITIONS: std::cell::RefCell<HashMap<String, Vec<YieldPosition>>> = std::cell::RefCell::new(HashMap::new());
+    static STRATEGY_TVL: std::cell::RefCell<BTreeMap<String, u64>> = const { std::cell::RefCell::new(BTreeMap::new()) };
+    // (user, strategy) -> principal currently deposited
+    static USER_STRATEGY_DEPOSITS: std::cell::RefCell<BTreeMap<(String, String), u64>> = const { std::cell::RefCell::new(BTreeMap::new()) };
+    static YIELD_ADMIN: std::cell::RefCell<Option<Principal>> = const { std::cell::RefCell::new(None) };
+    // strategy -> superseded rates, oldest first
+    static APY_HISTORY: std::cell::RefCell<BTreeMap<String, VecDeque<ApySnapshot>>> = const { std::cell::RefCell::new(BTreeMap::new()) };
+    static PORTFOLIOS: std::cell::RefCell<BTreeMap<String, Portfolio>> = const { std::cell::RefCell::new(BTreeMap::new()) };
+    static NEXT_PORTFOLIO_ID: std::cell::RefCell<u64> = const { std::cell::RefCell::new(0) };
+    // referred user -> referrer
+    static REFERRALS: std::cell::RefCell<BTreeMap<String, String>> = const { std::cell::RefCell::new(BTreeMap::new()) };
+    // referrer -> bonuses on each referred deposit
+    static REFERRAL_BONUSES: std::cell::RefCell<BTreeMap<String, Vec<ReferralBonus>>> = const { std::cell::RefCell::new(BTreeMap::new()) };
+    static REFERRAL_STATS: std::cell::RefCell<BTreeMap<String, ReferralStats>> = const { std::cell::RefCell::new(BTreeMap::new()) };
+    static REFERRAL_BONUS_RATE: std::cell::RefCell<u64> = const { std::cell::RefCell::new(DEFAULT_REFERRAL_BONUS_RATE) };
+    // token ledger -> tokens this canister holds on it, as last moved or reconciled
+    static CANISTER_TOKEN_BALANCES: std::cell::RefCell<BTreeMap<Principal, u64>> = const { std::cell::RefCell::new(BTreeMap::new()) };
+    // token ledger -> early-exit penalties collected on it and not yet claimed
+    // by the admin; accounting-only positions have no tokens to collect
+    static PENALTY_TREASURY: std::cell::RefCell<BTreeMap<Principal, u64>> = const { std::cell::RefCell::new(BTreeMap::new()) };
+    // user -> compounding events across that user's positions
+    static COMPOUND_HISTORY: std::cell::RefCell<BTreeMap<String, Vec<CompoundEvent>>> = const { std::cell::RefCell::new(BTreeMap::new()) };
+
+    // Stable memory, written in pre_upgrade and read back in post_upgrade
+    static MEMORY_MANAGER: std::cell::RefCell<MemoryManager<DefaultMemoryImpl>> =
+        std::cell::RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+    static STABLE_STRATEGIES: std::cell::RefCell<StableBTreeMap<String, YieldStrategy, Memory>> = std::cell::RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(STRATEGIES_MEMORY_ID)))
+    );
+    static STABLE_POSITIONS: std::cell::RefCell<StableBTreeMap<String, StoredPositions, Memory>> = std::cell::RefCell::new(
+        StableBTreeMap::init(MEMORY_MANAGER.with(|m| m.borrow().get(POSITIONS_MEMORY_ID)))
+    );
+    static UPGRADE_CHECKPOINT: std::cell::RefCell<StableCell<UpgradeCheckpoint, Memory>> = std::cell::RefCell::new(
+        StableCell::init(MEMORY_MANAGER.with(|m| m.borrow().get(CHECKPOINT_MEMORY_ID)), UpgradeCheckpoint::default())
+            .expect("Failed to initialize upgrade checkpoint")
+    );
 }
 
 #[init]
 fn init() {
+    YIELD_ADMIN.with(|a| *a.borrow_mut() = Some(ic_cdk::caller()));
+    ic_cdk_timers::set_timer_interval(COMPOUND_INTERVAL, compound_positions);
+    memory::start_memory_monitor(memory::log_memory_alert);
+
     // Initialize default yield strategies
     let strategies = vec![
         YieldStrategy {
             name: "BTC Staking".to_string(),
-            apy: 5.2,
+            apy_basis_points: 520,
             risk_level: 2,
             is_active: true,
+            withdrawal_lock_period_seconds: 7 * 24 * 60 * 60,
+            max_user_deposit: 10_000_000_000, // 100 BTC
+            max_total_tvl: 1_000_000_000_000, // 10,000 BTC
+            pause_reason: None,
+            wound_down_at: None,
+            lock_period_days: Some(90),
+            early_exit_penalty_basis_points: Some(100),
         },
         YieldStrategy {
             name: "ICP Staking".to_string(),
-            apy: 8.5,
+            apy_basis_points: 850,
             risk_level: 3,
             is_active: true,
+            withdrawal_lock_period_seconds: 30 * 24 * 60 * 60,
+            max_user_deposit: 10_000_000_000, // 100 BTC
+            max_total_tvl: 1_000_000_000_000, // 10,000 BTC
+            pause_reason: None,
+            wound_down_at: None,
+            lock_period_days: Some(180),
+            early_exit_penalty_basis_points: Some(250),
         },
         YieldStrategy {
             name: "Stable Yield".to_string(),
-            apy: 3.8,
+            apy_basis_points: 380,
             risk_level: 1,
             is_active: true,
+            withdrawal_lock_period_seconds: 0,
+            max_user_deposit: 10_000_000_000, // 100 BTC
+            max_total_tvl: 1_000_000_000_000, // 10,000 BTC
+            pause_reason: None,
+            wound_down_at: None,
+            lock_period_days: Some(0),
+            early_exit_penalty_basis_points: Some(0),
         },
     ];
 
@@ -55,6 +339,111 @@ fn init() {
     });
 }
 
+#[pre_upgrade]
+fn pre_upgrade() {
+    STABLE_STRATEGIES.with(|stable| {
+        let mut stable = stable.borrow_mut();
+        let stale: Vec<String> = stable.iter().map(|(k, _)| k).collect();
+        for key in stale {
+            stable.remove(&key);
+        }
+        YIELD_STRATEGIES.with(|s| {
+            for (name, strategy) in s.borrow().iter() {
+                stable.insert(name.clone(), strategy.clone());
+            }
+        });
+    });
+
+    STABLE_POSITIONS.with(|stable| {
+        let mut stable = stable.borrow_mut();
+        let stale: Vec<String> = stable.iter().map(|(k, _)| k).collect();
+        for key in stale {
+            stable.remove(&key);
+        }
+        USER_POSITIONS.with(|p| {
+            for (user, positions) in p.borrow().iter() {
+                stable.insert(user.clone(), StoredPositions(positions.clone()));
+            }
+        });
+    });
+
+    let checkpoint = UpgradeCheckpoint {
+        upgraded_at: ic_cdk::api::time(),
+        yield_admin: YIELD_ADMIN.with(|a| *a.borrow()),
+        apy_history: Some(APY_HISTORY.with(|h| h.borrow().clone())),
+        portfolios: Some(PORTFOLIOS.with(|p| p.borrow().clone())),
+        next_portfolio_id: Some(NEXT_PORTFOLIO_ID.with(|n| *n.borrow())),
+        referrals: Some(REFERRALS.with(|r| r.borrow().clone())),
+        referral_bonuses: Some(REFERRAL_BONUSES.with(|b| b.borrow().clone())),
+        referral_stats: Some(REFERRAL_STATS.with(|s| s.borrow().clone())),
+        referral_bonus_rate: Some(REFERRAL_BONUS_RATE.with(|r| *r.borrow())),
+        penalty_treasury: Some(PENALTY_TREASURY.with(|t| t.borrow().clone())),
+        canister_token_balances: Some(CANISTER_TOKEN_BALANCES.with(|b| b.borrow().clone())),
+        compound_history: Some(COMPOUND_HISTORY.with(|h| h.borrow().clone())),
+    };
+
+    UPGRADE_CHECKPOINT.with(|c| {
+        c.borrow_mut().set(checkpoint).expect("Failed to save upgrade checkpoint");
+    });
+}
+
+#[post_upgrade]
+fn post_upgrade() {
+    let checkpoint = UPGRADE_CHECKPOINT.with(|c| c.borrow().get().clone());
+
+    // No yield accrues while the canister is being upgraded
+    let upgrade_gap = ic_cdk::api::time().saturating_sub(checkpoint.upgraded_at);
+
+    YIELD_ADMIN.with(|a| *a.borrow_mut() = checkpoint.yield_admin);
+    APY_HISTORY.with(|h| {
+        let mut history = checkpoint.apy_history.unwrap_or_default();
+        for snapshot in history.values_mut().flatten() {
+            snapshot.timestamp = snapshot.timestamp.saturating_add(upgrade_gap);
+        }
+        *h.borrow_mut() = history;
+    });
+    PORTFOLIOS.with(|p| *p.borrow_mut() = checkpoint.portfolios.unwrap_or_default());
+    NEXT_PORTFOLIO_ID.with(|n| *n.borrow_mut() = checkpoint.next_portfolio_id.unwrap_or_default());
+    REFERRALS.with(|r| *r.borrow_mut() = checkpoint.referrals.unwrap_or_default());
+    REFERRAL_BONUSES.with(|b| {
+        let mut bonuses = checkpoint.referral_bonuses.unwrap_or_default();
+        for bonus in bonuses.values_mut().flatten() {
+            bonus.start_time = bonus.start_time.saturating_add(upgrade_gap);
+        }
+        *b.borrow_mut() = bonuses;
+    });
+    REFERRAL_STATS.with(|s| *s.borrow_mut() = checkpoint.referral_stats.unwrap_or_default());
+    REFERRAL_BONUS_RATE.with(|r| *r.borrow_mut() = checkpoint.referral_bonus_rate.unwrap_or(DEFAULT_REFERRAL_BONUS_RATE));
+    PENALTY_TREASURY.with(|t| *t.borrow_mut() = checkpoint.penalty_treasury.unwrap_or_default());
+    CANISTER_TOKEN_BALANCES.with(|b| *b.borrow_mut() = checkpoint.canister_token_balances.unwrap_or_default());
+    COMPOUND_HISTORY.with(|h| *h.borrow_mut() = checkpoint.compound_history.unwrap_or_default());
+
+    STABLE_STRATEGIES.with(|stable| {
+        YIELD_STRATEGIES.with(|s| {
+            let mut strategies = s.borrow_mut();
+            for (name, mut strategy) in stable.borrow().iter() {
+                strategy.wound_down_at = strategy.wound_down_at.map(|t| t.saturating_add(upgrade_gap));
+                strategies.insert(name, strategy);
+            }
+        });
+    });
+
+    STABLE_POSITIONS.with(|stable| {
+        for (user, StoredPositions(mut positions)) in stable.borrow().iter() {
+            for position in positions.iter_mut() {
+                position.start_time = position.start_time.saturating_add(upgrade_gap);
+                record_deposit(&user, &position.strategy, position.amount);
+            }
+            USER_POSITIONS.with(|p| {
+                p.borrow_mut().insert(user, positions);
+            });
+        }
+    });
+
+    ic_cdk_timers::set_timer_interval(COMPOUND_INTERVAL, compound_positions);
+    memory::start_memory_monitor(memory::log_memory_alert);
+}
+
 #[query]
 fn get_yield_strategies() -> Vec<YieldStrategy> {
     YIELD_STRATEGIES.with(|s| {
@@ -63,41 +452,1446 @@ fn get_yield_strategies() -> Vec<YieldStrategy> {
 }
 
 #[update]
-fn deposit_for_yield(strategy_name: String, amount: u64) -> Result<String, String> {
+fn deposit_for_yield(strategy_name: String, amount: u64) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "deposit_for_yield")?;
+
     let caller = ic_cdk::caller().to_string();
-    
-    // Check if strategy exists
-    let strategy_exists = YIELD_STRATEGIES.with(|s| {
-        s.borrow().contains_key(&strategy_name)
-    });
 
-    if !strategy_exists {
-        return Err("Strategy not found".to_string());
+    validate_deposit(&caller, &strategy_name, amount)?;
+    open_position(&caller, &strategy_name, amount, None, ic_cdk::api::time());
+
+    Ok(format!("Deposited {} to {}", amount, strategy_name))
+}
+
+/// Deposits ICRC tokens into a strategy. The caller approves this canister on
+/// the token ledger first; the allowance is checked up front so an obviously
+/// short approval fails before icrc2_transfer_from is attempted.
+#[update]
+async fn initiate_deposit(strategy_name: String, token_canister_id: Principal, amount: u64) -> Result<String, CustodyError> {
+    let caller = ic_cdk::caller();
+    check_rate_limit(caller, "initiate_deposit")?;
+    let user = caller.to_string();
+
+    if amount == 0 {
+        return Err(CustodyError::invalid_input("amount", "must be greater than zero"));
+    }
+
+    validate_deposit(&user, &strategy_name, amount)?;
+
+    let allowance_args = Icrc2AllowanceArgs {
+        account: Icrc1Account { owner: caller, subaccount: None },
+        spender: Icrc1Account { owner: ic_cdk::id(), subaccount: None },
+    };
+
+    let allowance: Result<(Icrc2Allowance,), _> =
+        ic_cdk::call(token_canister_id, "icrc2_allowance", (allowance_args,)).await;
+
+    let allowance = match allowance {
+        Ok((allowance,)) => u64::try_from(allowance.allowance.0).unwrap_or(u64::MAX),
+        Err((code, msg)) => return Err(CustodyError::InternalError(format!("Allowance check failed: {:?} {}", code, msg))),
+    };
+
+    if allowance < amount {
+        return Err(CustodyError::InsufficientBalance { available: allowance, required: amount });
+    }
+
+    let args = Icrc2TransferFromArgs {
+        spender_subaccount: None,
+        from: Icrc1Account { owner: caller, subaccount: None },
+        to: Icrc1Account { owner: ic_cdk::id(), subaccount: None },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+
+    let result: Result<(Result<Nat, Icrc2TransferFromError>,), _> =
+        ic_cdk::call(token_canister_id, "icrc2_transfer_from", (args,)).await;
+
+    match result {
+        Ok((Ok(_),)) => {}
+        Ok((Err(e),)) => return Err(CustodyError::InternalError(format!("Token deposit rejected: {:?}", e))),
+        Err((code, msg)) => return Err(CustodyError::InternalError(format!("Token deposit failed: {:?} {}", code, msg))),
+    }
+
+    adjust_token_balance(token_canister_id, amount, true);
+
+    // The tokens have moved, so open the position even if the caps changed meanwhile
+    let position_index = open_position(&user, &strategy_name, amount, Some(token_canister_id), ic_cdk::api::time());
+
+    Ok(format!("{}:{}", user, position_index))
+}
+
+fn validate_deposit(user: &str, strategy_name: &str, amount: u64) -> Result<(), CustodyError> {
+    // New positions grow state, so they wait until memory is back in hand
+    if memory::is_memory_critical() {
+        return Err(CustodyError::status_conflict("memory critically high", "memory below alert threshold"));
     }
 
+    let strategy = YIELD_STRATEGIES.with(|s| s.borrow().get(strategy_name).cloned())
+        .ok_or_else(|| CustodyError::not_found("Strategy", strategy_name))?;
+
+    if !strategy.is_active {
+        return Err(CustodyError::status_conflict(
+            format!("Paused ({})", strategy.pause_reason.as_deref().unwrap_or("no reason given")),
+            "Active",
+        ));
+    }
+
+    check_deposit_caps(user, &strategy, amount)
+}
+
+// Returns the new position's index in the user's positions
+fn open_position(
+    user: &str,
+    strategy_name: &str,
+    amount: u64,
+    token_canister_id: Option<Principal>,
+    current_time: u64,
+) -> u32 {
+    let lock_period_days = YIELD_STRATEGIES.with(|s| {
+        s.borrow().get(strategy_name).and_then(|strategy| strategy.lock_period_days).unwrap_or(0)
+    });
+
+    let locked_until = (lock_period_days > 0)
+        .then(|| current_time.saturating_add(lock_period_days as u64 * NANOS_PER_DAY));
+
     let position = YieldPosition {
-        strategy: strategy_name.clone(),
+        strategy: strategy_name.to_string(),
         amount,
-        start_time: ic_cdk::api::time(),
+        start_time: current_time,
         accumulated_yield: 0,
+        deposit_time: current_time,
+        auto_compound: None,
+        locked_until,
+        token_canister_id,
     };
 
+    record_deposit(user, strategy_name, amount);
+    record_referral_bonus(user, strategy_name, amount, token_canister_id, current_time);
+
+    USER_POSITIONS.with(|p| {
+        let mut positions = p.borrow_mut();
+        let user_positions = positions.entry(user.to_string()).or_default();
+        user_positions.push(position);
+        (user_positions.len() - 1) as u32
+    })
+}
+
+/// Pays out the position's accrued yield, leaving its principal in place
+#[update]
+async fn claim_yield(user: String, position_index: u32) -> Result<u64, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "claim_yield")?;
+
+    let caller = ic_cdk::caller().to_string();
+
+    if caller != user {
+        return Err(CustodyError::unauthorized("claim_yield"));
+    }
+
+    let recipient = Principal::from_text(&user).map_err(|e| CustodyError::invalid_input("user", e.to_string()))?;
+    let current_time = ic_cdk::api::time();
+
+    let (claimable, position) = USER_POSITIONS.with(|p| {
+        let mut positions = p.borrow_mut();
+        let position = positions
+            .get_mut(&user)
+            .and_then(|user_positions| user_positions.get_mut(position_index as usize))
+            .ok_or_else(|| CustodyError::not_found("Position", position_index.to_string()))?;
+
+        let strategy = YIELD_STRATEGIES.with(|s| s.borrow().get(&position.strategy).cloned())
+            .ok_or_else(|| CustodyError::not_found("Strategy", position.strategy.clone()))?;
+
+        let claimable = position.accumulated_yield
+            .saturating_add(calculate_yield(position, &strategy, current_time)?);
+
+        position.accumulated_yield = 0;
+        position.start_time = current_time;
+
+        Ok::<_, CustodyError>((claimable, position.clone()))
+    })?;
+
+    if claimable == 0 {
+        return Ok(0);
+    }
+
+    if let Err(e) = icrc1_transfer(position.token_canister_id, recipient, claimable).await {
+        restore_position(&user, &position, 0, claimable);
+        return Err(e);
+    }
+
+    Ok(claimable)
+}
+
+#[update]
+fn toggle_auto_compound(user: String, position_index: u32, enabled: bool) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "toggle_auto_compound")?;
+
+    let caller = ic_cdk::caller().to_string();
+
+    if caller != user {
+        return Err(CustodyError::unauthorized("toggle_auto_compound"));
+    }
+
     USER_POSITIONS.with(|p| {
         let mut positions = p.borrow_mut();
-        positions.entry(caller).or_insert(Vec::new()).push(position);
+        let position = positions
+            .get_mut(&user)
+            .and_then(|user_positions| user_positions.get_mut(position_index as usize))
+            .ok_or_else(|| CustodyError::not_found("Position", position_index.to_string()))?;
+
+        position.auto_compound = Some(enabled);
+
+        Ok(format!(
+            "Auto-compounding {} for position {}",
+            if enabled { "enabled" } else { "disabled" },
+            position_index
+        ))
+    })
+}
+
+#[query]
+fn get_compound_history(user: String) -> Vec<CompoundEvent> {
+    COMPOUND_HISTORY.with(|h| h.borrow().get(&user).cloned().unwrap_or_default())
+}
+
+#[update]
+async fn withdraw_from_yield(user: String, position_index: u32, amount: u64) -> Result<u64, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "withdraw_from_yield")?;
+
+    let caller = ic_cdk::caller().to_string();
+
+    if caller != user {
+        return Err(CustodyError::unauthorized("withdraw_from_yield"));
+    }
+
+    if amount == 0 {
+        return Err(CustodyError::invalid_input("amount", "must be greater than zero"));
+    }
+
+    let recipient = Principal::from_text(&user).map_err(|e| CustodyError::invalid_input("user", e.to_string()))?;
+    let current_time = ic_cdk::api::time();
+
+    // An emptied position stays in place until the transfer succeeds, so
+    // indices don't shift while it is in flight
+    let (total, accrued, penalty, position) = USER_POSITIONS.with(|p| {
+        let mut positions = p.borrow_mut();
+        let position = positions
+            .get_mut(&user)
+            .and_then(|user_positions| user_positions.get_mut(position_index as usize))
+            .ok_or_else(|| CustodyError::not_found("Position", position_index.to_string()))?;
+
+        let strategy = YIELD_STRATEGIES.with(|s| s.borrow().get(&position.strategy).cloned())
+            .ok_or_else(|| CustodyError::not_found("Strategy", position.strategy.clone()))?;
+
+        check_withdrawal_lock(position, &strategy, current_time)?;
+
+        if amount > position.amount {
+            return Err(CustodyError::InsufficientBalance { available: position.amount, required: amount });
+        }
+
+        let accrued = position.accumulated_yield
+            .saturating_add(calculate_yield(position, &strategy, current_time)?);
+        let penalty = early_exit_penalty(position, &strategy, amount, current_time);
+
+        release_deposit(&user, &position.strategy, amount);
+
+        position.amount -= amount;
+        position.accumulated_yield = 0;
+        position.start_time = current_time;
+
+        Ok((amount.saturating_add(accrued) - penalty, accrued, penalty, position.clone()))
+    })?;
+
+    adjust_penalty_treasury(position.token_canister_id, penalty, true);
+
+    if let Err(e) = icrc1_transfer(position.token_canister_id, recipient, total).await {
+        // Only this withdrawal is undone; other calls may have changed the
+        // user's positions while the transfer was pending
+        adjust_penalty_treasury(position.token_canister_id, penalty, false);
+        restore_position(&user, &position, amount, accrued);
+        return Err(e);
+    }
+
+    remove_emptied_positions(&user);
+    reduce_referral_bonuses(&user, &position, amount, current_time);
+
+    Ok(total)
+}
+
+#[update]
+async fn exit_all_positions(user: String) -> Result<Vec<u64>, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "exit_all_positions")?;
+
+    let caller = ic_cdk::caller().to_string();
+
+    if caller != user {
+        return Err(CustodyError::unauthorized("exit_all_positions"));
+    }
+
+    let recipient = Principal::from_text(&user).map_err(|e| CustodyError::invalid_input("user", e.to_string()))?;
+    let current_time = ic_cdk::api::time();
+
+    // (position as emptied, principal, accrued yield) for each position, to
+    // undo just these withdrawals if the transfer fails
+    let (exits, totals, penalties) = USER_POSITIONS.with(|p| {
+        let mut positions = p.borrow_mut();
+        let user_positions = match positions.get_mut(&user) {
+            Some(user_positions) if !user_positions.is_empty() => user_positions,
+            _ => return Err(CustodyError::not_found("Position", user.clone())),
+        };
+
+        // The exit is paid in a single transfer, so all positions must share a ledger
+        let token_canister_id = user_positions[0].token_canister_id;
+        if user_positions.iter().any(|position| position.token_canister_id != token_canister_id) {
+            return Err(CustodyError::invalid_input("user", "positions are held in different tokens; withdraw them individually"));
+        }
+
+        // Every position must be unlocked, and its yield computable, before
+        // any is withdrawn
+        let mut payouts = Vec::new();
+        for position in user_positions.iter() {
+            let strategy = YIELD_STRATEGIES.with(|s| s.borrow().get(&position.strategy).cloned())
+                .ok_or_else(|| CustodyError::not_found("Strategy", position.strategy.clone()))?;
+            check_withdrawal_lock(position, &strategy, current_time)?;
+
+            let accrued = position.accumulated_yield
+                .saturating_add(calculate_yield(position, &strategy, current_time)?);
+            let penalty = early_exit_penalty(position, &strategy, position.amount, current_time);
+            payouts.push((accrued, penalty));
+        }
+
+        let mut exits = Vec::new();
+        let mut totals = Vec::new();
+        let mut penalties = 0u64;
+        for (position, (accrued, penalty)) in user_positions.iter_mut().zip(payouts) {
+            let amount = position.amount;
+            penalties = penalties.saturating_add(penalty);
+            totals.push(amount.saturating_add(accrued) - penalty);
+
+            release_deposit(&user, &position.strategy, amount);
+
+            // Emptied positions stay in place until the transfer succeeds
+            position.amount = 0;
+            position.accumulated_yield = 0;
+            position.start_time = current_time;
+
+            exits.push((position.clone(), amount, accrued));
+        }
+
+        Ok((exits, totals, penalties))
+    })?;
+
+    let token_canister_id = exits[0].0.token_canister_id;
+    let grand_total = totals.iter().fold(0u64, |acc, t| acc.saturating_add(*t));
+
+    adjust_penalty_treasury(token_canister_id, penalties, true);
+
+    if let Err(e) = icrc1_transfer(token_canister_id, recipient, grand_total).await {
+        // Only these withdrawals are undone; other calls may have changed the
+        // user's positions while the transfer was pending
+        adjust_penalty_treasury(token_canister_id, penalties, false);
+        for (position, amount, accrued) in &exits {
+            restore_position(&user, position, *amount, *accrued);
+        }
+        return Err(e);
+    }
+
+    remove_emptied_positions(&user);
+
+    for (position, amount, _) in &exits {
+        reduce_referral_bonuses(&user, position, *amount, current_time);
+    }
+
+    Ok(totals)
+}
+
+#[update]
+fn create_portfolio(user: String, allocations: Vec<(String, u8)>, total_amount: u64) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "create_portfolio")?;
+
+    let caller = ic_cdk::caller().to_string();
+
+    if caller != user {
+        return Err(CustodyError::unauthorized("create_portfolio"));
+    }
+
+    if allocations.is_empty() {
+        return Err(CustodyError::invalid_input("allocations", "need at least one allocation"));
+    }
+
+    if allocations.iter().map(|(_, percent)| *percent as u32).sum::<u32>() != 100 {
+        return Err(CustodyError::invalid_input("allocations", "must sum to 100%"));
+    }
+
+    let mut seen = std::collections::BTreeSet::new();
+    if !allocations.iter().all(|(strategy_name, _)| seen.insert(strategy_name)) {
+        return Err(CustodyError::invalid_input("allocations", "each strategy may appear only once"));
+    }
+
+    let amounts = split_by_percent(total_amount, allocations.iter().map(|(_, percent)| *percent));
+
+    // Validate every slice before opening any position
+    for ((strategy_name, _), amount) in allocations.iter().zip(&amounts) {
+        if *amount == 0 {
+            return Err(CustodyError::invalid_input("total_amount", format!("allocation to {} is zero", strategy_name)));
+        }
+        validate_deposit(&user, strategy_name, *amount)?;
+    }
+
+    let current_time = ic_cdk::api::time();
+
+    let portfolio_allocations = allocations
+        .into_iter()
+        .zip(amounts)
+        .map(|((strategy_name, allocation_percent), amount)| PortfolioAllocation {
+            position_index: open_position(&user, &strategy_name, amount, None, current_time),
+            strategy_name,
+            allocation_percent,
+        })
+        .collect();
+
+    let id = NEXT_PORTFOLIO_ID.with(|n| {
+        let mut next = n.borrow_mut();
+        *next += 1;
+        format!("portfolio-{}", *next)
     });
 
-    Ok(format!("Deposited {} to {}", amount, strategy_name))
+    PORTFOLIOS.with(|p| {
+        p.borrow_mut().insert(id.clone(), Portfolio {
+            id: id.clone(),
+            owner: user,
+            allocations: portfolio_allocations,
+            total_deposited: total_amount,
+            created_at: current_time,
+        });
+    });
+
+    Ok(id)
 }
 
 #[query]
-fn get_user_positions(user: String) -> Vec<YieldPosition> {
+fn get_portfolio_value(portfolio_id: String) -> u64 {
+    let portfolio = match PORTFOLIOS.with(|p| p.borrow().get(&portfolio_id).cloned()) {
+        Some(portfolio) => portfolio,
+        None => return 0,
+    };
+
+    let current_time = ic_cdk::api::time();
+
     USER_POSITIONS.with(|p| {
-        p.borrow().get(&user).cloned().unwrap_or_default()
+        let positions = p.borrow();
+        let user_positions = match positions.get(&portfolio.owner) {
+            Some(user_positions) => user_positions,
+            None => return 0,
+        };
+
+        portfolio.allocations.iter()
+            .filter_map(|allocation| user_positions.get(allocation.position_index as usize))
+            .fold(0u64, |total, position| {
+                let accrued = YIELD_STRATEGIES.with(|s| s.borrow().get(&position.strategy).cloned())
+                    .and_then(|strategy| calculate_yield(position, &strategy, current_time).ok())
+                    .unwrap_or(0);
+                total
+                    .saturating_add(position.amount)
+                    .saturating_add(position.accumulated_yield)
+                    .saturating_add(accrued)
+            })
     })
 }
 
 #[query]
-fn greet(name: String) -> String {
-    format!("Hello, {}! This is the Yield Engine canister.", name)
+fn get_portfolio(portfolio_id: String) -> Option<Portfolio> {
+    PORTFOLIOS.with(|p| p.borrow().get(&portfolio_id).cloned())
 }
+
+/// Withdraws `percent` of every position in the portfolio, plus all their
+/// accrued yield, so the allocation split is preserved
+#[update]
+async fn withdraw_portfolio(portfolio_id: String, percent: u8) -> Result<u64, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "withdraw_portfolio")?;
+
+    let caller = ic_cdk::caller().to_string();
+
+    let portfolio = PORTFOLIOS.with(|p| p.borrow().get(&portfolio_id).cloned())
+        .ok_or_else(|| CustodyError::not_found("Portfolio", portfolio_id.clone()))?;
+
+    if caller != portfolio.owner {
+        return Err(CustodyError::unauthorized("withdraw_portfolio"));
+    }
+
+    if percent == 0 || percent > 100 {
+        return Err(CustodyError::invalid_input("percent", "must be between 1 and 100"));
+    }
+
+    let user = portfolio.owner.clone();
+    let recipient = Principal::from_text(&user).map_err(|e| CustodyError::invalid_input("user", e.to_string()))?;
+    let current_time = ic_cdk::api::time();
+
+    // (position after the withdrawal, principal, accrued yield) for each
+    // position, to undo just these withdrawals if the transfer fails
+    let (withdrawals, total) = USER_POSITIONS.with(|p| {
+        let mut positions = p.borrow_mut();
+        let user_positions = positions.get_mut(&user).ok_or_else(|| CustodyError::not_found("Position", user.clone()))?;
+
+        // Every position must be unlocked, and its yield computable, before
+        // any is withdrawn
+        let mut payouts = Vec::new();
+        for allocation in &portfolio.allocations {
+            let position = user_positions.get(allocation.position_index as usize)
+                .ok_or_else(|| CustodyError::not_found("Position", allocation.position_index.to_string()))?;
+            let strategy = YIELD_STRATEGIES.with(|s| s.borrow().get(&position.strategy).cloned())
+                .ok_or_else(|| CustodyError::not_found("Strategy", position.strategy.clone()))?;
+            check_withdrawal_lock(position, &strategy, current_time)?;
+
+            let amount = (position.amount as u128 * percent as u128 / 100) as u64;
+            let accrued = position.accumulated_yield
+                .saturating_add(calculate_yield(position, &strategy, current_time)?);
+            let penalty = early_exit_penalty(position, &strategy, amount, current_time);
+            payouts.push((allocation.position_index as usize, amount, accrued, penalty));
+        }
+
+        let mut withdrawals = Vec::new();
+        let mut total = 0u64;
+        for (index, amount, accrued, penalty) in payouts {
+            let position = &mut user_positions[index];
+
+            release_deposit(&user, &position.strategy, amount);
+
+            // Emptied positions stay in place until the transfer succeeds
+            position.amount -= amount;
+            position.accumulated_yield = 0;
+            position.start_time = current_time;
+
+            total = total.saturating_add(amount.saturating_add(accrued) - penalty);
+            withdrawals.push((position.clone(), amount, accrued));
+        }
+
+        Ok::<_, CustodyError>((withdrawals, total))
+    })?;
+
+    // Portfolio positions are accounting-only, with no token ledger behind
+    // them, so their penalties have no tokens to collect into the treasury
+    if let Err(e) = icrc1_transfer(None, recipient, total).await {
+        // Only these withdrawals are undone; other calls may have changed the
+        // user's positions while the transfer was pending
+        for (position, amount, accrued) in &withdrawals {
+            restore_position(&user, position, *amount, *accrued);
+        }
+        return Err(e);
+    }
+
+    let withdrawn_principal = withdrawals.iter().fold(0u64, |acc, (_, amount, _)| acc.saturating_add(*amount));
+
+    PORTFOLIOS.with(|p| {
+        if let Some(portfolio) = p.borrow_mut().get_mut(&portfolio_id) {
+            portfolio.total_deposited = portfolio.total_deposited.saturating_sub(withdrawn_principal);
+        }
+    });
+    remove_emptied_positions(&user);
+
+    for (position, amount, _) in &withdrawals {
+        reduce_referral_bonuses(&user, position, *amount, current_time);
+    }
+
+    Ok(total)
+}
+
+/// Pays the penalties collected on one token ledger to the admin
+#[update]
+async fn claim_penalty_treasury(token_canister_id: Principal) -> Result<u64, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "claim_penalty_treasury")?;
+
+    require_yield_admin("claim the penalty treasury")?;
+
+    let amount = PENALTY_TREASURY.with(|t| t.borrow_mut().remove(&token_canister_id).unwrap_or(0));
+
+    if amount == 0 {
+        return Err(CustodyError::not_found("Penalty treasury balance", token_canister_id.to_text()));
+    }
+
+    if let Err(e) = icrc1_transfer(Some(token_canister_id), ic_cdk::caller(), amount).await {
+        adjust_penalty_treasury(Some(token_canister_id), amount, true);
+        return Err(e);
+    }
+
+    Ok(amount)
+}
+
+#[query]
+fn get_penalty_treasury() -> Vec<(Principal, u64)> {
+    PENALTY_TREASURY.with(|t| t.borrow().iter().map(|(token, amount)| (*token, *amount)).collect())
+}
+
+#[query]
+fn get_position_unlock_time(user: String, position_index: u32) -> Option<u64> {
+    USER_POSITIONS.with(|p| {
+        p.borrow()
+            .get(&user)
+            .and_then(|user_positions| user_positions.get(position_index as usize))
+            .and_then(|position| position.locked_until)
+    })
+}
+
+#[query]
+fn can_withdraw_without_penalty(user: String, position_index: u32) -> bool {
+    let current_time = ic_cdk::api::time();
+
+    USER_POSITIONS.with(|p| {
+        p.borrow()
+            .get(&user)
+            .and_then(|user_positions| user_positions.get(position_index as usize))
+            .is_some_and(|position| position.locked_until.is_none_or(|t| current_time >= t))
+    })
+}
+
+// Fee on principal withdrawn before the position's commitment period ends
+fn early_exit_penalty(position: &YieldPosition, strategy: &YieldStrategy, amount: u64, current_time: u64) -> u64 {
+    match position.locked_until {
+        Some(locked_until) if current_time < locked_until => {
+            let penalty_basis_points = strategy.early_exit_penalty_basis_points.unwrap_or(0);
+            (amount as u128 * penalty_basis_points as u128 / BASIS_POINTS) as u64
+        }
+        _ => 0,
+    }
+}
+
+fn adjust_penalty_treasury(token_canister_id: Option<Principal>, amount: u64, credit: bool) {
+    let Some(token_canister_id) = token_canister_id else {
+        return;
+    };
+
+    PENALTY_TREASURY.with(|t| {
+        let mut treasury = t.borrow_mut();
+        let collected = treasury.entry(token_canister_id).or_default();
+        *collected = if credit {
+            collected.saturating_add(amount)
+        } else {
+            collected.saturating_sub(amount)
+        };
+        if *collected == 0 {
+            treasury.remove(&token_canister_id);
+        }
+    });
+}
+
+#[update]
+fn register_referral(referred_user: String, referrer_user: String) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "register_referral")?;
+
+    let caller = ic_cdk::caller().to_string();
+
+    if caller != referred_user {
+        return Err(CustodyError::unauthorized("register_referral"));
+    }
+
+    if referred_user == referrer_user {
+        return Err(CustodyError::invalid_input("referrer_user", "users cannot refer themselves"));
+    }
+
+    REFERRALS.with(|r| {
+        let mut referrals = r.borrow_mut();
+
+        if referrals.contains_key(&referred_user) {
+            return Err(CustodyError::status_conflict("referrer registered", "no referrer"));
+        }
+
+        // Two users may not refer each other
+        if referrals.get(&referrer_user) == Some(&referred_user) {
+            return Err(CustodyError::invalid_input("referrer_user", "was referred by this user"));
+        }
+
+        referrals.insert(referred_user.clone(), referrer_user.clone());
+        Ok(())
+    })?;
+
+    REFERRAL_STATS.with(|s| {
+        s.borrow_mut().entry(referrer_user.clone()).or_default().referred_users += 1;
+    });
+
+    Ok(format!("Registered {} as referrer of {}", referrer_user, referred_user))
+}
+
+#[update]
+fn set_referral_bonus_rate(rate_basis_points: u64) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "set_referral_bonus_rate")?;
+
+    require_yield_admin("set the referral bonus rate")?;
+
+    if rate_basis_points as u128 > BASIS_POINTS {
+        return Err(CustodyError::invalid_input("rate_basis_points", "cannot exceed 100%"));
+    }
+
+    REFERRAL_BONUS_RATE.with(|r| *r.borrow_mut() = rate_basis_points);
+
+    Ok(format!("Referral bonus rate set to {} basis points", rate_basis_points))
+}
+
+#[query]
+fn get_referral_earnings(referrer: String) -> u64 {
+    let current_time = ic_cdk::api::time();
+    let settled = REFERRAL_STATS.with(|s| s.borrow().get(&referrer).map_or(0, |stats| stats.earned_bonuses));
+
+    REFERRAL_BONUSES.with(|b| {
+        b.borrow()
+            .get(&referrer)
+            .into_iter()
+            .flatten()
+            .fold(settled, |total, bonus| total.saturating_add(referral_bonus_accrued(bonus, current_time)))
+    })
+}
+
+#[query]
+fn get_referral_stats(referrer: String) -> ReferralStats {
+    REFERRAL_STATS.with(|s| s.borrow().get(&referrer).cloned().unwrap_or_default())
+}
+
+#[query]
+fn get_referral_bonuses(referrer: String) -> Vec<ReferralBonus> {
+    REFERRAL_BONUSES.with(|b| b.borrow().get(&referrer).cloned().unwrap_or_default())
+}
+
+/// Pays out everything the referrer's bonus positions on one token ledger
+/// have earned. The positions stay open and keep accruing on the amounts
+/// their referrals still hold
+#[update]
+async fn claim_referral_bonus(referrer: String, token_canister_id: Option<Principal>) -> Result<u64, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "claim_referral_bonus")?;
+
+    if ic_cdk::caller().to_string() != referrer {
+        return Err(CustodyError::unauthorized("claim_referral_bonus"));
+    }
+
+    let recipient = Principal::from_text(&referrer).map_err(|e| CustodyError::invalid_input("referrer", e.to_string()))?;
+    let current_time = ic_cdk::api::time();
+
+    // (bonus as claimed, amount claimed from it), to undo just this claim if
+    // the transfer fails
+    let (claims, live_accrual) = REFERRAL_BONUSES.with(|b| {
+        let mut bonuses = b.borrow_mut();
+        let Some(referrer_bonuses) = bonuses.get_mut(&referrer) else {
+            return (Vec::new(), 0);
+        };
+
+        let mut claims = Vec::new();
+        let mut live_accrual = 0u64;
+        for bonus in referrer_bonuses.iter_mut().filter(|bonus| bonus.token_canister_id == token_canister_id) {
+            let accrued = referral_bonus_accrued(bonus, current_time);
+            live_accrual = live_accrual.saturating_add(accrued);
+
+            let claimable = bonus.accumulated_bonus.take().unwrap_or(0).saturating_add(accrued);
+            bonus.start_time = current_time;
+            if claimable > 0 {
+                claims.push((bonus.clone(), claimable));
+            }
+        }
+
+        referrer_bonuses.retain(|bonus| bonus.referred_amount > 0 || bonus.accumulated_bonus.unwrap_or(0) > 0);
+        (claims, live_accrual)
+    });
+
+    // Accrual up to now counts as earned whether or not the transfer goes through
+    REFERRAL_STATS.with(|s| {
+        let mut stats = s.borrow_mut();
+        let entry = stats.entry(referrer.clone()).or_default();
+        entry.earned_bonuses = entry.earned_bonuses.saturating_add(live_accrual);
+    });
+
+    let total = claims.iter().fold(0u64, |acc, (_, amount)| acc.saturating_add(*amount));
+    if total == 0 {
+        return Ok(0);
+    }
+
+    if let Err(e) = icrc1_transfer(token_canister_id, recipient, total).await {
+        for (bonus, amount) in &claims {
+            restore_referral_bonus(&referrer, bonus, *amount);
+        }
+        return Err(e);
+    }
+
+    Ok(total)
+}
+
+// Puts a failed claim back on the bonus position it came from, found by
+// referral, strategy and ledger; if that position closed meanwhile, the
+// amount comes back as a settled position of its own
+fn restore_referral_bonus(referrer: &str, bonus: &ReferralBonus, amount: u64) {
+    REFERRAL_BONUSES.with(|b| {
+        let mut bonuses = b.borrow_mut();
+        let referrer_bonuses = bonuses.entry(referrer.to_string()).or_default();
+
+        match referrer_bonuses.iter_mut().find(|current| {
+            current.referred_user == bonus.referred_user
+                && current.strategy == bonus.strategy
+                && current.token_canister_id == bonus.token_canister_id
+        }) {
+            Some(current) => {
+                current.accumulated_bonus = Some(current.accumulated_bonus.unwrap_or(0).saturating_add(amount));
+            }
+            None => referrer_bonuses.push(ReferralBonus {
+                referred_amount: 0,
+                accumulated_bonus: Some(amount),
+                ..bonus.clone()
+            }),
+        }
+    });
+}
+
+fn record_referral_bonus(
+    user: &str,
+    strategy_name: &str,
+    amount: u64,
+    token_canister_id: Option<Principal>,
+    current_time: u64,
+) {
+    let referrer = match REFERRALS.with(|r| r.borrow().get(user).cloned()) {
+        Some(referrer) => referrer,
+        None => return,
+    };
+
+    let bonus = ReferralBonus {
+        referred_user: user.to_string(),
+        strategy: strategy_name.to_string(),
+        referred_amount: amount,
+        bonus_rate_basis_points: REFERRAL_BONUS_RATE.with(|r| *r.borrow()),
+        start_time: current_time,
+        accumulated_bonus: None,
+        token_canister_id,
+    };
+
+    REFERRAL_BONUSES.with(|b| {
+        b.borrow_mut().entry(referrer.clone()).or_default().push(bonus);
+    });
+
+    REFERRAL_STATS.with(|s| {
+        let mut stats = s.borrow_mut();
+        let entry = stats.entry(referrer).or_default();
+        entry.total_referred_deposits = entry.total_referred_deposits.saturating_add(amount);
+    });
+}
+
+// The referrer's share of the yield the referred amount has earned since the
+// bonus last settled
+fn referral_bonus_accrued(bonus: &ReferralBonus, current_time: u64) -> u64 {
+    let strategy = match YIELD_STRATEGIES.with(|s| s.borrow().get(&bonus.strategy).cloned()) {
+        Some(strategy) => strategy,
+        None => return 0,
+    };
+
+    let referred = YieldPosition {
+        strategy: bonus.strategy.clone(),
+        amount: bonus.referred_amount,
+        start_time: bonus.start_time,
+        accumulated_yield: 0,
+        deposit_time: bonus.start_time,
+        auto_compound: None,
+        locked_until: None,
+        token_canister_id: None,
+    };
+
+    let base_yield = calculate_yield(&referred, &strategy, current_time).unwrap_or(0) as u128;
+    (base_yield * bonus.bonus_rate_basis_points as u128 / BASIS_POINTS) as u64
+}
+
+// Settles the bonuses on a user's withdrawn principal and stops them accruing,
+// oldest deposits first. Settled bonuses stay claimable by the referrer
+fn reduce_referral_bonuses(user: &str, position: &YieldPosition, amount: u64, current_time: u64) {
+    let referrer = match REFERRALS.with(|r| r.borrow().get(user).cloned()) {
+        Some(referrer) => referrer,
+        None => return,
+    };
+
+    let mut remaining = amount;
+    let mut settled = 0u64;
+
+    REFERRAL_BONUSES.with(|b| {
+        let mut bonuses = b.borrow_mut();
+        let referrer_bonuses = match bonuses.get_mut(&referrer) {
+            Some(referrer_bonuses) => referrer_bonuses,
+            None => return,
+        };
+
+        for bonus in referrer_bonuses.iter_mut() {
+            if remaining == 0 {
+                break;
+            }
+            if bonus.referred_user != user
+                || bonus.strategy != position.strategy
+                || bonus.token_canister_id != position.token_canister_id
+            {
+                continue;
+            }
+
+            let accrued = referral_bonus_accrued(bonus, current_time);
+            settled = settled.saturating_add(accrued);
+            bonus.accumulated_bonus = Some(bonus.accumulated_bonus.unwrap_or(0).saturating_add(accrued));
+            bonus.start_time = current_time;
+
+            let reduction = remaining.min(bonus.referred_amount);
+            bonus.referred_amount -= reduction;
+            remaining -= reduction;
+        }
+
+        referrer_bonuses.retain(|bonus| bonus.referred_amount > 0 || bonus.accumulated_bonus.unwrap_or(0) > 0);
+    });
+
+    REFERRAL_STATS.with(|s| {
+        let mut stats = s.borrow_mut();
+        let entry = stats.entry(referrer).or_default();
+        entry.earned_bonuses = entry.earned_bonuses.saturating_add(settled);
+    });
+}
+
+// Splits `total` by whole percentages, giving the rounding remainder to the
+// last share so the shares add up to `total`
+fn split_by_percent(total: u64, percents: impl Iterator<Item = u8>) -> Vec<u64> {
+    let mut shares: Vec<u64> = percents
+        .map(|percent| (total as u128 * percent as u128 / 100) as u64)
+        .collect();
+
+    let allocated = shares.iter().fold(0u64, |acc, share| acc.saturating_add(*share));
+    if let Some(last) = shares.last_mut() {
+        *last = last.saturating_add(total.saturating_sub(allocated));
+    }
+
+    shares
+}
+
+// Puts back what a withdrawal or claim took from a position after its
+// transfer failed. The position is found by strategy and deposit time, as
+// positions removed meanwhile may have shifted its index; if it is gone, the
+// restored amounts come back as a position of their own
+fn restore_position(user: &str, position: &YieldPosition, amount: u64, accrued: u64) {
+    record_deposit(user, &position.strategy, amount);
+
+    USER_POSITIONS.with(|p| {
+        let mut positions = p.borrow_mut();
+        let user_positions = positions.entry(user.to_string()).or_default();
+
+        match user_positions
+            .iter_mut()
+            .find(|current| current.strategy == position.strategy && current.deposit_time == position.deposit_time)
+        {
+            Some(current) => {
+                current.amount = current.amount.saturating_add(amount);
+                current.accumulated_yield = current.accumulated_yield.saturating_add(accrued);
+            }
+            None => user_positions.push(YieldPosition {
+                amount,
+                accumulated_yield: accrued,
+                ..position.clone()
+            }),
+        }
+    });
+}
+
+// Drops the user's positions emptied by completed withdrawals
+fn remove_emptied_positions(user: &str) {
+    let removed: Vec<usize> = USER_POSITIONS.with(|p| {
+        let mut positions = p.borrow_mut();
+        let Some(user_positions) = positions.get_mut(user) else {
+            return Vec::new();
+        };
+
+        let removed = user_positions
+            .iter()
+            .enumerate()
+            .filter(|(_, position)| position.amount == 0)
+            .map(|(index, _)| index)
+            .collect();
+        user_positions.retain(|position| position.amount != 0);
+
+        if user_positions.is_empty() {
+            positions.remove(user);
+        }
+        removed
+    });
+
+    reindex_portfolios(user, &removed);
+}
+
+// Keeps allocations pointing at the right positions once the positions at
+// `removed` have been dropped; portfolios left with no positions are deleted
+fn reindex_portfolios(user: &str, removed: &[usize]) {
+    if removed.is_empty() {
+        return;
+    }
+
+    PORTFOLIOS.with(|p| {
+        let mut portfolios = p.borrow_mut();
+        for portfolio in portfolios.values_mut().filter(|pf| pf.owner == user) {
+            portfolio.allocations.retain(|a| !removed.contains(&(a.position_index as usize)));
+            for allocation in portfolio.allocations.iter_mut() {
+                let shift = removed.iter().filter(|&&index| index < allocation.position_index as usize).count();
+                allocation.position_index -= shift as u32;
+            }
+        }
+        portfolios.retain(|_, pf| pf.owner != user || !pf.allocations.is_empty());
+    });
+}
+
+#[update]
+fn update_strategy_caps(strategy_name: String, max_user_deposit: u64, max_total_tvl: u64) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "update_strategy_caps")?;
+
+    require_yield_admin("update strategy caps")?;
+
+    if max_user_deposit > max_total_tvl {
+        return Err(CustodyError::invalid_input("max_user_deposit", "cannot exceed max_total_tvl"));
+    }
+
+    YIELD_STRATEGIES.with(|s| {
+        match s.borrow_mut().get_mut(&strategy_name) {
+            Some(strategy) => {
+                strategy.max_user_deposit = max_user_deposit;
+                strategy.max_total_tvl = max_total_tvl;
+                Ok(format!("Updated caps for {}", strategy_name))
+            }
+            None => Err(CustodyError::not_found("Strategy", strategy_name.clone())),
+        }
+    })
+}
+
+#[update]
+fn pause_strategy(strategy_name: String, reason: String) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "pause_strategy")?;
+
+    require_yield_admin("pause strategies")?;
+
+    YIELD_STRATEGIES.with(|s| {
+        let mut strategies = s.borrow_mut();
+        let strategy = strategies.get_mut(&strategy_name).ok_or_else(|| CustodyError::not_found("Strategy", strategy_name.clone()))?;
+
+        if strategy.wound_down_at.is_some() {
+            return Err(CustodyError::status_conflict("WoundDown", "Active"));
+        }
+
+        // Existing positions keep accruing yield; only new deposits stop
+        strategy.is_active = false;
+        strategy.pause_reason = Some(reason.clone());
+
+        ic_cdk::println!("Strategy {} paused by {}: {}", strategy_name, ic_cdk::caller(), reason);
+        Ok(format!("Paused {}", strategy_name))
+    })
+}
+
+#[update]
+fn resume_strategy(strategy_name: String) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "resume_strategy")?;
+
+    require_yield_admin("resume strategies")?;
+
+    YIELD_STRATEGIES.with(|s| {
+        let mut strategies = s.borrow_mut();
+        let strategy = strategies.get_mut(&strategy_name).ok_or_else(|| CustodyError::not_found("Strategy", strategy_name.clone()))?;
+
+        if strategy.wound_down_at.is_some() {
+            return Err(CustodyError::status_conflict("WoundDown", "Paused"));
+        }
+
+        if strategy.is_active {
+            return Err(CustodyError::status_conflict("Active", "Paused"));
+        }
+
+        strategy.is_active = true;
+        strategy.pause_reason = None;
+
+        ic_cdk::println!("Strategy {} resumed by {}", strategy_name, ic_cdk::caller());
+        Ok(format!("Resumed {}", strategy_name))
+    })
+}
+
+#[update]
+fn wind_down_strategy(strategy_name: String) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "wind_down_strategy")?;
+
+    require_yield_admin("wind down strategies")?;
+
+    let current_time = ic_cdk::api::time();
+
+    YIELD_STRATEGIES.with(|s| {
+        let mut strategies = s.borrow_mut();
+        let strategy = strategies.get_mut(&strategy_name).ok_or_else(|| CustodyError::not_found("Strategy", strategy_name.clone()))?;
+
+        if strategy.wound_down_at.is_some() {
+            return Err(CustodyError::status_conflict("WoundDown", "Active or Paused"));
+        }
+
+        // Users may still withdraw, but earn nothing past this point
+        strategy.is_active = false;
+        strategy.pause_reason = Some("Strategy wound down".to_string());
+        strategy.wound_down_at = Some(current_time);
+
+        ic_cdk::println!("Strategy {} wound down by {} at {}", strategy_name, ic_cdk::caller(), current_time);
+        Ok(format!("Wound down {}", strategy_name))
+    })
+}
+
+#[update]
+fn update_strategy_apy(strategy_name: String, new_apy: u64) -> Result<String, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "update_strategy_apy")?;
+
+    require_yield_admin("update strategy APY")?;
+
+    if new_apy as u128 > BASIS_POINTS {
+        return Err(CustodyError::invalid_input("new_apy", "cannot exceed 100%"));
+    }
+
+    let current_time = ic_cdk::api::time();
+
+    let old_apy = YIELD_STRATEGIES.with(|s| {
+        let mut strategies = s.borrow_mut();
+        let strategy = strategies.get_mut(&strategy_name).ok_or_else(|| CustodyError::not_found("Strategy", strategy_name.clone()))?;
+        Ok::<_, CustodyError>(std::mem::replace(&mut strategy.apy_basis_points, new_apy))
+    })?;
+
+    APY_HISTORY.with(|h| {
+        let mut history = h.borrow_mut();
+        let snapshots = history.entry(strategy_name.clone()).or_default();
+        if snapshots.len() >= MAX_APY_HISTORY {
+            snapshots.pop_front();
+        }
+        snapshots.push_back(ApySnapshot {
+            timestamp: current_time,
+            apy_basis_points: old_apy,
+            updated_by: ic_cdk::caller(),
+        });
+    });
+
+    ic_cdk::println!("Strategy {} APY changed from {} to {} basis points", strategy_name, old_apy, new_apy);
+    Ok(format!("Updated APY for {}", strategy_name))
+}
+
+#[query]
+fn get_apy_history(strategy_name: String, limit: u32) -> Vec<ApySnapshot> {
+    APY_HISTORY.with(|h| {
+        h.borrow()
+            .get(&strategy_name)
+            .map(|snapshots| {
+                let skip = snapshots.len().saturating_sub(limit as usize);
+                snapshots.iter().skip(skip).cloned().collect()
+            })
+            .unwrap_or_default()
+    })
+}
+
+fn require_yield_admin(action: &str) -> Result<(), CustodyError> {
+    if YIELD_ADMIN.with(|a| *a.borrow()) != Some(ic_cdk::caller()) {
+        return Err(CustodyError::unauthorized(action));
+    }
+
+    Ok(())
+}
+
+#[query]
+fn get_strategy_tvl(strategy_name: String) -> u64 {
+    STRATEGY_TVL.with(|t| t.borrow().get(&strategy_name).copied().unwrap_or(0))
+}
+
+// Enforce per-user and global allocation caps
+fn check_deposit_caps(user: &str, strategy: &YieldStrategy, amount: u64) -> Result<(), CustodyError> {
+    let user_deposits = USER_STRATEGY_DEPOSITS.with(|d| {
+        d.borrow().get(&(user.to_string(), strategy.name.clone())).copied().unwrap_or(0)
+    });
+
+    if user_deposits.saturating_add(amount) > strategy.max_user_deposit {
+        return Err(CustodyError::LimitExceeded {
+            limit: strategy.max_user_deposit,
+            actual: user_deposits.saturating_add(amount),
+        });
+    }
+
+    let tvl = get_strategy_tvl(strategy.name.clone()).saturating_add(amount);
+    if tvl > strategy.max_total_tvl {
+        return Err(CustodyError::LimitExceeded { limit: strategy.max_total_tvl, actual: tvl });
+    }
+
+    Ok(())
+}
+
+// Runs once a day and rolls every auto-compounding position's yield into
+// its principal, subject to the same caps as a fresh deposit
+fn compound_positions() {
+    let current_time = ic_cdk::api::time();
+    let users: Vec<String> = USER_POSITIONS.with(|p| p.borrow().keys().cloned().collect());
+
+    for user in users {
+        let events = USER_POSITIONS.with(|p| {
+            let mut positions = p.borrow_mut();
+            let user_positions = match positions.get_mut(&user) {
+                Some(user_positions) => user_positions,
+                None => return Vec::new(),
+            };
+
+            user_positions
+                .iter_mut()
+                .enumerate()
+                .filter(|(_, position)| position.auto_compound == Some(true))
+                .filter_map(|(index, position)| compound_position(&user, index, position, current_time))
+                .collect::<Vec<_>>()
+        });
+
+        if !events.is_empty() {
+            COMPOUND_HISTORY.with(|h| {
+                h.borrow_mut().entry(user).or_default().extend(events);
+            });
+        }
+    }
+}
+
+fn compound_position(
+    user: &str,
+    index: usize,
+    position: &mut YieldPosition,
+    current_time: u64,
+) -> Option<CompoundEvent> {
+    let strategy = YIELD_STRATEGIES.with(|s| s.borrow().get(&position.strategy).cloned())?;
+
+    let accrued = position.accumulated_yield
+        .saturating_add(calculate_yield(position, &strategy, current_time).ok()?);
+    position.start_time = current_time;
+
+    // Yield that would breach a cap keeps accumulating for a manual claim
+    if accrued == 0 || check_deposit_caps(user, &strategy, accrued).is_err() {
+        position.accumulated_yield = accrued;
+        return None;
+    }
+
+    record_deposit(user, &position.strategy, accrued);
+    position.amount = position.amount.saturating_add(accrued);
+    position.accumulated_yield = 0;
+
+    Some(CompoundEvent {
+        position_id: format!("{}:{}", user, index),
+        timestamp: current_time,
+        compounded_amount: accrued,
+        new_principal: position.amount,
+    })
+}
+
+fn record_deposit(user: &str, strategy_name: &str, amount: u64) {
+    STRATEGY_TVL.with(|t| {
+        let mut tvl = t.borrow_mut();
+        let total = tvl.entry(strategy_name.to_string()).or_insert(0);
+        *total = total.saturating_add(amount);
+    });
+    USER_STRATEGY_DEPOSITS.with(|d| {
+        let mut deposits = d.borrow_mut();
+        let total = deposits.entry((user.to_string(), strategy_name.to_string())).or_insert(0);
+        *total = total.saturating_add(amount);
+    });
+}
+
+fn release_deposit(user: &str, strategy_name: &str, amount: u64) {
+    STRATEGY_TVL.with(|t| {
+        if let Some(total) = t.borrow_mut().get_mut(strategy_name) {
+            *total = total.saturating_sub(amount);
+        }
+    });
+    USER_STRATEGY_DEPOSITS.with(|d| {
+        let mut deposits = d.borrow_mut();
+        let key = (user.to_string(), strategy_name.to_string());
+        if let Some(total) = deposits.get_mut(&key) {
+            *total = total.saturating_sub(amount);
+            if *total == 0 {
+                deposits.remove(&key);
+            }
+        }
+    });
+}
+
+fn check_withdrawal_lock(position: &YieldPosition, strategy: &YieldStrategy, current_time: u64) -> Result<(), CustodyError> {
+    let unlock_time = position.deposit_time
+        .saturating_add(strategy.withdrawal_lock_period_seconds.saturating_mul(1_000_000_000));
+
+    if current_time < unlock_time {
+        return Err(CustodyError::status_conflict(format!("locked until {}", unlock_time), "unlocked"));
+    }
+
+    Ok(())
+}
+
+// Accounting-only amounts (no token ledger) are just logged
+async fn icrc1_transfer(token_canister_id: Option<Principal>, to: Principal, amount: u64) -> Result<(), CustodyError> {
+    let Some(token_canister_id) = token_canister_id else {
+        ic_cdk::println!("ICRC-1 transfer of {} to {}", amount, to);
+        return Ok(());
+    };
+
+    let args = Icrc1TransferArg {
+        from_subaccount: None,
+        to: Icrc1Account { owner: to, subaccount: None },
+        amount: Nat::from(amount),
+        fee: None,
+        memo: None,
+        created_at_time: None,
+    };
+
+    let result: Result<(Result<Nat, Icrc1TransferError>,), _> =
+        ic_cdk::call(token_canister_id, "icrc1_transfer", (args,)).await;
+
+    match result {
+        Ok((Ok(_),)) => {
+            adjust_token_balance(token_canister_id, amount, false);
+            Ok(())
+        }
+        Ok((Err(e),)) => Err(CustodyError::InternalError(format!("Ledger transfer failed: {:?}", e))),
+        Err((code, msg)) => Err(CustodyError::InternalError(format!("Ledger transfer failed: {:?} {}", code, msg))),
+    }
+}
+
+fn adjust_token_balance(token_canister_id: Principal, amount: u64, credit: bool) {
+    CANISTER_TOKEN_BALANCES.with(|b| {
+        let mut balances = b.borrow_mut();
+        let balance = balances.entry(token_canister_id).or_default();
+        *balance = if credit {
+            balance.saturating_add(amount)
+        } else {
+            balance.saturating_sub(amount)
+        };
+    });
+}
+
+/// Compares the principal recorded in positions against what this canister
+/// actually holds on each token ledger. Returns actual minus recorded for
+/// every token that does not match; a negative value means positions are
+/// under-backed.
+#[update]
+async fn reconcile_balances() -> Result<BTreeMap<String, i64>, CustodyError> {
+    check_rate_limit(ic_cdk::caller(), "reconcile_balances")?;
+
+    let mut recorded: BTreeMap<Principal, u64> = CANISTER_TOKEN_BALANCES.with(|b| {
+        b.borrow().keys().map(|token| (*token, 0)).collect()
+    });
+
+    USER_POSITIONS.with(|p| {
+        for position in p.borrow().values().flatten() {
+            if let Some(token) = position.token_canister_id {
+                let total = recorded.entry(token).or_default();
+                *total = total.saturating_add(position.amount);
+            }
+        }
+    });
+
+    let mut discrepancies = BTreeMap::new();
+    for (token, recorded_amount) in recorded {
+        let account = Icrc1Account { owner: ic_cdk::id(), subaccount: None };
+        let result: Result<(Nat,), _> = ic_cdk::call(token, "icrc1_balance_of", (account,)).await;
+
+        let actual = match result {
+            Ok((balance,)) => u64::try_from(balance.0).unwrap_or(u64::MAX),
+            Err((code, msg)) => {
+                ic_cdk::println!("Balance check on {} failed: {:?} {}", token, code, msg);
+                continue;
+            }
+        };
+
+        CANISTER_TOKEN_BALANCES.with(|b| {
+            b.borrow_mut().insert(token, actual);
+        });
+
+        let difference = (actual as i128 - recorded_amount as i128).clamp(i64::MIN as i128, i64::MAX as i128) as i64;
+        if difference != 0 {
+            discrepancies.insert(token.to_text(), difference);
+        }
+    }
+
+    Ok(discrepancies)
+}
+
+/// Continuously compounded yield, principal * (e^(apy * t) - 1), using a
+/// fixed-point Taylor series so no floating point is involved. When the rate
+/// changed during the position, apy * t is the sum over each rate's period.
+/// Errors rather than saturating, so an overflow can't pay out u64::MAX.
+fn calculate_yield(position: &YieldPosition, strategy: &YieldStrategy, current_time: u64) -> Result<u64, CustodyError> {
+    let accrual_end = strategy.wound_down_at.map_or(current_time, |t| t.min(current_time));
+
+    // x = apy * t, scaled by YIELD_SCALE
+    let x = match apy_time_integral(strategy, position.start_time, accrual_end)
+        .and_then(|v| v.checked_mul(YIELD_SCALE))
+    {
+        Some(v) => v / (BASIS_POINTS * NANOS_PER_YEAR),
+        None => return Err(CustodyError::InternalError(YIELD_OVERFLOW.to_string())),
+    };
+
+    // e^x - 1 = x + x^2/2! + x^3/3! + ...
+    let mut term = x;
+    let mut growth = x;
+    let mut n = 2u128;
+    while term > 0 && n <= 64 {
+        term = match term.checked_mul(x) {
+            Some(v) => v / (n * YIELD_SCALE),
+            None => return Err(CustodyError::InternalError(YIELD_OVERFLOW.to_string())),
+        };
+        growth = growth.saturating_add(term);
+        n += 1;
+    }
+
+    let accrued = (position.amount as u128).checked_mul(growth)
+        .ok_or_else(|| CustodyError::InternalError(YIELD_OVERFLOW.to_string()))? / YIELD_SCALE;
+    u64::try_from(accrued).map_err(|_| CustodyError::InternalError(YIELD_OVERFLOW.to_string()))
+}
+
+// Sum of apy * elapsed nanoseconds over [start, end], using the rate that was
+// in force during each part of the period; None on overflow
+fn apy_time_integral(strategy: &YieldStrategy, start: u64, end: u64) -> Option<u128> {
+    if end <= start {
+        return Some(0);
+    }
+
+    APY_HISTORY.with(|h| {
+        let history = h.borrow();
+        let mut total: u128 = 0;
+        let mut segment_start = start;
+
+        for snapshot in history.get(&strategy.name).into_iter().flatten() {
+            if snapshot.timestamp <= segment_start {
+                continue;
+            }
+
+            let segment_end = snapshot.timestamp.min(end);
+            let weighted = (snapshot.apy_basis_points as u128).checked_mul((segment_end - segment_start) as u128)?;
+            total = total.checked_add(weighted)?;
+            segment_start = segment_end;
+
+            if segment_start == end {
+                return Some(total);
+            }
+        }
+
+        // The current rate applies since the last change
+        let weighted = (strategy.apy_basis_points as u128).checked_mul((end - segment_start) as u128)?;
+        total.checked_add(weighted)
+    })
+}
+
+#[query]
+fn get_user_positions(user: String) -> Vec<YieldPosition> {
+    USER_POSITIONS.with(|p| {
+        p.borrow().get(&user).cloned().unwrap_or_default()
+    })
+}
+
+#[query]
+fn get_memory_stats() -> MemoryStats {
+    memory::get_memory_stats()
+}
+
+#[query]
+fn greet(name: String) -> String {
+    format!("Hello, {}! This is the Yield Engine canister.", name)
+}
+
+const INTERFACE_VERSION: InterfaceVersion = (1, 0, 0);
+
+#[query]
+fn get_candid_interface_version() -> u32 {
+    INTERFACE_VERSION.0
+}
+
+#[query]
+fn get_interface_metadata() -> CandidVersion {
+    CandidVersion::new(INTERFACE_VERSION, &__export_service())
+}
+
+ic_cdk::export_candid!();
@@ -0,0 +1,75 @@
+//! Role checks against the auth canister
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use candid::Principal;
+
+/// How long a role lookup is trusted before the auth canister is asked again
+pub const ROLE_CACHE_TTL_NANOS: u64 = 60 * 1_000_000_000;
+
+thread_local! {
+    static AUTH_CANISTER: RefCell<Option<Principal>> = const { RefCell::new(None) };
+    // (principal, role_name) -> (has_role, checked_at)
+    static ROLE_CACHE: RefCell<BTreeMap<(Principal, String), (bool, u64)>> = const { RefCell::new(BTreeMap::new()) };
+}
+
+pub fn set_auth_canister(auth_canister: Option<Principal>) {
+    AUTH_CANISTER.with(|canister| *canister.borrow_mut() = auth_canister);
+    ROLE_CACHE.with(|cache| cache.borrow_mut().clear());
+}
+
+pub fn auth_canister() -> Option<Principal> {
+    AUTH_CANISTER.with(|canister| *canister.borrow())
+}
+
+/// Looks up a role granted to `principal` on this canister only from the
+/// cache, for query endpoints that cannot make inter-canister calls
+pub fn has_cached_role(principal: &Principal, role_name: &str) -> bool {
+    let now = ic_cdk::api::time();
+    
+    ROLE_CACHE.with(|cache| {
+        match cache.borrow().get(&(*principal, role_name.to_string())) {
+            Some((has_role, checked_at)) => *has_role && now.saturating_sub(*checked_at) < ROLE_CACHE_TTL_NANOS,
+            None => false,
+        }
+    })
+}
+
+/// Asks the auth canister whether `principal` holds `role_name` on this
+/// canister. Answers are cached for a minute; a failed call counts as no role.
+pub async fn has_role(principal: Principal, role_name: &str) -> bool {
+    let auth_canister = match auth_canister() {
+        Some(canister) => canister,
+        None => return false,
+    };
+    
+    let now = ic_cdk::api::time();
+    let cache_key = (principal, role_name.to_string());
+    
+    let cached = ROLE_CACHE.with(|cache| cache.borrow().get(&cache_key).copied());
+    if let Some((has_role, checked_at)) = cached {
+        if now.saturating_sub(checked_at) < ROLE_CACHE_TTL_NANOS {
+            return has_role;
+        }
+    }
+    
+    let result: Result<(bool,), _> = ic_cdk::call(
+        auth_canister,
+        "has_role",
+        (principal, ic_cdk::id().to_string(), role_name.to_string()),
+    )
+    .await;
+    
+    match result {
+        Ok((has_role,)) => {
+            ROLE_CACHE.with(|cache| {
+                let mut cache = cache.borrow_mut();
+                cache.retain(|_, (_, checked_at)| now.saturating_sub(*checked_at) < ROLE_CACHE_TTL_NANOS);
+                cache.insert(cache_key, (has_role, now));
+            });
+            has_role
+        },
+        Err(_) => false,
+    }
+}
@@ -0,0 +1,66 @@
+//! Identity of the user behind a chain of inter-canister calls
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+thread_local! {
+    // Canisters allowed to act on behalf of the user named in their context
+    static TRUSTED_CANISTERS: RefCell<BTreeSet<Principal>> = const { RefCell::new(BTreeSet::new()) };
+}
+
+/// Passed along with every inter-canister call so the receiving canister can
+/// attribute its records to the user who started the request rather than to
+/// the canister relaying it
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct RequestContext {
+    pub original_caller: Principal,
+    pub session_id: String,
+    pub correlation_id: String,
+    pub initiated_at: u64,
+}
+
+impl RequestContext {
+    pub fn new(original_caller: Principal, correlation_id: impl Into<String>) -> Self {
+        let initiated_at = ic_cdk::api::time();
+        RequestContext {
+            original_caller,
+            session_id: format!("{}-{}", original_caller, initiated_at),
+            correlation_id: correlation_id.into(),
+            initiated_at,
+        }
+    }
+}
+
+/// The context an upstream canister passed in, or a new one naming the direct
+/// caller when the request did not come through another canister
+pub fn inject_request_context(context: Option<RequestContext>, correlation_id: impl Into<String>) -> RequestContext {
+    context.unwrap_or_else(|| RequestContext::new(ic_cdk::caller(), correlation_id))
+}
+
+pub fn set_trusted_canister(canister: Principal, trusted: bool) {
+    TRUSTED_CANISTERS.with(|canisters| {
+        let mut canisters = canisters.borrow_mut();
+        if trusted {
+            canisters.insert(canister);
+        } else {
+            canisters.remove(&canister);
+        }
+    });
+}
+
+pub fn is_trusted_canister(principal: &Principal) -> bool {
+    TRUSTED_CANISTERS.with(|canisters| canisters.borrow().contains(principal))
+}
+
+pub fn trusted_canisters() -> Vec<Principal> {
+    TRUSTED_CANISTERS.with(|canisters| canisters.borrow().iter().copied().collect())
+}
+
+/// Drops a context sent by a caller that isn't a trusted canister. Anyone can
+/// put any principal in `original_caller`, so only trusted relays are believed
+pub fn verified_request_context(caller: Principal, context: Option<RequestContext>) -> Option<RequestContext> {
+    context.filter(|_| is_trusted_canister(&caller))
+}
@@ -0,0 +1,89 @@
+//! Cycle balance tracking and low balance alerts
+
+use std::cell::RefCell;
+use std::time::Duration;
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+/// How often the timer started by `start_cycle_monitor` checks the balance
+pub const CYCLE_CHECK_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+/// 1T cycles
+pub const DEFAULT_CYCLE_ALERT_THRESHOLD: u64 = 1_000_000_000_000;
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+pub struct CycleStats {
+    pub current_balance: u64,
+    pub threshold: u64,
+    pub last_checked: u64,
+}
+
+thread_local! {
+    static CYCLE_ALERT_THRESHOLD: RefCell<u64> = const { RefCell::new(DEFAULT_CYCLE_ALERT_THRESHOLD) };
+    static CYCLES_LAST_CHECKED: RefCell<u64> = const { RefCell::new(0) };
+    // Canister exposing `record_cycle_alert(text, nat64, nat64)` that low
+    // balance alerts are forwarded to
+    static CYCLE_ALERT_CANISTER: RefCell<Option<Principal>> = const { RefCell::new(None) };
+}
+
+pub fn check_cycle_balance() -> u64 {
+    ic_cdk::api::canister_balance()
+}
+
+pub fn set_cycle_alert_threshold(threshold: u64) {
+    CYCLE_ALERT_THRESHOLD.with(|t| *t.borrow_mut() = threshold);
+}
+
+pub fn set_cycle_alert_canister(alert_canister: Option<Principal>) {
+    CYCLE_ALERT_CANISTER.with(|canister| *canister.borrow_mut() = alert_canister);
+}
+
+pub fn get_cycle_stats() -> CycleStats {
+    CycleStats {
+        current_balance: check_cycle_balance(),
+        threshold: CYCLE_ALERT_THRESHOLD.with(|t| *t.borrow()),
+        last_checked: CYCLES_LAST_CHECKED.with(|t| *t.borrow()),
+    }
+}
+
+/// Checks the balance every `CYCLE_CHECK_INTERVAL` and calls `notify` with
+/// the balance and threshold whenever the balance is below the threshold.
+/// Timers don't survive upgrades, so call this from both init and post_upgrade.
+pub fn start_cycle_monitor(notify: fn(u64, u64)) {
+    ic_cdk_timers::set_timer_interval(CYCLE_CHECK_INTERVAL, move || run_cycle_check(notify));
+}
+
+fn run_cycle_check(notify: fn(u64, u64)) {
+    let balance = check_cycle_balance();
+    let threshold = CYCLE_ALERT_THRESHOLD.with(|t| *t.borrow());
+    
+    CYCLES_LAST_CHECKED.with(|t| *t.borrow_mut() = ic_cdk::api::time());
+    
+    if balance < threshold {
+        notify(balance, threshold);
+    }
+}
+
+/// Default notifier: forwards the alert to the configured alert canister
+pub fn forward_cycle_alert(balance: u64, threshold: u64) {
+    ic_cdk::println!("Cycle balance {} is below alert threshold {}", balance, threshold);
+    
+    let alert_canister = match CYCLE_ALERT_CANISTER.with(|canister| *canister.borrow()) {
+        Some(canister) => canister,
+        None => return,
+    };
+    
+    ic_cdk::spawn(async move {
+        let result: Result<(), _> = ic_cdk::call(
+            alert_canister,
+            "record_cycle_alert",
+            (ic_cdk::id().to_string(), balance, threshold),
+        )
+        .await;
+        
+        if let Err((code, msg)) = result {
+            ic_cdk::println!("Failed to forward cycle alert: {:?} {}", code, msg);
+        }
+    });
+}
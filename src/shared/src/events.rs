@@ -0,0 +1,84 @@
+//! Events published through the event_bus canister
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::CustodyError;
+
+pub const TOPIC_TRANSACTION_EXECUTED: &str = "transaction.executed";
+pub const TOPIC_ACCOUNT_FROZEN: &str = "account.frozen";
+pub const TOPIC_KYC_APPROVED: &str = "kyc.approved";
+pub const TOPIC_SAR_FILED: &str = "sar.filed";
+
+/// Method the event bus calls on each subscriber, as `(CanisterEvent) -> ()`
+pub const EVENT_HANDLER_METHOD: &str = "handle_event";
+
+/// An event as the bus delivers it. The payload is the candid encoding of
+/// the topic's payload type below; the bus fills in the publisher and time
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct CanisterEvent {
+    pub topic: String,
+    pub payload: Vec<u8>,
+    pub publisher: Principal,
+    pub published_at: u64,
+}
+
+/// Payload of `transaction.executed`
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct TransactionExecutedEvent {
+    pub account_id: String,
+    pub transaction_id: String,
+    pub amount: u64,
+    pub transaction_type: String,
+    pub initiated_by: Principal,
+}
+
+/// Payload of `account.frozen`
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct AccountFrozenEvent {
+    pub account_id: String,
+}
+
+/// Payload of `kyc.approved`
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct KycApprovedEvent {
+    pub kyc_id: String,
+    pub principal: Principal,
+    pub verification_level: String,
+}
+
+/// Payload of `sar.filed`
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct SarFiledEvent {
+    pub sar_id: String,
+    pub account_id: String,
+    pub reference_number: String,
+}
+
+/// Encodes `payload` and publishes it on `topic`. Delivery is best effort,
+/// so failures are logged rather than returned to the publishing canister
+pub async fn publish_event<T: CandidType>(event_bus: Principal, topic: &str, payload: T) {
+    let payload = match candid::encode_one(payload) {
+        Ok(payload) => payload,
+        Err(e) => {
+            ic_cdk::println!("Failed to encode {} event: {}", topic, e);
+            return;
+        }
+    };
+    
+    let event = CanisterEvent {
+        topic: topic.to_string(),
+        payload,
+        publisher: ic_cdk::id(),
+        published_at: ic_cdk::api::time(),
+    };
+    
+    let result: Result<(Result<String, CustodyError>,), _> =
+        ic_cdk::call(event_bus, "publish", (event,)).await;
+    
+    match result {
+        Ok((Ok(_),)) => {},
+        Ok((Err(e),)) => ic_cdk::println!("Event bus rejected {} event: {}", topic, e),
+        Err((code, msg)) => ic_cdk::println!("Publishing {} event failed: {:?} {}", topic, code, msg),
+    }
+}
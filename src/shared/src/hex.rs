@@ -0,0 +1,32 @@
+//! Lowercase hex encoding for hashes, signatures and raw transactions
+
+pub fn encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decodes a hex string of either case. Non-ASCII input is rejected up front,
+/// since slicing it into byte pairs could split a multi-byte character.
+pub fn decode(hex: &str) -> Result<Vec<u8>, String> {
+    if !hex.is_ascii() || !hex.len().is_multiple_of(2) {
+        return Err("Invalid hex string".to_string());
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).map_err(|e| e.to_string()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_hex_round_trip_and_invalid_input() {
+        assert_eq!(decode(&encode(&[0x00, 0xab, 0xff])).unwrap(), vec![0x00, 0xab, 0xff]);
+        assert_eq!(decode("ABcd").unwrap(), vec![0xab, 0xcd]);
+        assert!(decode("abc").is_err());
+        assert!(decode("zz").is_err());
+        // Two bytes long, but one character
+        assert!(decode("é").is_err());
+    }
+}
@@ -0,0 +1,31 @@
+//! Versioning of each canister's Candid interface
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+/// (major, minor, patch). Bump major when a method is removed or its
+/// signature changes, minor when a method is added, patch otherwise
+pub type InterfaceVersion = (u32, u32, u32);
+
+/// Returned by every canister's `get_interface_metadata`. Clients compare
+/// the major version, or the hash for any change at all, before calling
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct CandidVersion {
+    pub major: u32,
+    pub minor: u32,
+    pub patch: u32,
+    // SHA-256 of the exported Candid text
+    pub interface_hash: String,
+}
+
+impl CandidVersion {
+    pub fn new((major, minor, patch): InterfaceVersion, candid: &str) -> Self {
+        CandidVersion {
+            major,
+            minor,
+            patch,
+            interface_hash: format!("{:x}", Sha256::digest(candid.as_bytes())),
+        }
+    }
+}
@@ -0,0 +1,72 @@
+//! Types shared by all custody canisters
+
+use candid::CandidType;
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+
+pub mod auth;
+pub mod context;
+pub mod cycles;
+pub mod events;
+pub mod hex;
+pub mod interface;
+pub mod memory;
+pub mod rate_limit;
+
+pub use context::{inject_request_context, RequestContext};
+pub use rate_limit::check_rate_limit;
+
+/// Error returned by every canister endpoint so callers can branch on the
+/// reason instead of matching on message strings
+#[derive(Clone, Debug, PartialEq, Eq, Error, CandidType, Serialize, Deserialize)]
+pub enum CustodyError {
+    #[error("{resource} not found: {id}")]
+    NotFound { resource: String, id: String },
+    #[error("Unauthorized: {action}")]
+    Unauthorized { action: String },
+    #[error("Invalid {field}: {reason}")]
+    InvalidInput { field: String, reason: String },
+    #[error("Insufficient balance: {available} available, {required} required")]
+    InsufficientBalance { available: u64, required: u64 },
+    #[error("Status is {current}, expected {required}")]
+    StatusConflict { current: String, required: String },
+    #[error("Limit of {limit} exceeded: {actual}")]
+    LimitExceeded { limit: u64, actual: u64 },
+    #[error("Rate limit exceeded, retry after {retry_after} seconds")]
+    RateLimitExceeded { retry_after: u64 },
+    #[error("Velocity limit of {limit} per {window_minutes} minutes exceeded: {actual}")]
+    VelocityLimitExceeded { limit: u64, actual: u64, window_minutes: u32 },
+    #[error("Blocked by risk management: {reason}")]
+    RiskBlocked { reason: String },
+    #[error("{0}")]
+    InternalError(String),
+}
+
+impl CustodyError {
+    pub fn not_found(resource: &str, id: impl Into<String>) -> Self {
+        CustodyError::NotFound {
+            resource: resource.to_string(),
+            id: id.into(),
+        }
+    }
+    
+    pub fn unauthorized(action: &str) -> Self {
+        CustodyError::Unauthorized {
+            action: action.to_string(),
+        }
+    }
+    
+    pub fn invalid_input(field: &str, reason: impl Into<String>) -> Self {
+        CustodyError::InvalidInput {
+            field: field.to_string(),
+            reason: reason.into(),
+        }
+    }
+    
+    pub fn status_conflict(current: impl Into<String>, required: impl Into<String>) -> Self {
+        CustodyError::StatusConflict {
+            current: current.into(),
+            required: required.into(),
+        }
+    }
+}
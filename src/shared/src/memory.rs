@@ -0,0 +1,142 @@
+//! Heap and stable memory tracking and high usage alerts
+
+use std::cell::RefCell;
+use std::time::Duration;
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::{CustodyError, RequestContext};
+
+/// How often the timer started by `start_memory_monitor` checks usage
+pub const MEMORY_CHECK_INTERVAL: Duration = Duration::from_secs(6 * 60 * 60);
+
+/// Heap utilization above which the canister reports high memory
+pub const HIGH_MEMORY_THRESHOLD_PERCENT: u8 = 80;
+
+const WASM_PAGE_BYTES: u64 = 64 * 1024;
+
+/// A wasm32 heap can't address more than 4 GiB
+pub const HEAP_LIMIT_BYTES: u64 = 4 * 1024 * 1024 * 1024;
+
+/// Per-canister stable memory limit, 500 GiB
+pub const STABLE_LIMIT_BYTES: u64 = 500 * 1024 * 1024 * 1024;
+
+#[derive(Clone, Debug, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct MemoryStats {
+    pub heap_bytes: u64,
+    pub stable_bytes: u64,
+    pub heap_limit: u64,
+    pub stable_limit: u64,
+    pub heap_utilization_percent: u8,
+}
+
+thread_local! {
+    // Set by the last check when heap utilization was above the threshold
+    static HIGH_MEMORY_FLAG: RefCell<bool> = const { RefCell::new(false) };
+}
+
+pub fn get_memory_stats() -> MemoryStats {
+    let heap_bytes = heap_pages() * WASM_PAGE_BYTES;
+    let stable_bytes = ic_cdk::api::stable::stable_size() * WASM_PAGE_BYTES;
+    
+    MemoryStats {
+        heap_bytes,
+        stable_bytes,
+        heap_limit: HEAP_LIMIT_BYTES,
+        stable_limit: STABLE_LIMIT_BYTES,
+        heap_utilization_percent: (heap_bytes.saturating_mul(100) / HEAP_LIMIT_BYTES).min(100) as u8,
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+fn heap_pages() -> u64 {
+    core::arch::wasm32::memory_size(0) as u64
+}
+
+// Native builds (unit tests) have no wasm heap to measure
+#[cfg(not(target_arch = "wasm32"))]
+fn heap_pages() -> u64 {
+    0
+}
+
+/// Whether the last check found memory critically high. Canisters refuse
+/// work that would grow their state while this is set
+pub fn is_memory_critical() -> bool {
+    HIGH_MEMORY_FLAG.with(|flag| *flag.borrow())
+}
+
+/// Checks usage every `MEMORY_CHECK_INTERVAL` and calls `notify` with the
+/// stats whenever heap utilization is above the threshold. Timers don't
+/// survive upgrades, so call this from both init and post_upgrade.
+pub fn start_memory_monitor(notify: fn(&MemoryStats)) {
+    ic_cdk_timers::set_timer_interval(MEMORY_CHECK_INTERVAL, move || run_memory_check(notify));
+}
+
+fn run_memory_check(notify: fn(&MemoryStats)) {
+    let stats = get_memory_stats();
+    let critical = stats.heap_utilization_percent > HIGH_MEMORY_THRESHOLD_PERCENT;
+    
+    // The flag clears again once usage drops back under the threshold
+    HIGH_MEMORY_FLAG.with(|flag| *flag.borrow_mut() = critical);
+    
+    if critical {
+        notify(&stats);
+    }
+}
+
+/// Default notifier for canisters without an audit trail to report to
+pub fn log_memory_alert(stats: &MemoryStats) {
+    ic_cdk::println!(
+        "Heap utilization {}% ({} of {} bytes) is above {}%",
+        stats.heap_utilization_percent,
+        stats.heap_bytes,
+        stats.heap_limit,
+        HIGH_MEMORY_THRESHOLD_PERCENT,
+    );
+}
+
+// Mirrors of the audit_trail canister's event and resource types
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+enum AuditEventType {
+    SystemConfiguration,
+}
+
+#[derive(Clone, Debug, CandidType, Serialize, Deserialize)]
+enum AuditResourceType {
+    System,
+}
+
+/// Records a high memory alert as a SystemConfiguration event on the
+/// audit_trail canister
+pub async fn audit_memory_alert(audit_canister: Principal, stats: MemoryStats) {
+    log_memory_alert(&stats);
+    
+    let canister_id = ic_cdk::id();
+    let details = format!(
+        "Heap utilization {}%: {} heap bytes, {} stable bytes",
+        stats.heap_utilization_percent, stats.heap_bytes, stats.stable_bytes,
+    );
+    let context = RequestContext::new(canister_id, format!("memory-{}", ic_cdk::api::time()));
+    
+    let result: Result<(Result<String, CustodyError>,), _> = ic_cdk::call(
+        audit_canister,
+        "log_audit_event",
+        (
+            AuditEventType::SystemConfiguration,
+            AuditResourceType::System,
+            canister_id.to_text(),
+            "high_memory_usage".to_string(),
+            details,
+            None::<()>,
+            true,
+            Some(context),
+        ),
+    ).await;
+    
+    match result {
+        Ok((Ok(_),)) => {},
+        Ok((Err(e),)) => ic_cdk::println!("Audit trail rejected high memory alert: {}", e),
+        Err((code, msg)) => ic_cdk::println!("High memory alert failed: {:?} {}", code, msg),
+    }
+}
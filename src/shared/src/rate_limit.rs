@@ -0,0 +1,141 @@
+//! Per-principal, per-method call rate limiting for update endpoints
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+use candid::{CandidType, Principal};
+use serde::{Deserialize, Serialize};
+
+use crate::CustodyError;
+
+/// Length of a rate limit window
+pub const RATE_LIMIT_WINDOW_NANOS: u64 = 60 * 1_000_000_000;
+
+/// Calls allowed per window for methods without an explicit limit
+pub const DEFAULT_CALLS_PER_WINDOW: u32 = 30;
+
+/// Per-method limits. Expensive endpoints get tighter limits, endpoints that
+/// other canisters call once per user transaction get looser ones.
+const METHOD_CALL_LIMITS: &[(&str, u32)] = &[
+    ("initiate_transaction", 10),
+    ("initiate_multisig_transaction", 10),
+    ("schedule_transaction", 10),
+    ("batch_process_transactions", 5),
+    ("submit_transaction", 10),
+    ("submit_batch_transactions", 2),
+    ("batch_create_kyc_profiles", 2),
+    ("batch_sanctions_screen", 5),
+    ("generate_compliance_report", 5),
+    ("export_compliance_report_json", 5),
+    ("export_audit_data", 5),
+    ("run_stress_test", 5),
+    ("enforce_retention_policy", 2),
+    ("broadcast_transaction", 10),
+    ("log_audit_event", 600),
+    ("batch_log_audit_events", 60),
+    ("monitor_transaction", 600),
+    ("assess_risk", 600),
+    ("evaluate_transaction", 600),
+    ("publish", 600),
+];
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, CandidType, Serialize, Deserialize)]
+pub struct RateLimitState {
+    pub window_start: u64,
+    pub call_count: u32,
+}
+
+thread_local! {
+    // (caller, method) -> window, so each method's limit is its own budget
+    static RATE_LIMITS: RefCell<BTreeMap<(Principal, String), RateLimitState>> = const { RefCell::new(BTreeMap::new()) };
+    static LAST_PRUNED_AT: RefCell<u64> = const { RefCell::new(0) };
+}
+
+pub fn method_call_limit(method_name: &str) -> u32 {
+    METHOD_CALL_LIMITS
+        .iter()
+        .find(|(name, _)| *name == method_name)
+        .map(|(_, limit)| *limit)
+        .unwrap_or(DEFAULT_CALLS_PER_WINDOW)
+}
+
+/// Records a call from `caller` and rejects it once the caller has used up
+/// the limit for `method_name` in the current window. Calls to other methods
+/// don't count against it.
+pub fn check_rate_limit(caller: Principal, method_name: &str) -> Result<(), CustodyError> {
+    check_rate_limit_at(caller, method_name, ic_cdk::api::time())
+}
+
+pub fn check_rate_limit_at(caller: Principal, method_name: &str, now: u64) -> Result<(), CustodyError> {
+    prune_expired_windows(now);
+    
+    let limit = method_call_limit(method_name);
+    
+    RATE_LIMITS.with(|limits| {
+        let mut limits = limits.borrow_mut();
+        let state = limits.entry((caller, method_name.to_string())).or_default();
+        
+        if now.saturating_sub(state.window_start) >= RATE_LIMIT_WINDOW_NANOS {
+            state.window_start = now;
+            state.call_count = 0;
+        }
+        
+        if state.call_count >= limit {
+            let window_end = state.window_start + RATE_LIMIT_WINDOW_NANOS;
+            let retry_after = window_end.saturating_sub(now).div_ceil(1_000_000_000);
+            return Err(CustodyError::RateLimitExceeded { retry_after });
+        }
+        
+        state.call_count += 1;
+        Ok(())
+    })
+}
+
+/// Drops (caller, method) windows that have expired. Runs at most once per window
+/// so the store only holds callers active in the last minute.
+fn prune_expired_windows(now: u64) {
+    let due = LAST_PRUNED_AT.with(|last| {
+        let mut last = last.borrow_mut();
+        if now.saturating_sub(*last) < RATE_LIMIT_WINDOW_NANOS {
+            return false;
+        }
+        *last = now;
+        true
+    });
+    
+    if due {
+        RATE_LIMITS.with(|limits| {
+            limits.borrow_mut().retain(|_, state| {
+                now.saturating_sub(state.window_start) < RATE_LIMIT_WINDOW_NANOS
+            });
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_rate_limit_window() {
+        let caller = Principal::anonymous();
+        let start = RATE_LIMIT_WINDOW_NANOS;
+        
+        for _ in 0..method_call_limit("submit_batch_transactions") {
+            assert!(check_rate_limit_at(caller, "submit_batch_transactions", start).is_ok());
+        }
+        assert_eq!(
+            check_rate_limit_at(caller, "submit_batch_transactions", start + 1_500_000_000),
+            Err(CustodyError::RateLimitExceeded { retry_after: 59 })
+        );
+        // Other methods keep their own budget
+        assert!(check_rate_limit_at(caller, "log_audit_event", start + 1_500_000_000).is_ok());
+        
+        // A new window resets the count and pruning drops idle callers
+        let next_window = start + RATE_LIMIT_WINDOW_NANOS;
+        assert!(check_rate_limit_at(caller, "submit_batch_transactions", next_window).is_ok());
+        let later = next_window + 2 * RATE_LIMIT_WINDOW_NANOS;
+        check_rate_limit_at(Principal::management_canister(), "assess_risk", later).unwrap();
+        assert_eq!(RATE_LIMITS.with(|limits| limits.borrow().len()), 1);
+    }
+}
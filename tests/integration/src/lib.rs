@@ -0,0 +1,375 @@
+//! PocketIC harness for end-to-end tests across the custody canisters.
+//!
+//! Every canister is installed from its compiled WASM, so build them first:
+//!
+//! ```text
+//! cargo build --target wasm32-unknown-unknown --release \
+//!     -p custody_core -p compliance_engine -p multisig_wallet -p audit_trail -p risk_management
+//! ```
+//!
+//! and point `POCKET_IC_BIN` at a PocketIC server binary. WASM files are read from
+//! `target/wasm32-unknown-unknown/release` unless `CANISTER_WASM_DIR` is set. Each
+//! test passes without running when `POCKET_IC_BIN` is unset, so a plain
+//! `cargo test --workspace` works without the server; set it to run the suite.
+
+use candid::{decode_one, encode_args, encode_one, CandidType, Deserialize, Principal};
+use pocket_ic::{PocketIc, WasmResult};
+use serde::de::DeserializeOwned;
+use std::path::PathBuf;
+use std::time::{Duration, UNIX_EPOCH};
+
+pub use shared::CustodyError;
+
+const INIT_CYCLES: u128 = 2_000_000_000_000;
+const SETTLE_ROUNDS: usize = 10;
+
+pub struct TestEnv {
+    pub pic: PocketIc,
+    pub admin: Principal,
+    pub custody_core: Principal,
+    pub compliance_engine: Principal,
+    pub multisig_wallet: Principal,
+    pub audit_trail: Principal,
+    pub risk_management: Principal,
+}
+
+impl TestEnv {
+    /// Deploys the canisters when `POCKET_IC_BIN` is set. Without it there is
+    /// no server to run against, so the calling test is skipped.
+    pub fn from_env() -> Option<Self> {
+        if std::env::var_os("POCKET_IC_BIN").is_none() {
+            eprintln!("POCKET_IC_BIN is not set; skipping end-to-end test");
+            return None;
+        }
+        Some(Self::new())
+    }
+
+    /// Deploys all five canisters with `admin` as the installer, which gives it the
+    /// emergency contact, compliance officer and auditor roles each init grants, and
+    /// wires custody_core to compliance, risk management and the audit trail.
+    pub fn new() -> Self {
+        let pic = PocketIc::new();
+        let admin = principal("admin");
+
+        let no_auth = encode_one(None::<Principal>).unwrap();
+        let compliance_engine = install(&pic, admin, "compliance_engine", no_auth.clone());
+        let audit_trail = install(&pic, admin, "audit_trail", no_auth.clone());
+        let risk_management = install(&pic, admin, "risk_management", no_auth.clone());
+        let multisig_wallet = install(&pic, admin, "multisig_wallet", no_auth);
+
+        let integration = IntegrationConfig {
+            compliance_canister: Some(compliance_engine),
+            compliance_check_timeout_ns: 30_000_000_000,
+            btc_integration_canister: None,
+        };
+        let custody_core = install(
+            &pic,
+            admin,
+            "custody_core",
+            encode_args((None::<Principal>, Some(integration))).unwrap(),
+        );
+
+        let env = Self {
+            pic,
+            admin,
+            custody_core,
+            compliance_engine,
+            multisig_wallet,
+            audit_trail,
+            risk_management,
+        };
+
+        for (method, target) in [
+            ("set_risk_management_canister", risk_management),
+            ("set_audit_trail_canister", audit_trail),
+            ("add_authorized_operator", admin),
+        ] {
+            let result: Result<String, CustodyError> =
+                env.update(custody_core, admin, method, encode_one(target).unwrap());
+            result.unwrap_or_else(|e| panic!("{} failed: {}", method, e));
+        }
+
+        env
+    }
+
+    pub fn update<R: CandidType + DeserializeOwned>(
+        &self,
+        canister: Principal,
+        sender: Principal,
+        method: &str,
+        args: Vec<u8>,
+    ) -> R {
+        let result = self.pic.update_call(canister, sender, method, args);
+        decode_reply(method, result)
+    }
+
+    pub fn query<R: CandidType + DeserializeOwned>(
+        &self,
+        canister: Principal,
+        sender: Principal,
+        method: &str,
+        args: Vec<u8>,
+    ) -> R {
+        let result = self.pic.query_call(canister, sender, method, args);
+        decode_reply(method, result)
+    }
+
+    /// Moves the replica clock forward and lets timers and spawned calls run
+    pub fn advance_time(&self, duration: Duration) {
+        self.pic.advance_time(duration);
+        self.settle();
+    }
+
+    /// Executes enough rounds for `ic_cdk::spawn`ed inter-canister calls to finish
+    pub fn settle(&self) {
+        for _ in 0..SETTLE_ROUNDS {
+            self.pic.tick();
+        }
+    }
+
+    pub fn now_nanos(&self) -> u64 {
+        self.pic
+            .get_time()
+            .duration_since(UNIX_EPOCH)
+            .expect("PocketIC time is after the epoch")
+            .as_nanos() as u64
+    }
+}
+
+impl Default for TestEnv {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Deterministic test identity derived from a label
+pub fn principal(label: &str) -> Principal {
+    Principal::self_authenticating(label.as_bytes())
+}
+
+fn install(pic: &PocketIc, controller: Principal, name: &str, init_args: Vec<u8>) -> Principal {
+    let canister = pic.create_canister_with_settings(Some(controller), None);
+    pic.add_cycles(canister, INIT_CYCLES);
+    pic.install_canister(canister, wasm(name), init_args, Some(controller));
+    canister
+}
+
+fn wasm(name: &str) -> Vec<u8> {
+    let dir = std::env::var("CANISTER_WASM_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(|_| {
+            PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("../../target/wasm32-unknown-unknown/release")
+        });
+    let path = dir.join(format!("{}.wasm", name));
+    std::fs::read(&path).unwrap_or_else(|e| {
+        panic!(
+            "Failed to read {}: {}. Build the canisters for wasm32-unknown-unknown first",
+            path.display(),
+            e
+        )
+    })
+}
+
+fn decode_reply<R: CandidType + DeserializeOwned, E: std::fmt::Debug>(
+    method: &str,
+    result: Result<WasmResult, E>,
+) -> R {
+    match result {
+        Ok(WasmResult::Reply(bytes)) => decode_one(&bytes)
+            .unwrap_or_else(|e| panic!("Failed to decode {} reply: {}", method, e)),
+        Ok(WasmResult::Reject(msg)) => panic!("{} rejected: {}", method, msg),
+        Err(e) => panic!("{} trapped: {:?}", method, e),
+    }
+}
+
+// === Candid Mirrors ===
+//
+// Records only list the fields the tests read; candid skips the rest on decode.
+// Variants are mirrored in full so every value the canisters return decodes.
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct IntegrationConfig {
+    pub compliance_canister: Option<Principal>,
+    pub compliance_check_timeout_ns: u64,
+    pub btc_integration_canister: Option<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum AccountType {
+    CorporateCustody,
+    GovernmentCustody,
+    InstitutionalCustody,
+    TrustCustody,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum AccountStatus {
+    Active,
+    Frozen,
+    PendingApproval,
+    Suspended,
+    Closed,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum TransactionType {
+    Deposit,
+    Withdrawal,
+    Transfer,
+    Emergency,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum TransactionStatus {
+    Pending,
+    Approved,
+    Executing,
+    Executed,
+    Rejected,
+    Cancelled,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct CustodyAccount {
+    pub id: String,
+    pub owner: Principal,
+    pub status: AccountStatus,
+    pub balance: u64,
+    pub authorized_users: Vec<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct Transaction {
+    pub id: String,
+    pub account_id: String,
+    pub transaction_type: TransactionType,
+    pub amount: u64,
+    pub status: TransactionStatus,
+    pub approvals: Vec<Principal>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AccountClosure {
+    pub account_id: String,
+    pub closed_at: Option<u64>,
+    pub sweep_transaction_id: Option<String>,
+    pub audit_entry_id: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum EntityType {
+    Individual,
+    Corporation,
+    Government,
+    FinancialInstitution,
+    CustodyProvider,
+    TrustCompany,
+    NonProfit,
+    Partnership,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum DocumentType {
+    IdentityDocument,
+    ProofOfAddress,
+    ArticlesOfIncorporation,
+    CertificateOfIncorporation,
+    TaxDocument,
+    BankStatement,
+    ComplianceCertificate,
+    LicenseDocument,
+    PowerOfAttorney,
+    BoardResolution,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum VerificationLevel {
+    Basic,
+    Enhanced,
+    Institutional,
+    Government,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct AuditEntry {
+    pub id: String,
+    pub timestamp: u64,
+    pub actor: Principal,
+    pub resource_id: String,
+    pub action: String,
+    pub details: String,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum WalletType {
+    CorporateOperational,
+    CorporateTreasury,
+    GovernmentOperational,
+    GovernmentEmergency,
+    InstitutionalCold,
+    InstitutionalHot,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub enum TransactionPriority {
+    Low,
+    Normal,
+    High,
+    Emergency,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MultisigWallet {
+    pub id: String,
+    pub name: String,
+    pub owners: Vec<Principal>,
+    pub threshold: u8,
+    pub balance: u64,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct MultisigTransaction {
+    pub id: String,
+    pub wallet_id: String,
+    pub to: String,
+    pub amount: u64,
+    pub confirmations: Vec<Principal>,
+    pub executed: bool,
+    pub asset_id: Option<String>,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug, PartialEq)]
+pub enum AuditAction {
+    WalletCreated,
+    OwnerAdded,
+    OwnerRemoved,
+    ThresholdChanged,
+    TransactionSubmitted,
+    TransactionConfirmed,
+    TransactionRejected,
+    TransactionCancelled,
+    TransactionExecuted,
+    WalletFrozen,
+    WalletUnfrozen,
+    PolicyUpdated,
+    EmergencyAction,
+    VelocityLimitExceeded,
+    GuardianAdded,
+    GuardianRemoved,
+    RecoveryInitiated,
+    RecoveryConfirmed,
+    RecoveryCancelled,
+    RecoveryExecuted,
+    MetadataUpdated,
+    AssetDeposited,
+    InternalFunding,
+}
+
+#[derive(CandidType, Deserialize, Clone, Debug)]
+pub struct WalletAuditLog {
+    pub id: String,
+    pub wallet_id: String,
+    pub action: AuditAction,
+    pub actor: Principal,
+    pub transaction_id: Option<String>,
+}
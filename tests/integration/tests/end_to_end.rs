@@ -0,0 +1,415 @@
+use candid::{encode_args, encode_one, Principal};
+use integration_tests::*;
+use std::time::Duration;
+
+/// Walks a KYC profile through document verification and approval so
+/// `check_compliance_status` reports the principal as compliant
+fn approve_kyc(env: &TestEnv, subject: Principal) {
+    let kyc_id: Result<String, CustodyError> = env.update(
+        env.compliance_engine,
+        env.admin,
+        "create_kyc_profile",
+        encode_args((
+            subject,
+            EntityType::Corporation,
+            "Acme Holdings AG",
+            "Switzerland",
+            Some("CHE-123.456.789"),
+        ))
+        .unwrap(),
+    );
+    let kyc_id = kyc_id.expect("KYC profile created");
+
+    let document_id: Result<String, CustodyError> = env.update(
+        env.compliance_engine,
+        env.admin,
+        "add_kyc_document",
+        encode_args((
+            kyc_id.clone(),
+            DocumentType::CertificateOfIncorporation,
+            "certificate_of_incorporation.pdf",
+            "9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08",
+            "{}",
+        ))
+        .unwrap(),
+    );
+    let document_id = document_id.expect("KYC document added");
+
+    let verified: Result<String, CustodyError> = env.update(
+        env.compliance_engine,
+        env.admin,
+        "verify_kyc_document",
+        encode_args((kyc_id.clone(), document_id, true)).unwrap(),
+    );
+    verified.expect("KYC document verified");
+
+    let approved: Result<String, CustodyError> = env.update(
+        env.compliance_engine,
+        env.admin,
+        "approve_kyc_profile",
+        encode_args((kyc_id, VerificationLevel::Institutional)).unwrap(),
+    );
+    approved.expect("KYC profile approved");
+}
+
+fn create_account(env: &TestEnv, owner: Principal) -> String {
+    let account_id: Result<String, CustodyError> = env.update(
+        env.custody_core,
+        owner,
+        "create_custody_account",
+        encode_args(("Acme Holdings AG", AccountType::CorporateCustody, 2u8)).unwrap(),
+    );
+    account_id.expect("custody account created")
+}
+
+fn approve_account(env: &TestEnv, account_id: &str) -> Result<String, CustodyError> {
+    env.update(
+        env.custody_core,
+        env.admin,
+        "approve_custody_account",
+        encode_args((account_id, None::<String>)).unwrap(),
+    )
+}
+
+/// Opens a two-approval corporate account for a KYC-approved `owner` and adds
+/// `cosigner` as the second authorized user
+fn open_account(env: &TestEnv, owner: Principal, cosigner: Principal) -> String {
+    approve_kyc(env, owner);
+    let account_id = create_account(env, owner);
+    approve_account(env, &account_id).expect("custody account approved");
+
+    let added: Result<String, CustodyError> = env.update(
+        env.custody_core,
+        owner,
+        "add_authorized_user",
+        encode_args((account_id.clone(), cosigner, None::<String>)).unwrap(),
+    );
+    added.expect("cosigner authorized");
+
+    account_id
+}
+
+fn initiate_deposit(env: &TestEnv, initiator: Principal, account_id: &str, amount: u64) -> Result<String, CustodyError> {
+    env.update(
+        env.custody_core,
+        initiator,
+        "initiate_multisig_transaction",
+        encode_args((account_id, TransactionType::Deposit, amount, None::<String>)).unwrap(),
+    )
+}
+
+/// Moves `amount` ckBTC from a two-of-two treasury wallet owned by `owner` and
+/// `cosigner` to `destination`, returning the executed multisig transaction
+fn fund_from_treasury(
+    env: &TestEnv,
+    owner: Principal,
+    cosigner: Principal,
+    destination: &str,
+    amount: u64,
+) -> MultisigTransaction {
+    let wallet_id: Result<String, CustodyError> = env.update(
+        env.multisig_wallet,
+        owner,
+        "create_multisig_wallet",
+        encode_args((
+            "Acme Treasury",
+            vec![owner, cosigner],
+            2u8,
+            WalletType::CorporateTreasury,
+            1_000_000u64,
+        ))
+        .unwrap(),
+    );
+    let wallet_id = wallet_id.expect("treasury wallet created");
+
+    let deposited: Result<String, CustodyError> = env.update(
+        env.multisig_wallet,
+        owner,
+        "deposit_asset",
+        encode_args((wallet_id.clone(), "ckBTC", amount)).unwrap(),
+    );
+    deposited.expect("treasury funded");
+
+    let submitted: Result<String, CustodyError> = env.update(
+        env.multisig_wallet,
+        owner,
+        "submit_transaction",
+        encode_args((
+            wallet_id,
+            destination,
+            amount,
+            Vec::<u8>::new(),
+            TransactionPriority::Normal,
+            Some("ckBTC"),
+        ))
+        .unwrap(),
+    );
+    let multisig_transaction_id = submitted.expect("treasury transfer submitted");
+
+    // The submitter's confirmation counts, so the cosigner's reaches the threshold
+    let confirmed: Result<String, CustodyError> = env.update(
+        env.multisig_wallet,
+        cosigner,
+        "confirm_transaction",
+        encode_one(multisig_transaction_id.clone()).unwrap(),
+    );
+    confirmed.expect("cosigner confirmation accepted");
+    env.settle();
+
+    let transaction: Option<MultisigTransaction> = env.query(
+        env.multisig_wallet,
+        owner,
+        "get_transaction",
+        encode_one(multisig_transaction_id).unwrap(),
+    );
+    transaction.expect("treasury transfer exists")
+}
+
+fn get_transaction(env: &TestEnv, transaction_id: &str) -> Transaction {
+    let transaction: Option<Transaction> = env.query(
+        env.custody_core,
+        env.admin,
+        "get_transaction",
+        encode_one(transaction_id).unwrap(),
+    );
+    transaction.expect("transaction exists")
+}
+
+#[test]
+fn kyc_gated_transaction_executes_and_closure_reaches_audit_trail() {
+    let Some(env) = TestEnv::from_env() else {
+        return;
+    };
+    let owner = principal("owner");
+    let cosigner = principal("cosigner");
+    let account_id = create_account(&env, owner);
+
+    // custody_core asks compliance_engine before activating the account
+    assert!(approve_account(&env, &account_id).is_err(), "account activated before KYC approval");
+
+    approve_kyc(&env, owner);
+    approve_account(&env, &account_id).expect("custody account approved");
+
+    let added: Result<String, CustodyError> = env.update(
+        env.custody_core,
+        owner,
+        "add_authorized_user",
+        encode_args((account_id.clone(), cosigner, None::<String>)).unwrap(),
+    );
+    added.expect("cosigner authorized");
+
+    // The deposit comes out of the corporate treasury, whose owners approve
+    // the transfer in multisig_wallet before custody_core books it
+    let treasury_transfer = fund_from_treasury(&env, owner, cosigner, &account_id, 50_000);
+    assert!(treasury_transfer.executed, "treasury transfer executed once both owners confirmed");
+    assert_eq!(treasury_transfer.confirmations.len(), 2);
+    assert_eq!(treasury_transfer.asset_id.as_deref(), Some("ckBTC"));
+
+    let wallet_log: Vec<WalletAuditLog> = env.query(
+        env.multisig_wallet,
+        owner,
+        "get_audit_logs",
+        encode_one(treasury_transfer.wallet_id.clone()).unwrap(),
+    );
+    let transfer_actions: Vec<AuditAction> = wallet_log
+        .iter()
+        .filter(|log| log.transaction_id.as_deref() == Some(treasury_transfer.id.as_str()))
+        .map(|log| log.action.clone())
+        .collect();
+    assert_eq!(
+        transfer_actions,
+        vec![
+            AuditAction::TransactionSubmitted,
+            AuditAction::TransactionConfirmed,
+            AuditAction::TransactionExecuted,
+        ]
+    );
+
+    // ...and again before accepting a transaction
+    let transaction_id = initiate_deposit(&env, owner, &account_id, 50_000).expect("compliant owner can initiate");
+    assert_eq!(get_transaction(&env, &transaction_id).status, TransactionStatus::Pending);
+
+    let approved: Result<String, CustodyError> = env.update(
+        env.custody_core,
+        cosigner,
+        "approve_transaction",
+        encode_one(transaction_id.clone()).unwrap(),
+    );
+    approved.expect("cosigner approval accepted");
+    env.settle();
+
+    assert_eq!(get_transaction(&env, &transaction_id).status, TransactionStatus::Executed);
+    let account: Option<CustodyAccount> = env.query(
+        env.custody_core,
+        owner,
+        "get_custody_account",
+        encode_one(account_id.clone()).unwrap(),
+    );
+    assert_eq!(account.expect("account exists").balance, 50_000);
+
+    // Closure needs the owner and an operator; the second sign-off sweeps and audits
+    for signer in [owner, env.admin] {
+        let result: Result<String, CustodyError> = env.update(
+            env.custody_core,
+            signer,
+            "close_account",
+            encode_args((account_id.clone(), "bc1qdestinationaddress0000000000000000000", "Relationship ended")).unwrap(),
+        );
+        result.expect("closure sign-off accepted");
+    }
+    env.settle();
+
+    let closure: Option<AccountClosure> = env.query(
+        env.custody_core,
+        owner,
+        "get_account_closure",
+        encode_one(account_id.clone()).unwrap(),
+    );
+    let closure = closure.expect("closure recorded");
+    assert!(closure.closed_at.is_some());
+    let sweep_transaction_id = closure.sweep_transaction_id.clone().expect("closure swept the balance");
+
+    // Both executions and the closure reach the audit trail, and nothing else
+    let entries: Vec<AuditEntry> = env.query(
+        env.audit_trail,
+        env.admin,
+        "get_audit_entries_by_resource",
+        encode_args((account_id.clone(), 50u32, None::<String>)).unwrap(),
+    );
+    let mut actions: Vec<&str> = entries.iter().map(|entry| entry.action.as_str()).collect();
+    actions.sort_unstable();
+    assert_eq!(actions, vec!["close_account", "execute_transaction", "execute_transaction"]);
+
+    for executed_id in [&transaction_id, &sweep_transaction_id] {
+        assert!(
+            entries
+                .iter()
+                .any(|entry| entry.action == "execute_transaction" && entry.details.contains(executed_id.as_str())),
+            "audit trail is missing the execution of {}",
+            executed_id
+        );
+    }
+
+    let closure_entry = entries
+        .iter()
+        .find(|entry| entry.action == "close_account")
+        .expect("audit trail received the closure event");
+    assert_eq!(closure.audit_entry_id.as_deref(), Some(closure_entry.id.as_str()));
+    assert!(entries.iter().all(|entry| entry.actor == env.custody_core));
+}
+
+#[test]
+fn scheduled_transaction_is_released_once_time_advances() {
+    let Some(env) = TestEnv::from_env() else {
+        return;
+    };
+    let owner = principal("owner");
+    let account_id = open_account(&env, owner, principal("cosigner"));
+
+    let execute_at = env.now_nanos() + Duration::from_secs(10 * 60).as_nanos() as u64;
+    let scheduled: Result<String, CustodyError> = env.update(
+        env.custody_core,
+        owner,
+        "schedule_transaction",
+        encode_args((
+            account_id.clone(),
+            TransactionType::Deposit,
+            10_000u64,
+            None::<String>,
+            execute_at,
+            Some("Quarterly funding"),
+            None::<String>,
+        ))
+        .unwrap(),
+    );
+    scheduled.expect("transaction scheduled");
+
+    let early: Result<Vec<String>, CustodyError> =
+        env.update(env.custody_core, owner, "process_scheduled_transactions", encode_args(()).unwrap());
+    assert!(early.expect("processing succeeds").is_empty());
+
+    env.advance_time(Duration::from_secs(11 * 60));
+
+    let due: Result<Vec<String>, CustodyError> =
+        env.update(env.custody_core, owner, "process_scheduled_transactions", encode_args(()).unwrap());
+    assert_eq!(due.expect("processing succeeds").len(), 1);
+
+    let pending: Vec<Transaction> = env.query(
+        env.custody_core,
+        owner,
+        "get_pending_transactions",
+        encode_one(account_id).unwrap(),
+    );
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].amount, 10_000);
+}
+
+#[test]
+fn expired_kyc_blocks_new_transactions() {
+    let Some(env) = TestEnv::from_env() else {
+        return;
+    };
+    let owner = principal("owner");
+    let account_id = open_account(&env, owner, principal("cosigner"));
+
+    initiate_deposit(&env, owner, &account_id, 10_000).expect("freshly approved owner can initiate");
+
+    // KYC approvals lapse after a year
+    env.advance_time(Duration::from_secs(366 * 24 * 60 * 60));
+
+    let status: Result<String, CustodyError> = env.query(
+        env.compliance_engine,
+        env.admin,
+        "check_compliance_status",
+        encode_one(owner).unwrap(),
+    );
+    assert!(matches!(status, Err(CustodyError::StatusConflict { ref current, .. }) if current == "Expired"));
+
+    let expired = initiate_deposit(&env, owner, &account_id, 10_000);
+    assert!(matches!(expired, Err(CustodyError::StatusConflict { .. })), "expired KYC still transacts: {:?}", expired);
+}
+
+#[test]
+fn multisig_wallet_refuses_unfunded_submission() {
+    let Some(env) = TestEnv::from_env() else {
+        return;
+    };
+    let owners = vec![env.admin, principal("treasurer"), principal("controller")];
+
+    let wallet_id: Result<String, CustodyError> = env.update(
+        env.multisig_wallet,
+        env.admin,
+        "create_multisig_wallet",
+        encode_args((
+            "Treasury",
+            owners.clone(),
+            2u8,
+            WalletType::CorporateTreasury,
+            1_000_000u64,
+        ))
+        .unwrap(),
+    );
+    let wallet_id = wallet_id.expect("wallet created");
+
+    let wallet: Option<MultisigWallet> =
+        env.query(env.multisig_wallet, env.admin, "get_wallet", encode_one(wallet_id.clone()).unwrap());
+    let wallet = wallet.expect("wallet exists");
+    assert_eq!(wallet.owners.len(), owners.len());
+    assert_eq!(wallet.threshold, 2);
+
+    // Only token deposits are recorded, so native BTC proposals cannot pass the balance check
+    let submitted: Result<String, CustodyError> = env.update(
+        env.multisig_wallet,
+        env.admin,
+        "submit_transaction",
+        encode_args((
+            wallet_id,
+            "bc1qdestinationaddress0000000000000000000",
+            10_000u64,
+            Vec::<u8>::new(),
+            TransactionPriority::Normal,
+        ))
+        .unwrap(),
+    );
+    assert!(matches!(submitted, Err(CustodyError::InsufficientBalance { .. })));
+}